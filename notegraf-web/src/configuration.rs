@@ -1,3 +1,4 @@
+use notegraf::notestore::compaction::{CompactionPolicy, CompactionRule};
 use notegraf::notestore::BoxedNoteStore;
 use notegraf::{InMemoryStore, PostgreSQLStoreBuilder};
 use sqlx::postgres::PgConnectOptions;
@@ -19,6 +20,17 @@ pub struct Settings {
     pub debug: bool,
     notestoretype: NoteStoreType,
     populatetestdata: bool,
+    frontmattersync: bool,
+    /// The request header with the user making a request, whom new revisions are attributed to,
+    /// or no attribution if not set.
+    ///
+    /// The header is trusted as is, so only set it behind a reverse proxy that authenticates users
+    /// and strips this header from the requests of clients.
+    pub authorheader: Option<String>,
+    /// How many days deleted notes are kept for before they are purged, or forever if not set.
+    pub trashretentiondays: Option<u64>,
+    /// How often to remove old revisions of notes, or never if not set.
+    pub compaction: Option<CompactionSettings>,
     pub otlpendpoint: Option<String>,
     pub loglevel: Option<String>,
 }
@@ -30,7 +42,9 @@ impl Settings {
         log_statement_filter: LevelFilter,
     ) -> BoxedNoteStore<crate::NoteType> {
         let store: BoxedNoteStore<crate::NoteType> = match self.notestoretype {
-            NoteStoreType::InMemory => Box::new(InMemoryStore::new()),
+            NoteStoreType::InMemory => {
+                Box::new(InMemoryStore::new().with_front_matter_sync(self.frontmattersync))
+            }
             NoteStoreType::PostgreSQL => {
                 let database_settings = CONFIGURATION.database
                     .as_ref()
@@ -50,16 +64,67 @@ impl Settings {
                     database_settings.options()
                 }
                 .log_statements(log_statement_filter);
-                Box::new(PostgreSQLStoreBuilder::new(db_options).build().await)
+                Box::new(
+                    PostgreSQLStoreBuilder::new(db_options)
+                        .front_matter_sync(self.frontmattersync)
+                        .build()
+                        .await,
+                )
             }
         };
-        if cfg!(feature = "notetype_markdown") && self.populatetestdata {
+        // Write back the metadata of old notes, which is otherwise upgraded whenever it is read
+        let migrated = store
+            .migrate_metadata()
+            .await
+            .expect("Failed to migrate the metadata of notes");
+        if migrated > 0 {
+            info!("Migrated the metadata of {} notes", migrated);
+        }
+        // The test data is written in Markdown
+        #[cfg(any(feature = "notetype_markdown", feature = "notetype_dynamic"))]
+        if self.populatetestdata {
             notegraf::notestore::util::populate_test_data(&store).await;
         }
+        #[cfg(not(any(feature = "notetype_markdown", feature = "notetype_dynamic")))]
+        if self.populatetestdata {
+            warn!("populatetestdata is ignored because notes are not in Markdown");
+        }
         store
     }
 }
 
+#[derive(serde::Deserialize, Debug)]
+pub struct CompactionSettings {
+    pub intervalhours: u64,
+    /// The rules of the policy, or the default policy if not set.
+    pub rules: Option<Vec<CompactionRuleSettings>>,
+}
+
+/// See [`CompactionRule`], in hours.
+#[derive(serde::Deserialize, Debug)]
+pub struct CompactionRuleSettings {
+    pub uptohours: Option<u64>,
+    pub keepeveryhours: Option<u64>,
+}
+
+impl CompactionSettings {
+    pub fn policy(&self) -> CompactionPolicy {
+        let hours = |h: Option<u64>| h.map(|h| chrono::Duration::hours(h as i64));
+        match self.rules {
+            Some(ref rules) => CompactionPolicy {
+                rules: rules
+                    .iter()
+                    .map(|r| CompactionRule {
+                        up_to: hours(r.uptohours),
+                        keep_every: hours(r.keepeveryhours),
+                    })
+                    .collect(),
+            },
+            None => CompactionPolicy::default(),
+        }
+    }
+}
+
 #[derive(serde::Deserialize, Clone, Debug)]
 pub struct DatabaseSettings {
     pub port: String,
@@ -100,6 +165,7 @@ pub fn get_configuration() -> Result<Settings, config::ConfigError> {
         .set_default("debug", false)?
         .set_default("host", "localhost")?
         .set_default("populatetestdata", false)?
+        .set_default("frontmattersync", false)?
         .add_source(config::File::with_name("configuration").required(false))
         .add_source(
             config::Environment::default()
@@ -88,6 +88,14 @@ const renderer: RendererObject = {
         href = cleanHref;
         if (href.indexOf("notegraf:") === 0) {
             href = href.slice(9);
+            // Aliases are accepted wherever note IDs are
+            if (href.indexOf("/alias/") === 0) {
+                href = "/note/" + href.slice(7);
+            }
+            // Links to deleted notes are left as tombstones
+            if (href.indexOf("/deleted/") === 0) {
+                return '<del title="Deleted note">' + text + '</del>';
+            }
         }
         const isExternalURL = new URL(href, location.origin).origin !== location.origin;
         let out = '<a href="' + href + '"';
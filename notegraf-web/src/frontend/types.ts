@@ -3,6 +3,11 @@ export type NoteMetadata = {
     created_at: string,
     modified_at: string,
     tags: string[],
+    aliases: string[],
+    pinned: boolean,
+    starred: boolean,
+    archived: boolean,
+    author: string | null,
     custom_metadata: any
 }
 
@@ -8,7 +8,56 @@ pub mod routes;
 pub mod startup;
 pub mod telemetry;
 
+// The note type is chosen by exactly one feature, so the default one must be disabled to choose
+// another
+#[cfg(any(
+    all(
+        feature = "notetype_dynamic",
+        any(
+            feature = "notetype_plain",
+            feature = "notetype_markdown",
+            feature = "notetype_org",
+            feature = "notetype_asciidoc",
+            feature = "notetype_block"
+        )
+    ),
+    all(
+        feature = "notetype_plain",
+        any(
+            feature = "notetype_markdown",
+            feature = "notetype_org",
+            feature = "notetype_asciidoc",
+            feature = "notetype_block"
+        )
+    ),
+    all(
+        feature = "notetype_markdown",
+        any(
+            feature = "notetype_org",
+            feature = "notetype_asciidoc",
+            feature = "notetype_block"
+        )
+    ),
+    all(
+        feature = "notetype_org",
+        any(feature = "notetype_asciidoc", feature = "notetype_block")
+    ),
+    all(feature = "notetype_asciidoc", feature = "notetype_block"),
+))]
+compile_error!(
+    "only one of the notetype_* features can be enabled, use --no-default-features to choose \
+     another note type than the default"
+);
+
+#[cfg(feature = "notetype_dynamic")]
+pub type NoteType = notegraf::DynamicNote;
 #[cfg(feature = "notetype_plain")]
 pub type NoteType = notegraf::PlainNote;
 #[cfg(feature = "notetype_markdown")]
 pub type NoteType = notegraf::MarkdownNote;
+#[cfg(feature = "notetype_org")]
+pub type NoteType = notegraf::OrgNote;
+#[cfg(feature = "notetype_asciidoc")]
+pub type NoteType = notegraf::AsciiDocNote;
+#[cfg(feature = "notetype_block")]
+pub type NoteType = notegraf::BlockNote;
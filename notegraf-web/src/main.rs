@@ -30,6 +30,9 @@ async fn main() -> std::io::Result<()> {
             .get_note_store(false, LevelFilter::Debug)
             .await,
         CONFIGURATION.debug,
+        CONFIGURATION.authorheader.clone(),
+        CONFIGURATION.trashretentiondays,
+        CONFIGURATION.compaction.as_ref(),
     )?
     .await?;
     opentelemetry::global::shutdown_tracer_provider();
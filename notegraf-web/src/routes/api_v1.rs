@@ -1,12 +1,28 @@
+use crate::startup::Author;
 use crate::NoteType;
+use actix_web::http::header;
 use actix_web::{delete, get, post, web, HttpResponse, Responder};
+use chrono::{DateTime, NaiveDate, Utc};
 use notegraf::errors::NoteStoreError;
-use notegraf::notemetadata::NoteMetadataEditable;
+use notegraf::notemetadata::{NoteFlag, NoteMetadataEditable};
+use notegraf::notestore::batch::{BatchOperation, NoteRef};
+use notegraf::notestore::delete::DeleteOptions;
+use notegraf::notestore::schema::SchemaViolation;
+use notegraf::notestore::structure::MoveTarget;
+use notegraf::notestore::task::TaskQuery;
 use notegraf::notestore::BoxedNoteStore;
-use notegraf::{NoteLocator, NoteSerializable};
-use serde::Deserialize;
+use notegraf::notetype::{Heading, LinkRewrite, NoteFormat};
+use notegraf::NoteType as _;
+use notegraf::{NoteID, NoteLocator, NoteSerializable};
+use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 
+#[derive(Serialize)]
+struct MetadataViolations<'a> {
+    error: String,
+    violations: &'a [SchemaViolation],
+}
+
 fn notestore_error_handler(e: &NoteStoreError) -> HttpResponse {
     match e {
         NoteStoreError::NoteNotExist(_) => HttpResponse::NotFound().body(e.to_string()),
@@ -20,10 +36,35 @@ fn notestore_error_handler(e: &NoteStoreError) -> HttpResponse {
         NoteStoreError::UpdateOldRevision(_, _) => HttpResponse::Conflict().body(e.to_string()),
         NoteStoreError::DeleteOldRevision(_, _) => HttpResponse::Conflict().body(e.to_string()),
         NoteStoreError::NotAChild(_, _) => HttpResponse::Conflict().body(e.to_string()),
+        NoteStoreError::Inconsistent(_, _) => {
+            error!("Note store internal error {:?}", e);
+            HttpResponse::InternalServerError().body(e.to_string())
+        }
         NoteStoreError::ExistingNext(_, _) => HttpResponse::Conflict().body(e.to_string()),
+        NoteStoreError::Cycle(_, _) => HttpResponse::Conflict().body(e.to_string()),
+        NoteStoreError::InvalidPlaceholder(_) => HttpResponse::BadRequest().body(e.to_string()),
         NoteStoreError::HasBranches(_) => HttpResponse::Conflict().body(e.to_string()),
         NoteStoreError::HasReferences(_) => HttpResponse::Conflict().body(e.to_string()),
+        NoteStoreError::HasNext(_) => HttpResponse::Conflict().body(e.to_string()),
+        NoteStoreError::NotDeleted(_) => HttpResponse::Conflict().body(e.to_string()),
+        NoteStoreError::TaskNotExist(_, _) => HttpResponse::NotFound().body(e.to_string()),
+        NoteStoreError::ConversionNotSupported(_, _) => {
+            HttpResponse::BadRequest().body(e.to_string())
+        }
         NoteStoreError::ParseError(_) => HttpResponse::BadRequest().body(e.to_string()),
+        NoteStoreError::MetadataSchemaTooNew(_, _) => {
+            error!("Note store internal error {:?}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+        NoteStoreError::InvalidMetadataSchema(_) => HttpResponse::BadRequest().body(e.to_string()),
+        NoteStoreError::MetadataSchemaViolation(violations) => HttpResponse::UnprocessableEntity()
+            .json(MetadataViolations {
+                error: e.to_string(),
+                violations,
+            }),
+        NoteStoreError::InvalidAlias(_) => HttpResponse::BadRequest().body(e.to_string()),
+        NoteStoreError::AliasConflict(_, _) => HttpResponse::Conflict().body(e.to_string()),
+        NoteStoreError::AliasNotExist(_) => HttpResponse::NotFound().body(e.to_string()),
         NoteStoreError::PostgreSQLError(_) => {
             error!("Note store internal error {:?}", e);
             HttpResponse::InternalServerError().finish()
@@ -36,7 +77,7 @@ fn notestore_error_handler(e: &NoteStoreError) -> HttpResponse {
 async fn get_note_by_locator(
     store: web::Data<BoxedNoteStore<NoteType>>,
     loc: &NoteLocator,
-) -> impl Responder {
+) -> HttpResponse {
     let result = store.as_ref().get_note(loc).await;
     match result {
         Ok(note) => HttpResponse::Ok().json(NoteSerializable::all_fields(note)),
@@ -44,22 +85,45 @@ async fn get_note_by_locator(
     }
 }
 
+#[derive(Deserialize)]
+struct DeleteQuery {
+    /// Also delete the branches and the next notes of the note.
+    #[serde(default)]
+    cascade: bool,
+    /// Rewrite the links to the deleted notes, as `plain_text` or `tombstone`.
+    rewrite: Option<LinkRewrite>,
+    /// Only report what would be changed.
+    #[serde(default)]
+    dry_run: bool,
+}
+
 #[delete("/note/{note_id}")]
 #[instrument(
-    skip(store, params),
+    skip(store, params, query),
     fields(
-        note_id = %params.0
+        note_id = %params.0,
+        cascade = query.cascade,
+        rewrite = ?query.rewrite,
+        dry_run = query.dry_run
     )
 )]
 async fn delete_note_current(
     store: web::Data<BoxedNoteStore<NoteType>>,
     params: web::Path<(String,)>,
+    query: web::Query<DeleteQuery>,
+    author: Author,
 ) -> impl Responder {
     let (note_id,) = params.into_inner();
     let loc = NoteLocator::Current(note_id.into());
-    let res = store.delete_note(&loc).await;
+    let query = query.into_inner();
+    let options = DeleteOptions {
+        cascade: query.cascade,
+        rewrite_references: query.rewrite,
+        dry_run: query.dry_run,
+    };
+    let res = store.delete_note_with(&loc, &options, author.0).await;
     match res {
-        Ok(_) => HttpResponse::Ok().finish(),
+        Ok(report) => HttpResponse::Ok().json(report),
         Err(e) => notestore_error_handler(&e),
     }
 }
@@ -85,7 +149,13 @@ async fn get_note_specific(
 struct NotePostData {
     title: String,
     note_inner: String,
+    /// The default format of the note type if omitted, or the current format when updating a note
+    #[serde(default)]
+    format: Option<NoteFormat>,
     metadata_tags: String,
+    /// Comma-separated like the tags. The aliases are unchanged if omitted
+    #[serde(default)]
+    metadata_aliases: Option<String>,
     metadata_custom_metadata: String,
 }
 
@@ -99,21 +169,40 @@ impl TryFrom<NotePostData> for NoteStoreEditArgument {
     type Error = String;
 
     fn try_from(note: NotePostData) -> Result<Self, Self::Error> {
-        let custom_metadata =
-            serde_json::from_str(&note.metadata_custom_metadata).map_err(|e| e.to_string())?;
-        let tags: HashSet<String> = HashSet::from_iter(
-            note.metadata_tags
-                .split(',')
-                .map(|tag| tag.trim().to_owned())
-                .filter(|tag| !tag.is_empty()),
-        );
+        let metadata = note.metadata()?;
+        let note_inner =
+            NoteType::parse(note.format, note.note_inner).map_err(|e| e.to_string())?;
+        if let Some(format) = note.format {
+            if note_inner.get_format() != format {
+                return Err(format!("note format `{format}` is not supported"));
+            }
+        }
         Ok(NoteStoreEditArgument {
             title: note.title,
-            note_inner: NoteType::from(note.note_inner),
-            metadata: NoteMetadataEditable {
-                tags: Some(tags),
-                custom_metadata: Some(custom_metadata),
-            },
+            note_inner,
+            metadata,
+        })
+    }
+}
+
+impl NotePostData {
+    fn metadata(&self) -> Result<NoteMetadataEditable, String> {
+        let custom_metadata =
+            serde_json::from_str(&self.metadata_custom_metadata).map_err(|e| e.to_string())?;
+        let split = |s: &str| -> HashSet<String> {
+            HashSet::from_iter(
+                s.split(',')
+                    .map(|tag| tag.trim().to_owned())
+                    .filter(|tag| !tag.is_empty()),
+            )
+        };
+        let tags = split(&self.metadata_tags);
+        let aliases = self.metadata_aliases.as_deref().map(split);
+        Ok(NoteMetadataEditable {
+            tags: Some(tags),
+            aliases,
+            custom_metadata: Some(custom_metadata),
+            author: None,
         })
     }
 }
@@ -123,12 +212,14 @@ impl TryFrom<NotePostData> for NoteStoreEditArgument {
 async fn new_note(
     store: web::Data<BoxedNoteStore<NoteType>>,
     note: web::Json<NotePostData>,
+    author: Author,
 ) -> impl Responder {
     let note: Result<NoteStoreEditArgument, String> = note.into_inner().try_into();
     if let Err(e) = note {
         return HttpResponse::BadRequest().body(e);
     }
-    let note = note.unwrap();
+    let mut note = note.unwrap();
+    note.metadata.author = author.0;
     let res = store
         .new_note(note.title, note.note_inner, note.metadata)
         .await;
@@ -163,6 +254,35 @@ async fn get_revisions(
     HttpResponse::Ok().json(revisions)
 }
 
+#[derive(Deserialize, Debug)]
+struct DiffQuery {
+    from: String,
+    to: String,
+}
+
+#[get("/note/{note_id}/diff")]
+#[instrument(
+    skip(store, params, query),
+    fields(
+        note_id = %params.0,
+        from = %query.from,
+        to = %query.to
+    )
+)]
+async fn get_diff(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    params: web::Path<(String,)>,
+    query: web::Query<DiffQuery>,
+) -> impl Responder {
+    let (note_id,) = params.into_inner();
+    let DiffQuery { from, to } = query.into_inner();
+    let res = store.diff(&note_id.into(), &from.into(), &to.into()).await;
+    match res {
+        Ok(diff) => HttpResponse::Ok().json(diff),
+        Err(e) => notestore_error_handler(&e),
+    }
+}
+
 #[post("/note/{note_id}/revision")]
 #[instrument(
     skip(store, params, note),
@@ -174,14 +294,35 @@ async fn update_note(
     store: web::Data<BoxedNoteStore<NoteType>>,
     params: web::Path<(String,)>,
     note: web::Json<NotePostData>,
+    author: Author,
 ) -> impl Responder {
     let (note_id,) = params.into_inner();
+    let note = note.into_inner();
+    if note.format.is_none() {
+        // The store parses the body in the format that the note has when it is updated
+        let mut metadata = match note.metadata() {
+            Ok(m) => m,
+            Err(e) => return HttpResponse::BadRequest().body(e),
+        };
+        metadata.author = author.0;
+        let operation = BatchOperation::UpdateSource {
+            note: NoteRef::Id(note_id.into()),
+            title: Some(note.title),
+            note_inner: note.note_inner,
+            metadata,
+        };
+        return match store.apply_batch(vec![operation]).await {
+            Ok(mut results) => HttpResponse::Ok().json(results.pop().flatten()),
+            Err(e) => notestore_error_handler(&e),
+        };
+    }
     let loc = NoteLocator::Current(note_id.into());
-    let note: Result<NoteStoreEditArgument, String> = note.into_inner().try_into();
+    let note: Result<NoteStoreEditArgument, String> = note.try_into();
     if let Err(e) = note {
         return HttpResponse::BadRequest().body(e);
     }
-    let note = note.unwrap();
+    let mut note = note.unwrap();
+    note.metadata.author = author.0;
     let res = store
         .update_note(&loc, Some(note.title), Some(note.note_inner), note.metadata)
         .await;
@@ -191,6 +332,104 @@ async fn update_note(
     }
 }
 
+#[derive(Deserialize, Debug)]
+struct RevertQuery {
+    /// The revision that the client expects to be current, to avoid reverting over someone
+    /// else's update.
+    current: Option<String>,
+}
+
+#[post("/note/{note_id}/revision/{revision_id}/revert")]
+#[instrument(
+    skip(store, params, query),
+    fields(
+        note_id = %params.0,
+        revision_id = %params.1,
+        current = ?query.current
+    )
+)]
+async fn revert_note(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    params: web::Path<(String, String)>,
+    query: web::Query<RevertQuery>,
+    author: Author,
+) -> impl Responder {
+    let (note_id, revision_id) = params.into_inner();
+    let loc = match query.into_inner().current {
+        Some(current) => NoteLocator::Specific(note_id.into(), current.into()),
+        None => NoteLocator::Current(note_id.into()),
+    };
+    let res = store.revert_note(&loc, &revision_id.into(), author.0).await;
+    match res {
+        Ok(loc) => HttpResponse::Ok().json(loc),
+        Err(e) => notestore_error_handler(&e),
+    }
+}
+
+/// Keep a revision when revisions are compacted, regardless of the policy.
+#[post("/note/{note_id}/revision/{revision_id}/keep")]
+#[instrument(
+    skip(store, params),
+    fields(
+        note_id = %params.0,
+        revision_id = %params.1
+    )
+)]
+async fn keep_revision(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    params: web::Path<(String, String)>,
+) -> impl Responder {
+    let (note_id, revision_id) = params.into_inner();
+    let res = store
+        .keep_revision(&note_id.into(), &revision_id.into(), true)
+        .await;
+    match res {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(e) => notestore_error_handler(&e),
+    }
+}
+
+#[delete("/note/{note_id}/revision/{revision_id}/keep")]
+#[instrument(
+    skip(store, params),
+    fields(
+        note_id = %params.0,
+        revision_id = %params.1
+    )
+)]
+async fn unkeep_revision(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    params: web::Path<(String, String)>,
+) -> impl Responder {
+    let (note_id, revision_id) = params.into_inner();
+    let res = store
+        .keep_revision(&note_id.into(), &revision_id.into(), false)
+        .await;
+    match res {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(e) => notestore_error_handler(&e),
+    }
+}
+
+#[get("/note/{note_id}/kept")]
+#[instrument(
+    skip(store, params),
+    fields(
+        note_id = %params.0
+    )
+)]
+async fn get_kept_revisions(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    params: web::Path<(String,)>,
+) -> impl Responder {
+    let (note_id,) = params.into_inner();
+    let res = store.get_kept_revisions(&note_id.into()).await;
+    match res {
+        Ok(kept) => HttpResponse::Ok().json(kept),
+        Err(e) => notestore_error_handler(&e),
+    }
+}
+
 #[post("/note/{note_id}/branch")]
 #[instrument(
     skip(store, params, note),
@@ -202,6 +441,7 @@ async fn new_branch(
     store: web::Data<BoxedNoteStore<NoteType>>,
     params: web::Path<(String,)>,
     note: web::Json<NotePostData>,
+    author: Author,
 ) -> impl Responder {
     let (note_id,) = params.into_inner();
     let loc = NoteLocator::Current(note_id.into());
@@ -209,7 +449,8 @@ async fn new_branch(
     if let Err(e) = note {
         return HttpResponse::BadRequest().body(e);
     }
-    let note = note.unwrap();
+    let mut note = note.unwrap();
+    note.metadata.author = author.0;
     let res = store
         .add_branch(loc.get_id(), note.title, note.note_inner, note.metadata)
         .await;
@@ -230,6 +471,7 @@ async fn new_next(
     store: web::Data<BoxedNoteStore<NoteType>>,
     params: web::Path<(String,)>,
     note: web::Json<NotePostData>,
+    author: Author,
 ) -> impl Responder {
     let (note_id,) = params.into_inner();
     let loc = NoteLocator::Current(note_id.into());
@@ -237,7 +479,8 @@ async fn new_next(
     if let Err(e) = note {
         return HttpResponse::BadRequest().body(e);
     }
-    let note = note.unwrap();
+    let mut note = note.unwrap();
+    note.metadata.author = author.0;
     let res = store
         .append_note(loc.get_id(), note.title, note.note_inner, note.metadata)
         .await;
@@ -247,6 +490,9 @@ async fn new_next(
     }
 }
 
+/// A note can also be referred to by an alias, and IDs take precedence over aliases.
+/// Previous aliases of a note are redirected to the note temporarily, as another note can take
+/// them later.
 #[get("/note/{note_id}")]
 #[instrument(
     skip(store, params),
@@ -259,8 +505,41 @@ async fn get_note_current(
     params: web::Path<(String,)>,
 ) -> impl Responder {
     let (note_id,) = params.into_inner();
-    let loc = NoteLocator::Current(note_id.into());
-    get_note_by_locator(store, &loc).await
+    let loc = NoteLocator::Current(note_id.clone().into());
+    let e = match store.get_note(&loc).await {
+        Ok(note) => return HttpResponse::Ok().json(NoteSerializable::all_fields(note)),
+        Err(e @ (NoteStoreError::NoteNotExist(_) | NoteStoreError::NotUuid(_))) => e,
+        Err(e) => return notestore_error_handler(&e),
+    };
+    match store.lookup_alias(&note_id).await {
+        Ok(target) if target.redirect => HttpResponse::TemporaryRedirect()
+            .insert_header((header::LOCATION, format!("/api/v1/note/{}", target.id)))
+            .finish(),
+        Ok(target) => get_note_by_locator(store, &NoteLocator::Current(target.id)).await,
+        // Neither an ID nor an alias. Non-UUIDs in PostgreSQL are reported as missing aliases
+        Err(NoteStoreError::AliasNotExist(_)) if matches!(e, NoteStoreError::NoteNotExist(_)) => {
+            notestore_error_handler(&e)
+        }
+        Err(e) => notestore_error_handler(&e),
+    }
+}
+
+#[get("/alias/{alias}")]
+#[instrument(
+    skip(store, params),
+    fields(
+        alias = %params.0
+    )
+)]
+async fn lookup_alias(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    params: web::Path<(String,)>,
+) -> impl Responder {
+    let (alias,) = params.into_inner();
+    match store.lookup_alias(&alias).await {
+        Ok(target) => HttpResponse::Ok().json(target),
+        Err(e) => notestore_error_handler(&e),
+    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -303,15 +582,459 @@ async fn get_tags(store: web::Data<BoxedNoteStore<NoteType>>) -> impl Responder
     HttpResponse::Ok().json(res.unwrap())
 }
 
+#[get("/metadata_schema")]
+#[instrument(skip(store))]
+async fn get_metadata_schemas(store: web::Data<BoxedNoteStore<NoteType>>) -> impl Responder {
+    let res = store.metadata_schemas().await;
+    match res {
+        Ok(schemas) => HttpResponse::Ok().json(schemas),
+        Err(e) => notestore_error_handler(&e),
+    }
+}
+
+async fn set_metadata_schema(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    tag: Option<&str>,
+    schema: Option<serde_json::Value>,
+) -> HttpResponse {
+    let res = store.set_metadata_schema(tag, schema).await;
+    match res {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(e) => notestore_error_handler(&e),
+    }
+}
+
+#[post("/metadata_schema/default")]
+#[instrument(skip(store, schema))]
+async fn set_default_metadata_schema(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    schema: web::Json<serde_json::Value>,
+) -> impl Responder {
+    set_metadata_schema(store, None, Some(schema.into_inner())).await
+}
+
+#[delete("/metadata_schema/default")]
+#[instrument(skip(store))]
+async fn delete_default_metadata_schema(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+) -> impl Responder {
+    set_metadata_schema(store, None, None).await
+}
+
+#[post("/metadata_schema/tag/{tag}")]
+#[instrument(
+    skip(store, params, schema),
+    fields(
+        tag = %params.0
+    )
+)]
+async fn set_tag_metadata_schema(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    params: web::Path<(String,)>,
+    schema: web::Json<serde_json::Value>,
+) -> impl Responder {
+    let (tag,) = params.into_inner();
+    set_metadata_schema(store, Some(&tag), Some(schema.into_inner())).await
+}
+
+#[delete("/metadata_schema/tag/{tag}")]
+#[instrument(
+    skip(store, params),
+    fields(
+        tag = %params.0
+    )
+)]
+async fn delete_tag_metadata_schema(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    params: web::Path<(String,)>,
+) -> impl Responder {
+    let (tag,) = params.into_inner();
+    set_metadata_schema(store, Some(&tag), None).await
+}
+
+#[derive(Deserialize, Debug)]
+struct TasksQuery {
+    done: Option<bool>,
+    due_after: Option<NaiveDate>,
+    due_before: Option<NaiveDate>,
+    tags: Option<String>,
+}
+
+#[get("/tasks")]
+#[instrument(skip(store))]
+async fn get_tasks(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    query: web::Query<TasksQuery>,
+) -> impl Responder {
+    let query = query.into_inner();
+    let tags = match query.tags {
+        Some(t) => t
+            .split(',')
+            .map(|tag| tag.trim().to_owned())
+            .filter(|tag| !tag.is_empty())
+            .collect(),
+        None => vec![],
+    };
+    let task_query = TaskQuery {
+        done: query.done,
+        due_after: query.due_after,
+        due_before: query.due_before,
+        tags,
+    };
+    let res = store.tasks(&task_query).await;
+    match res {
+        Ok(tasks) => HttpResponse::Ok().json(tasks),
+        Err(e) => notestore_error_handler(&e),
+    }
+}
+
+#[post("/note/{note_id}/task/{index}/toggle")]
+#[instrument(
+    skip(store, params),
+    fields(
+        note_id = %params.0,
+        index = %params.1
+    )
+)]
+async fn toggle_task(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    params: web::Path<(String, usize)>,
+    author: Author,
+) -> impl Responder {
+    let (note_id, index) = params.into_inner();
+    let loc = NoteLocator::Current(note_id.into());
+    let res = store.toggle_task(&loc, index, author.0).await;
+    match res {
+        Ok(loc) => HttpResponse::Ok().json(loc),
+        Err(e) => notestore_error_handler(&e),
+    }
+}
+
+/// Toggle whether a note is pinned, starred or archived.
+#[post("/note/{note_id}/{flag}/toggle")]
+#[instrument(
+    skip(store, params),
+    fields(
+        note_id = %params.0,
+        flag = ?params.1
+    )
+)]
+async fn toggle_flag(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    params: web::Path<(String, NoteFlag)>,
+    author: Author,
+) -> impl Responder {
+    let (note_id, flag) = params.into_inner();
+    let loc = NoteLocator::Current(note_id.into());
+    let res = store.toggle_flag(&loc, flag, author.0).await;
+    match res {
+        Ok(loc) => HttpResponse::Ok().json(loc),
+        Err(e) => notestore_error_handler(&e),
+    }
+}
+
+#[derive(Deserialize)]
+struct ConvertData {
+    format: NoteFormat,
+}
+
+#[post("/note/{note_id}/convert")]
+#[instrument(
+    skip(store, params, data),
+    fields(
+        note_id = %params.0
+    )
+)]
+async fn convert_note(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    params: web::Path<(String,)>,
+    data: web::Json<ConvertData>,
+    author: Author,
+) -> impl Responder {
+    let (note_id,) = params.into_inner();
+    let loc = NoteLocator::Current(note_id.into());
+    let res = store.convert_note(&loc, data.format, author.0).await;
+    match res {
+        Ok(loc) => HttpResponse::Ok().json(loc),
+        Err(e) => notestore_error_handler(&e),
+    }
+}
+
+#[derive(Serialize)]
+struct DeletedNoteSerializable {
+    #[serde(flatten)]
+    note: NoteSerializable<NoteType>,
+    deleted_at: DateTime<Utc>,
+}
+
+#[get("/trash")]
+#[instrument(skip(store))]
+async fn list_deleted(store: web::Data<BoxedNoteStore<NoteType>>) -> impl Responder {
+    let res = store.list_deleted().await;
+    match res {
+        Ok(deleted) => {
+            let deleted: Vec<DeletedNoteSerializable> = deleted
+                .into_iter()
+                .map(|d| DeletedNoteSerializable {
+                    note: NoteSerializable::all_fields(d.note),
+                    deleted_at: d.deleted_at,
+                })
+                .collect();
+            HttpResponse::Ok().json(deleted)
+        }
+        Err(e) => notestore_error_handler(&e),
+    }
+}
+
+#[delete("/trash/{note_id}")]
+#[instrument(
+    skip(store, params),
+    fields(
+        note_id = %params.0
+    )
+)]
+async fn purge_note(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    params: web::Path<(String,)>,
+) -> impl Responder {
+    let (note_id,) = params.into_inner();
+    let res = store.purge_note(&note_id.into()).await;
+    match res {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(e) => notestore_error_handler(&e),
+    }
+}
+
+#[get("/integrity")]
+#[instrument(skip(store))]
+async fn check_integrity(store: web::Data<BoxedNoteStore<NoteType>>) -> impl Responder {
+    match store.check_integrity(false).await {
+        Ok(issues) => HttpResponse::Ok().json(issues),
+        Err(e) => notestore_error_handler(&e),
+    }
+}
+
+#[post("/integrity/repair")]
+#[instrument(skip(store))]
+async fn repair_integrity(store: web::Data<BoxedNoteStore<NoteType>>) -> impl Responder {
+    match store.check_integrity(true).await {
+        Ok(issues) => HttpResponse::Ok().json(issues),
+        Err(e) => notestore_error_handler(&e),
+    }
+}
+
+/// An operation in `POST /batch`, where notes are given by `{"id": ...}`, or by
+/// `{"created": index}` for the note of an earlier operation in the batch.
+#[derive(Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum BatchOperationData {
+    New {
+        note: NotePostData,
+    },
+    Append {
+        last: NoteRef,
+        note: NotePostData,
+    },
+    Branch {
+        parent: NoteRef,
+        note: NotePostData,
+    },
+    /// The format is the current format of the note if omitted, like `POST /note/{id}/revision`.
+    Update {
+        target: NoteRef,
+        note: NotePostData,
+    },
+    Delete {
+        target: NoteRef,
+    },
+    Move {
+        target: NoteRef,
+        to: MoveTarget<NoteRef>,
+    },
+}
+
+/// Turn the operations in a batch into what the store applies.
+fn batch_operations(
+    data: Vec<BatchOperationData>,
+    author: Option<String>,
+) -> Result<Vec<BatchOperation<NoteType>>, HttpResponse> {
+    let parse = |note: NotePostData| -> Result<NoteStoreEditArgument, HttpResponse> {
+        let mut note: NoteStoreEditArgument = note
+            .try_into()
+            .map_err(|e: String| HttpResponse::BadRequest().body(e))?;
+        note.metadata.author = author.clone();
+        Ok(note)
+    };
+    let mut operations = vec![];
+    for operation in data {
+        let operation = match operation {
+            BatchOperationData::New { note } => {
+                let note = parse(note)?;
+                BatchOperation::New {
+                    title: note.title,
+                    note_inner: note.note_inner,
+                    metadata: note.metadata,
+                }
+            }
+            BatchOperationData::Append { last, note } => {
+                let note = parse(note)?;
+                BatchOperation::Append {
+                    last,
+                    title: note.title,
+                    note_inner: note.note_inner,
+                    metadata: note.metadata,
+                }
+            }
+            BatchOperationData::Branch { parent, note } => {
+                let note = parse(note)?;
+                BatchOperation::Branch {
+                    parent,
+                    title: note.title,
+                    note_inner: note.note_inner,
+                    metadata: note.metadata,
+                }
+            }
+            // The store parses the body in the format that the note has when it is updated
+            BatchOperationData::Update { target, note } if note.format.is_none() => {
+                let mut metadata = note
+                    .metadata()
+                    .map_err(|e| HttpResponse::BadRequest().body(e))?;
+                metadata.author = author.clone();
+                BatchOperation::UpdateSource {
+                    note: target,
+                    title: Some(note.title),
+                    note_inner: note.note_inner,
+                    metadata,
+                }
+            }
+            BatchOperationData::Update { target, note } => {
+                let note = parse(note)?;
+                BatchOperation::Update {
+                    note: target,
+                    title: Some(note.title),
+                    note_inner: Some(note.note_inner),
+                    metadata: note.metadata,
+                }
+            }
+            BatchOperationData::Delete { target } => BatchOperation::Delete { note: target },
+            BatchOperationData::Move { target, to } => BatchOperation::Move { note: target, to },
+        };
+        operations.push(operation);
+    }
+    Ok(operations)
+}
+
+/// Apply several operations atomically.
+///
+/// Returns the locator of the note of each operation, which is `null` for deletions.
+#[post("/batch")]
+#[instrument(skip(store, data))]
+async fn apply_batch(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    data: web::Json<Vec<BatchOperationData>>,
+    author: Author,
+) -> impl Responder {
+    let operations = match batch_operations(data.into_inner(), author.0) {
+        Ok(operations) => operations,
+        Err(response) => return response,
+    };
+    match store.apply_batch(operations).await {
+        Ok(results) => HttpResponse::Ok().json(results),
+        Err(e) => notestore_error_handler(&e),
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct TocEntry {
+    id: NoteID,
+    title: String,
+    outline: Vec<Heading>,
+}
+
+/// Get the outlines of all notes in the sequence that the note is in, from the first note.
+async fn sequence_toc(
+    store: &BoxedNoteStore<NoteType>,
+    note_id: NoteID,
+) -> Result<Vec<TocEntry>, NoteStoreError> {
+    let mut note = store.get_note(&NoteLocator::Current(note_id)).await?;
+    // Guard against a malformed sequence that loops
+    let mut visited = HashSet::from([note.get_id()]);
+    while let Some(prev) = note.get_prev() {
+        if !visited.insert(prev.clone()) {
+            break;
+        }
+        note = store.get_note(&NoteLocator::Current(prev)).await?;
+    }
+    let mut visited = HashSet::from([note.get_id()]);
+    let mut toc = vec![];
+    loop {
+        let outline = note
+            .get_note_inner()
+            .get_outline()
+            .map_err(NoteStoreError::note_inner)?;
+        toc.push(TocEntry {
+            id: note.get_id(),
+            title: note.get_title(),
+            outline,
+        });
+        match note.get_next() {
+            Some(next) if visited.insert(next.clone()) => {
+                note = store.get_note(&NoteLocator::Current(next)).await?;
+            }
+            _ => break,
+        }
+    }
+    Ok(toc)
+}
+
+#[get("/note/{note_id}/toc")]
+#[instrument(
+    skip(store, params),
+    fields(
+        note_id = %params.0
+    )
+)]
+async fn get_sequence_toc(
+    store: web::Data<BoxedNoteStore<NoteType>>,
+    params: web::Path<(String,)>,
+) -> impl Responder {
+    let (note_id,) = params.into_inner();
+    match sequence_toc(store.as_ref(), note_id.into()).await {
+        Ok(toc) => HttpResponse::Ok().json(toc),
+        Err(e) => notestore_error_handler(&e),
+    }
+}
+
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(get_note_current)
         .service(get_note_specific)
         .service(new_note)
         .service(delete_note_current)
+        .service(list_deleted)
+        .service(purge_note)
+        .service(check_integrity)
+        .service(repair_integrity)
+        .service(apply_batch)
         .service(update_note)
+        .service(revert_note)
+        .service(keep_revision)
+        .service(unkeep_revision)
+        .service(get_kept_revisions)
         .service(get_revisions)
+        .service(get_diff)
         .service(search)
         .service(new_branch)
         .service(new_next)
-        .service(get_tags);
+        .service(lookup_alias)
+        .service(get_tags)
+        .service(get_metadata_schemas)
+        .service(set_default_metadata_schema)
+        .service(delete_default_metadata_schema)
+        .service(set_tag_metadata_schema)
+        .service(delete_tag_metadata_schema)
+        .service(get_tasks)
+        .service(toggle_task)
+        .service(toggle_flag)
+        .service(convert_note)
+        .service(get_sequence_toc);
 }
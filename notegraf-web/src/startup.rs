@@ -1,24 +1,98 @@
+use crate::configuration::CompactionSettings;
 use crate::routes::*;
 use crate::NoteType;
 use actix_files::{Files, NamedFile};
-use actix_web::dev::Server;
+use actix_web::dev::{Payload, Server};
 use actix_web::middleware::{NormalizePath, TrailingSlash};
 use actix_web::web::Data;
-use actix_web::{web, App, HttpServer};
+use actix_web::{web, App, FromRequest, HttpRequest, HttpServer};
+use chrono::Utc;
+use notegraf::notestore::compaction::CompactionPolicy;
 use notegraf::notestore::BoxedNoteStore;
+use std::convert::Infallible;
+use std::future::{ready, Ready};
 use std::net::TcpListener;
+use std::time::Duration;
 use tracing_actix_web::TracingLogger;
 
 async fn index_file() -> actix_web::Result<NamedFile> {
     Ok(NamedFile::open("./dist/index.html")?)
 }
 
+/// The request header with the user making a request, if configured.
+struct AuthorHeader(Option<String>);
+
+/// The user making a request, as given in the configured author header.
+#[derive(Debug)]
+pub struct Author(pub Option<String>);
+
+impl FromRequest for Author {
+    type Error = Infallible;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let author = req
+            .app_data::<Data<AuthorHeader>>()
+            .and_then(|h| h.0.as_ref())
+            .and_then(|h| req.headers().get(h))
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.trim().to_owned())
+            .filter(|v| !v.is_empty());
+        ready(Ok(Author(author)))
+    }
+}
+
+/// Purge the notes that have been deleted for longer than the retention period, once an hour.
+async fn purge_expired_notes(store: Data<BoxedNoteStore<NoteType>>, retention_days: u64) {
+    let mut interval = tokio::time::interval(Duration::from_secs(60 * 60));
+    loop {
+        interval.tick().await;
+        let before = Utc::now() - chrono::Duration::days(retention_days as i64);
+        match store.purge_deleted(before).await {
+            Ok(purged) if !purged.is_empty() => info!("Purged {} deleted notes", purged.len()),
+            Ok(_) => {}
+            Err(e) => error!("Failed to purge deleted notes: {:?}", e),
+        }
+    }
+}
+
+/// Remove old revisions of notes according to a policy, once every `interval_hours`.
+async fn compact_revisions(
+    store: Data<BoxedNoteStore<NoteType>>,
+    interval_hours: u64,
+    policy: CompactionPolicy,
+) {
+    let mut interval = tokio::time::interval(Duration::from_secs(interval_hours * 60 * 60));
+    loop {
+        interval.tick().await;
+        match store.compact_revisions(&policy).await {
+            Ok(removed) if removed > 0 => info!("Removed {} old revisions", removed),
+            Ok(_) => {}
+            Err(e) => error!("Failed to compact revisions: {:?}", e),
+        }
+    }
+}
+
 pub fn run(
     listener: TcpListener,
     note_store: BoxedNoteStore<NoteType>,
     debug: bool,
+    author_header: Option<String>,
+    trash_retention_days: Option<u64>,
+    compaction: Option<&CompactionSettings>,
 ) -> Result<Server, std::io::Error> {
     let ns: Data<BoxedNoteStore<NoteType>> = Data::new(note_store);
+    let author_header = Data::new(AuthorHeader(author_header));
+    if let Some(days) = trash_retention_days {
+        tokio::spawn(purge_expired_notes(ns.clone(), days));
+    }
+    if let Some(compaction) = compaction {
+        tokio::spawn(compact_revisions(
+            ns.clone(),
+            compaction.intervalhours.max(1),
+            compaction.policy(),
+        ));
+    }
     let server = HttpServer::new(move || {
         App::new()
             .wrap(NormalizePath::new(TrailingSlash::Trim))
@@ -34,6 +108,7 @@ pub fn run(
             // https://create-react-app.dev/docs/deployment/#serving-apps-with-client-side-routing
             .service(web::resource("/{tail}*").route(web::get().to(index_file)))
             .app_data(ns.clone())
+            .app_data(author_header.clone())
     })
     .listen(listener)?
     .run();
@@ -5,6 +5,7 @@ use reqwest::{Client, StatusCode};
 
 use notegraf::NoteLocator;
 use serde_json::{json, Value};
+use uuid::Uuid;
 
 async fn create_note_helper(
     client: &Client,
@@ -16,6 +17,7 @@ async fn create_note_helper(
     post_note_helper(client, address, "note", title, note_inner, tags).await
 }
 
+#[allow(clippy::needless_borrows_for_generic_args)]
 async fn post_note_helper(
     client: &Client,
     address: &str,
@@ -40,6 +42,7 @@ async fn post_note_helper(
         .expect("Failed to parse response")
 }
 
+#[allow(clippy::needless_borrows_for_generic_args)]
 async fn get_note_helper(client: &Client, address: &str, loc: &NoteLocator) -> Value {
     client
         .get(&format!(
@@ -91,6 +94,7 @@ async fn note_retrieve() {
 }
 
 #[tokio::test]
+#[allow(clippy::needless_borrows_for_generic_args)]
 async fn note_delete() {
     let app = spawn_app().await;
     let client = Client::new();
@@ -143,6 +147,7 @@ async fn note_update() {
 }
 
 #[tokio::test]
+#[allow(clippy::needless_borrows_for_generic_args)]
 async fn note_revisions() {
     let app = spawn_app().await;
     let client = Client::new();
@@ -181,6 +186,7 @@ async fn note_revisions() {
 }
 
 #[tokio::test]
+#[allow(clippy::needless_borrows_for_generic_args)]
 async fn recent_notes() {
     let app = spawn_app().await;
     let client = Client::new();
@@ -203,6 +209,7 @@ async fn recent_notes() {
 }
 
 #[tokio::test]
+#[allow(clippy::needless_borrows_for_generic_args)]
 async fn search_notes() {
     let app = spawn_app().await;
     let client = Client::new();
@@ -307,6 +314,7 @@ async fn append_note() {
 }
 
 #[tokio::test]
+#[allow(clippy::needless_borrows_for_generic_args)]
 async fn orphan_reference() {
     let app = spawn_app().await;
     let client = Client::new();
@@ -335,6 +343,7 @@ async fn orphan_reference() {
 }
 
 #[tokio::test]
+#[allow(clippy::needless_borrows_for_generic_args)]
 async fn orphan_prev() {
     let app = spawn_app().await;
     let client = Client::new();
@@ -364,6 +373,7 @@ async fn orphan_prev() {
 }
 
 #[tokio::test]
+#[allow(clippy::needless_borrows_for_generic_args)]
 async fn orphan_parent() {
     let app = spawn_app().await;
     let client = Client::new();
@@ -393,6 +403,7 @@ async fn orphan_parent() {
 }
 
 #[tokio::test]
+#[allow(clippy::needless_borrows_for_generic_args)]
 async fn tags() {
     let app = spawn_app().await;
     let client = Client::new();
@@ -415,6 +426,1047 @@ async fn tags() {
 }
 
 #[tokio::test]
+async fn inline_tags() {
+    let app = spawn_app().await;
+    let client = Client::new();
+
+    let loc = create_note_helper(&client, &app.address, "foo", "#tag2", "tag1").await;
+
+    let response = get_note_helper(&client, &app.address, &loc).await;
+    assert_eq!(response["metadata"]["tags"], json!(["tag1"]));
+    assert_eq!(response["inline_tags"], json!(["tag2"]));
+
+    let response = client
+        .get(format!("{}/api/v1/tags", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json::<Value>()
+        .await
+        .expect("Failed to parse response");
+    let tags = response.as_array().unwrap();
+    assert_eq!(tags.len(), 2);
+    assert!(tags.contains(&json! {"tag1"}));
+    assert!(tags.contains(&json! {"tag2"}));
+}
+
+#[tokio::test]
+async fn tasks() {
+    let app = spawn_app().await;
+    let client = Client::new();
+
+    let loc = create_note_helper(
+        &client,
+        &app.address,
+        "todo",
+        "- [ ] foo @due(2026-10-20)\n- [x] bar\n- [ ] baz",
+        "tag1",
+    )
+    .await;
+    let _loc2 = create_note_helper(&client, &app.address, "other", "- [ ] qux", "").await;
+
+    let response = client
+        .get(format!(
+            "{}/api/v1/tasks?done=false&tags=tag1",
+            &app.address
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json::<Value>()
+        .await
+        .expect("Failed to parse response");
+    let tasks = response.as_array().unwrap();
+    assert_eq!(tasks.len(), 2);
+    assert_eq!(tasks[0]["id"], json!(loc.get_id().as_ref()));
+    assert_eq!(tasks[0]["title"], json!("todo"));
+    assert_eq!(tasks[0]["index"], json!(0));
+    assert_eq!(tasks[0]["text"], json!("foo"));
+    assert_eq!(tasks[0]["done"], json!(false));
+    assert_eq!(tasks[0]["due"], json!("2026-10-20"));
+    assert_eq!(tasks[1]["text"], json!("baz"));
+
+    let response = client
+        .get(format!(
+            "{}/api/v1/tasks?due_after=2026-10-19&due_before=2026-10-25",
+            &app.address
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json::<Value>()
+        .await
+        .expect("Failed to parse response");
+    assert_eq!(response.as_array().unwrap().len(), 1);
+
+    let response = client
+        .post(format!(
+            "{}/api/v1/note/{}/task/0/toggle",
+            &app.address,
+            loc.get_id().as_ref()
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert!(response.status().is_success());
+    let response = get_note_helper(&client, &app.address, &loc).await;
+    assert_eq!(
+        response["note_inner"],
+        json!("- [x] foo @due(2026-10-20)\n- [x] bar\n- [ ] baz")
+    );
+
+    let response = client
+        .post(format!(
+            "{}/api/v1/note/{}/task/3/toggle",
+            &app.address,
+            loc.get_id().as_ref()
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn sequence_toc() {
+    let app = spawn_app().await;
+    let client = Client::new();
+
+    let loc1 = create_note_helper(&client, &app.address, "first", "# Intro\n## Goal", "").await;
+    let loc2 = post_note_helper(
+        &client,
+        &app.address,
+        &format!("note/{}/next", loc1.get_id().as_ref()),
+        "second",
+        "no headings",
+        "",
+    )
+    .await;
+    let _loc3 = post_note_helper(
+        &client,
+        &app.address,
+        &format!("note/{}/next", loc2.get_id().as_ref()),
+        "third",
+        "# Intro",
+        "",
+    )
+    .await;
+
+    // Any note in the sequence gives the outline of the whole sequence
+    let response = client
+        .get(format!(
+            "{}/api/v1/note/{}/toc",
+            &app.address,
+            loc2.get_id().as_ref()
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json::<Value>()
+        .await
+        .expect("Failed to parse response");
+    let toc = response.as_array().unwrap();
+    assert_eq!(toc.len(), 3);
+    assert_eq!(toc[0]["id"], json!(loc1.get_id().as_ref()));
+    assert_eq!(toc[0]["title"], json!("first"));
+    assert_eq!(
+        toc[0]["outline"],
+        json!([
+            {"level": 1, "text": "Intro", "slug": "intro", "offset": 0},
+            {"level": 2, "text": "Goal", "slug": "goal", "offset": 8}
+        ])
+    );
+    assert_eq!(toc[1]["outline"], json!([]));
+    assert_eq!(toc[2]["title"], json!("third"));
+    assert_eq!(toc[2]["outline"][0]["slug"], json!("intro"));
+}
+
+#[cfg(feature = "notetype_dynamic")]
+#[tokio::test]
+async fn note_format() {
+    let app = spawn_app().await;
+    let client = Client::new();
+
+    let loc_md = create_note_helper(&client, &app.address, "", "# Foo", "").await;
+    let body = format!(
+        "* Bar\n[[notegraf:/note/{}][foo]]",
+        loc_md.get_id().as_ref()
+    );
+    let loc_org: NoteLocator = client
+        .post(format!("{}/api/v1/note", &app.address))
+        .json(&json!({
+            "title": "",
+            "note_inner": body,
+            "format": "org",
+            "metadata_tags": "",
+            "metadata_custom_metadata": "null"
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json()
+        .await
+        .expect("Failed to parse response");
+    let note_md = get_note_helper(&client, &app.address, &loc_md).await;
+    assert_eq!(note_md["format"], json!("markdown"));
+    assert_eq!(note_md["references"], json!([loc_org.get_id().as_ref()]));
+    let note_org = get_note_helper(&client, &app.address, &loc_org).await;
+    assert_eq!(note_org["format"], json!("org"));
+    assert_eq!(note_org["note_inner"], json!(body));
+
+    // Updating a note without a format keeps its format
+    let _loc = post_note_helper(
+        &client,
+        &app.address,
+        &format!("note/{}/revision", loc_org.get_id().as_ref()),
+        "Bar",
+        &body,
+        "",
+    )
+    .await;
+    let note_org = get_note_helper(&client, &app.address, &loc_org).await;
+    assert_eq!(note_org["title"], json!("Bar"));
+    assert_eq!(note_org["format"], json!("org"));
+    assert_eq!(note_org["referents"], json!([loc_md.get_id().as_ref()]));
+
+    let response = client
+        .post(format!("{}/api/v1/note", &app.address))
+        .json(&json!({
+            "title": "",
+            "note_inner": "",
+            "format": "rst",
+            "metadata_tags": "",
+            "metadata_custom_metadata": "null"
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[cfg(feature = "notetype_dynamic")]
+#[tokio::test]
+async fn convert_note() {
+    let app = spawn_app().await;
+    let client = Client::new();
+
+    let loc = create_note_helper(&client, &app.address, "", "# Foo", "").await;
+    let response = client
+        .post(format!(
+            "{}/api/v1/note/{}/convert",
+            &app.address,
+            loc.get_id().as_ref()
+        ))
+        .json(&json!({"format": "plain"}))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert!(response.status().is_success());
+    let note = get_note_helper(&client, &app.address, &loc).await;
+    assert_eq!(note["format"], json!("plain"));
+    assert_eq!(note["note_inner"]["body"], json!("# Foo"));
+
+    let response = client
+        .post(format!(
+            "{}/api/v1/note/{}/convert",
+            &app.address,
+            loc.get_id().as_ref()
+        ))
+        .json(&json!({"format": "asciidoc"}))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[cfg(feature = "notetype_dynamic")]
+#[tokio::test]
+async fn block_note() {
+    let app = spawn_app().await;
+    let client = Client::new();
+
+    let loc1 = create_note_helper(&client, &app.address, "", "# Foo", "").await;
+    let blocks = json!({"blocks": [
+        {"type": "heading", "level": 1, "text": "Bar"},
+        {"type": "checklist", "items": [{"text": "Baz", "done": false}]},
+        {"type": "reference", "note": loc1.get_id().as_ref(), "text": "foo"}
+    ]});
+    let loc2: NoteLocator = client
+        .post(format!("{}/api/v1/note", &app.address))
+        .json(&json!({
+            "title": "",
+            "note_inner": blocks.to_string(),
+            "format": "block",
+            "metadata_tags": "",
+            "metadata_custom_metadata": "null"
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json()
+        .await
+        .expect("Failed to parse response");
+    let note2 = get_note_helper(&client, &app.address, &loc2).await;
+    assert_eq!(note2["format"], json!("block"));
+    assert_eq!(note2["note_inner"], blocks);
+    assert_eq!(note2["referents"], json!([loc1.get_id().as_ref()]));
+
+    let response = client
+        .post(format!(
+            "{}/api/v1/note/{}/convert",
+            &app.address,
+            loc2.get_id().as_ref()
+        ))
+        .json(&json!({"format": "markdown"}))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert!(response.status().is_success());
+    let note2 = get_note_helper(&client, &app.address, &loc2).await;
+    assert_eq!(note2["format"], json!("markdown"));
+    assert_eq!(
+        note2["note_inner"],
+        json!(format!(
+            "# Bar\n\n- [ ] Baz\n\n[foo](notegraf:/note/{})\n",
+            loc1.get_id().as_ref()
+        ))
+    );
+    assert_eq!(note2["referents"], json!([loc1.get_id().as_ref()]));
+}
+
+#[cfg(feature = "notetype_dynamic")]
+#[tokio::test]
+async fn unparsable_note() {
+    let app = spawn_app().await;
+    let client = Client::new();
+
+    // Plain notes are JSON, so plain text cannot be parsed as one
+    let response = client
+        .post(format!("{}/api/v1/note", &app.address))
+        .json(&json!({
+            "title": "",
+            "note_inner": "Hello, world!",
+            "format": "plain",
+            "metadata_tags": "",
+            "metadata_custom_metadata": "null"
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .starts_with("note cannot be parsed"));
+}
+
+#[tokio::test]
+async fn metadata_schema() {
+    let app = spawn_app().await;
+    let client = Client::new();
+
+    let response = client
+        .post(format!(
+            "{}/api/v1/metadata_schema/tag/project",
+            &app.address
+        ))
+        .json(&json!({
+            "type": "object",
+            "properties": {"status": {"enum": ["todo", "done"]}},
+            "additionalProperties": false
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert!(response.status().is_success());
+    let schemas = client
+        .get(format!("{}/api/v1/metadata_schema", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json::<Value>()
+        .await
+        .expect("Failed to parse response");
+    assert_eq!(schemas["default"], Value::Null);
+    assert_eq!(schemas["tags"]["project"]["additionalProperties"], false);
+
+    let response = client
+        .post(format!("{}/api/v1/note", &app.address))
+        .json(&json!({
+            "title": "",
+            "note_inner": "",
+            "metadata_tags": "project",
+            "metadata_custom_metadata": "{\"stauts\": \"done\"}"
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 422);
+    let body = response.json::<Value>().await.unwrap();
+    assert_eq!(body["violations"][0]["tag"], "project");
+    assert_eq!(body["violations"][0]["instance_path"], "");
+
+    let response = client
+        .delete(format!(
+            "{}/api/v1/metadata_schema/tag/project",
+            &app.address
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert!(response.status().is_success());
+    create_note_helper(&client, &app.address, "", "", "project").await;
+
+    let response = client
+        .post(format!("{}/api/v1/metadata_schema/default", &app.address))
+        .json(&json!({"type": 1}))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn note_alias() {
+    let app = spawn_app().await;
+    let client = Client::new();
+
+    let post_note = |endpoint: String, aliases: &str| {
+        client
+            .post(format!("{}/api/v1/{endpoint}", &app.address))
+            .json(&json!({
+                "title": "Meeting",
+                "note_inner": "",
+                "metadata_tags": "",
+                "metadata_aliases": aliases,
+                "metadata_custom_metadata": "{}"
+            }))
+            .send()
+    };
+    let loc = post_note("note".to_owned(), "meeting")
+        .await
+        .expect("Failed to execute request.")
+        .json::<NoteLocator>()
+        .await
+        .unwrap();
+    let response = client
+        .get(format!("{}/api/v1/note/meeting", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json::<Value>()
+        .await
+        .expect("Failed to parse response");
+    assert_eq!(response["id"], loc.get_id().as_ref());
+    assert_eq!(response["metadata"]["aliases"], json!(["meeting"]));
+
+    let response = post_note("note".to_owned(), "meeting")
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    let response = post_note(format!("note/{}/revision", loc.get_id()), "standup")
+        .await
+        .expect("Failed to execute request.");
+    assert!(response.status().is_success());
+    let target = client
+        .get(format!("{}/api/v1/alias/meeting", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json::<Value>()
+        .await
+        .expect("Failed to parse response");
+    assert_eq!(target["id"], loc.get_id().as_ref());
+    assert_eq!(target["redirect"], true);
+    // The previous alias is redirected to the note
+    let response = Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+        .get(format!("{}/api/v1/note/meeting", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+    assert_eq!(
+        response.headers()["location"],
+        format!("/api/v1/note/{}", loc.get_id()).as_str()
+    );
+    let response = client
+        .get(format!("{}/api/v1/note/meeting", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json::<Value>()
+        .await
+        .expect("Failed to parse response");
+    assert_eq!(response["metadata"]["aliases"], json!(["standup"]));
+
+    let response = client
+        .get(format!("{}/api/v1/note/nonexist", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn toggle_flag() {
+    let app = spawn_app().await;
+    let client = Client::new();
+
+    let loc1 = create_note_helper(&client, &app.address, "Dashboard", "", "").await;
+    let _loc2 = create_note_helper(&client, &app.address, "Newer", "", "").await;
+    let response = client
+        .post(format!(
+            "{}/api/v1/note/{}/pinned/toggle",
+            &app.address,
+            loc1.get_id()
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert!(response.status().is_success());
+    let notes = client
+        .get(format!("{}/api/v1/note", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json::<Value>()
+        .await
+        .expect("Failed to parse response");
+    assert_eq!(notes[0]["title"], "Dashboard");
+    assert_eq!(notes[0]["metadata"]["pinned"], true);
+
+    let response = client
+        .post(format!(
+            "{}/api/v1/note/{}/archived/toggle",
+            &app.address,
+            loc1.get_id()
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert!(response.status().is_success());
+    let notes = client
+        .get(format!("{}/api/v1/note", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json::<Value>()
+        .await
+        .expect("Failed to parse response");
+    assert_eq!(notes.as_array().unwrap().len(), 1);
+
+    let response = client
+        .post(format!(
+            "{}/api/v1/note/{}/hidden/toggle",
+            &app.address,
+            loc1.get_id()
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn revision_author() {
+    let app = spawn_app().await;
+    let client = Client::new();
+
+    let post_note = |endpoint: String, author: &'static str| {
+        client
+            .post(format!("{}/api/v1/{endpoint}", &app.address))
+            .header("Remote-User", author)
+            .json(&json!({
+                "title": "Runbook",
+                "note_inner": "Restart the server",
+                "metadata_tags": "",
+                "metadata_custom_metadata": "null"
+            }))
+            .send()
+    };
+    let loc: NoteLocator = post_note("note".to_owned(), "alice")
+        .await
+        .expect("Failed to execute request.")
+        .json()
+        .await
+        .expect("Failed to parse response");
+    let response = post_note(format!("note/{}/revision", loc.get_id()), "bob")
+        .await
+        .expect("Failed to execute request.");
+    assert!(response.status().is_success());
+
+    let revisions = client
+        .get(format!(
+            "{}/api/v1/note/{}/revision",
+            &app.address,
+            loc.get_id()
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json::<Value>()
+        .await
+        .expect("Failed to parse response");
+    assert_eq!(revisions[0]["metadata"]["author"], "alice");
+    assert_eq!(revisions[1]["metadata"]["author"], "bob");
+    // Toggling a flag also records the author
+    let response = client
+        .post(format!(
+            "{}/api/v1/note/{}/starred/toggle",
+            &app.address,
+            loc.get_id()
+        ))
+        .header("Remote-User", "carol")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert!(response.status().is_success());
+    let note = client
+        .get(format!("{}/api/v1/note/{}", &app.address, loc.get_id()))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json::<Value>()
+        .await
+        .expect("Failed to parse response");
+    assert_eq!(note["metadata"]["author"], "carol");
+
+    let notes = client
+        .get(format!("{}/api/v1/note", &app.address))
+        .query(&[("query", "author:alice")])
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json::<Value>()
+        .await
+        .expect("Failed to parse response");
+    assert_eq!(notes.as_array().unwrap().len(), 1);
+    assert_eq!(notes[0]["id"], loc.get_id().as_ref());
+    // Requests without the header are anonymous
+    let _loc2 = create_note_helper(&client, &app.address, "Anonymous", "", "").await;
+    let notes = client
+        .get(format!("{}/api/v1/note", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json::<Value>()
+        .await
+        .expect("Failed to parse response");
+    assert_eq!(notes[0]["title"], "Anonymous");
+    assert_eq!(notes[0]["metadata"]["author"], Value::Null);
+}
+
+#[tokio::test]
+async fn revision_author_header_unset() {
+    let app = spawn_app_with_author_header(None).await;
+    let client = Client::new();
+
+    // Without a configured header, the header of the client is not trusted
+    let response = client
+        .post(format!("{}/api/v1/note", &app.address))
+        .header("Remote-User", "mallory")
+        .json(&json!({
+            "title": "Runbook",
+            "note_inner": "Restart the server",
+            "metadata_tags": "",
+            "metadata_custom_metadata": "null"
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert!(response.status().is_success());
+    let notes = client
+        .get(format!("{}/api/v1/note", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json::<Value>()
+        .await
+        .expect("Failed to parse response");
+    assert_eq!(notes[0]["metadata"]["author"], Value::Null);
+}
+
+#[tokio::test]
+async fn keep_revision() {
+    let app = spawn_app().await;
+    let client = Client::new();
+
+    let loc = create_note_helper(&client, &app.address, "title", "foo", "").await;
+    let keep_url = format!(
+        "{}/api/v1/note/{}/revision/{}/keep",
+        &app.address,
+        loc.get_id(),
+        loc.get_revision().unwrap()
+    );
+    let get_kept = || async {
+        client
+            .get(format!(
+                "{}/api/v1/note/{}/kept",
+                &app.address,
+                loc.get_id()
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .json::<Value>()
+            .await
+            .expect("Failed to parse response")
+    };
+    let response = client
+        .post(&keep_url)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert!(response.status().is_success());
+    assert_eq!(get_kept().await, json!([loc.get_revision().unwrap()]));
+    let response = client
+        .delete(&keep_url)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert!(response.status().is_success());
+    assert_eq!(get_kept().await, json!([]));
+}
+
+#[tokio::test]
+async fn note_diff() {
+    let app = spawn_app().await;
+    let client = Client::new();
+
+    let loc1 = create_note_helper(&client, &app.address, "title", "foo\nbar", "ops").await;
+    let loc2 = post_note_helper(
+        &client,
+        &app.address,
+        &format!("note/{}/revision", loc1.get_id()),
+        "New title",
+        "foo\nbaz",
+        "runbook",
+    )
+    .await;
+
+    let response = client
+        .get(format!(
+            "{}/api/v1/note/{}/diff",
+            &app.address,
+            loc1.get_id()
+        ))
+        .query(&[
+            ("from", loc1.get_revision().unwrap().as_ref()),
+            ("to", loc2.get_revision().unwrap().as_ref()),
+        ])
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert!(response.status().is_success());
+    let diff = response
+        .json::<Value>()
+        .await
+        .expect("Failed to parse response");
+    assert_eq!(diff["title"], json!(["title", "New title"]));
+    assert_eq!(
+        diff["note_inner"][1],
+        json!({"kind": "delete", "old_line": 2, "new_line": null, "text": "bar"})
+    );
+    assert_eq!(diff["tags_added"], json!(["runbook"]));
+    assert_eq!(diff["tags_removed"], json!(["ops"]));
+
+    let nonexist = Uuid::new_v4().to_string();
+    let response = client
+        .get(format!(
+            "{}/api/v1/note/{}/diff",
+            &app.address,
+            loc1.get_id()
+        ))
+        .query(&[
+            ("from", loc1.get_revision().unwrap().as_ref()),
+            ("to", nonexist.as_str()),
+        ])
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn note_revert() {
+    let app = spawn_app().await;
+    let client = Client::new();
+
+    let loc1 = create_note_helper(&client, &app.address, "title", "body", "").await;
+    let loc2 = post_note_helper(
+        &client,
+        &app.address,
+        &format!("note/{}/revision", loc1.get_id()),
+        "New title",
+        "New body",
+        "",
+    )
+    .await;
+    let revert = |current: &NoteLocator| {
+        client
+            .post(format!(
+                "{}/api/v1/note/{}/revision/{}/revert",
+                &app.address,
+                loc1.get_id(),
+                loc1.get_revision().unwrap()
+            ))
+            .query(&[("current", current.get_revision().unwrap().as_ref())])
+            .send()
+    };
+
+    // The revert is rejected if the note was updated in the meantime
+    let response = revert(&loc1).await.expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let loc3: NoteLocator = revert(&loc2)
+        .await
+        .expect("Failed to execute request.")
+        .json()
+        .await
+        .expect("Failed to parse response");
+    assert_ne!(loc3.get_revision(), loc1.get_revision());
+    let response = get_note_helper(&client, &app.address, &loc1).await;
+    assert_eq!(response["revision"], loc3.get_revision().unwrap().as_ref());
+    assert_eq!(response["title"], "title");
+    assert_eq!(response["note_inner"], "body");
+}
+
+#[tokio::test]
+async fn trash() {
+    let app = spawn_app().await;
+    let client = Client::new();
+
+    let loc1 = create_note_helper(&client, &app.address, "secret", "hunter2", "").await;
+    let loc2 = create_note_helper(&client, &app.address, "title", "body", "").await;
+    client
+        .delete(format!("{}/api/v1/note/{}", &app.address, loc1.get_id()))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let get_trash = || async {
+        client
+            .get(format!("{}/api/v1/trash", &app.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .json::<Value>()
+            .await
+            .expect("Failed to parse response")
+    };
+    let trash = get_trash().await;
+    assert_eq!(trash.as_array().unwrap().len(), 1);
+    assert_eq!(trash[0]["id"], loc1.get_id().as_ref());
+    assert_eq!(trash[0]["note_inner"], "hunter2");
+    assert!(trash[0]["deleted_at"].is_string());
+
+    let purge = |loc: &NoteLocator| {
+        client
+            .delete(format!("{}/api/v1/trash/{}", &app.address, loc.get_id()))
+            .send()
+    };
+    // Notes that are not deleted cannot be purged
+    let response = purge(&loc2).await.expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let response = purge(&loc1).await.expect("Failed to execute request.");
+    assert!(response.status().is_success());
+    assert!(get_trash().await.as_array().unwrap().is_empty());
+    let response = client
+        .get(format!(
+            "{}/api/v1/note/{}/revision/{}",
+            &app.address,
+            loc1.get_id(),
+            loc1.get_revision().unwrap()
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn delete_cascade() {
+    let app = spawn_app().await;
+    let client = Client::new();
+
+    let loc1 = create_note_helper(&client, &app.address, "parent", "", "").await;
+    let loc2 = post_note_helper(
+        &client,
+        &app.address,
+        &format!("note/{}/branch", loc1.get_id()),
+        "branch",
+        "",
+        "",
+    )
+    .await;
+    let loc3 = create_note_helper(
+        &client,
+        &app.address,
+        "referrer",
+        &format!("See [the branch](notegraf:/note/{}).", loc2.get_id()),
+        "",
+    )
+    .await;
+    let delete = |query: &str| {
+        client
+            .delete(format!(
+                "{}/api/v1/note/{}?{}",
+                &app.address,
+                loc1.get_id(),
+                query
+            ))
+            .send()
+    };
+    let response = delete("").await.expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let response = delete("cascade=true")
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let report: Value = delete("cascade=true&rewrite=plain_text&dry_run=true")
+        .await
+        .expect("Failed to execute request.")
+        .json()
+        .await
+        .expect("Failed to parse response");
+    assert_eq!(
+        report["deleted"],
+        json!([loc1.get_id().as_ref(), loc2.get_id().as_ref()])
+    );
+    assert_eq!(report["rewritten"], json!([loc3.get_id().as_ref()]));
+    get_note_helper(&client, &app.address, &loc1).await;
+
+    let response = delete("cascade=true&rewrite=plain_text")
+        .await
+        .expect("Failed to execute request.");
+    assert!(response.status().is_success());
+    let response = get_note_helper(&client, &app.address, &loc3).await;
+    assert_eq!(response["note_inner"], "See the branch.");
+    let response = client
+        .get(format!("{}/api/v1/note/{}", &app.address, loc2.get_id()))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn integrity() {
+    let app = spawn_app().await;
+    let client = Client::new();
+
+    let loc1 = create_note_helper(&client, &app.address, "parent", "", "").await;
+    post_note_helper(
+        &client,
+        &app.address,
+        &format!("note/{}/branch", loc1.get_id()),
+        "branch",
+        &format!("See [the parent](notegraf:/note/{}).", loc1.get_id()),
+        "",
+    )
+    .await;
+    let response: Value = client
+        .get(format!("{}/api/v1/integrity", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json()
+        .await
+        .expect("Failed to parse response");
+    assert_eq!(response, json!([]));
+    let response: Value = client
+        .post(format!("{}/api/v1/integrity/repair", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json()
+        .await
+        .expect("Failed to parse response");
+    assert_eq!(response, json!([]));
+}
+
+#[tokio::test]
+async fn batch() {
+    let app = spawn_app().await;
+    let client = Client::new();
+
+    let loc1 = create_note_helper(&client, &app.address, "existing", "", "").await;
+    let note = |title: &str, note_inner: &str| {
+        json!({
+            "title": title,
+            "note_inner": note_inner,
+            "metadata_tags": "",
+            "metadata_custom_metadata": "{}"
+        })
+    };
+    let mut org_note = note("first", "* Heading");
+    org_note["format"] = json!("org");
+    let response = client
+        .post(format!("{}/api/v1/batch", &app.address))
+        .json(&json!([
+            {"op": "new", "note": org_note},
+            {"op": "append", "last": {"created": 0}, "note": note("second", "")},
+            {"op": "branch", "parent": {"id": loc1.get_id()}, "note": note("branch", "")},
+            {"op": "update", "target": {"created": 0}, "note": note("first", "* Updated")},
+            {"op": "move", "target": {"created": 1}, "to": {"kind": "branch_of", "note": {"created": 2}}},
+            {"op": "delete", "target": {"id": loc1.get_id()}}
+        ]))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    // The existing note still has a branch
+    assert_eq!(response.status().as_u16(), 409);
+    let response: Vec<Option<NoteLocator>> = client
+        .post(format!("{}/api/v1/batch", &app.address))
+        .json(&json!([
+            {"op": "new", "note": org_note},
+            {"op": "append", "last": {"created": 0}, "note": note("second", "")},
+            {"op": "branch", "parent": {"id": loc1.get_id()}, "note": note("branch", "")},
+            {"op": "update", "target": {"created": 0}, "note": note("first", "* Updated")},
+            {"op": "move", "target": {"created": 1}, "to": {"kind": "branch_of", "note": {"created": 2}}}
+        ]))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json()
+        .await
+        .expect("Failed to parse response");
+    assert_eq!(response.len(), 5);
+    let locs: Vec<NoteLocator> = response.into_iter().map(Option::unwrap).collect();
+    let first = get_note_helper(&client, &app.address, &locs[0]).await;
+    assert_eq!(first["note_inner"], json!("* Updated"));
+    // Updates keep the format of notes created in the same batch
+    assert_eq!(first["format"], json!("org"));
+    assert_eq!(first["next"], Value::Null);
+    let branch = get_note_helper(&client, &app.address, &locs[2]).await;
+    assert_eq!(branch["parent"], json!(loc1.get_id()));
+    assert_eq!(branch["branches"], json!([locs[1].get_id()]));
+
+    // Nothing is created by the failed batch
+    let response = client
+        .get(format!("{}/api/v1/note", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json::<Value>()
+        .await
+        .expect("Failed to parse response");
+    assert_eq!(response.as_array().unwrap().len(), 4);
+
+    let response = client
+        .post(format!("{}/api/v1/batch", &app.address))
+        .json(&json!([{"op": "delete", "target": {"created": 0}}]))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+#[allow(clippy::needless_borrows_for_generic_args)]
 async fn limit_override() {
     let app = spawn_app().await;
     let client = Client::new();
@@ -447,6 +1499,7 @@ async fn limit_override() {
 }
 
 #[tokio::test]
+#[allow(clippy::needless_borrows_for_generic_args)]
 async fn tag_exclude() {
     let app = spawn_app().await;
     let client = Client::new();
@@ -501,6 +1554,7 @@ async fn tag_exclude() {
 }
 
 #[tokio::test]
+#[allow(clippy::needless_borrows_for_generic_args)]
 async fn lexeme_exclude() {
     let app = spawn_app().await;
     let client = Client::new();
@@ -555,6 +1609,7 @@ async fn lexeme_exclude() {
 }
 
 #[tokio::test]
+#[allow(clippy::needless_borrows_for_generic_args)]
 async fn issue_158() {
     let app = spawn_app().await;
     let client = Client::new();
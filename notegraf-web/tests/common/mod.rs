@@ -19,13 +19,26 @@ pub struct TestApp {
 }
 
 pub async fn spawn_app() -> TestApp {
+    // The tests play the reverse proxy that sets the header
+    spawn_app_with_author_header(Some("Remote-User".to_owned())).await
+}
+
+pub async fn spawn_app_with_author_header(author_header: Option<String>) -> TestApp {
     let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind random port");
     // We retrieve the port assigned to us by the OS
     let port = listener.local_addr().unwrap().port();
     let address = format!("http://127.0.0.1:{port}");
     lazy_static::initialize(&TRACING);
     let store = CONFIGURATION.get_note_store(true, LevelFilter::Debug).await;
-    let server = run(listener, store, CONFIGURATION.debug).expect("Failed to bind address");
+    let server = run(
+        listener,
+        store,
+        CONFIGURATION.debug,
+        author_header,
+        CONFIGURATION.trashretentiondays,
+        CONFIGURATION.compaction.as_ref(),
+    )
+    .expect("Failed to bind address");
     drop(tokio::spawn(server));
     TestApp { address }
 }
@@ -2,6 +2,7 @@ mod common;
 use common::*;
 
 #[tokio::test]
+#[allow(clippy::needless_borrows_for_generic_args)]
 async fn health_check_works() {
     let app = spawn_app().await;
     let client = reqwest::Client::new();
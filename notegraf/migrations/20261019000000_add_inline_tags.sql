@@ -0,0 +1,6 @@
+-- Tags written in the note body, as opposed to metadata_tags which are set explicitly.
+-- Existing revisions have their inline tags extracted when Notegraf next starts,
+-- see 20261019000010_add_reindex.sql.
+ALTER TABLE revision ADD COLUMN inline_tags text[] NOT NULL DEFAULT '{}';
+
+CREATE INDEX revision_idx_inline_tags ON revision USING GIN (inline_tags);
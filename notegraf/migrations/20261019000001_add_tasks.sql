@@ -0,0 +1,4 @@
+-- Tasks written in the note body, as a JSON array of tasks.
+-- Like inline_tags, existing revisions have their tasks extracted when Notegraf next starts,
+-- see 20261019000010_add_reindex.sql.
+ALTER TABLE revision ADD COLUMN tasks jsonb NOT NULL DEFAULT '[]';
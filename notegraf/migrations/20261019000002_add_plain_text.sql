@@ -0,0 +1,39 @@
+-- Index the plain text of notes extracted by Notegraf, rather than the stored note_inner,
+-- which might contain markup or be serialized.
+-- Existing revisions fall back to note_inner until Notegraf next starts and extracts their plain
+-- text, see 20261019000010_add_reindex.sql.
+DROP VIEW revision_only_current;
+DROP VIEW revision_is_current;
+
+ALTER TABLE revision DROP COLUMN text_searchable;
+ALTER TABLE revision ADD COLUMN plain_text text;
+UPDATE revision SET plain_text = note_inner;
+ALTER TABLE revision ALTER COLUMN plain_text SET NOT NULL;
+ALTER TABLE revision ADD COLUMN text_searchable tsvector GENERATED ALWAYS AS (to_tsvector('english', title || ' ' || plain_text)) STORED;
+
+CREATE INDEX revision_idx_text_searchable ON revision USING GIN (text_searchable);
+
+CREATE VIEW revision_is_current AS
+    SELECT
+        revision.revision,
+        revision.id,
+        revision.title,
+        revision.note_inner,
+        revision.text_searchable,
+        revision.parent,
+        revision.prev,
+        revision.referents,
+        revision.metadata_schema_version,
+        revision.metadata_created_at,
+        revision.metadata_modified_at,
+        revision.metadata_tags,
+        revision.metadata_custom_metadata,
+        cr.current_revision IS NOT NULL AS is_current
+    FROM revision
+    LEFT JOIN current_revision cr on revision.revision = cr.current_revision;
+
+CREATE VIEW revision_only_current AS
+    SELECT
+        *
+    FROM revision_is_current
+    WHERE is_current;
@@ -0,0 +1,4 @@
+-- Record the format of each revision, so that notes of different formats can share a store.
+-- Revisions made before formats were recorded have a NULL format, and are read as the note type
+-- of the store.
+ALTER TABLE revision ADD COLUMN format text;
@@ -0,0 +1,8 @@
+-- JSON Schemas that the custom metadata of notes must conform to.
+-- The schema with a NULL tag is the default schema, which applies to all notes.
+CREATE TABLE metadata_schema (
+    tag text,
+    schema jsonb NOT NULL
+);
+CREATE UNIQUE INDEX metadata_schema_tag ON metadata_schema (tag) WHERE tag IS NOT NULL;
+CREATE UNIQUE INDEX metadata_schema_default ON metadata_schema ((tag IS NULL)) WHERE tag IS NULL;
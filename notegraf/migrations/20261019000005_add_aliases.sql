@@ -0,0 +1,5 @@
+-- Aliases of notes, which must be unique among the current revisions.
+-- The previous aliases of a note are those in its older revisions, so no separate table is needed
+-- to keep them working.
+ALTER TABLE revision ADD COLUMN metadata_aliases text[] NOT NULL DEFAULT '{}';
+CREATE INDEX revision_idx_metadata_aliases ON revision USING GIN (metadata_aliases);
@@ -0,0 +1,5 @@
+-- Flags of notes, added in version 1 of the metadata schema.
+-- They are unset for existing revisions, which is what migrating their metadata results in.
+ALTER TABLE revision ADD COLUMN metadata_pinned boolean NOT NULL DEFAULT false;
+ALTER TABLE revision ADD COLUMN metadata_starred boolean NOT NULL DEFAULT false;
+ALTER TABLE revision ADD COLUMN metadata_archived boolean NOT NULL DEFAULT false;
@@ -0,0 +1,2 @@
+-- Who made each revision, as given by the client. Existing revisions have no author.
+ALTER TABLE revision ADD COLUMN metadata_author text;
@@ -0,0 +1,15 @@
+-- When notes were deleted, so that they can be purged after a retention period.
+CREATE TABLE deleted_note
+(
+    id         uuid        NOT NULL,
+    PRIMARY KEY (id),
+    FOREIGN KEY (id) REFERENCES note (id),
+    deleted_at timestamptz NOT NULL
+);
+
+-- Notes deleted before this was recorded are taken to be deleted when they were last modified.
+INSERT INTO deleted_note (id, deleted_at)
+SELECT revision.id, max(revision.metadata_modified_at)
+FROM revision
+WHERE revision.id NOT IN (SELECT id FROM current_revision)
+GROUP BY revision.id;
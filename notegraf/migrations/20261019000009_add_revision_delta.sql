@@ -0,0 +1,19 @@
+-- Store non-current revisions compactly.
+-- Their notes are moved from note_inner to note_delta, which holds the note either in full or as a
+-- delta against delta_base, an older revision of the same note.
+-- Their plain_text is cleared, so the tsvector is no longer computed for them, as only current
+-- revisions are searched.
+ALTER TABLE revision ALTER COLUMN note_inner DROP NOT NULL;
+ALTER TABLE revision ALTER COLUMN plain_text DROP NOT NULL;
+ALTER TABLE revision ADD COLUMN note_delta bytea;
+ALTER TABLE revision ADD COLUMN delta_base uuid;
+ALTER TABLE revision ADD FOREIGN KEY (delta_base) REFERENCES revision (revision);
+CREATE INDEX revision_idx_delta_base ON revision USING HASH (delta_base);
+
+-- Existing revisions are moved in full, flagged (with the first byte) to be encoded against
+-- their bases by Notegraf when it next starts.
+UPDATE revision
+SET note_delta = '\x04'::bytea || convert_to(note_inner, 'UTF8'), note_inner = NULL, plain_text = NULL
+WHERE NOT EXISTS (SELECT 1 FROM current_revision cr WHERE cr.current_revision = revision.revision);
+
+ALTER TABLE revision ADD CONSTRAINT revision_note_stored CHECK (note_inner IS NOT NULL OR note_delta IS NOT NULL);
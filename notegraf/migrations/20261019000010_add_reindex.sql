@@ -0,0 +1,7 @@
+-- Revisions whose columns derived from note_inner, e.g., inline_tags, are yet to be computed.
+-- They are computed by Notegraf when it next starts, as only it can parse notes.
+-- Non-current revisions don't need them, as only current revisions are searched.
+ALTER TABLE revision ADD COLUMN reindex boolean NOT NULL DEFAULT false;
+UPDATE revision SET reindex = true WHERE note_inner IS NOT NULL;
+
+CREATE INDEX revision_idx_reindex ON revision (revision) WHERE reindex;
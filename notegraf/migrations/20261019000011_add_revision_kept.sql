@@ -0,0 +1,2 @@
+-- Revisions kept by compaction regardless of the policy, which is not part of the metadata.
+ALTER TABLE revision ADD COLUMN kept boolean NOT NULL DEFAULT false;
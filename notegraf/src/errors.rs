@@ -1,6 +1,8 @@
 use thiserror::Error;
 use url::ParseError;
 
+use crate::notestore::schema::SchemaViolation;
+use crate::notetype::NoteFormat;
 use crate::{NoteID, Revision};
 
 #[derive(Error, Debug)]
@@ -21,22 +23,60 @@ pub enum NoteStoreError {
     DeleteOldRevision(NoteID, Revision),
     #[error("inconsistency detected: note `{1}` is not a child of note `{0}`")]
     NotAChild(NoteID, NoteID),
+    #[error("inconsistency detected in note `{0}`: {1}, which an integrity check can repair")]
+    Inconsistent(NoteID, String),
     #[error("cannot append note `{1}` to note `{0}`, because note `{0}` is not the last note in a sequence")]
     ExistingNext(NoteID, NoteID),
+    #[error("cannot link note `{0}` to note `{1}`, because it would form a cycle")]
+    Cycle(NoteID, NoteID),
     #[error("cannot delete note `{0}`, because it has branches")]
     HasBranches(NoteID),
     #[error("cannot delete note `{0}`, because other notes refer to it")]
     HasReferences(NoteID),
+    #[error("cannot purge note `{0}`, because another note comes after it in a sequence")]
+    HasNext(NoteID),
+    #[error("cannot purge note `{0}`, because it is not deleted")]
+    NotDeleted(NoteID),
+    #[error("task `{1}` of note `{0}` doesn't exist")]
+    TaskNotExist(NoteID, usize),
+    #[error("note `{0}` cannot be converted into {1}")]
+    ConversionNotSupported(NoteID, NoteFormat),
     #[error("note cannot be parsed: `{0}`")]
     ParseError(String),
+    #[error("metadata schema version `{0}` is newer than the latest supported version `{1}`")]
+    MetadataSchemaTooNew(u64, u64),
+    #[error("invalid metadata schema: {0}")]
+    InvalidMetadataSchema(String),
+    #[error(
+        "custom metadata doesn't conform to its schemas: {}",
+        .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("; ")
+    )]
+    MetadataSchemaViolation(Vec<SchemaViolation>),
+    #[error("alias `{0}` is invalid")]
+    InvalidAlias(String),
+    #[error("alias `{0}` is already used by note `{1}`")]
+    AliasConflict(String, NoteID),
+    #[error("alias `{0}` doesn't exist")]
+    AliasNotExist(String),
+    #[error("placeholder `{0}` doesn't refer to the note of an earlier operation in the batch")]
+    InvalidPlaceholder(usize),
     #[error("PostgreSQL error")]
     PostgreSQLError(#[from] sqlx::Error),
-    #[error("error processing note inner")]
+    #[error("error processing note inner: {0}")]
     NoteInnerError(String),
     #[error("id or revision is not UUID")]
     NotUuid(String),
 }
 
+impl NoteStoreError {
+    /// Wrap an error returned by a method of a [`NoteType`](crate::NoteType).
+    ///
+    /// Every store reports such errors the same way, so that callers don't depend on the backend.
+    pub fn note_inner(e: impl std::fmt::Display) -> Self {
+        NoteStoreError::NoteInnerError(e.to_string())
+    }
+}
+
 /// Error type for Notegraf URL parsing.
 #[derive(Error, Debug)]
 pub enum URLParseError {
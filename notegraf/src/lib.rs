@@ -12,4 +12,10 @@ pub mod url;
 
 pub use note::{Note, NoteID, NoteLocator, NoteSerializable, Revision};
 pub use notestore::{InMemoryStore, NoteStore, PostgreSQLStore, PostgreSQLStoreBuilder};
-pub use notetype::{MarkdownNote, NoteType, PlainNote};
+#[cfg(feature = "notetype_asciidoc")]
+pub use notetype::AsciiDocNote;
+#[cfg(feature = "notetype_block")]
+pub use notetype::BlockNote;
+#[cfg(feature = "notetype_org")]
+pub use notetype::OrgNote;
+pub use notetype::{DynamicNote, MarkdownNote, NoteType, PlainNote};
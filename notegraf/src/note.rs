@@ -145,8 +145,8 @@ impl From<Uuid> for Revision {
 /// Expensive computation can be cached, but it's the storage's responsibility to keep the cache
 /// coherent.
 ///
-/// Revision properties (title, note_inner, id, revision, referents, metadata) are immutable for a
-/// given revision.
+/// Revision properties (title, note_inner, id, revision, referents, inline tags, metadata) are
+/// immutable for a given revision.
 ///
 /// Note properties (parent, branches, prev, next, references) reflect the current global view
 /// of a note store, and relationship between notes (which are represented by their respective
@@ -167,6 +167,8 @@ pub trait Note<T: NoteType>: Debug {
     // Represents bi-directional references
     fn get_references(&self) -> HashSet<NoteID>;
     fn get_referents(&self) -> HashSet<NoteID>;
+    // Tags written in the body, as opposed to the tags in the metadata
+    fn get_inline_tags(&self) -> HashSet<String>;
     fn get_metadata(&self) -> NoteMetadata;
     fn is_current(&self) -> bool;
 }
@@ -174,6 +176,7 @@ pub trait Note<T: NoteType>: Debug {
 pub enum NoteField {
     Title,
     NoteInner,
+    Format,
     ID,
     Revision,
     Parent,
@@ -182,6 +185,7 @@ pub enum NoteField {
     Next,
     References,
     Referents,
+    InlineTags,
     Metadata,
     IsCurrent,
 }
@@ -201,6 +205,7 @@ impl Default for NoteFieldSelector {
         NoteFieldSelector::new(vec![
             NoteField::Title,
             NoteField::NoteInner,
+            NoteField::Format,
             NoteField::ID,
             NoteField::Revision,
             NoteField::Parent,
@@ -209,6 +214,7 @@ impl Default for NoteFieldSelector {
             NoteField::Next,
             NoteField::References,
             NoteField::Referents,
+            NoteField::InlineTags,
             NoteField::Metadata,
             NoteField::IsCurrent,
         ])
@@ -237,6 +243,9 @@ where
                 NoteField::NoteInner => {
                     s.serialize_field("note_inner", &self.n.get_note_inner())?;
                 }
+                NoteField::Format => {
+                    s.serialize_field("format", &self.n.get_note_inner().get_format())?;
+                }
                 NoteField::ID => {
                     s.serialize_field("id", &self.n.get_id())?;
                 }
@@ -261,6 +270,9 @@ where
                 NoteField::Referents => {
                     s.serialize_field("referents", &self.n.get_referents())?;
                 }
+                NoteField::InlineTags => {
+                    s.serialize_field("inline_tags", &self.n.get_inline_tags())?;
+                }
                 NoteField::Metadata => {
                     s.serialize_field("metadata", &self.n.get_metadata())?;
                 }
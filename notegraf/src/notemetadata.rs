@@ -1,8 +1,38 @@
+use crate::errors::NoteStoreError;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 
-pub static NOTE_METADATA_CURRENT_SCHEMA_VERSION: u64 = 0;
+pub const NOTE_METADATA_CURRENT_SCHEMA_VERSION: u64 = 1;
+
+/// Upgrade metadata from one schema version to the next.
+///
+/// The schema version itself is bumped by the caller.
+pub type NoteMetadataMigration = fn(&mut NoteMetadata);
+
+/// Migrations of the metadata schema, keyed by the schema version that they upgrade from.
+///
+/// Bumping [`NOTE_METADATA_CURRENT_SCHEMA_VERSION`] requires adding a migration here.
+static NOTE_METADATA_MIGRATIONS: [NoteMetadataMigration;
+    NOTE_METADATA_CURRENT_SCHEMA_VERSION as usize] = [add_flags];
+
+/// Version 1 adds the pinned, starred and archived flags, which are unset for existing notes.
+fn add_flags(metadata: &mut NoteMetadata) {
+    metadata.pinned = false;
+    metadata.starred = false;
+    metadata.archived = false;
+}
+
+/// Flags of a note, which can be toggled on their own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NoteFlag {
+    /// Pinned notes come first in search results.
+    Pinned,
+    Starred,
+    /// Archived notes are excluded from search results, unless asked for with `!archived`.
+    Archived,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct NoteMetadata {
@@ -10,6 +40,20 @@ pub struct NoteMetadata {
     pub created_at: DateTime<Utc>,
     pub modified_at: DateTime<Utc>,
     pub tags: HashSet<String>,
+    /// Human-readable names of the note, such as `meeting-notes`, which are unique among notes.
+    ///
+    /// See [`NoteStore::lookup_alias`](crate::NoteStore::lookup_alias).
+    #[serde(default)]
+    pub aliases: HashSet<String>,
+    #[serde(default)]
+    pub pinned: bool,
+    #[serde(default)]
+    pub starred: bool,
+    #[serde(default)]
+    pub archived: bool,
+    /// Who made the revision, if known.
+    #[serde(default)]
+    pub author: Option<String>,
     pub custom_metadata: serde_json::Value,
 }
 
@@ -21,22 +65,66 @@ impl Default for NoteMetadata {
             created_at: now,
             modified_at: now,
             tags: HashSet::new(),
+            aliases: HashSet::new(),
+            pinned: false,
+            starred: false,
+            archived: false,
+            author: None,
             custom_metadata: serde_json::json!({}),
         }
     }
 }
 
 impl NoteMetadata {
-    pub(crate) fn on_update_note(&self) -> Self {
-        // We cannot update the metadata if it's based on a newer schema
-        assert!(NOTE_METADATA_CURRENT_SCHEMA_VERSION >= self.schema_version);
-        NoteMetadata {
-            schema_version: NOTE_METADATA_CURRENT_SCHEMA_VERSION,
-            created_at: self.created_at,
-            modified_at: Utc::now(),
-            tags: self.tags.clone(),
-            custom_metadata: self.custom_metadata.clone(),
+    pub(crate) fn on_update_note(&self) -> Result<Self, NoteStoreError> {
+        let mut metadata = self.clone();
+        metadata.migrate()?;
+        metadata.modified_at = Utc::now();
+        Ok(metadata)
+    }
+
+    /// Upgrade the metadata to the current schema version.
+    ///
+    /// Returns whether the metadata was upgraded.
+    /// We cannot use the metadata if it's based on a newer schema.
+    pub fn migrate(&mut self) -> Result<bool, NoteStoreError> {
+        self.migrate_with(&NOTE_METADATA_MIGRATIONS)
+    }
+
+    fn migrate_with(
+        &mut self,
+        migrations: &[NoteMetadataMigration],
+    ) -> Result<bool, NoteStoreError> {
+        let current = migrations.len() as u64;
+        if self.schema_version > current {
+            return Err(NoteStoreError::MetadataSchemaTooNew(
+                self.schema_version,
+                current,
+            ));
         }
+        let migrated = self.schema_version < current;
+        for migration in &migrations[self.schema_version as usize..] {
+            migration(self);
+            self.schema_version += 1;
+        }
+        Ok(migrated)
+    }
+
+    pub fn flag(&self, flag: NoteFlag) -> bool {
+        match flag {
+            NoteFlag::Pinned => self.pinned,
+            NoteFlag::Starred => self.starred,
+            NoteFlag::Archived => self.archived,
+        }
+    }
+
+    pub fn toggle_flag(&mut self, flag: NoteFlag) {
+        let f = match flag {
+            NoteFlag::Pinned => &mut self.pinned,
+            NoteFlag::Starred => &mut self.starred,
+            NoteFlag::Archived => &mut self.archived,
+        };
+        *f = !*f;
     }
 
     pub fn from_editable(m: NoteMetadataEditable) -> Self {
@@ -51,10 +139,19 @@ impl NoteMetadata {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct NoteMetadataEditable {
     pub tags: Option<HashSet<String>>,
+    pub aliases: Option<HashSet<String>>,
     pub custom_metadata: Option<serde_json::Value>,
+    /// Who makes the revision, if known.
+    ///
+    /// Unlike the other fields, this always replaces the author of the previous revision.
+    /// Operations that make revisions without a `NoteMetadataEditable`, e.g., toggling a flag,
+    /// take the author separately, and revisions that the store makes by itself, e.g., when a
+    /// note is moved, have no author.
+    #[serde(skip)]
+    pub author: Option<String>,
 }
 
 impl NoteMetadataEditable {
@@ -62,25 +159,101 @@ impl NoteMetadataEditable {
         if let Some(t) = self.tags {
             n.tags = t;
         }
+        if let Some(a) = self.aliases {
+            n.aliases = a;
+        }
         if let Some(c) = self.custom_metadata {
             n.custom_metadata = c;
         }
+        n.author = self.author;
     }
 
     pub fn unchanged() -> Self {
         NoteMetadataEditable {
             tags: None,
+            aliases: None,
             custom_metadata: None,
+            author: None,
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::notemetadata::NoteMetadataEditable;
+    use crate::errors::NoteStoreError;
+    use crate::notemetadata::{
+        NoteFlag, NoteMetadata, NoteMetadataEditable, NoteMetadataMigration,
+        NOTE_METADATA_CURRENT_SCHEMA_VERSION,
+    };
     use crate::{InMemoryStore, NoteStore, PlainNote};
+    use serde_json::json;
     use std::option::Option::None;
 
+    #[test]
+    fn migrate() {
+        let migrations: [NoteMetadataMigration; 2] = [
+            |m| {
+                m.tags.insert("v1".to_owned());
+            },
+            |m| {
+                if m.tags.remove("v1") {
+                    m.tags.insert("v2".to_owned());
+                }
+            },
+        ];
+        let mut metadata = NoteMetadata {
+            schema_version: 0,
+            ..Default::default()
+        };
+        assert!(metadata.migrate_with(&migrations).unwrap());
+        assert_eq!(metadata.schema_version, 2);
+        assert_eq!(metadata.tags, ["v2".to_owned()].into());
+        // Migrating again does nothing
+        assert!(!metadata.migrate_with(&migrations).unwrap());
+        assert_eq!(metadata.schema_version, 2);
+        // Migrations start from the version of the metadata
+        let mut metadata = NoteMetadata {
+            schema_version: 1,
+            ..Default::default()
+        };
+        metadata.migrate_with(&migrations).unwrap();
+        assert!(metadata.tags.is_empty());
+    }
+
+    #[test]
+    fn migrate_newer_schema() {
+        let mut metadata = NoteMetadata {
+            schema_version: 1,
+            ..Default::default()
+        };
+        assert!(matches!(
+            metadata.migrate_with(&[]),
+            Err(NoteStoreError::MetadataSchemaTooNew(1, 0))
+        ));
+        metadata.schema_version = NOTE_METADATA_CURRENT_SCHEMA_VERSION + 1;
+        assert!(metadata.on_update_note().is_err());
+    }
+
+    #[test]
+    fn migrate_v0() {
+        let mut metadata: NoteMetadata = serde_json::from_value(json!({
+            "schema_version": 0,
+            "created_at": "2022-09-12T00:00:00Z",
+            "modified_at": "2022-09-12T00:00:00Z",
+            "tags": ["foo"],
+            "custom_metadata": {}
+        }))
+        .unwrap();
+        assert!(metadata.migrate().unwrap());
+        assert_eq!(
+            metadata.schema_version,
+            NOTE_METADATA_CURRENT_SCHEMA_VERSION
+        );
+        assert!(!metadata.pinned && !metadata.starred && !metadata.archived);
+        metadata.toggle_flag(NoteFlag::Starred);
+        assert!(metadata.flag(NoteFlag::Starred));
+    }
+
     #[tokio::test]
     async fn update_note_tags() {
         let store: InMemoryStore<PlainNote> = InMemoryStore::new();
@@ -102,7 +275,9 @@ mod tests {
         tags.insert("my_tag".to_owned());
         let new_metadata = NoteMetadataEditable {
             tags: Some(tags),
+            aliases: None,
             custom_metadata: None,
+            author: None,
         };
         let loc2 = store
             .update_note(&loc1, None, None, new_metadata)
@@ -0,0 +1,94 @@
+//! Aliases of notes.
+use crate::errors::NoteStoreError;
+use crate::NoteID;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// The note that an alias refers to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AliasTarget {
+    pub id: NoteID,
+    /// Whether the alias was only used by a previous revision of the note, so that it should be
+    /// redirected to the note.
+    pub redirect: bool,
+}
+
+/// Whether an alias can be used in a URL as is.
+///
+/// Aliases are made of letters, digits, hyphens and underscores.
+pub fn is_valid_alias(alias: &str) -> bool {
+    !alias.is_empty()
+        && alias
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Check that the aliases of a note are valid and not used by any other note.
+///
+/// `owners` maps the aliases in the current revisions of all notes to the notes, and `id` is the
+/// note being updated, if any.
+/// `is_note_id` tells whether a string has the form of a note ID of the store, which cannot be
+/// an alias, as looking it up would be ambiguous.
+pub(crate) fn check_aliases(
+    id: Option<&NoteID>,
+    aliases: &HashSet<String>,
+    owners: &HashMap<String, NoteID>,
+    is_note_id: fn(&str) -> bool,
+) -> Result<(), NoteStoreError> {
+    for alias in aliases {
+        if !is_valid_alias(alias) || is_note_id(alias) {
+            return Err(NoteStoreError::InvalidAlias(alias.clone()));
+        }
+        if let Some(owner) = owners.get(alias) {
+            if Some(owner) != id {
+                return Err(NoteStoreError::AliasConflict(alias.clone(), owner.clone()));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_alias() {
+        assert!(is_valid_alias("meeting-notes_2026"));
+        assert!(is_valid_alias("会议"));
+        assert!(!is_valid_alias(""));
+        assert!(!is_valid_alias("foo/bar"));
+        assert!(!is_valid_alias("foo bar"));
+    }
+
+    #[test]
+    fn conflict() {
+        let id1 = NoteID::new("note-1".to_owned());
+        let owners = HashMap::from([("foo".to_owned(), id1.clone())]);
+        let aliases = HashSet::from(["foo".to_owned()]);
+        let is_note_id = |s: &str| s.starts_with("note-");
+        check_aliases(Some(&id1), &aliases, &owners, is_note_id).unwrap();
+        assert!(matches!(
+            check_aliases(None, &aliases, &owners, is_note_id),
+            Err(NoteStoreError::AliasConflict(_, id)) if id == id1
+        ));
+        assert!(matches!(
+            check_aliases(
+                None,
+                &HashSet::from(["a b".to_owned()]),
+                &owners,
+                is_note_id
+            ),
+            Err(NoteStoreError::InvalidAlias(_))
+        ));
+        assert!(matches!(
+            check_aliases(
+                None,
+                &HashSet::from(["note-2".to_owned()]),
+                &owners,
+                is_note_id
+            ),
+            Err(NoteStoreError::InvalidAlias(_))
+        ));
+    }
+}
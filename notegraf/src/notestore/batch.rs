@@ -0,0 +1,85 @@
+//! Applying several operations at once, see
+//! [`NoteStore::apply_batch`](crate::NoteStore::apply_batch).
+use crate::errors::NoteStoreError;
+use crate::notemetadata::NoteMetadataEditable;
+use crate::notestore::structure::MoveTarget;
+use crate::{NoteID, NoteLocator};
+use serde::Deserialize;
+
+/// A note in a batch.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoteRef {
+    /// An existing note.
+    Id(NoteID),
+    /// The note of an earlier operation in the batch, by the index of the operation.
+    ///
+    /// This is how notes created in the same batch are referred to.
+    Created(usize),
+}
+
+impl NoteRef {
+    /// Get the ID of the note, given the results of the earlier operations.
+    pub fn resolve(&self, results: &[Option<NoteLocator>]) -> Result<NoteID, NoteStoreError> {
+        match self {
+            NoteRef::Id(id) => Ok(id.clone()),
+            NoteRef::Created(i) => match results.get(*i) {
+                Some(Some(loc)) => Ok(loc.get_id().clone()),
+                _ => Err(NoteStoreError::InvalidPlaceholder(*i)),
+            },
+        }
+    }
+}
+
+/// An operation in a batch, which does what the [`NoteStore`](crate::NoteStore) method of the
+/// same name does.
+///
+/// Notes are updated, deleted and moved at their current revisions.
+#[derive(Debug, Clone)]
+pub enum BatchOperation<T> {
+    /// See [`NoteStore::new_note`](crate::NoteStore::new_note).
+    New {
+        title: String,
+        note_inner: T,
+        metadata: NoteMetadataEditable,
+    },
+    /// See [`NoteStore::append_note`](crate::NoteStore::append_note).
+    Append {
+        last: NoteRef,
+        title: String,
+        note_inner: T,
+        metadata: NoteMetadataEditable,
+    },
+    /// See [`NoteStore::add_branch`](crate::NoteStore::add_branch).
+    Branch {
+        parent: NoteRef,
+        title: String,
+        note_inner: T,
+        metadata: NoteMetadataEditable,
+    },
+    /// See [`NoteStore::update_note`](crate::NoteStore::update_note).
+    Update {
+        note: NoteRef,
+        title: Option<String>,
+        note_inner: Option<T>,
+        metadata: NoteMetadataEditable,
+    },
+    /// Like `Update`, but with the body as the string that a note in the current format of the
+    /// note is converted into (see [`NoteType::parse`](crate::NoteType::parse)).
+    ///
+    /// The body is parsed when the operation is applied, so the format is that of the note
+    /// after the earlier operations.
+    UpdateSource {
+        note: NoteRef,
+        title: Option<String>,
+        note_inner: String,
+        metadata: NoteMetadataEditable,
+    },
+    /// See [`NoteStore::delete_note`](crate::NoteStore::delete_note).
+    Delete { note: NoteRef },
+    /// See [`NoteStore::move_note`](crate::NoteStore::move_note).
+    Move {
+        note: NoteRef,
+        to: MoveTarget<NoteRef>,
+    },
+}
@@ -0,0 +1,234 @@
+//! Policies for removing old revisions of notes.
+use crate::Revision;
+use chrono::{DateTime, TimeDelta, Utc};
+use std::collections::HashSet;
+
+/// A rule of a [`CompactionPolicy`] for revisions up to some age.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompactionRule {
+    /// Revisions at least this old are covered by later rules, or `None` for no limit.
+    pub up_to: Option<TimeDelta>,
+    /// Keep the newest revision in each period of this length, or `None` to keep all revisions.
+    ///
+    /// Periods are counted from where the rule starts, i.e., the `up_to` of the previous rule.
+    /// Periods are at least a millisecond long.
+    pub keep_every: Option<TimeDelta>,
+}
+
+/// Which revisions to keep, depending on how old they are.
+///
+/// Each revision follows the first rule that covers its age, and revisions older than every
+/// rule are removed.
+/// Regardless of the policy, [`NoteStore::compact_revisions`](crate::NoteStore::compact_revisions)
+/// always keeps the current (or for deleted notes, the last) revision, revisions marked with
+/// [`NoteStore::keep_revision`](crate::NoteStore::keep_revision), revisions that current notes
+/// link to (see [`NotegrafURL::Revision`](crate::url::NotegrafURL::Revision)), and the newest
+/// revision with each previous alias, so that the alias keeps working.
+/// Pinned notes (see [`NoteFlag::Pinned`](crate::notemetadata::NoteFlag::Pinned)) are compacted
+/// like any other note.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompactionPolicy {
+    pub rules: Vec<CompactionRule>,
+}
+
+impl Default for CompactionPolicy {
+    /// Keep every revision from the last day, hourly ones for a week and daily ones after that.
+    fn default() -> Self {
+        CompactionPolicy {
+            rules: vec![
+                CompactionRule {
+                    up_to: Some(TimeDelta::days(1)),
+                    keep_every: None,
+                },
+                CompactionRule {
+                    up_to: Some(TimeDelta::weeks(1)),
+                    keep_every: Some(TimeDelta::hours(1)),
+                },
+                CompactionRule {
+                    up_to: None,
+                    keep_every: Some(TimeDelta::days(1)),
+                },
+            ],
+        }
+    }
+}
+
+/// A revision considered for compaction.
+pub(crate) struct RevisionAge {
+    pub revision: Revision,
+    pub modified_at: DateTime<Utc>,
+    /// Whether the revision is kept regardless of the policy.
+    pub keep: bool,
+    pub aliases: HashSet<String>,
+}
+
+impl CompactionPolicy {
+    /// The revisions of a note to remove, where the last revision is always kept.
+    pub(crate) fn revisions_to_remove(
+        &self,
+        mut revisions: Vec<RevisionAge>,
+        now: DateTime<Utc>,
+    ) -> Vec<Revision> {
+        // From newer to older, so that the first revision seen in a period is the one kept
+        revisions.sort_by_key(|r| std::cmp::Reverse(r.modified_at));
+        let mut periods_seen = HashSet::new();
+        let mut aliases_seen = HashSet::new();
+        let mut removed = vec![];
+        for (i, r) in revisions.into_iter().enumerate() {
+            let new_alias = !r.aliases.is_subset(&aliases_seen);
+            aliases_seen.extend(r.aliases);
+            if i == 0 || r.keep || new_alias {
+                continue;
+            }
+            let age = now - r.modified_at;
+            let mut start = TimeDelta::zero();
+            let mut period = None;
+            for (rule_index, rule) in self.rules.iter().enumerate() {
+                if rule.up_to.is_none_or(|up_to| age < up_to) {
+                    period = Some(match rule.keep_every {
+                        Some(every) if every > TimeDelta::zero() => {
+                            let every = every.num_milliseconds().max(1);
+                            Some((rule_index, (age - start).num_milliseconds() / every))
+                        }
+                        _ => None,
+                    });
+                    break;
+                }
+                start = rule.up_to.unwrap();
+            }
+            match period {
+                // Older than every rule
+                None => removed.push(r.revision),
+                // Covered by a rule that keeps all revisions
+                Some(None) => {}
+                Some(Some(p)) => {
+                    if !periods_seen.insert(p) {
+                        removed.push(r.revision);
+                    }
+                }
+            }
+        }
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn revisions(now: DateTime<Utc>, ages: &[(TimeDelta, bool)]) -> Vec<RevisionAge> {
+        ages.iter()
+            .enumerate()
+            .map(|(i, &(age, keep))| RevisionAge {
+                revision: i.to_string().into(),
+                modified_at: now - age,
+                keep,
+                aliases: HashSet::new(),
+            })
+            .collect()
+    }
+
+    fn removed(policy: &CompactionPolicy, ages: &[(TimeDelta, bool)]) -> Vec<String> {
+        let now = Utc::now();
+        let mut removed: Vec<String> = policy
+            .revisions_to_remove(revisions(now, ages), now)
+            .into_iter()
+            .map(|r| r.to_string())
+            .collect();
+        removed.sort();
+        removed
+    }
+
+    #[test]
+    fn default_policy() {
+        let policy = CompactionPolicy::default();
+        let ages = [
+            (TimeDelta::minutes(1), false),
+            // Within a day, all kept
+            (TimeDelta::minutes(2), false),
+            (TimeDelta::hours(23), false),
+            // Hourly, where 3 and 4 are in the same hour and 3 is newer
+            (TimeDelta::minutes(24 * 60 + 10), false),
+            (TimeDelta::minutes(24 * 60 + 20), false),
+            (TimeDelta::minutes(24 * 60 + 70), false),
+            // Daily
+            (TimeDelta::days(8), false),
+            (TimeDelta::days(8) + TimeDelta::hours(1), false),
+            (TimeDelta::days(8) + TimeDelta::hours(2), true),
+        ];
+        assert_eq!(removed(&policy, &ages), vec!["4", "7"]);
+    }
+
+    #[test]
+    fn older_than_every_rule() {
+        let policy = CompactionPolicy {
+            rules: vec![CompactionRule {
+                up_to: Some(TimeDelta::days(30)),
+                keep_every: None,
+            }],
+        };
+        let ages = [
+            (TimeDelta::days(40), false),
+            (TimeDelta::days(35), false),
+            (TimeDelta::days(31), true),
+            (TimeDelta::days(1), false),
+        ];
+        assert_eq!(removed(&policy, &ages), vec!["0", "1"]);
+        // The last revision is kept however old it is
+        let ages = [(TimeDelta::days(40), false)];
+        assert!(removed(&policy, &ages).is_empty());
+    }
+
+    #[test]
+    fn sub_second_periods() {
+        let policy = CompactionPolicy {
+            rules: vec![CompactionRule {
+                up_to: None,
+                keep_every: Some(TimeDelta::milliseconds(500)),
+            }],
+        };
+        let ages = [
+            (TimeDelta::zero(), false),
+            (TimeDelta::milliseconds(100), false),
+            (TimeDelta::milliseconds(200), false),
+            (TimeDelta::milliseconds(600), false),
+        ];
+        assert_eq!(removed(&policy, &ages), vec!["2"]);
+        // Shorter periods count as a millisecond
+        let policy = CompactionPolicy {
+            rules: vec![CompactionRule {
+                up_to: None,
+                keep_every: Some(TimeDelta::microseconds(1)),
+            }],
+        };
+        let ages = [
+            (TimeDelta::zero(), false),
+            (TimeDelta::microseconds(1100), false),
+            (TimeDelta::microseconds(1200), false),
+            (TimeDelta::microseconds(2100), false),
+        ];
+        assert_eq!(removed(&policy, &ages), vec!["2"]);
+    }
+
+    #[test]
+    fn previous_aliases() {
+        let policy = CompactionPolicy { rules: vec![] };
+        let now = Utc::now();
+        let mut revisions = revisions(
+            now,
+            &[
+                (TimeDelta::days(3), false),
+                (TimeDelta::days(2), false),
+                (TimeDelta::days(1), false),
+                (TimeDelta::zero(), false),
+            ],
+        );
+        revisions[0].aliases = HashSet::from(["old".to_owned()]);
+        revisions[1].aliases = HashSet::from(["old".to_owned()]);
+        revisions[2].aliases = HashSet::from(["new".to_owned()]);
+        revisions[3].aliases = HashSet::from(["new".to_owned()]);
+        let removed = policy.revisions_to_remove(revisions, now);
+        assert_eq!(removed.len(), 2);
+        assert!(removed.contains(&"0".into()) && removed.contains(&"2".into()));
+    }
+}
@@ -0,0 +1,16 @@
+//! Conversion of the notes in a store between formats.
+use crate::errors::NoteStoreError;
+use crate::notetype::NoteFormat;
+use crate::{NoteID, NoteType};
+
+/// Convert the body of note `id` into `format`, see [`NoteType::convert_to`].
+pub(crate) fn convert_note_inner<T: NoteType>(
+    id: &NoteID,
+    note_inner: T,
+    format: NoteFormat,
+) -> Result<T, NoteStoreError> {
+    note_inner
+        .convert_to(format)
+        .map_err(NoteStoreError::note_inner)?
+        .ok_or_else(|| NoteStoreError::ConversionNotSupported(id.clone(), format))
+}
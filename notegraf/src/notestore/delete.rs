@@ -0,0 +1,30 @@
+//! Deleting notes together with the notes around them.
+use crate::notetype::LinkRewrite;
+use crate::NoteID;
+use serde::Serialize;
+
+/// How [`NoteStore::delete_note_with`](crate::NoteStore::delete_note_with) deletes a note.
+///
+/// The default options delete the note like
+/// [`NoteStore::delete_note`](crate::NoteStore::delete_note).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DeleteOptions {
+    /// Also delete the branches and the next notes of the note, recursively.
+    pub cascade: bool,
+    /// Rewrite the links in other notes to the deleted notes, instead of refusing to delete notes
+    /// that other notes refer to.
+    pub rewrite_references: Option<LinkRewrite>,
+    /// Only report what would be changed.
+    pub dry_run: bool,
+}
+
+/// What a deletion changed, or would change for a dry run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DeleteReport {
+    /// The deleted notes, starting with the note itself.
+    ///
+    /// Each note comes after its parent or its previous note.
+    pub deleted: Vec<NoteID>,
+    /// The notes whose links to the deleted notes were rewritten, sorted.
+    pub rewritten: Vec<NoteID>,
+}
@@ -0,0 +1,149 @@
+//! Differences between revisions of a note.
+use crate::errors::NoteStoreError;
+use crate::{Note, NoteID, NoteType, Revision};
+use serde::Serialize;
+use similar::{ChangeTag, TextDiff};
+use std::collections::HashSet;
+use std::hash::Hash;
+
+/// What changed from one revision of a note to another.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NoteDiff {
+    pub id: NoteID,
+    pub from: Revision,
+    pub to: Revision,
+    /// The old and new titles, or `None` if the title is unchanged.
+    pub title: Option<(String, String)>,
+    /// A line-level diff of the bodies of the notes, as given by [`NoteType::get_body`].
+    pub note_inner: Vec<LineChange>,
+    pub tags_added: Vec<String>,
+    pub tags_removed: Vec<String>,
+    /// A JSON Patch (RFC 6902) that turns the old custom metadata into the new one.
+    pub custom_metadata: json_patch::Patch,
+    pub referents_added: Vec<NoteID>,
+    pub referents_removed: Vec<NoteID>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineChangeKind {
+    Equal,
+    Insert,
+    Delete,
+}
+
+/// A line of the diff of two notes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LineChange {
+    pub kind: LineChangeKind,
+    /// The line number in the old note, starting from 1, or `None` for inserted lines.
+    pub old_line: Option<usize>,
+    /// The line number in the new note, starting from 1, or `None` for deleted lines.
+    pub new_line: Option<usize>,
+    /// The line without its line ending.
+    pub text: String,
+}
+
+/// A line-level diff of two strings, including the lines that are unchanged.
+pub fn diff_lines(old: &str, new: &str) -> Vec<LineChange> {
+    TextDiff::from_lines(old, new)
+        .iter_all_changes()
+        .map(|c| LineChange {
+            kind: match c.tag() {
+                ChangeTag::Equal => LineChangeKind::Equal,
+                ChangeTag::Insert => LineChangeKind::Insert,
+                ChangeTag::Delete => LineChangeKind::Delete,
+            },
+            old_line: c.old_index().map(|i| i + 1),
+            new_line: c.new_index().map(|i| i + 1),
+            text: c.value().trim_end_matches(['\n', '\r']).to_owned(),
+        })
+        .collect()
+}
+
+/// Items in `a` but not in `b`, sorted by `key`.
+fn sorted_difference<I: Clone + Eq + Hash>(
+    a: &HashSet<I>,
+    b: &HashSet<I>,
+    key: fn(&I) -> &str,
+) -> Vec<I> {
+    let mut items: Vec<I> = a.difference(b).cloned().collect();
+    items.sort_by(|x, y| key(x).cmp(key(y)));
+    items
+}
+
+/// Compare two revisions of a note.
+pub(crate) fn diff_notes<T: NoteType>(
+    from: &dyn Note<T>,
+    to: &dyn Note<T>,
+) -> Result<NoteDiff, NoteStoreError> {
+    let (old_title, new_title) = (from.get_title(), to.get_title());
+    let old_inner = from
+        .get_note_inner()
+        .get_body()
+        .map_err(NoteStoreError::note_inner)?;
+    let new_inner = to
+        .get_note_inner()
+        .get_body()
+        .map_err(NoteStoreError::note_inner)?;
+    let (old_metadata, new_metadata) = (from.get_metadata(), to.get_metadata());
+    let (old_referents, new_referents) = (from.get_referents(), to.get_referents());
+    Ok(NoteDiff {
+        id: to.get_id(),
+        from: from.get_revision(),
+        to: to.get_revision(),
+        title: (old_title != new_title).then_some((old_title, new_title)),
+        note_inner: diff_lines(&old_inner, &new_inner),
+        tags_added: sorted_difference(&new_metadata.tags, &old_metadata.tags, String::as_str),
+        tags_removed: sorted_difference(&old_metadata.tags, &new_metadata.tags, String::as_str),
+        custom_metadata: json_patch::diff(
+            &old_metadata.custom_metadata,
+            &new_metadata.custom_metadata,
+        ),
+        referents_added: sorted_difference(
+            &new_referents,
+            &old_referents,
+            <NoteID as AsRef<str>>::as_ref,
+        ),
+        referents_removed: sorted_difference(
+            &old_referents,
+            &new_referents,
+            <NoteID as AsRef<str>>::as_ref,
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines() {
+        let changes = diff_lines("a\nb\nc\n", "a\nc\nd");
+        let kinds: Vec<LineChangeKind> = changes.iter().map(|c| c.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                LineChangeKind::Equal,
+                LineChangeKind::Delete,
+                LineChangeKind::Equal,
+                LineChangeKind::Insert
+            ]
+        );
+        assert_eq!(changes[1].text, "b");
+        assert_eq!((changes[1].old_line, changes[1].new_line), (Some(2), None));
+        assert_eq!(
+            (changes[2].old_line, changes[2].new_line),
+            (Some(3), Some(2))
+        );
+        assert_eq!((changes[3].old_line, changes[3].new_line), (None, Some(3)));
+    }
+
+    #[test]
+    fn identical() {
+        assert!(diff_lines("", "").is_empty());
+        assert!(diff_lines("a\nb", "a\nb")
+            .iter()
+            .all(|c| c.kind == LineChangeKind::Equal));
+    }
+}
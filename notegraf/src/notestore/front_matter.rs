@@ -0,0 +1,57 @@
+//! Synchronization between the front matter of a note and its metadata.
+use crate::errors::NoteStoreError;
+use crate::notemetadata::NoteMetadata;
+use crate::notetype::FrontMatter;
+use crate::NoteType;
+use serde_json::Value;
+
+/// Reconcile the front matter embedded in a note with its title and metadata.
+///
+/// If the body was edited, the front matter is the source of truth.
+/// Its title and tags, if present, replace the title and tags of the note,
+/// and all other keys replace the custom metadata.
+///
+/// Afterwards, the front matter is rewritten from the title and metadata, so that edits made
+/// through the metadata alone are reflected in the body.
+///
+/// Notes without a front matter are left untouched, except that their note type now
+/// recognizes a front matter.
+pub(crate) fn sync_front_matter<T: NoteType>(
+    title: &mut String,
+    note_inner: &mut T,
+    metadata: &mut NoteMetadata,
+    body_edited: bool,
+) -> Result<(), NoteStoreError> {
+    note_inner.enable_front_matter();
+    let front_matter = match note_inner
+        .get_front_matter()
+        .map_err(NoteStoreError::note_inner)?
+    {
+        Some(f) => f,
+        None => return Ok(()),
+    };
+    if body_edited {
+        if let Some(t) = front_matter.title {
+            *title = t;
+        }
+        if let Some(t) = front_matter.tags {
+            metadata.tags = t;
+        }
+        metadata.custom_metadata = Value::Object(front_matter.custom_metadata);
+    }
+    let synced = FrontMatter {
+        title: if title.is_empty() {
+            None
+        } else {
+            Some(title.clone())
+        },
+        tags: Some(metadata.tags.clone()),
+        custom_metadata: match metadata.custom_metadata {
+            Value::Object(ref m) => m.clone(),
+            _ => Default::default(),
+        },
+    };
+    note_inner
+        .set_front_matter(&synced)
+        .map_err(NoteStoreError::note_inner)
+}
@@ -1,16 +1,32 @@
 //! In-memory storage of notes
 use crate::errors::NoteStoreError;
 use crate::note::NoteLocator;
-use crate::notemetadata::{NoteMetadata, NoteMetadataEditable};
+use crate::notemetadata::{NoteFlag, NoteMetadata, NoteMetadataEditable};
+use crate::notestore::alias::{check_aliases, AliasTarget};
+use crate::notestore::batch::BatchOperation;
+use crate::notestore::compaction::{CompactionPolicy, RevisionAge};
+use crate::notestore::convert::convert_note_inner;
+use crate::notestore::delete::{DeleteOptions, DeleteReport};
+use crate::notestore::diff::{diff_notes, NoteDiff};
+use crate::notestore::front_matter::sync_front_matter;
+use crate::notestore::integrity::{IntegrityIssue, NoteGraph, Repair};
+use crate::notestore::schema::MetadataSchemas;
 use crate::notestore::search::SearchRequest;
+use crate::notestore::structure::MoveTarget;
+use crate::notestore::task::{self, NoteTask, TaskQuery};
+use crate::notestore::trash::DeletedNote;
 use crate::notestore::Revisions;
+use crate::notetype::{LinkRewrite, NoteFormat};
+use crate::url::find_revision_links;
 use crate::{Note, NoteID, NoteStore, NoteType, Revision};
+use chrono::{DateTime, Utc};
 use futures::future::BoxFuture;
 use serde::{Deserialize, Serialize};
 use std::cmp::Reverse;
 use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::fs::File;
+use std::hash::Hash;
 use std::io::Write;
 use std::marker::PhantomData;
 use std::path::Path;
@@ -20,6 +36,9 @@ use tokio::sync::RwLock;
 pub struct InMemoryNoteStored<T> {
     title: String,
     note_inner: String,
+    /// Missing in backups made before formats were recorded
+    #[serde(default)]
+    format: Option<NoteFormat>,
     id: NoteID,
     revision: Revision,
     branches: HashSet<NoteID>,
@@ -28,6 +47,23 @@ pub struct InMemoryNoteStored<T> {
     _phantom: PhantomData<T>,
 }
 
+impl<T: NoteType> InMemoryNoteStored<T> {
+    /// Parse the note, recognizing its front matter if the store synchronizes it.
+    fn get_note_inner(&self, front_matter: bool) -> Result<T, NoteStoreError> {
+        let mut note_inner: T = T::parse(self.format, self.note_inner.clone())
+            .map_err(|e| NoteStoreError::ParseError(e.to_string()))?;
+        if front_matter {
+            note_inner.enable_front_matter();
+        }
+        Ok(note_inner)
+    }
+
+    fn set_note_inner(&mut self, note_inner: T) {
+        self.format = Some(note_inner.get_format());
+        self.note_inner = note_inner.into();
+    }
+}
+
 #[derive(Debug, Clone)]
 struct InMemoryNoteComputed<T> {
     title: String,
@@ -41,6 +77,7 @@ struct InMemoryNoteComputed<T> {
     next: Option<NoteID>,
     referents: HashSet<NoteID>,
     references: HashSet<NoteID>,
+    inline_tags: HashSet<String>,
     metadata: NoteMetadata,
 }
 
@@ -88,6 +125,10 @@ where
         self.referents.clone()
     }
 
+    fn get_inline_tags(&self) -> HashSet<String> {
+        self.inline_tags.clone()
+    }
+
     fn get_metadata(&self) -> NoteMetadata {
         self.metadata.clone()
     }
@@ -100,12 +141,45 @@ where
 /// In-memory storage.
 ///
 /// This is mostly designed for development use, because there is no persistence layer.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct InMemoryStoreInner<T> {
     pub notes: HashMap<NoteID, HashMap<Revision, InMemoryNoteStored<T>>>,
     current_revision: HashMap<NoteID, Revision>,
     note_id_counter: u64,
     revision_id_counter: HashMap<NoteID, u64>,
+    /// Whether to synchronize the front matter of notes with their metadata.
+    ///
+    /// This is a setting of the store rather than its content, so it is not backed up.
+    #[serde(skip)]
+    front_matter_sync: bool,
+    #[serde(default)]
+    metadata_schemas: MetadataSchemas,
+    /// When notes were deleted, which is missing in backups made before it was recorded.
+    #[serde(default)]
+    deleted_at: HashMap<NoteID, DateTime<Utc>>,
+    /// Revisions kept by compaction regardless of the policy.
+    #[serde(default)]
+    kept_revisions: HashMap<NoteID, HashSet<Revision>>,
+    /// How to undo the batch being applied, if any.
+    #[serde(skip)]
+    batch_undo: Option<HashMap<NoteID, BatchUndo>>,
+}
+
+/// The state of a note before a batch first changed it, with the revisions the batch added.
+#[derive(Debug, Clone)]
+struct BatchUndo {
+    current_revision: Option<Revision>,
+    deleted_at: Option<DateTime<Utc>>,
+    revision_id_counter: Option<u64>,
+    added: Vec<Revision>,
+}
+
+/// Set `key` to `value`, or remove it if there is no value.
+fn restore<K: Eq + Hash, V>(map: &mut HashMap<K, V>, key: K, value: Option<V>) {
+    match value {
+        Some(v) => map.insert(key, v),
+        None => map.remove(&key),
+    };
 }
 
 impl<T: NoteType> Default for InMemoryStoreInner<T> {
@@ -115,6 +189,11 @@ impl<T: NoteType> Default for InMemoryStoreInner<T> {
             current_revision: Default::default(),
             note_id_counter: 0,
             revision_id_counter: Default::default(),
+            front_matter_sync: false,
+            metadata_schemas: Default::default(),
+            deleted_at: Default::default(),
+            kept_revisions: Default::default(),
+            batch_undo: None,
         }
     }
 }
@@ -143,10 +222,31 @@ fn note_excludes_lexemes(title: &str, note_inner: &str, lexemes_excluded: &[Stri
     true
 }
 
+/// Tags in the metadata and tags written in the body.
+fn note_all_tags<T: NoteType>(note: &dyn Note<T>) -> HashSet<String> {
+    let mut tags = note.get_metadata().tags;
+    tags.extend(note.get_inline_tags());
+    tags
+}
+
+/// Whether the flags of a note match the search, where archived notes are excluded by default.
+fn note_matches_flags<T: NoteType>(note: &dyn Note<T>, sr: &SearchRequest) -> bool {
+    let metadata = note.get_metadata();
+    (!sr.pinned || metadata.pinned)
+        && (!sr.starred || metadata.starred)
+        && metadata.archived == sr.archived
+}
+
 fn note_is_orphan<T: NoteType>(note: &dyn Note<T>) -> bool {
     note.get_prev().is_none() && note.get_parent().is_none() && note.get_references().is_empty()
 }
 
+/// Whether a string has the form of the IDs made by [`InMemoryStoreInner::get_new_noteid`].
+fn is_note_id(s: &str) -> bool {
+    s.strip_prefix("note-")
+        .is_some_and(|n| n.parse::<u64>().is_ok())
+}
+
 impl<T: NoteType> InMemoryStoreInner<T> {
     pub fn new() -> Self {
         Default::default()
@@ -165,12 +265,28 @@ impl<T: NoteType> InMemoryStoreInner<T> {
     ///
     /// We use a deterministic sequential format for easy testing
     fn get_new_revision(&mut self, note_id: &NoteID) -> Revision {
+        self.touch(note_id);
         let revision_counter = self.revision_id_counter.entry(note_id.clone()).or_insert(0);
         let revision = Revision::new(format!("revision-{}", *revision_counter));
         *revision_counter += 1;
+        if let Some(undo) = self.batch_undo.as_mut() {
+            undo.get_mut(note_id).unwrap().added.push(revision.clone());
+        }
         revision
     }
 
+    /// Record the state of a note before it is changed, if a batch is being applied.
+    fn touch(&mut self, id: &NoteID) {
+        if let Some(undo) = self.batch_undo.as_mut() {
+            undo.entry(id.clone()).or_insert_with(|| BatchUndo {
+                current_revision: self.current_revision.get(id).cloned(),
+                deleted_at: self.deleted_at.get(id).cloned(),
+                revision_id_counter: self.revision_id_counter.get(id).cloned(),
+                added: vec![],
+            });
+        }
+    }
+
     /// Does the locator points to a current revision
     fn is_current(&self, loc: &NoteLocator) -> Result<bool, NoteStoreError> {
         if let Some(r) = loc.get_revision() {
@@ -218,7 +334,7 @@ impl<T: NoteType> InMemoryStoreInner<T> {
     {
         let (id, rev) = loc.unpack();
         let is_resurrecting = self.is_deleted(loc)?;
-        let old_note = if is_resurrecting || self.is_current(loc)? {
+        let mut old_note = if is_resurrecting || self.is_current(loc)? {
             self.get_note_stored(loc)?
         } else {
             return Err(NoteStoreError::UpdateOldRevision(
@@ -226,6 +342,18 @@ impl<T: NoteType> InMemoryStoreInner<T> {
                 rev.unwrap().clone(),
             ));
         };
+        old_note.metadata.migrate()?;
+        // The new revision has an author only if the operation gives one
+        old_note.metadata.author = None;
+        // update note
+        let mut updated_note = op(&old_note)?;
+        for child in updated_note.branches.iter().chain(&updated_note.next) {
+            let is_new =
+                !old_note.branches.contains(child) && old_note.next.as_ref() != Some(child);
+            if is_new && self.comes_before(child, id) {
+                return Err(NoteStoreError::Cycle(child.clone(), id.clone()));
+            }
+        }
         // get new revision number
         let new_revision = self.get_new_revision(id);
         let note_revisions = self
@@ -234,13 +362,16 @@ impl<T: NoteType> InMemoryStoreInner<T> {
             .ok_or_else(|| NoteStoreError::NoteNotExist(id.clone()))?;
         // sanity check
         assert!(!note_revisions.contains_key(&new_revision));
-        // update note
-        let mut updated_note = op(&old_note)?;
         updated_note.revision = new_revision.clone();
-        updated_note.metadata = updated_note.metadata.on_update_note();
+        updated_note.metadata = updated_note.metadata.on_update_note()?;
         if is_resurrecting {
             // If a note has branches, it cannot be deleted in the first place
-            assert!(updated_note.branches.is_empty());
+            if !updated_note.branches.is_empty() {
+                return Err(NoteStoreError::Inconsistent(
+                    id.clone(),
+                    "it is deleted but has branches".to_owned(),
+                ));
+            }
             // If a note previously has a next note, we will clear the attribute, in case the next
             // note now has a prev
             updated_note.next = None;
@@ -248,6 +379,7 @@ impl<T: NoteType> InMemoryStoreInner<T> {
         note_revisions.insert(new_revision.clone(), updated_note);
         self.current_revision
             .insert(id.clone(), new_revision.clone());
+        self.deleted_at.remove(id);
         Ok(NoteLocator::Specific(id.clone(), new_revision))
     }
 
@@ -282,19 +414,20 @@ impl<T: NoteType> InMemoryStoreInner<T> {
         }
     }
 
-    fn get_references(&self, referent: &NoteID) -> HashSet<NoteID> {
+    fn get_references(&self, referent: &NoteID) -> Result<HashSet<NoteID>, NoteStoreError> {
         let mut references = HashSet::new();
         for (id, revision) in &self.current_revision {
             let note = self.get_note_by_revision(id, revision).unwrap();
-            if T::from(note.note_inner)
+            if note
+                .get_note_inner(self.front_matter_sync)?
                 .get_referents()
-                .unwrap()
+                .map_err(NoteStoreError::note_inner)?
                 .contains(referent)
             {
                 references.insert(note.id.clone());
             }
         }
-        references
+        Ok(references)
     }
 
     fn get_parent(&self, child: &NoteID) -> Option<NoteID> {
@@ -319,6 +452,42 @@ impl<T: NoteType> InMemoryStoreInner<T> {
         None
     }
 
+    /// Whether `ancestor` is reached from `note` by following parents and previous notes.
+    fn comes_before(&self, ancestor: &NoteID, note: &NoteID) -> bool {
+        let mut visited = HashSet::new();
+        let mut stack = vec![note.clone()];
+        while let Some(id) = stack.pop() {
+            if &id == ancestor {
+                return true;
+            }
+            if visited.insert(id.clone()) {
+                stack.extend(self.get_parent(&id));
+                stack.extend(self.get_prev(&id));
+            }
+        }
+        false
+    }
+
+    /// Map the aliases in the current revisions of all notes to the notes.
+    fn get_alias_owners(&self) -> HashMap<String, NoteID> {
+        let mut owners = HashMap::new();
+        for note in self.get_all_current_notes() {
+            for alias in note.metadata.aliases {
+                owners.insert(alias, note.id.clone());
+            }
+        }
+        owners
+    }
+
+    /// Whether each of the authors made a revision of the note.
+    fn note_has_authors(&self, id: &NoteID, authors: &[String]) -> bool {
+        let note_authors: HashSet<&String> = self.notes[id]
+            .values()
+            .filter_map(|n| n.metadata.author.as_ref())
+            .collect();
+        authors.iter().all(|a| note_authors.contains(a))
+    }
+
     fn get_all_current_notes(&self) -> Vec<InMemoryNoteStored<T>> {
         self.current_revision
             .iter()
@@ -328,20 +497,32 @@ impl<T: NoteType> InMemoryStoreInner<T> {
 
     fn new_note_helper(
         &mut self,
-        title: String,
-        note_inner: T,
+        mut title: String,
+        mut note_inner: T,
         metadata: NoteMetadataEditable,
-    ) -> NoteLocator {
+    ) -> Result<NoteLocator, NoteStoreError> {
+        let mut metadata = NoteMetadata::from_editable(metadata);
+        if self.front_matter_sync {
+            sync_front_matter(&mut title, &mut note_inner, &mut metadata, true)?;
+        }
+        self.metadata_schemas.validate(&metadata)?;
+        check_aliases(
+            None,
+            &metadata.aliases,
+            &self.get_alias_owners(),
+            is_note_id,
+        )?;
         let id = self.get_new_noteid();
         let revision = self.get_new_revision(&id);
         let note = InMemoryNoteStored {
             title,
+            format: Some(note_inner.get_format()),
             note_inner: note_inner.into(),
             id: id.clone(),
             revision: revision.clone(),
             branches: Default::default(),
             next: None,
-            metadata: NoteMetadata::from_editable(metadata),
+            metadata,
             _phantom: PhantomData,
         };
         assert!(!self.notes.contains_key(&id));
@@ -353,7 +534,7 @@ impl<T: NoteType> InMemoryStoreInner<T> {
             .insert(revision.clone(), note);
         assert!(!self.current_revision.contains_key(&id));
         self.current_revision.insert(id.clone(), revision.clone());
-        NoteLocator::Specific(id, revision)
+        Ok(NoteLocator::Specific(id, revision))
     }
 
     // The methods above are helper methods
@@ -364,18 +545,23 @@ impl<T: NoteType> InMemoryStoreInner<T> {
         note_inner: T,
         metadata: NoteMetadataEditable,
     ) -> Result<NoteLocator, NoteStoreError> {
-        Ok(self.new_note_helper(title, note_inner, metadata))
+        self.new_note_helper(title, note_inner, metadata)
     }
 
     fn compute_stored_note(
         &self,
         s: InMemoryNoteStored<T>,
     ) -> Result<InMemoryNoteComputed<T>, NoteStoreError> {
-        let note_inner = T::from(s.note_inner);
+        let note_inner = s.get_note_inner(self.front_matter_sync)?;
+        let mut metadata = s.metadata;
+        metadata.migrate()?;
         let referents = note_inner
             .get_referents()
-            .map_err(|e| NoteStoreError::ParseError(format!("{e:?}")))?;
-        let references = self.get_references(&s.id);
+            .map_err(NoteStoreError::note_inner)?;
+        let inline_tags = note_inner
+            .get_inline_tags()
+            .map_err(NoteStoreError::note_inner)?;
+        let references = self.get_references(&s.id)?;
         let parent = self.get_parent(&s.id);
         let prev = self.get_prev(&s.id);
         let current_revision = self.get_current_revision(&NoteLocator::Current(s.id.clone()))?;
@@ -396,7 +582,8 @@ impl<T: NoteType> InMemoryStoreInner<T> {
             next: s.next,
             referents,
             references,
-            metadata: s.metadata,
+            inline_tags,
+            metadata,
         })
     }
 
@@ -405,6 +592,25 @@ impl<T: NoteType> InMemoryStoreInner<T> {
         Ok(Box::new(self.compute_stored_note(note_stored)?) as Box<dyn Note<T>>)
     }
 
+    fn lookup_alias(&self, alias: &str) -> Result<AliasTarget, NoteStoreError> {
+        if let Some(id) = self.get_alias_owners().remove(alias) {
+            return Ok(AliasTarget {
+                id,
+                redirect: false,
+            });
+        }
+        self.current_revision
+            .keys()
+            .flat_map(|id| self.notes[id].values())
+            .filter(|n| n.metadata.aliases.contains(alias))
+            .max_by_key(|n| n.metadata.modified_at)
+            .map(|n| AliasTarget {
+                id: n.id.clone(),
+                redirect: true,
+            })
+            .ok_or_else(|| NoteStoreError::AliasNotExist(alias.to_owned()))
+    }
+
     fn update_note(
         &mut self,
         loc: &NoteLocator,
@@ -412,21 +618,77 @@ impl<T: NoteType> InMemoryStoreInner<T> {
         note_inner: Option<T>,
         note_metadata: NoteMetadataEditable,
     ) -> Result<NoteLocator, NoteStoreError> {
+        let front_matter_sync = self.front_matter_sync;
+        let metadata_schemas = self.metadata_schemas.clone();
+        let alias_owners = self.get_alias_owners();
         self.update_note_helper(loc, |old_note| {
             let mut note = old_note.clone();
             if let Some(t) = title {
                 note.title = t;
             }
+            let body_edited = note_inner.is_some();
             if let Some(n) = note_inner {
-                note.note_inner = n.into();
+                note.set_note_inner(n);
             }
 
             note.metadata = note.metadata.apply_editable(note_metadata);
+            if front_matter_sync {
+                let mut note_inner = note.get_note_inner(front_matter_sync)?;
+                sync_front_matter(
+                    &mut note.title,
+                    &mut note_inner,
+                    &mut note.metadata,
+                    body_edited,
+                )?;
+                note.set_note_inner(note_inner);
+            }
+            if note.metadata.tags != old_note.metadata.tags
+                || note.metadata.custom_metadata != old_note.metadata.custom_metadata
+            {
+                metadata_schemas.validate(&note.metadata)?;
+            }
+            check_aliases(
+                Some(&note.id),
+                &note.metadata.aliases,
+                &alias_owners,
+                is_note_id,
+            )?;
             Ok(note)
         })
     }
 
+    fn revert_note(
+        &mut self,
+        loc: &NoteLocator,
+        to: &Revision,
+        author: Option<String>,
+    ) -> Result<NoteLocator, NoteStoreError> {
+        let mut target = self.get_note_by_revision(loc.get_id(), to)?;
+        target.metadata.migrate()?;
+        let note_inner = target.get_note_inner(self.front_matter_sync)?;
+        self.update_note(
+            loc,
+            Some(target.title),
+            Some(note_inner),
+            NoteMetadataEditable {
+                tags: Some(target.metadata.tags),
+                aliases: Some(target.metadata.aliases),
+                custom_metadata: Some(target.metadata.custom_metadata),
+                author,
+            },
+        )
+    }
+
     fn delete_note(&mut self, loc: &NoteLocator) -> Result<(), NoteStoreError> {
+        self.delete_note_helper(loc, &HashSet::new())
+    }
+
+    /// Delete a note, ignoring references from `deleted_with`, which are deleted along with it.
+    fn delete_note_helper(
+        &mut self,
+        loc: &NoteLocator,
+        deleted_with: &HashSet<NoteID>,
+    ) -> Result<(), NoteStoreError> {
         let (id, rev) = loc.unpack();
         if self.is_current(loc)? {
             let note = self.get_note_stored(loc).unwrap();
@@ -434,28 +696,39 @@ impl<T: NoteType> InMemoryStoreInner<T> {
                 return Err(NoteStoreError::HasBranches(id.clone()));
             }
             // Avoid dangling references
-            if !self.get_references(id).is_empty() {
+            if !self.get_references(id)?.is_subset(deleted_with) {
                 return Err(NoteStoreError::HasReferences(id.clone()));
             }
             // This note was created by branching out from some other note
             // It's not possible to be in the middle of a note sequence
             // And vice versa
-            assert!(self.get_parent(id).is_none() || self.get_prev(id).is_none());
+            if self.get_parent(id).is_some() && self.get_prev(id).is_some() {
+                return Err(NoteStoreError::Inconsistent(
+                    id.clone(),
+                    "it is both a branch and in a sequence".to_owned(),
+                ));
+            }
             // Since only next is stored, our next note is not aware of us
             // But we want to make sure our prev note is consistent
             if let Some(prev_id) = self.get_prev(id) {
-                self.update_note_helper(&NoteLocator::Current(prev_id), |old_note| {
+                self.update_note_helper(&NoteLocator::Current(prev_id.clone()), |old_note| {
                     let mut parent_note = old_note.clone();
-                    assert_eq!(parent_note.next.as_ref(), Some(id));
+                    if parent_note.next.as_ref() != Some(id) {
+                        return Err(NoteStoreError::Inconsistent(
+                            prev_id.clone(),
+                            format!("note `{id}` is not the note after it"),
+                        ));
+                    }
                     parent_note.next.clone_from(&note.next);
                     Ok(parent_note)
                 })?;
             }
             if let Some(parent_id) = self.get_parent(id) {
-                self.update_note_helper(&NoteLocator::Current(parent_id), |old_note| {
+                self.update_note_helper(&NoteLocator::Current(parent_id.clone()), |old_note| {
                     let mut parent_note = old_note.clone();
-                    assert!(parent_note.branches.contains(id));
-                    parent_note.branches.remove(id);
+                    if !parent_note.branches.remove(id) {
+                        return Err(NoteStoreError::NotAChild(parent_id.clone(), id.clone()));
+                    }
                     if let Some(next_id) = &note.next {
                         parent_note.branches.insert(next_id.clone());
                     }
@@ -464,7 +737,9 @@ impl<T: NoteType> InMemoryStoreInner<T> {
             }
             // Mark the note as delete at last to avoid the previous steps from referring to
             // a delete note
+            self.touch(id);
             self.current_revision.remove(id).unwrap();
+            self.deleted_at.insert(id.clone(), Utc::now());
             Ok(())
         } else {
             Err(NoteStoreError::DeleteOldRevision(
@@ -474,6 +749,280 @@ impl<T: NoteType> InMemoryStoreInner<T> {
         }
     }
 
+    fn delete_note_with(
+        &mut self,
+        loc: &NoteLocator,
+        options: &DeleteOptions,
+        author: Option<String>,
+    ) -> Result<DeleteReport, NoteStoreError> {
+        let (id, rev) = loc.unpack();
+        if !self.is_current(loc)? {
+            return Err(NoteStoreError::DeleteOldRevision(
+                id.clone(),
+                rev.unwrap().clone(),
+            ));
+        }
+        // Parents and previous notes come before their branches and next notes
+        let mut deleted = vec![id.clone()];
+        let mut i = 0;
+        while i < deleted.len() {
+            let note = self.get_note_stored(&NoteLocator::Current(deleted[i].clone()))?;
+            if !options.cascade {
+                if !note.branches.is_empty() {
+                    return Err(NoteStoreError::HasBranches(id.clone()));
+                }
+                break;
+            }
+            for child in note.branches.into_iter().chain(note.next) {
+                if !deleted.contains(&child) {
+                    deleted.push(child);
+                }
+            }
+            i += 1;
+        }
+        let deleted_with: HashSet<NoteID> = deleted.iter().cloned().collect();
+        let mut referrers: HashMap<NoteID, Vec<NoteID>> = HashMap::new();
+        for referent in &deleted {
+            for referrer in self.get_references(referent)? {
+                if deleted_with.contains(&referrer) {
+                    continue;
+                }
+                if options.rewrite_references.is_none() {
+                    return Err(NoteStoreError::HasReferences(referent.clone()));
+                }
+                referrers
+                    .entry(referrer)
+                    .or_default()
+                    .push(referent.clone());
+            }
+        }
+        // Rewrite every note before changing anything, so that a failure leaves no trace
+        let mut rewritten = vec![];
+        if let Some(rewrite) = options.rewrite_references {
+            for (referrer, referents) in referrers {
+                let mut note_inner = self
+                    .get_note_stored(&NoteLocator::Current(referrer.clone()))?
+                    .get_note_inner(self.front_matter_sync)?;
+                for referent in referents {
+                    note_inner
+                        .remove_referent(referent, rewrite)
+                        .map_err(NoteStoreError::note_inner)?;
+                }
+                rewritten.push((referrer, note_inner));
+            }
+        }
+        rewritten.sort_by(|(a, _), (b, _)| a.as_ref().cmp(b.as_ref()));
+        let report = DeleteReport {
+            deleted: deleted.clone(),
+            rewritten: rewritten.iter().map(|(id, _)| id.clone()).collect(),
+        };
+        if options.dry_run {
+            return Ok(report);
+        }
+        for (referrer, note_inner) in rewritten {
+            self.update_note_helper(&NoteLocator::Current(referrer), |old_note| {
+                let mut note = old_note.clone();
+                note.set_note_inner(note_inner);
+                note.metadata.author = author.clone();
+                Ok(note)
+            })?;
+        }
+        for id in deleted.iter().rev() {
+            self.delete_note_helper(&NoteLocator::Current(id.clone()), &deleted_with)?;
+        }
+        Ok(report)
+    }
+
+    fn list_deleted(&self) -> Result<Vec<DeletedNote<T>>, NoteStoreError> {
+        let mut deleted = vec![];
+        for id in self.notes.keys() {
+            if self.current_revision.contains_key(id) {
+                continue;
+            }
+            let note = self
+                .get_revisions(&NoteLocator::Current(id.clone()))?
+                .pop()
+                .unwrap();
+            let deleted_at = self
+                .deleted_at
+                .get(id)
+                .cloned()
+                .unwrap_or_else(|| note.get_metadata().modified_at);
+            deleted.push(DeletedNote { note, deleted_at });
+        }
+        deleted.sort_by_key(|d| Reverse(d.deleted_at));
+        Ok(deleted)
+    }
+
+    fn purge_note(&mut self, id: &NoteID) -> Result<(), NoteStoreError> {
+        if !self.is_deleted(&NoteLocator::Current(id.clone()))? {
+            return Err(NoteStoreError::NotDeleted(id.clone()));
+        }
+        if !self.get_references(id)?.is_empty() {
+            return Err(NoteStoreError::HasReferences(id.clone()));
+        }
+        // Parents and previous notes are derived from current revisions, so a deleted note is
+        // never the parent or the previous note of another note.
+        // Older revisions of other notes might still point to it.
+        for revisions in self.notes.values_mut() {
+            for note in revisions.values_mut() {
+                note.branches.remove(id);
+                if note.next.as_ref() == Some(id) {
+                    note.next = None;
+                }
+            }
+        }
+        self.notes.remove(id);
+        self.revision_id_counter.remove(id);
+        self.deleted_at.remove(id);
+        self.kept_revisions.remove(id);
+        Ok(())
+    }
+
+    fn purge_deleted(&mut self, before: DateTime<Utc>) -> Result<Vec<NoteID>, NoteStoreError> {
+        let mut purged = vec![];
+        for deleted in self.list_deleted()? {
+            if deleted.deleted_at >= before {
+                continue;
+            }
+            let id = deleted.note.get_id();
+            match self.purge_note(&id) {
+                Ok(()) => purged.push(id),
+                Err(NoteStoreError::HasReferences(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(purged)
+    }
+
+    fn compact_revisions(&mut self, policy: &CompactionPolicy) -> Result<usize, NoteStoreError> {
+        let now = Utc::now();
+        let mut linked = HashSet::new();
+        for (id, revision) in self.current_revision.iter() {
+            linked.extend(find_revision_links(&self.notes[id][revision].note_inner));
+        }
+        let mut removed = 0;
+        for (id, revisions) in self.notes.iter_mut() {
+            let current = self.current_revision.get(id);
+            let kept = self.kept_revisions.get(id);
+            let ages = revisions
+                .values()
+                .map(|n| RevisionAge {
+                    revision: n.revision.clone(),
+                    modified_at: n.metadata.modified_at,
+                    keep: Some(&n.revision) == current
+                        || kept.is_some_and(|k| k.contains(&n.revision))
+                        || linked.contains(&(id.clone(), n.revision.clone())),
+                    aliases: n.metadata.aliases.clone(),
+                })
+                .collect();
+            for revision in policy.revisions_to_remove(ages, now) {
+                revisions.remove(&revision);
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    fn keep_revision(
+        &mut self,
+        id: &NoteID,
+        revision: &Revision,
+        keep: bool,
+    ) -> Result<(), NoteStoreError> {
+        self.get_note_by_revision(id, revision)?;
+        let kept = self.kept_revisions.entry(id.clone()).or_default();
+        if keep {
+            kept.insert(revision.clone());
+        } else {
+            kept.remove(revision);
+        }
+        Ok(())
+    }
+
+    fn get_kept_revisions(&self, id: &NoteID) -> Result<HashSet<Revision>, NoteStoreError> {
+        if !self.notes.contains_key(id) {
+            return Err(NoteStoreError::NoteNotExist(id.clone()));
+        }
+        Ok(self.kept_revisions.get(id).cloned().unwrap_or_default())
+    }
+
+    fn get_note_graph(&self) -> NoteGraph {
+        let mut graph = NoteGraph::default();
+        for (id, revision) in &self.current_revision {
+            graph.current.insert(id.clone());
+            let referents = self.get_note_by_revision(id, revision).and_then(|note| {
+                for branch in &note.branches {
+                    graph.branches.push((id.clone(), branch.clone()));
+                }
+                if let Some(next) = &note.next {
+                    graph.sequence.push((id.clone(), next.clone()));
+                }
+                note.get_note_inner(self.front_matter_sync)?
+                    .get_referents()
+                    .map_err(NoteStoreError::note_inner)
+            });
+            match referents {
+                Ok(referents) => graph
+                    .referents
+                    .extend(referents.into_iter().map(|r| (id.clone(), r))),
+                Err(e) => graph
+                    .unreadable
+                    .push((id.clone(), revision.clone(), e.to_string())),
+            }
+        }
+        // A note without a current revision is deleted, so no revision is orphaned
+        graph
+    }
+
+    fn check_integrity(&mut self, repair: bool) -> Result<Vec<IntegrityIssue>, NoteStoreError> {
+        let (issues, repairs) = self.get_note_graph().check();
+        if repair {
+            for r in repairs {
+                self.repair(r)?;
+            }
+        }
+        Ok(issues)
+    }
+
+    fn repair(&mut self, repair: Repair) -> Result<(), NoteStoreError> {
+        match repair {
+            Repair::RemoveBranch { parent, child } => {
+                self.update_note_helper(&NoteLocator::Current(parent), |old_note| {
+                    let mut note = old_note.clone();
+                    note.branches.remove(&child);
+                    Ok(note)
+                })?;
+            }
+            Repair::RemoveNext { prev, next } => {
+                self.update_note_helper(&NoteLocator::Current(prev), |old_note| {
+                    let mut note = old_note.clone();
+                    if note.next.as_ref() == Some(&next) {
+                        note.next = None;
+                    }
+                    Ok(note)
+                })?;
+            }
+            Repair::RemoveReferent { note, referent } => {
+                let front_matter_sync = self.front_matter_sync;
+                self.update_note_helper(&NoteLocator::Current(note), |old_note| {
+                    let mut note = old_note.clone();
+                    let mut note_inner = note.get_note_inner(front_matter_sync)?;
+                    note_inner
+                        .remove_referent(referent, LinkRewrite::Tombstone)
+                        .map_err(NoteStoreError::note_inner)?;
+                    note.set_note_inner(note_inner);
+                    Ok(note)
+                })?;
+            }
+            Repair::Trash(id) => {
+                self.current_revision.remove(&id);
+                self.deleted_at.insert(id, Utc::now());
+            }
+        }
+        Ok(())
+    }
+
     fn get_current_revision(&self, loc: &NoteLocator) -> Result<Option<Revision>, NoteStoreError> {
         let id = loc.get_id();
         if let Some(r) = self.current_revision.get(id) {
@@ -518,7 +1067,7 @@ impl<T: NoteType> InMemoryStoreInner<T> {
         if let Some(n) = last_note.next {
             return Err(NoteStoreError::ExistingNext(last_note.id, n));
         }
-        let loc = self.new_note_helper(title, note_inner, metadata);
+        let loc = self.new_note_helper(title, note_inner, metadata)?;
         self.update_note_helper(&last_loc, |old_note| {
             let mut note = old_note.clone();
             note.next = Some(loc.get_id().clone());
@@ -535,7 +1084,7 @@ impl<T: NoteType> InMemoryStoreInner<T> {
         metadata: NoteMetadataEditable,
     ) -> Result<NoteLocator, NoteStoreError> {
         let parent_loc = NoteLocator::Current(parent.clone());
-        let child_loc = self.new_note_helper(title, note_inner, metadata);
+        let child_loc = self.new_note_helper(title, note_inner, metadata)?;
         self.update_note_helper(&parent_loc, |old_note| {
             let mut note = old_note.clone();
             note.branches.insert(child_loc.get_id().clone());
@@ -544,36 +1093,198 @@ impl<T: NoteType> InMemoryStoreInner<T> {
         Ok(child_loc)
     }
 
+    fn move_note(&mut self, id: &NoteID, to: &MoveTarget) -> Result<NoteLocator, NoteStoreError> {
+        let loc = NoteLocator::Current(id.clone());
+        if self.is_deleted(&loc)? {
+            return Err(NoteStoreError::NoteNotExist(id.clone()));
+        }
+        let (parent, prev) = (self.get_parent(id), self.get_prev(id));
+        // Attach the note first, so that nothing is changed if that fails
+        match to {
+            MoveTarget::Standalone => {}
+            MoveTarget::BranchOf(target) if parent.as_ref() != Some(target) => {
+                self.update_note_helper(&NoteLocator::Current(target.clone()), |old_note| {
+                    let mut note = old_note.clone();
+                    note.branches.insert(id.clone());
+                    Ok(note)
+                })?;
+            }
+            MoveTarget::After(target) if prev.as_ref() != Some(target) => {
+                self.update_note_helper(&NoteLocator::Current(target.clone()), |old_note| {
+                    if let Some(n) = &old_note.next {
+                        return Err(NoteStoreError::ExistingNext(target.clone(), n.clone()));
+                    }
+                    let mut note = old_note.clone();
+                    note.next = Some(id.clone());
+                    Ok(note)
+                })?;
+            }
+            // Already there
+            _ => {
+                return Ok(NoteLocator::Specific(
+                    id.clone(),
+                    self.current_revision[id].clone(),
+                ))
+            }
+        }
+        if let Some(parent) = parent {
+            self.update_note_helper(&NoteLocator::Current(parent), |old_note| {
+                let mut note = old_note.clone();
+                note.branches.remove(id);
+                Ok(note)
+            })?;
+        }
+        if let Some(prev) = prev {
+            self.update_note_helper(&NoteLocator::Current(prev), |old_note| {
+                let mut note = old_note.clone();
+                note.next = None;
+                Ok(note)
+            })?;
+        }
+        Ok(NoteLocator::Specific(
+            id.clone(),
+            self.current_revision[id].clone(),
+        ))
+    }
+
+    fn apply_batch(
+        &mut self,
+        operations: Vec<BatchOperation<T>>,
+    ) -> Result<Vec<Option<NoteLocator>>, NoteStoreError> {
+        // Record what the operations change, to put it back if an operation fails
+        let note_id_counter = self.note_id_counter;
+        self.batch_undo = Some(HashMap::new());
+        let results = self.apply_operations(operations);
+        let undo = self.batch_undo.take().unwrap();
+        if results.is_err() {
+            for (id, undo) in undo {
+                if let Some(revisions) = self.notes.get_mut(&id) {
+                    for revision in &undo.added {
+                        revisions.remove(revision);
+                    }
+                    if revisions.is_empty() {
+                        self.notes.remove(&id);
+                    }
+                }
+                restore(
+                    &mut self.current_revision,
+                    id.clone(),
+                    undo.current_revision,
+                );
+                restore(&mut self.deleted_at, id.clone(), undo.deleted_at);
+                restore(&mut self.revision_id_counter, id, undo.revision_id_counter);
+            }
+            self.note_id_counter = note_id_counter;
+        }
+        results
+    }
+
+    fn apply_operations(
+        &mut self,
+        operations: Vec<BatchOperation<T>>,
+    ) -> Result<Vec<Option<NoteLocator>>, NoteStoreError> {
+        let mut results = vec![];
+        for operation in operations {
+            let result = match operation {
+                BatchOperation::New {
+                    title,
+                    note_inner,
+                    metadata,
+                } => Some(self.new_note(title, note_inner, metadata)?),
+                BatchOperation::Append {
+                    last,
+                    title,
+                    note_inner,
+                    metadata,
+                } => {
+                    let last = last.resolve(&results)?;
+                    Some(self.append_note(&last, title, note_inner, metadata)?)
+                }
+                BatchOperation::Branch {
+                    parent,
+                    title,
+                    note_inner,
+                    metadata,
+                } => {
+                    let parent = parent.resolve(&results)?;
+                    Some(self.add_branch(&parent, title, note_inner, metadata)?)
+                }
+                BatchOperation::Update {
+                    note,
+                    title,
+                    note_inner,
+                    metadata,
+                } => {
+                    let loc = NoteLocator::Current(note.resolve(&results)?);
+                    Some(self.update_note(&loc, title, note_inner, metadata)?)
+                }
+                BatchOperation::UpdateSource {
+                    note,
+                    title,
+                    note_inner,
+                    metadata,
+                } => {
+                    let loc = NoteLocator::Current(note.resolve(&results)?);
+                    let format = self.get_note_stored(&loc)?.format;
+                    let note_inner =
+                        T::parse(format, note_inner).map_err(NoteStoreError::note_inner)?;
+                    Some(self.update_note(&loc, title, Some(note_inner), metadata)?)
+                }
+                BatchOperation::Delete { note } => {
+                    self.delete_note(&NoteLocator::Current(note.resolve(&results)?))?;
+                    None
+                }
+                BatchOperation::Move { note, to } => {
+                    let to = to.try_map(|n| n.resolve(&results))?;
+                    Some(self.move_note(&note.resolve(&results)?, &to)?)
+                }
+            };
+            results.push(result);
+        }
+        Ok(results)
+    }
+
     fn search(&self, sr: &SearchRequest) -> Result<Revisions<T>, NoteStoreError> {
         let notes: Vec<InMemoryNoteStored<T>> = self.get_all_current_notes();
-        let revisions: Result<Revisions<T>, NoteStoreError> = notes
+        let revisions: Revisions<T> = notes
             .into_iter()
             .map(|x| {
                 self.compute_stored_note(x)
                     .map(|x_computed| Box::new(x_computed) as Box<dyn Note<T>>)
             })
-            .collect();
-        let mut revisions: Revisions<T> = revisions?
+            .collect::<Result<Revisions<T>, NoteStoreError>>()?;
+        // Match lexemes against the plain text rather than the markup of the note
+        let plain_texts: Vec<String> = revisions
+            .iter()
+            .map(|x| {
+                x.get_note_inner()
+                    .get_plain_text()
+                    .map_err(NoteStoreError::note_inner)
+            })
+            .collect::<Result<Vec<String>, NoteStoreError>>()?;
+        let mut revisions: Revisions<T> = revisions
             .into_iter()
-            .filter(|x| {
-                note_contains_lexemes(&x.get_title(), &x.get_note_inner().into(), &sr.lexemes)
-                    && note_excludes_lexemes(
-                        &x.get_title(),
-                        &x.get_note_inner().into(),
-                        &sr.lexemes_excluded,
-                    )
-                    && HashSet::from_iter(sr.tags.to_vec()).is_subset(&x.get_metadata().tags)
+            .zip(plain_texts)
+            .filter(|(x, plain_text)| {
+                note_contains_lexemes(&x.get_title(), plain_text, &sr.lexemes)
+                    && note_excludes_lexemes(&x.get_title(), plain_text, &sr.lexemes_excluded)
+                    && HashSet::from_iter(sr.tags.to_vec()).is_subset(&note_all_tags(x.as_ref()))
                     && HashSet::from_iter(sr.tags_excluded.to_vec())
-                        .intersection(&x.get_metadata().tags)
+                        .intersection(&note_all_tags(x.as_ref()))
                         .count()
                         == 0
                     && (!sr.orphan || note_is_orphan(x.as_ref()))
-                    && (!sr.no_tag || x.get_metadata().tags.is_empty())
+                    && (!sr.no_tag || note_all_tags(x.as_ref()).is_empty())
+                    && note_matches_flags(x.as_ref(), sr)
+                    && self.note_has_authors(&x.get_id(), &sr.authors)
             })
+            .map(|(x, _)| x)
             .collect();
         if sr.sort_by_created_at() {
             revisions.sort_by_key(|n| Reverse(n.get_metadata().created_at));
         }
+        // The sort is stable, so the order above is kept among pinned notes
+        revisions.sort_by_key(|n| !n.get_metadata().pinned);
         if let Some(l) = sr.limit {
             revisions = revisions.into_iter().take(l as usize).collect();
         }
@@ -583,14 +1294,116 @@ impl<T: NoteType> InMemoryStoreInner<T> {
     fn tags(&self) -> Result<Vec<String>, NoteStoreError> {
         let mut tags = HashSet::new();
         let notes: Vec<InMemoryNoteStored<T>> = self.get_all_current_notes();
-        for note in &notes {
+        for note in notes {
             for tag in &note.metadata.tags {
                 tags.insert(tag.clone());
             }
+            let inline_tags = note
+                .get_note_inner(self.front_matter_sync)?
+                .get_inline_tags()
+                .map_err(NoteStoreError::note_inner)?;
+            tags.extend(inline_tags);
         }
         Ok(Vec::from_iter(tags))
     }
 
+    fn tasks(&self, query: &TaskQuery) -> Result<Vec<NoteTask>, NoteStoreError> {
+        let mut tasks = vec![];
+        for note in self.get_all_current_notes() {
+            let note_inner = note.get_note_inner(self.front_matter_sync)?;
+            let mut tags = note.metadata.tags;
+            tags.extend(
+                note_inner
+                    .get_inline_tags()
+                    .map_err(NoteStoreError::note_inner)?,
+            );
+            let note_tasks = note_inner.get_tasks().map_err(NoteStoreError::note_inner)?;
+            for t in note_tasks {
+                if query.matches(&t, &tags) {
+                    tasks.push(NoteTask {
+                        id: note.id.clone(),
+                        revision: note.revision.clone(),
+                        title: note.title.clone(),
+                        task: t,
+                    });
+                }
+            }
+        }
+        task::sort_tasks(&mut tasks);
+        Ok(tasks)
+    }
+
+    fn toggle_task(
+        &mut self,
+        loc: &NoteLocator,
+        index: usize,
+        author: Option<String>,
+    ) -> Result<NoteLocator, NoteStoreError> {
+        let front_matter_sync = self.front_matter_sync;
+        self.update_note_helper(loc, |old_note| {
+            let mut note = old_note.clone();
+            let mut note_inner = note.get_note_inner(front_matter_sync)?;
+            task::toggle_task(&note.id, &mut note_inner, index)?;
+            note.set_note_inner(note_inner);
+            note.metadata.author = author;
+            Ok(note)
+        })
+    }
+
+    fn toggle_flag(
+        &mut self,
+        loc: &NoteLocator,
+        flag: NoteFlag,
+        author: Option<String>,
+    ) -> Result<NoteLocator, NoteStoreError> {
+        self.update_note_helper(loc, |old_note| {
+            let mut note = old_note.clone();
+            note.metadata.toggle_flag(flag);
+            note.metadata.author = author;
+            Ok(note)
+        })
+    }
+
+    fn convert_note(
+        &mut self,
+        loc: &NoteLocator,
+        format: NoteFormat,
+        author: Option<String>,
+    ) -> Result<NoteLocator, NoteStoreError> {
+        let front_matter_sync = self.front_matter_sync;
+        self.update_note_helper(loc, |old_note| {
+            let mut note = old_note.clone();
+            let note_inner =
+                convert_note_inner(&note.id, note.get_note_inner(front_matter_sync)?, format)?;
+            note.set_note_inner(note_inner);
+            note.metadata.author = author;
+            Ok(note)
+        })
+    }
+
+    fn migrate_metadata(&mut self) -> Result<usize, NoteStoreError> {
+        let mut migrated = 0;
+        for (id, revision) in &self.current_revision {
+            let note = self
+                .notes
+                .get_mut(id)
+                .and_then(|rs| rs.get_mut(revision))
+                .ok_or_else(|| NoteStoreError::RevisionNotExist(id.clone(), revision.clone()))?;
+            if note.metadata.migrate()? {
+                migrated += 1;
+            }
+        }
+        Ok(migrated)
+    }
+
+    fn set_metadata_schema(
+        &mut self,
+        tag: Option<&str>,
+        schema: Option<serde_json::Value>,
+    ) -> Result<(), NoteStoreError> {
+        self.metadata_schemas.set(tag, schema)
+    }
+
     fn backup<P: AsRef<Path>>(&self, path: P) -> Result<(), NoteStoreError> {
         let p = path.as_ref().join("notegraf_in_memory.json");
 
@@ -620,6 +1433,13 @@ impl<T: NoteType> InMemoryStore<T> {
             ims: RwLock::new(InMemoryStoreInner::new()),
         }
     }
+
+    /// Synchronize the front matter of notes with their title and metadata when they are
+    /// created or updated.
+    pub fn with_front_matter_sync(mut self, enabled: bool) -> Self {
+        self.ims.get_mut().front_matter_sync = enabled;
+        self
+    }
 }
 
 impl<T: NoteType> Default for InMemoryStore<T> {
@@ -629,6 +1449,7 @@ impl<T: NoteType> Default for InMemoryStore<T> {
 }
 
 impl<T: NoteType> NoteStore<T> for InMemoryStore<T> {
+    #[allow(mismatched_lifetime_syntaxes)]
     fn new_note(
         &self,
         title: String,
@@ -651,6 +1472,16 @@ impl<T: NoteType> NoteStore<T> for InMemoryStore<T> {
         })
     }
 
+    fn lookup_alias<'a>(
+        &'a self,
+        alias: &'a str,
+    ) -> BoxFuture<'a, Result<AliasTarget, NoteStoreError>> {
+        Box::pin(async move {
+            let ims = self.ims.read().await;
+            ims.lookup_alias(alias)
+        })
+    }
+
     fn update_note<'a>(
         &'a self,
         loc: &'a NoteLocator,
@@ -664,6 +1495,18 @@ impl<T: NoteType> NoteStore<T> for InMemoryStore<T> {
         })
     }
 
+    fn revert_note<'a>(
+        &'a self,
+        loc: &'a NoteLocator,
+        to: &'a Revision,
+        author: Option<String>,
+    ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>> {
+        Box::pin(async move {
+            let mut ims = self.ims.write().await;
+            ims.revert_note(loc, to, author)
+        })
+    }
+
     fn delete_note<'a>(
         &'a self,
         loc: &'a NoteLocator,
@@ -674,6 +1517,89 @@ impl<T: NoteType> NoteStore<T> for InMemoryStore<T> {
         })
     }
 
+    fn delete_note_with<'a>(
+        &'a self,
+        loc: &'a NoteLocator,
+        options: &'a DeleteOptions,
+        author: Option<String>,
+    ) -> BoxFuture<'a, Result<DeleteReport, NoteStoreError>> {
+        Box::pin(async move {
+            let mut ims = self.ims.write().await;
+            ims.delete_note_with(loc, options, author)
+        })
+    }
+
+    fn list_deleted(&self) -> BoxFuture<'_, Result<Vec<DeletedNote<T>>, NoteStoreError>> {
+        Box::pin(async move {
+            let ims = self.ims.read().await;
+            ims.list_deleted()
+        })
+    }
+
+    fn purge_note<'a>(&'a self, id: &'a NoteID) -> BoxFuture<'a, Result<(), NoteStoreError>> {
+        Box::pin(async move {
+            let mut ims = self.ims.write().await;
+            ims.purge_note(id)
+        })
+    }
+
+    fn purge_deleted(
+        &self,
+        before: DateTime<Utc>,
+    ) -> BoxFuture<'_, Result<Vec<NoteID>, NoteStoreError>> {
+        Box::pin(async move {
+            let mut ims = self.ims.write().await;
+            ims.purge_deleted(before)
+        })
+    }
+
+    fn compact_revisions<'a>(
+        &'a self,
+        policy: &'a CompactionPolicy,
+    ) -> BoxFuture<'a, Result<usize, NoteStoreError>> {
+        Box::pin(async move {
+            let mut ims = self.ims.write().await;
+            ims.compact_revisions(policy)
+        })
+    }
+
+    fn keep_revision<'a>(
+        &'a self,
+        id: &'a NoteID,
+        revision: &'a Revision,
+        keep: bool,
+    ) -> BoxFuture<'a, Result<(), NoteStoreError>> {
+        Box::pin(async move {
+            let mut ims = self.ims.write().await;
+            ims.keep_revision(id, revision, keep)
+        })
+    }
+
+    fn get_kept_revisions<'a>(
+        &'a self,
+        id: &'a NoteID,
+    ) -> BoxFuture<'a, Result<HashSet<Revision>, NoteStoreError>> {
+        Box::pin(async move {
+            let ims = self.ims.read().await;
+            ims.get_kept_revisions(id)
+        })
+    }
+
+    fn check_integrity(
+        &self,
+        repair: bool,
+    ) -> BoxFuture<'_, Result<Vec<IntegrityIssue>, NoteStoreError>> {
+        Box::pin(async move {
+            if repair {
+                let mut ims = self.ims.write().await;
+                ims.check_integrity(repair)
+            } else {
+                let ims = self.ims.read().await;
+                Ok(ims.get_note_graph().check().0)
+            }
+        })
+    }
+
     fn get_revisions<'a>(
         &'a self,
         loc: &'a NoteLocator,
@@ -684,6 +1610,20 @@ impl<T: NoteType> NoteStore<T> for InMemoryStore<T> {
         })
     }
 
+    fn diff<'a>(
+        &'a self,
+        id: &'a NoteID,
+        from: &'a Revision,
+        to: &'a Revision,
+    ) -> BoxFuture<'a, Result<NoteDiff, NoteStoreError>> {
+        Box::pin(async move {
+            let ims = self.ims.read().await;
+            let from = ims.get_note(&NoteLocator::Specific(id.clone(), from.clone()))?;
+            let to = ims.get_note(&NoteLocator::Specific(id.clone(), to.clone()))?;
+            diff_notes(from.as_ref(), to.as_ref())
+        })
+    }
+
     fn get_current_revision<'a>(
         &'a self,
         loc: &'a NoteLocator,
@@ -720,6 +1660,27 @@ impl<T: NoteType> NoteStore<T> for InMemoryStore<T> {
         })
     }
 
+    fn move_note<'a>(
+        &'a self,
+        id: &'a NoteID,
+        to: &'a MoveTarget,
+    ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>> {
+        Box::pin(async move {
+            let mut ims = self.ims.write().await;
+            ims.move_note(id, to)
+        })
+    }
+
+    fn apply_batch(
+        &self,
+        operations: Vec<BatchOperation<T>>,
+    ) -> BoxFuture<'_, Result<Vec<Option<NoteLocator>>, NoteStoreError>> {
+        Box::pin(async move {
+            let mut ims = self.ims.write().await;
+            ims.apply_batch(operations)
+        })
+    }
+
     fn search<'a>(
         &'a self,
         sr: &'a SearchRequest,
@@ -730,6 +1691,7 @@ impl<T: NoteType> NoteStore<T> for InMemoryStore<T> {
         })
     }
 
+    #[allow(mismatched_lifetime_syntaxes)]
     fn tags(&self) -> BoxFuture<Result<Vec<String>, NoteStoreError>> {
         Box::pin(async move {
             let ims = self.ims.read().await;
@@ -737,6 +1699,78 @@ impl<T: NoteType> NoteStore<T> for InMemoryStore<T> {
         })
     }
 
+    fn tasks<'a>(
+        &'a self,
+        query: &'a TaskQuery,
+    ) -> BoxFuture<'a, Result<Vec<NoteTask>, NoteStoreError>> {
+        Box::pin(async move {
+            let ims = self.ims.read().await;
+            ims.tasks(query)
+        })
+    }
+
+    fn toggle_task<'a>(
+        &'a self,
+        loc: &'a NoteLocator,
+        index: usize,
+        author: Option<String>,
+    ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>> {
+        Box::pin(async move {
+            let mut ims = self.ims.write().await;
+            ims.toggle_task(loc, index, author)
+        })
+    }
+
+    fn toggle_flag<'a>(
+        &'a self,
+        loc: &'a NoteLocator,
+        flag: NoteFlag,
+        author: Option<String>,
+    ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>> {
+        Box::pin(async move {
+            let mut ims = self.ims.write().await;
+            ims.toggle_flag(loc, flag, author)
+        })
+    }
+
+    fn convert_note<'a>(
+        &'a self,
+        loc: &'a NoteLocator,
+        format: NoteFormat,
+        author: Option<String>,
+    ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>> {
+        Box::pin(async move {
+            let mut ims = self.ims.write().await;
+            ims.convert_note(loc, format, author)
+        })
+    }
+
+    fn migrate_metadata(&self) -> BoxFuture<'_, Result<usize, NoteStoreError>> {
+        Box::pin(async move {
+            let mut ims = self.ims.write().await;
+            ims.migrate_metadata()
+        })
+    }
+
+    fn metadata_schemas(&self) -> BoxFuture<'_, Result<MetadataSchemas, NoteStoreError>> {
+        Box::pin(async move {
+            let ims = self.ims.read().await;
+            Ok(ims.metadata_schemas.clone())
+        })
+    }
+
+    fn set_metadata_schema<'a>(
+        &'a self,
+        tag: Option<&'a str>,
+        schema: Option<serde_json::Value>,
+    ) -> BoxFuture<'a, Result<(), NoteStoreError>> {
+        Box::pin(async move {
+            let mut ims = self.ims.write().await;
+            ims.set_metadata_schema(tag, schema)
+        })
+    }
+
+    #[allow(mismatched_lifetime_syntaxes)]
     fn backup(&self, path: Box<dyn AsRef<Path> + Send>) -> BoxFuture<Result<(), NoteStoreError>> {
         Box::pin(async move {
             let ims = self.ims.read().await;
@@ -755,6 +1789,7 @@ impl<T: NoteType> NoteStore<T> for InMemoryStore<T> {
 mod tests {
     use super::*;
     use crate::note::NoteSerializable;
+    use crate::notemetadata::NOTE_METADATA_CURRENT_SCHEMA_VERSION;
     use crate::notestore::tests as common_tests;
     use crate::notetype::PlainNote;
     use std::env;
@@ -931,4 +1966,308 @@ mod tests {
     async fn issue_158() {
         common_tests::issue_158(InMemoryStore::new()).await;
     }
+
+    #[tokio::test]
+    async fn front_matter_new_note() {
+        common_tests::front_matter_new_note(InMemoryStore::new().with_front_matter_sync(true))
+            .await;
+    }
+
+    #[tokio::test]
+    async fn front_matter_update_metadata() {
+        common_tests::front_matter_update_metadata(
+            InMemoryStore::new().with_front_matter_sync(true),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn front_matter_update_body() {
+        common_tests::front_matter_update_body(InMemoryStore::new().with_front_matter_sync(true))
+            .await;
+    }
+
+    #[tokio::test]
+    async fn front_matter_sync_disabled() {
+        common_tests::front_matter_sync_disabled(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn inline_tags() {
+        common_tests::inline_tags(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn search_inline_tags() {
+        common_tests::search_inline_tags(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn tasks() {
+        common_tests::tasks(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn toggle_task() {
+        common_tests::toggle_task(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn search_plain_text() {
+        common_tests::search_plain_text(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn search_markdown_plain_text() {
+        common_tests::search_markdown_plain_text(InMemoryStore::new()).await;
+    }
+
+    #[cfg(feature = "notetype_org")]
+    #[tokio::test]
+    async fn dynamic_formats() {
+        common_tests::dynamic_formats(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn convert_note() {
+        common_tests::convert_note(InMemoryStore::new()).await;
+    }
+
+    #[cfg(feature = "notetype_block")]
+    #[tokio::test]
+    async fn block_note() {
+        common_tests::block_note(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn corrupt_note() {
+        let store: InMemoryStore<PlainNote> = InMemoryStore::new();
+        let loc = store
+            .new_note(
+                "".to_owned(),
+                PlainNote::new("Foo".into()),
+                NoteMetadataEditable::unchanged(),
+            )
+            .await
+            .unwrap();
+        {
+            let mut ims = store.ims.write().await;
+            let (id, revision) = loc.unpack();
+            let note = ims
+                .notes
+                .get_mut(id)
+                .unwrap()
+                .get_mut(revision.unwrap())
+                .unwrap();
+            note.note_inner = "Foo".to_owned();
+        }
+        assert!(matches!(
+            store.get_note(&loc).await,
+            Err(NoteStoreError::ParseError(_))
+        ));
+        let issues = store.check_integrity(false).await.unwrap();
+        assert!(matches!(
+            issues.as_slice(),
+            [IntegrityIssue::UnreadableRevision { note, .. }] if note == loc.get_id()
+        ));
+        // Unreadable notes are not repaired
+        assert_eq!(store.check_integrity(true).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn migrate_metadata() {
+        common_tests::migrate_metadata(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn metadata_schema() {
+        common_tests::metadata_schema(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn aliases() {
+        common_tests::aliases(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn note_flags() {
+        common_tests::note_flags(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn author() {
+        common_tests::author(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn operation_author() {
+        common_tests::operation_author(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn diff() {
+        common_tests::diff(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn diff_plain() {
+        common_tests::diff_plain(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn revert_note() {
+        common_tests::revert_note(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn trash() {
+        common_tests::trash(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn compact_revisions() {
+        common_tests::compact_revisions(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn keep_revision() {
+        common_tests::keep_revision(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn delete_note_with() {
+        common_tests::delete_note_with(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn check_integrity() {
+        common_tests::check_integrity(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn move_note() {
+        common_tests::move_note(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn move_note_cycle() {
+        common_tests::move_note_cycle(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn apply_batch() {
+        common_tests::apply_batch(InMemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn repair_integrity() {
+        let store: InMemoryStore<PlainNote> = InMemoryStore::new();
+        let new_note = |referents: &[&NoteID]| {
+            let mut note_inner = PlainNote::new("".into());
+            for referent in referents {
+                note_inner.add_referent((*referent).clone());
+            }
+            store.new_note("".to_owned(), note_inner, NoteMetadataEditable::unchanged())
+        };
+        let loc_a = new_note(&[]).await.unwrap();
+        let loc_b = store
+            .add_branch(
+                loc_a.get_id(),
+                "".to_owned(),
+                PlainNote::new("".into()),
+                NoteMetadataEditable::unchanged(),
+            )
+            .await
+            .unwrap();
+        let loc_c = new_note(&[]).await.unwrap();
+        let loc_e = new_note(&[]).await.unwrap();
+        let loc_d = new_note(&[loc_e.get_id()]).await.unwrap();
+        let (id_a, id_b, id_c) = (loc_a.get_id(), loc_b.get_id(), loc_c.get_id());
+        {
+            let mut ims = store.ims.write().await;
+            let mut current = |id: &NoteID| {
+                let revision = ims.current_revision[id].clone();
+                ims.notes
+                    .get_mut(id)
+                    .unwrap()
+                    .get_mut(&revision)
+                    .unwrap()
+                    .clone()
+            };
+            // A is a branch of B and B is a branch of A
+            let mut note_b = current(id_b);
+            note_b.branches.insert(id_a.clone());
+            // B is also the next note of C
+            let mut note_c = current(id_c);
+            note_c.next = Some(id_b.clone());
+            for note in [note_b, note_c] {
+                let revision = ims.current_revision[&note.id].clone();
+                ims.notes.get_mut(&note.id).unwrap().insert(revision, note);
+            }
+        }
+        let issues = store.check_integrity(false).await.unwrap();
+        assert_eq!(issues.len(), 2);
+        assert!(issues.contains(&IntegrityIssue::BranchInSequence {
+            note: id_b.clone(),
+            parent: id_a.clone(),
+            prev: id_c.clone(),
+        }));
+        assert!(issues
+            .iter()
+            .any(|i| matches!(i, IntegrityIssue::Cycle { notes } if notes.len() == 2)));
+        // E is deleted while D still refers to it
+        {
+            let mut ims = store.ims.write().await;
+            ims.current_revision.remove(loc_e.get_id());
+        }
+        assert_eq!(store.check_integrity(true).await.unwrap().len(), 3);
+        assert!(store.check_integrity(false).await.unwrap().is_empty());
+        let note_b = store.get_note(&loc_b.current()).await.unwrap();
+        assert_eq!(note_b.get_parent(), None);
+        assert_eq!(note_b.get_prev().as_ref(), Some(id_c));
+        assert!(store
+            .get_note(&loc_d.current())
+            .await
+            .unwrap()
+            .get_referents()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn newer_metadata_schema() {
+        let store: InMemoryStore<PlainNote> = InMemoryStore::new();
+        let loc = store
+            .new_note(
+                "".to_owned(),
+                PlainNote::new("Foo".into()),
+                NoteMetadataEditable::unchanged(),
+            )
+            .await
+            .unwrap();
+        {
+            let mut ims = store.ims.write().await;
+            let (id, revision) = loc.unpack();
+            let note = ims
+                .notes
+                .get_mut(id)
+                .unwrap()
+                .get_mut(revision.unwrap())
+                .unwrap();
+            note.metadata.schema_version = NOTE_METADATA_CURRENT_SCHEMA_VERSION + 1;
+        }
+        assert!(matches!(
+            store.get_note(&loc).await,
+            Err(NoteStoreError::MetadataSchemaTooNew(_, _))
+        ));
+        assert!(matches!(
+            store
+                .update_note(&loc, None, None, NoteMetadataEditable::unchanged())
+                .await,
+            Err(NoteStoreError::MetadataSchemaTooNew(_, _))
+        ));
+        assert!(matches!(
+            store.migrate_metadata().await,
+            Err(NoteStoreError::MetadataSchemaTooNew(_, _))
+        ));
+    }
 }
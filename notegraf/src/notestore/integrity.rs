@@ -0,0 +1,383 @@
+//! Checking the relationships between notes, see
+//! [`NoteStore::check_integrity`](crate::NoteStore::check_integrity).
+use crate::{NoteID, Revision};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// A problem with the relationships between notes.
+///
+/// Each issue comes with how it is repaired.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum IntegrityIssue {
+    /// A current note links to a note that is not current.
+    ///
+    /// Repaired by pointing the links at tombstones, see
+    /// [`LinkRewrite::Tombstone`](crate::notetype::LinkRewrite::Tombstone).
+    DanglingReferent { note: NoteID, referent: NoteID },
+    /// Several notes come right after a note in a sequence, sorted.
+    ///
+    /// Repaired by taking all but the first of them out of the sequence.
+    SeveralNexts { note: NoteID, nexts: Vec<NoteID> },
+    /// Following the parents and previous notes from a note leads back to it.
+    ///
+    /// The notes are in the order they are followed, starting from the smallest ID.
+    /// Repaired by detaching the first note from the next one.
+    Cycle { notes: Vec<NoteID> },
+    /// A note is both a branch of a note and in a sequence after another.
+    ///
+    /// Repaired by detaching the note from its parent.
+    BranchInSequence {
+        note: NoteID,
+        parent: NoteID,
+        prev: NoteID,
+    },
+    /// A revision of a note that is neither current nor deleted, so it cannot be reached.
+    ///
+    /// Repaired by moving the note to the trash.
+    OrphanedRevision { note: NoteID, revision: Revision },
+    /// The current revision of a note cannot be read, so its links are unknown.
+    ///
+    /// Not repaired, as only the stored note can be fixed.
+    UnreadableRevision {
+        note: NoteID,
+        revision: Revision,
+        error: String,
+    },
+}
+
+/// A change that repairs an [`IntegrityIssue`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Repair {
+    RemoveBranch { parent: NoteID, child: NoteID },
+    RemoveNext { prev: NoteID, next: NoteID },
+    RemoveReferent { note: NoteID, referent: NoteID },
+    Trash(NoteID),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Link {
+    Branch,
+    Sequence,
+}
+
+/// The relationships between notes, as recorded by the current revisions.
+#[derive(Debug, Default)]
+pub(crate) struct NoteGraph {
+    pub current: HashSet<NoteID>,
+    /// Pairs of a parent and a branch.
+    pub branches: Vec<(NoteID, NoteID)>,
+    /// Pairs of a note and its next note.
+    pub sequence: Vec<(NoteID, NoteID)>,
+    /// Pairs of a note and one of its referents.
+    pub referents: Vec<(NoteID, NoteID)>,
+    pub orphaned: Vec<(NoteID, Revision)>,
+    /// Current revisions that cannot be read, with why.
+    pub unreadable: Vec<(NoteID, Revision, String)>,
+}
+
+fn sort_ids(ids: &mut [NoteID]) {
+    ids.sort_by(|a, b| a.as_ref().cmp(b.as_ref()));
+}
+
+fn sort_pairs<A: AsRef<str>, B: AsRef<str>>(pairs: &mut [(A, B)]) {
+    pairs.sort_by(|(a, x), (b, y)| (a.as_ref(), x.as_ref()).cmp(&(b.as_ref(), y.as_ref())));
+}
+
+impl NoteGraph {
+    /// Find the issues, and the changes that repair them.
+    pub(crate) fn check(&self) -> (Vec<IntegrityIssue>, Vec<Repair>) {
+        let mut issues = vec![];
+        let mut repairs = vec![];
+        let mut add_repair = |repair: Repair| {
+            if !repairs.contains(&repair) {
+                repairs.push(repair);
+            }
+        };
+
+        let mut referents = self.referents.clone();
+        sort_pairs(&mut referents);
+        for (note, referent) in referents {
+            if !self.current.contains(&referent) {
+                add_repair(Repair::RemoveReferent {
+                    note: note.clone(),
+                    referent: referent.clone(),
+                });
+                issues.push(IntegrityIssue::DanglingReferent { note, referent });
+            }
+        }
+
+        let mut nexts: HashMap<&NoteID, Vec<NoteID>> = HashMap::new();
+        let mut prevs: HashMap<&NoteID, Vec<NoteID>> = HashMap::new();
+        for (prev, next) in &self.sequence {
+            nexts.entry(prev).or_default().push(next.clone());
+            prevs.entry(next).or_default().push(prev.clone());
+        }
+        let mut several_nexts: Vec<(&NoteID, Vec<NoteID>)> =
+            nexts.into_iter().filter(|(_, n)| n.len() > 1).collect();
+        several_nexts.sort_by(|(a, _), (b, _)| a.as_ref().cmp(b.as_ref()));
+        for (note, mut nexts) in several_nexts {
+            sort_ids(&mut nexts);
+            for next in &nexts[1..] {
+                add_repair(Repair::RemoveNext {
+                    prev: note.clone(),
+                    next: next.clone(),
+                });
+            }
+            issues.push(IntegrityIssue::SeveralNexts {
+                note: note.clone(),
+                nexts,
+            });
+        }
+
+        let mut parents: HashMap<&NoteID, Vec<NoteID>> = HashMap::new();
+        for (parent, child) in &self.branches {
+            parents.entry(child).or_default().push(parent.clone());
+        }
+        let mut in_both: Vec<&NoteID> = parents
+            .keys()
+            .filter(|note| prevs.contains_key(*note))
+            .copied()
+            .collect();
+        in_both.sort_by(|a, b| a.as_ref().cmp(b.as_ref()));
+        for note in in_both {
+            let (mut note_parents, mut note_prevs) = (parents[note].clone(), prevs[note].clone());
+            sort_ids(&mut note_parents);
+            sort_ids(&mut note_prevs);
+            for parent in &note_parents {
+                add_repair(Repair::RemoveBranch {
+                    parent: parent.clone(),
+                    child: note.clone(),
+                });
+            }
+            issues.push(IntegrityIssue::BranchInSequence {
+                note: note.clone(),
+                parent: note_parents.swap_remove(0),
+                prev: note_prevs.swap_remove(0),
+            });
+        }
+
+        for (notes, link) in self.find_cycles() {
+            let (from, to) = (notes[0].clone(), notes[1 % notes.len()].clone());
+            add_repair(match link {
+                Link::Branch => Repair::RemoveBranch {
+                    parent: to,
+                    child: from,
+                },
+                Link::Sequence => Repair::RemoveNext {
+                    prev: to,
+                    next: from,
+                },
+            });
+            issues.push(IntegrityIssue::Cycle { notes });
+        }
+
+        let mut orphaned = self.orphaned.clone();
+        sort_pairs(&mut orphaned);
+        for (note, revision) in orphaned {
+            add_repair(Repair::Trash(note.clone()));
+            issues.push(IntegrityIssue::OrphanedRevision { note, revision });
+        }
+
+        let mut unreadable = self.unreadable.clone();
+        unreadable.sort_by(|(a, x, _), (b, y, _)| {
+            (a.as_ref(), x.as_ref()).cmp(&(b.as_ref(), y.as_ref()))
+        });
+        for (note, revision, error) in unreadable {
+            issues.push(IntegrityIssue::UnreadableRevision {
+                note,
+                revision,
+                error,
+            });
+        }
+        (issues, repairs)
+    }
+
+    /// Find the cycles of parents and previous notes, each starting from its smallest note, with
+    /// how that note links to the next one.
+    fn find_cycles(&self) -> Vec<(Vec<NoteID>, Link)> {
+        let mut up: HashMap<&NoteID, Vec<(&NoteID, Link)>> = HashMap::new();
+        for (parent, child) in &self.branches {
+            up.entry(child).or_default().push((parent, Link::Branch));
+        }
+        for (prev, next) in &self.sequence {
+            up.entry(next).or_default().push((prev, Link::Sequence));
+        }
+        for links in up.values_mut() {
+            links.sort_by(|(a, _), (b, _)| a.as_ref().cmp(b.as_ref()));
+        }
+        let mut starts: Vec<&NoteID> = up.keys().copied().collect();
+        starts.sort_by(|a, b| a.as_ref().cmp(b.as_ref()));
+
+        let mut cycles = vec![];
+        let mut done: HashSet<&NoteID> = HashSet::new();
+        for start in starts {
+            if done.contains(start) {
+                continue;
+            }
+            // A depth-first search without recursion, as sequences can be long
+            let mut path: Vec<(&NoteID, usize)> = vec![(start, 0)];
+            let mut links: Vec<Link> = vec![];
+            let mut on_path: HashMap<&NoteID, usize> = HashMap::from([(start, 0)]);
+            while let Some((note, i)) = path.last_mut() {
+                let Some(&(to, link)) = up.get(*note).and_then(|links| links.get(*i)) else {
+                    done.insert(*note);
+                    on_path.remove(*note);
+                    path.pop();
+                    links.pop();
+                    continue;
+                };
+                *i += 1;
+                if let Some(&position) = on_path.get(to) {
+                    let mut cycle: Vec<(&NoteID, Link)> = path[position..]
+                        .iter()
+                        .map(|(n, _)| *n)
+                        .zip(links[position..].iter().copied().chain([link]))
+                        .collect();
+                    let smallest = (0..cycle.len())
+                        .min_by(|&a, &b| cycle[a].0.as_ref().cmp(cycle[b].0.as_ref()))
+                        .unwrap();
+                    cycle.rotate_left(smallest);
+                    cycles.push((
+                        cycle.iter().map(|(n, _)| (*n).clone()).collect(),
+                        cycle[0].1,
+                    ));
+                } else if !done.contains(to) {
+                    on_path.insert(to, path.len());
+                    path.push((to, 0));
+                    links.push(link);
+                }
+            }
+        }
+        cycles
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(s: &str) -> NoteID {
+        NoteID::new(s.to_owned())
+    }
+
+    fn pairs(pairs: &[(&str, &str)]) -> Vec<(NoteID, NoteID)> {
+        pairs.iter().map(|(a, b)| (id(a), id(b))).collect()
+    }
+
+    #[test]
+    fn consistent() {
+        let graph = NoteGraph {
+            current: ["a", "b", "c", "d"].into_iter().map(id).collect(),
+            branches: pairs(&[("a", "b")]),
+            sequence: pairs(&[("b", "c"), ("a", "d")]),
+            referents: pairs(&[("c", "a")]),
+            orphaned: vec![],
+            unreadable: vec![],
+        };
+        assert_eq!(graph.check(), (vec![], vec![]));
+    }
+
+    #[test]
+    fn issues() {
+        let graph = NoteGraph {
+            current: ["a", "b", "c", "d", "e"].into_iter().map(id).collect(),
+            branches: pairs(&[("a", "c"), ("e", "d")]),
+            sequence: pairs(&[("a", "b"), ("a", "c"), ("b", "d")]),
+            referents: pairs(&[("a", "x"), ("a", "b")]),
+            orphaned: vec![(id("y"), "1".into())],
+            unreadable: vec![(id("e"), "2".into(), "note cannot be parsed".to_owned())],
+        };
+        let (issues, repairs) = graph.check();
+        assert_eq!(
+            issues,
+            vec![
+                IntegrityIssue::DanglingReferent {
+                    note: id("a"),
+                    referent: id("x")
+                },
+                IntegrityIssue::SeveralNexts {
+                    note: id("a"),
+                    nexts: vec![id("b"), id("c")]
+                },
+                IntegrityIssue::BranchInSequence {
+                    note: id("c"),
+                    parent: id("a"),
+                    prev: id("a")
+                },
+                IntegrityIssue::BranchInSequence {
+                    note: id("d"),
+                    parent: id("e"),
+                    prev: id("b")
+                },
+                IntegrityIssue::OrphanedRevision {
+                    note: id("y"),
+                    revision: "1".into()
+                },
+                IntegrityIssue::UnreadableRevision {
+                    note: id("e"),
+                    revision: "2".into(),
+                    error: "note cannot be parsed".to_owned()
+                },
+            ]
+        );
+        assert_eq!(
+            repairs,
+            vec![
+                Repair::RemoveReferent {
+                    note: id("a"),
+                    referent: id("x")
+                },
+                Repair::RemoveNext {
+                    prev: id("a"),
+                    next: id("c")
+                },
+                Repair::RemoveBranch {
+                    parent: id("a"),
+                    child: id("c")
+                },
+                Repair::RemoveBranch {
+                    parent: id("e"),
+                    child: id("d")
+                },
+                Repair::Trash(id("y")),
+            ]
+        );
+    }
+
+    #[test]
+    fn cycles() {
+        let graph = NoteGraph {
+            current: ["a", "b", "c", "d"].into_iter().map(id).collect(),
+            // c -> b -> a -> c through parents and previous notes, and d -> d
+            branches: pairs(&[("c", "a"), ("d", "d")]),
+            sequence: pairs(&[("a", "b"), ("b", "c")]),
+            ..Default::default()
+        };
+        let (issues, repairs) = graph.check();
+        assert_eq!(
+            issues,
+            vec![
+                IntegrityIssue::Cycle {
+                    notes: vec![id("a"), id("c"), id("b")]
+                },
+                IntegrityIssue::Cycle {
+                    notes: vec![id("d")]
+                },
+            ]
+        );
+        assert_eq!(
+            repairs,
+            vec![
+                Repair::RemoveBranch {
+                    parent: id("c"),
+                    child: id("a")
+                },
+                Repair::RemoveBranch {
+                    parent: id("d"),
+                    child: id("d")
+                },
+            ]
+        );
+    }
+}
@@ -1,21 +1,45 @@
 //! Storage backends of notes.
 use crate::errors::NoteStoreError;
 use crate::note::*;
-use crate::notemetadata::NoteMetadataEditable;
-use crate::notetype::NoteType;
+use crate::notemetadata::{NoteFlag, NoteMetadataEditable};
+use crate::notetype::{NoteFormat, NoteType};
 use futures::future::BoxFuture;
 use std::path::Path;
 
+pub mod alias;
+pub mod batch;
+pub mod compaction;
+mod convert;
+pub mod delete;
+pub mod diff;
+mod front_matter;
 mod in_memory;
+pub mod integrity;
 mod postgresql;
+pub mod schema;
 pub mod search;
+pub mod structure;
+pub mod task;
 #[cfg(test)]
 mod tests;
+pub mod trash;
 pub mod util;
 
+use crate::notestore::alias::AliasTarget;
+use crate::notestore::batch::BatchOperation;
+use crate::notestore::compaction::CompactionPolicy;
+use crate::notestore::delete::{DeleteOptions, DeleteReport};
+use crate::notestore::diff::NoteDiff;
+use crate::notestore::integrity::IntegrityIssue;
+use crate::notestore::schema::MetadataSchemas;
 use crate::notestore::search::SearchRequest;
+use crate::notestore::structure::MoveTarget;
+use crate::notestore::task::{NoteTask, TaskQuery};
+use crate::notestore::trash::DeletedNote;
+use chrono::{DateTime, Utc};
 pub use in_memory::InMemoryStore;
 pub use postgresql::{PostgreSQLStore, PostgreSQLStoreBuilder};
+use std::collections::HashSet;
 
 pub type Revisions<T> = Vec<Box<dyn Note<T>>>;
 
@@ -30,6 +54,10 @@ where
     /// Create a new note.
     ///
     /// The storage backend assigns a [`NoteID`] and [`Revision`]
+    ///
+    /// The custom metadata is validated against the schemas that apply
+    /// (see [`NoteStore::set_metadata_schema`]), and the aliases must not be used by other notes.
+    #[allow(mismatched_lifetime_syntaxes)]
     fn new_note(
         &self,
         title: String,
@@ -44,6 +72,16 @@ where
         &'a self,
         loc: &'a NoteLocator,
     ) -> BoxFuture<'a, Result<Box<dyn Note<T>>, NoteStoreError>>;
+    /// Find the note that an alias refers to.
+    ///
+    /// Aliases in the current revisions of notes take precedence.
+    /// Otherwise, the alias was renamed, and it refers to the note that had it most recently, as
+    /// a redirect.
+    /// Aliases of deleted notes don't refer to anything.
+    fn lookup_alias<'a>(
+        &'a self,
+        alias: &'a str,
+    ) -> BoxFuture<'a, Result<AliasTarget, NoteStoreError>>;
     /// Update the content and metadata of a note.
     ///
     /// The new content will set to be the current revision.
@@ -51,10 +89,8 @@ where
     /// If a revision is specified, that revision should be the current revision.
     /// This can be used to prevent racy updates to the same note.
     ///
-    /// There is no separate operations for rolling back a note to a specific revision.
-    /// The idea is that you can just copy that revision and update the current revision to
-    /// that one.
-    /// Similarly, you can resurrect a deleted note by updating the note.
+    /// To roll back a note to a specific revision, use [`NoteStore::revert_note`].
+    /// You can resurrect a deleted note by updating the note.
     ///
     /// When resurrecting a note, it will become a standalone note and losing any
     /// parent-children or previous-next relationship.
@@ -62,6 +98,11 @@ where
     /// If a [`NoteStore`] caches the reference-referent relationships,
     /// it should check whether any of the relevant fields of note_inner is changed,
     /// and update the cache accordingly.
+    ///
+    /// If the tags or the custom metadata change, the custom metadata is validated against the
+    /// schemas that apply.
+    /// The aliases must not be used by other notes, but the previous aliases of the note keep
+    /// referring to it until another note takes them.
     fn update_note<'a>(
         &'a self,
         loc: &'a NoteLocator,
@@ -69,6 +110,21 @@ where
         note_inner: Option<T>,
         note_metadata: NoteMetadataEditable,
     ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>>;
+    /// Revert a note to an earlier revision.
+    ///
+    /// This creates a new current revision with the title, the body, and the tags, aliases and
+    /// custom metadata of revision `to`, as [`NoteStore::update_note`] would.
+    /// Relationships with other notes, flags and the revision history are kept.
+    ///
+    /// If a revision is specified in `loc`, that revision should be the current revision.
+    /// This can be used to prevent racy updates to the same note.
+    /// The new revision is made by `author`, if known.
+    fn revert_note<'a>(
+        &'a self,
+        loc: &'a NoteLocator,
+        to: &'a Revision,
+        author: Option<String>,
+    ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>>;
     /// Delete a note.
     ///
     /// If a revision is specified, that revision should be the current revision.
@@ -89,6 +145,68 @@ where
     /// A note cannot be deleted if it is the referent of any other note.
     fn delete_note<'a>(&'a self, loc: &'a NoteLocator)
         -> BoxFuture<'a, Result<(), NoteStoreError>>;
+    /// Delete a note like [`NoteStore::delete_note`], optionally with its branches and next notes
+    /// and rewriting the links to the deleted notes.
+    ///
+    /// Either every note is deleted and every link is rewritten, or nothing changes.
+    /// The branches and the next notes of a deleted note are deleted before the note, and links
+    /// between the deleted notes are left as they are.
+    /// The revisions that rewrite links are made by `author`, if known.
+    fn delete_note_with<'a>(
+        &'a self,
+        loc: &'a NoteLocator,
+        options: &'a DeleteOptions,
+        author: Option<String>,
+    ) -> BoxFuture<'a, Result<DeleteReport, NoteStoreError>>;
+    /// List the deleted notes that are not purged yet, the most recently deleted first.
+    fn list_deleted(&self) -> BoxFuture<'_, Result<Vec<DeletedNote<T>>, NoteStoreError>>;
+    /// Permanently remove a deleted note with all its revisions.
+    ///
+    /// A note cannot be purged if the current revision of any other note refers to it, or has
+    /// it as the parent or the previous note.
+    /// Older revisions of other notes that had the note as their parent or previous note lose
+    /// that relationship.
+    fn purge_note<'a>(&'a self, id: &'a NoteID) -> BoxFuture<'a, Result<(), NoteStoreError>>;
+    /// Purge the notes deleted before a time, skipping those that cannot be purged.
+    ///
+    /// Returns the notes that were purged.
+    /// This is meant for enforcing a retention period of deleted notes.
+    fn purge_deleted(
+        &self,
+        before: DateTime<Utc>,
+    ) -> BoxFuture<'_, Result<Vec<NoteID>, NoteStoreError>>;
+    /// Remove old revisions of all notes according to a policy.
+    ///
+    /// Returns the number of revisions removed.
+    /// See [`CompactionPolicy`] for the revisions that are always kept.
+    fn compact_revisions<'a>(
+        &'a self,
+        policy: &'a CompactionPolicy,
+    ) -> BoxFuture<'a, Result<usize, NoteStoreError>>;
+    /// Mark a revision of a note to be kept by [`NoteStore::compact_revisions`] regardless of the
+    /// policy, or unmark it.
+    ///
+    /// Unlike flags, this is not part of the metadata, so no new revision is created.
+    fn keep_revision<'a>(
+        &'a self,
+        id: &'a NoteID,
+        revision: &'a Revision,
+        keep: bool,
+    ) -> BoxFuture<'a, Result<(), NoteStoreError>>;
+    /// Get the revisions of a note marked with [`NoteStore::keep_revision`].
+    fn get_kept_revisions<'a>(
+        &'a self,
+        id: &'a NoteID,
+    ) -> BoxFuture<'a, Result<HashSet<Revision>, NoteStoreError>>;
+    /// Check the relationships between notes, which the store normally keeps consistent.
+    ///
+    /// Returns the issues found, and if `repair` is set, repairs them as described in
+    /// [`IntegrityIssue`].
+    /// Repairs that change a note create a new revision of it.
+    fn check_integrity(
+        &self,
+        repair: bool,
+    ) -> BoxFuture<'_, Result<Vec<IntegrityIssue>, NoteStoreError>>;
     /// Get all revisions of a note, in the order from older (smaller timestamp) to newer (larger
     /// timestamp).
     ///
@@ -97,6 +215,15 @@ where
         &'a self,
         loc: &'a NoteLocator,
     ) -> BoxFuture<'a, Result<Revisions<T>, NoteStoreError>>;
+    /// Compare two revisions of a note.
+    ///
+    /// Either revision can be older, and they can be the same, in which case nothing changed.
+    fn diff<'a>(
+        &'a self,
+        id: &'a NoteID,
+        from: &'a Revision,
+        to: &'a Revision,
+    ) -> BoxFuture<'a, Result<NoteDiff, NoteStoreError>>;
     /// Get the current revision of a note.
     ///
     /// No matter which variant of [`NoteLocator`] is used, we only care about the [`NoteID`].
@@ -130,14 +257,97 @@ where
         note_inner: T,
         metadata: NoteMetadataEditable,
     ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>>;
+    /// Move a note, along with its branches and the notes after it
+    ///
+    /// The note is detached from its parent or previous note first.
+    /// Moving a note under one of its branches, or after a note that comes after it, would form a
+    /// cycle and fails with [`NoteStoreError::Cycle`].
+    /// Returns the current revision of the moved note.
+    fn move_note<'a>(
+        &'a self,
+        id: &'a NoteID,
+        to: &'a MoveTarget,
+    ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>>;
+    /// Apply several operations in order, atomically.
+    ///
+    /// Either every operation succeeds, or nothing changes and the first error is returned.
+    /// Returns the current revision of the note of each operation, which is `None` for deletions.
+    /// Later operations can refer to these notes by the indices of the operations, see
+    /// [`NoteRef::Created`](batch::NoteRef::Created).
+    fn apply_batch(
+        &self,
+        operations: Vec<BatchOperation<T>>,
+    ) -> BoxFuture<'_, Result<Vec<Option<NoteLocator>>, NoteStoreError>>;
     /// Search for a note
+    ///
+    /// Archived notes are excluded unless asked for, and pinned notes come first.
     fn search<'a>(
         &'a self,
         sr: &'a SearchRequest,
     ) -> BoxFuture<'a, Result<Revisions<T>, NoteStoreError>>;
     /// List all known tags
+    #[allow(mismatched_lifetime_syntaxes)]
     fn tags(&self) -> BoxFuture<Result<Vec<String>, NoteStoreError>>;
+    /// Find tasks in the current revisions of all notes.
+    ///
+    /// Tasks are sorted by their due dates, and tasks without a due date come last.
+    fn tasks<'a>(
+        &'a self,
+        query: &'a TaskQuery,
+    ) -> BoxFuture<'a, Result<Vec<NoteTask>, NoteStoreError>>;
+    /// Toggle whether the task at `index` of a note is done.
+    ///
+    /// Like [`NoteStore::update_note`], this creates a new revision, and if a revision is
+    /// specified, that revision should be the current revision.
+    /// The new revision is made by `author`, if known.
+    fn toggle_task<'a>(
+        &'a self,
+        loc: &'a NoteLocator,
+        index: usize,
+        author: Option<String>,
+    ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>>;
+    /// Toggle a flag of a note, such as whether it is pinned.
+    ///
+    /// Like [`NoteStore::update_note`], this creates a new revision, and if a revision is
+    /// specified, that revision should be the current revision.
+    /// The new revision is made by `author`, if known.
+    fn toggle_flag<'a>(
+        &'a self,
+        loc: &'a NoteLocator,
+        flag: NoteFlag,
+        author: Option<String>,
+    ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>>;
+    /// Convert a note into another format, keeping the notes that it links to.
+    ///
+    /// Like [`NoteStore::update_note`], this creates a new revision, and if a revision is
+    /// specified, that revision should be the current revision.
+    /// The new revision is made by `author`, if known.
+    fn convert_note<'a>(
+        &'a self,
+        loc: &'a NoteLocator,
+        format: NoteFormat,
+        author: Option<String>,
+    ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>>;
+    /// Upgrade the metadata of the current revisions of all notes to the current schema version.
+    ///
+    /// Metadata is also upgraded whenever a note is read, but only this writes the upgraded
+    /// metadata back, in place rather than as new revisions.
+    /// Returns the number of revisions upgraded.
+    fn migrate_metadata(&self) -> BoxFuture<'_, Result<usize, NoteStoreError>>;
+    /// Get the JSON Schemas that custom metadata must conform to.
+    fn metadata_schemas(&self) -> BoxFuture<'_, Result<MetadataSchemas, NoteStoreError>>;
+    /// Set the JSON Schema of custom metadata for notes with a tag, or the default schema for all
+    /// notes if `tag` is `None`.
+    ///
+    /// Setting the schema to `None` removes it.
+    /// Existing notes are not validated against the new schema until their metadata is updated.
+    fn set_metadata_schema<'a>(
+        &'a self,
+        tag: Option<&'a str>,
+        schema: Option<serde_json::Value>,
+    ) -> BoxFuture<'a, Result<(), NoteStoreError>>;
     /// Backup the storage to a folder on some filesystem.
+    #[allow(mismatched_lifetime_syntaxes)]
     fn backup(&self, path: Box<dyn AsRef<Path> + Send>) -> BoxFuture<Result<(), NoteStoreError>>;
     /// Restore the storage from a folder on some filesystem.
     fn restore<P: AsRef<Path>>(path: P) -> Result<Self, NoteStoreError>
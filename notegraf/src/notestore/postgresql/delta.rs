@@ -0,0 +1,241 @@
+//! Encoding of the notes of non-current revisions.
+//!
+//! A non-current revision is stored either in full or as a delta against an older revision of
+//! the same note, its base.
+//! The first byte of the encoding holds flags, and the rest is the note or the delta, compressed
+//! if it is large.
+use crate::errors::NoteStoreError;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use similar::{DiffOp, TextDiff};
+use std::io::{Read, Write};
+
+/// The longest chain of deltas to follow to read a revision.
+///
+/// A revision whose base would be further away from a revision stored in full is stored in full.
+pub(super) const MAX_DELTA_DEPTH: usize = 16;
+/// Payloads at least this many bytes long are compressed.
+const COMPRESSION_THRESHOLD: usize = 1024;
+
+const FLAG_DELTA: u8 = 1;
+const FLAG_COMPRESSED: u8 = 2;
+/// A note in full converted by a migration, which is yet to be encoded against its base.
+pub(super) const FLAG_UNPACKED: u8 = 4;
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+enum DeltaOp {
+    /// Copy a range of bytes of the base.
+    Copy(usize, usize),
+    Insert(String),
+}
+
+/// A delta that turns `base` into `note_inner`.
+///
+/// The common prefix and suffix are copied as they are, so that notes stored on a single line,
+/// e.g., as JSON, still have compact deltas, and the rest is compared line by line.
+fn make_delta(base: &str, note_inner: &str) -> Vec<DeltaOp> {
+    let prefix = common_len(base.chars(), note_inner.chars());
+    let (base_rest, note_inner_rest) = (&base[prefix..], &note_inner[prefix..]);
+    let suffix = common_len(base_rest.chars().rev(), note_inner_rest.chars().rev());
+    let base_middle = &base_rest[..base_rest.len() - suffix];
+    let note_inner_middle = &note_inner_rest[..note_inner_rest.len() - suffix];
+    let diff = TextDiff::from_lines(base_middle, note_inner_middle);
+    let mut offsets = vec![prefix];
+    for line in diff.old_slices() {
+        offsets.push(offsets.last().unwrap() + line.len());
+    }
+    let new_lines = diff.new_slices();
+    let mut delta = vec![DeltaOp::Copy(0, prefix)];
+    for op in diff.ops() {
+        match *op {
+            DiffOp::Equal { old_index, len, .. } => {
+                delta.push(DeltaOp::Copy(offsets[old_index], offsets[old_index + len]))
+            }
+            DiffOp::Delete { .. } => {}
+            DiffOp::Insert {
+                new_index, new_len, ..
+            }
+            | DiffOp::Replace {
+                new_index, new_len, ..
+            } => delta.push(DeltaOp::Insert(
+                new_lines[new_index..new_index + new_len].concat(),
+            )),
+        }
+    }
+    delta.push(DeltaOp::Copy(base.len() - suffix, base.len()));
+    delta.retain(|op| !matches!(op, DeltaOp::Copy(start, end) if start == end));
+    delta
+}
+
+/// The length in bytes of the common start of two sequences of characters.
+fn common_len(a: impl Iterator<Item = char>, b: impl Iterator<Item = char>) -> usize {
+    a.zip(b)
+        .take_while(|(x, y)| x == y)
+        .map(|(x, _)| x.len_utf8())
+        .sum()
+}
+
+fn apply_delta(base: &str, delta: Vec<DeltaOp>) -> Result<String, NoteStoreError> {
+    let mut note_inner = String::new();
+    for op in delta {
+        match op {
+            DeltaOp::Copy(start, end) => note_inner.push_str(
+                base.get(start..end)
+                    .ok_or_else(|| corrupt("a delta copies beyond its base"))?,
+            ),
+            DeltaOp::Insert(s) => note_inner.push_str(&s),
+        }
+    }
+    Ok(note_inner)
+}
+
+fn corrupt(reason: &str) -> NoteStoreError {
+    NoteStoreError::ParseError(format!("Corrupt revision: {reason}"))
+}
+
+/// An encoded note of a revision.
+pub(super) struct Encoded {
+    pub(super) bytes: Vec<u8>,
+    /// Whether the note is encoded against the base, rather than in full.
+    pub(super) is_delta: bool,
+}
+
+/// Encode the note of a revision against a base, or in full if there is no base or the delta
+/// would be no smaller.
+pub(super) fn encode(note_inner: &str, base: Option<&str>) -> Result<Encoded, NoteStoreError> {
+    let mut flags = 0;
+    let mut payload = note_inner.as_bytes().to_vec();
+    if let Some(b) = base {
+        let delta = serde_json::to_vec(&make_delta(b, note_inner))?;
+        if delta.len() < payload.len() {
+            flags |= FLAG_DELTA;
+            payload = delta;
+        }
+    }
+    if payload.len() >= COMPRESSION_THRESHOLD {
+        let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+        encoder.write_all(&payload)?;
+        payload = encoder.finish()?;
+        flags |= FLAG_COMPRESSED;
+    }
+    let mut bytes = Vec::with_capacity(payload.len() + 1);
+    bytes.push(flags);
+    bytes.extend(payload);
+    Ok(Encoded {
+        bytes,
+        is_delta: flags & FLAG_DELTA != 0,
+    })
+}
+
+/// Whether an encoded note is a delta, which needs its base to be decoded.
+pub(super) fn is_delta(bytes: &[u8]) -> bool {
+    bytes.first().is_some_and(|flags| flags & FLAG_DELTA != 0)
+}
+
+/// Decode the note of a revision, given the note of its base if it is a delta.
+pub(super) fn decode(bytes: &[u8], base: Option<&str>) -> Result<String, NoteStoreError> {
+    let (flags, payload) = bytes
+        .split_first()
+        .ok_or_else(|| corrupt("the encoding is empty"))?;
+    let mut decompressed = vec![];
+    let payload = if flags & FLAG_COMPRESSED != 0 {
+        DeflateDecoder::new(payload)
+            .read_to_end(&mut decompressed)
+            .map_err(|e| corrupt(&e.to_string()))?;
+        &decompressed[..]
+    } else {
+        payload
+    };
+    if flags & FLAG_DELTA != 0 {
+        let delta: Vec<DeltaOp> =
+            serde_json::from_slice(payload).map_err(|e| corrupt(&e.to_string()))?;
+        apply_delta(base.ok_or_else(|| corrupt("a delta has no base"))?, delta)
+    } else {
+        String::from_utf8(payload.to_vec()).map_err(|e| corrupt(&e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(note_inner: &str, base: Option<&str>) -> Encoded {
+        let encoded = encode(note_inner, base).unwrap();
+        assert_eq!(decode(&encoded.bytes, base).unwrap(), note_inner);
+        encoded
+    }
+
+    #[test]
+    fn delta() {
+        let base = "# Title\n\nThe first paragraph, which is long enough to be worth copying.\n\
+                    The second paragraph, which is also long enough.\nThe end";
+        let note_inner = "# New title\n\nThe first paragraph, which is long enough to be worth \
+                          copying.\nThe end\nMore";
+        let encoded = roundtrip(note_inner, Some(base));
+        assert!(encoded.is_delta);
+        assert!(is_delta(&encoded.bytes));
+        assert!(encoded.bytes.len() < note_inner.len());
+        assert!(matches!(
+            decode(&encoded.bytes, None),
+            Err(NoteStoreError::ParseError(_))
+        ));
+        // Copying from a shorter base is caught
+        assert!(matches!(
+            decode(&encoded.bytes, Some("# Title")),
+            Err(NoteStoreError::ParseError(_))
+        ));
+    }
+
+    #[test]
+    fn single_line() {
+        let base = r#"{"body":"Some text that is long enough to be worth copying","referents":[]}"#;
+        let note_inner =
+            r#"{"body":"Some more text that is long enough to be worth copying","referents":[]}"#;
+        let encoded = roundtrip(note_inner, Some(base));
+        assert!(encoded.is_delta);
+        assert!(encoded.bytes.len() < note_inner.len());
+        // Prefixes and suffixes are not split within characters
+        roundtrip("aé€x", Some("aè€x"));
+        roundtrip("€€", Some("€"));
+    }
+
+    #[test]
+    fn full() {
+        let encoded = roundtrip("Foo", None);
+        assert!(!encoded.is_delta);
+        // The delta would be no smaller
+        assert!(!roundtrip("Bar", Some("Foo")).is_delta);
+        assert!(!roundtrip("", Some("")).is_delta);
+        assert!(
+            roundtrip("Unicode: 你好\r\nÀ", Some("Unicode: 你好\r\n"))
+                .bytes
+                .len()
+                > 1
+        );
+    }
+
+    #[test]
+    fn compressed() {
+        let note_inner = "Lorem ipsum dolor sit amet.\n".repeat(1000);
+        let encoded = roundtrip(&note_inner, None);
+        assert_ne!(encoded.bytes[0] & FLAG_COMPRESSED, 0);
+        assert!(encoded.bytes.len() < note_inner.len() / 10);
+        let edited = format!("{note_inner}One more line\n");
+        let encoded = roundtrip(&edited, Some(&note_inner));
+        assert!(encoded.is_delta);
+    }
+
+    #[test]
+    fn unpacked() {
+        let mut bytes = vec![FLAG_UNPACKED];
+        bytes.extend("Foo".as_bytes());
+        assert!(!is_delta(&bytes));
+        assert_eq!(decode(&bytes, None).unwrap(), "Foo");
+        assert!(matches!(
+            decode(&[], None),
+            Err(NoteStoreError::ParseError(_))
+        ));
+    }
+}
@@ -1,18 +1,36 @@
 use crate::errors::NoteStoreError;
-use crate::notemetadata::{NoteMetadata, NoteMetadataEditable};
+use crate::notemetadata::{NoteFlag, NoteMetadata, NoteMetadataEditable};
+use crate::notestore::alias::{check_aliases, AliasTarget};
+use crate::notestore::batch::BatchOperation;
+use crate::notestore::compaction::CompactionPolicy;
+use crate::notestore::convert::convert_note_inner;
+use crate::notestore::delete::{DeleteOptions, DeleteReport};
+use crate::notestore::diff::{diff_notes, NoteDiff};
+use crate::notestore::front_matter::sync_front_matter;
+use crate::notestore::integrity::IntegrityIssue;
+use crate::notestore::schema::{check_schema, MetadataSchemas};
+use crate::notestore::structure::MoveTarget;
+use crate::notestore::trash::DeletedNote;
 use crate::notestore::Revisions;
+use crate::notetype::NoteFormat;
+use crate::url::find_revision_links;
 use crate::{Note, NoteID, NoteLocator, NoteStore, NoteType, Revision};
+use chrono::{DateTime, Utc};
 use futures::future::BoxFuture;
 use sqlx::postgres::PgConnectOptions;
 use sqlx::{query, PgPool, Postgres, Transaction};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
 use std::marker::PhantomData;
 use std::ops::DerefMut;
 use std::path::Path;
+use std::time::Duration;
 use uuid::Uuid;
 
+mod delta;
 mod queries;
 use crate::notestore::search::SearchRequest;
+use crate::notestore::task::{self, NoteTask, TaskQuery};
 use queries::*;
 
 #[cfg(test)]
@@ -22,12 +40,60 @@ fn get_new_noteid() -> Uuid {
     Uuid::new_v4()
 }
 
+fn is_note_id(s: &str) -> bool {
+    Uuid::parse_str(s).is_ok()
+}
+
 fn get_new_revision() -> Uuid {
     Uuid::new_v4()
 }
 
+/// How many times an operation is retried after a serialization failure.
+const MAX_RETRIES: u32 = 8;
+/// How long to wait before the first retry, which doubles with each retry.
+const RETRY_DELAY: Duration = Duration::from_millis(5);
+
+/// Whether PostgreSQL aborted a transaction because of concurrent transactions, in which case
+/// the transaction can be run again.
+fn is_serialization_failure(e: &NoteStoreError) -> bool {
+    match e {
+        NoteStoreError::PostgreSQLError(sqlx::Error::Database(e)) => {
+            e.code().as_deref() == Some("40001")
+        }
+        _ => false,
+    }
+}
+
+/// Run an operation, which begins and commits its own transaction, again after a serialization
+/// failure.
+///
+/// All transactions are serializable, so concurrent transactions on the same notes can fail
+/// this way.
+/// The delays between the attempts grow exponentially, with random jitter so that the
+/// transactions that failed together don't run together again.
+async fn with_retries<R, F, Fut>(mut op: F) -> Result<R, NoteStoreError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<R, NoteStoreError>>,
+{
+    let mut retries = 0;
+    loop {
+        // The result is dropped before waiting, as it might not be `Send`
+        match op().await {
+            Err(e) if retries < MAX_RETRIES && is_serialization_failure(&e) => {}
+            res => return res,
+        }
+        let jitter = (Uuid::new_v4().as_u128() % 1000) as f64 / 1000.0;
+        let delay = (RETRY_DELAY * 2u32.pow(retries)).mul_f64(1.0 + jitter);
+        retries += 1;
+        debug!("Serialization failure, retrying in {:?}", delay);
+        tokio::time::sleep(delay).await;
+    }
+}
+
 pub struct PostgreSQLStoreBuilder<T> {
     db_options: PgConnectOptions,
+    front_matter_sync: bool,
     _phantom: PhantomData<T>,
 }
 
@@ -43,6 +109,7 @@ struct PostgreSQLNote<T> {
     next: Option<NoteID>,
     referents: HashSet<NoteID>,
     references: HashSet<NoteID>,
+    inline_tags: HashSet<String>,
     metadata: NoteMetadata,
     is_current: bool,
 }
@@ -91,6 +158,10 @@ where
         self.referents.clone()
     }
 
+    fn get_inline_tags(&self) -> HashSet<String> {
+        self.inline_tags.clone()
+    }
+
     fn get_metadata(&self) -> NoteMetadata {
         self.metadata.clone()
     }
@@ -104,10 +175,18 @@ impl<T: NoteType> PostgreSQLStoreBuilder<T> {
     pub fn new(db_options: PgConnectOptions) -> Self {
         Self {
             db_options,
+            front_matter_sync: false,
             _phantom: PhantomData,
         }
     }
 
+    /// Synchronize the front matter of notes with their title and metadata when they are
+    /// created or updated.
+    pub fn front_matter_sync(mut self, enabled: bool) -> Self {
+        self.front_matter_sync = enabled;
+        self
+    }
+
     pub async fn build(self) -> PostgreSQLStore<T> {
         let connection_pool = PgPool::connect_with(self.db_options)
             .await
@@ -116,8 +195,39 @@ impl<T: NoteType> PostgreSQLStoreBuilder<T> {
             .run(&connection_pool)
             .await
             .expect("Failed to migrate the database");
+        // Finish converting the revisions stored before delta compression
+        let mut transaction = connection_pool
+            .begin()
+            .await
+            .expect("Failed to connect to Postgres.");
+        let packed = pack_revisions(&mut transaction)
+            .await
+            .expect("Failed to compress old revisions");
+        transaction
+            .commit()
+            .await
+            .expect("Failed to compress old revisions");
+        if packed > 0 {
+            info!("Compressed {} old revisions", packed);
+        }
+        // Extract what the migrations cannot from the notes stored before they ran
+        let mut transaction = connection_pool
+            .begin()
+            .await
+            .expect("Failed to connect to Postgres.");
+        let reindexed = reindex_revisions::<T>(&mut transaction, self.front_matter_sync)
+            .await
+            .expect("Failed to reindex old revisions");
+        transaction
+            .commit()
+            .await
+            .expect("Failed to reindex old revisions");
+        if reindexed > 0 {
+            info!("Reindexed {} old revisions", reindexed);
+        }
         PostgreSQLStore {
             db_pool: connection_pool,
+            front_matter_sync: self.front_matter_sync,
             _phantom: PhantomData,
         }
     }
@@ -125,6 +235,7 @@ impl<T: NoteType> PostgreSQLStoreBuilder<T> {
 
 pub struct PostgreSQLStore<T> {
     db_pool: PgPool,
+    front_matter_sync: bool,
     _phantom: PhantomData<T>,
 }
 
@@ -132,12 +243,25 @@ impl<T: NoteType> PostgreSQLStore<T> {
     async fn new_note_helper(
         &self,
         transaction: &mut Transaction<'_, Postgres>,
-        title: String,
-        note_inner: T,
+        mut title: String,
+        mut note_inner: T,
         prev: Option<Uuid>,
         parent: Option<Uuid>,
         metadata: NoteMetadataEditable,
     ) -> Result<NoteLocator, NoteStoreError> {
+        let mut metadata = NoteMetadata::from_editable(metadata);
+        if self.front_matter_sync {
+            sync_front_matter(&mut title, &mut note_inner, &mut metadata, true)?;
+        }
+        get_metadata_schemas(transaction)
+            .await?
+            .validate(&metadata)?;
+        check_aliases(
+            None,
+            &metadata.aliases,
+            &get_alias_owners(transaction).await?,
+            is_note_id,
+        )?;
         let id = get_new_noteid();
         let revision = get_new_revision();
         // reborrowing hack to prevent transaction from moving
@@ -151,43 +275,138 @@ impl<T: NoteType> PostgreSQLStore<T> {
             note_inner,
             prev,
             parent,
-            metadata: NoteMetadata::from_editable(metadata),
+            metadata,
         };
         insert_revision(transaction, n).await?;
         upsert_current_revision(transaction, id, revision).await?;
         Ok(NoteLocator::Specific(id.into(), revision.into()))
     }
+
+    /// Append a note within a transaction, see [`NoteStore::append_note`].
+    async fn append_note_helper(
+        &self,
+        transaction: &mut Transaction<'_, Postgres>,
+        last: &NoteID,
+        title: String,
+        note_inner: T,
+        metadata: NoteMetadataEditable,
+    ) -> Result<NoteLocator, NoteStoreError> {
+        let last_note: PostgreSQLNote<T> =
+            get_note_by_loc(transaction, &NoteLocator::Current(last.clone()))
+                .await?
+                .into_note(self.front_matter_sync)?;
+        if let Some(n) = last_note.get_next() {
+            return Err(NoteStoreError::ExistingNext(last.clone(), n));
+        }
+        let last_uuid = last.try_to_uuid()?;
+        self.new_note_helper(
+            transaction,
+            title,
+            note_inner,
+            Some(last_uuid),
+            None,
+            metadata,
+        )
+        .await
+    }
+
+    /// Update a note within a transaction, see [`NoteStore::update_note`].
+    async fn edit_note(
+        &self,
+        transaction: &mut Transaction<'_, Postgres>,
+        loc: &NoteLocator,
+        title: Option<String>,
+        note_inner: Option<T>,
+        note_metadata: NoteMetadataEditable,
+    ) -> Result<NoteLocator, NoteStoreError> {
+        let metadata_schemas = get_metadata_schemas(transaction).await?;
+        let alias_owners = get_alias_owners(transaction).await?;
+        update_note_helper(transaction, loc, self.front_matter_sync, |old_note| {
+            let mut note = old_note.clone();
+            if let Some(t) = title {
+                note.title = t;
+            }
+            let body_edited = note_inner.is_some();
+            if let Some(n) = note_inner {
+                note.note_inner = n;
+            }
+
+            note.metadata = note.metadata.apply_editable(note_metadata);
+            if self.front_matter_sync {
+                sync_front_matter(
+                    &mut note.title,
+                    &mut note.note_inner,
+                    &mut note.metadata,
+                    body_edited,
+                )?;
+            }
+            if note.metadata.tags != old_note.metadata.tags
+                || note.metadata.custom_metadata != old_note.metadata.custom_metadata
+            {
+                metadata_schemas.validate(&note.metadata)?;
+            }
+            check_aliases(
+                Some(&note.id.into()),
+                &note.metadata.aliases,
+                &alias_owners,
+                is_note_id,
+            )?;
+            Ok(note)
+        })
+        .await
+    }
 }
 
 impl<T: NoteType> NoteStore<T> for PostgreSQLStore<T> {
+    #[allow(mismatched_lifetime_syntaxes)]
     fn new_note(
         &self,
         title: String,
         note_inner: T,
         metadata: NoteMetadataEditable,
     ) -> BoxFuture<Result<NoteLocator, NoteStoreError>> {
-        Box::pin(async move {
-            let mut transaction = self.db_pool.begin().await?;
-            read_write(&mut transaction).await?;
-            let loc = self
-                .new_note_helper(&mut transaction, title, note_inner, None, None, metadata)
-                .await;
-            transaction.commit().await?;
-            loc
-        })
+        Box::pin(with_retries(move || {
+            let title = title.clone();
+            let note_inner = note_inner.clone();
+            let metadata = metadata.clone();
+            async move {
+                let mut transaction = self.db_pool.begin().await?;
+                read_write(&mut transaction).await?;
+                let loc = self
+                    .new_note_helper(&mut transaction, title, note_inner, None, None, metadata)
+                    .await?;
+                transaction.commit().await?;
+                Ok(loc)
+            }
+        }))
     }
 
     fn get_note<'a>(
         &'a self,
         loc: &'a NoteLocator,
     ) -> BoxFuture<'a, Result<Box<dyn Note<T>>, NoteStoreError>> {
-        Box::pin(async move {
+        Box::pin(with_retries(move || async move {
             let mut transaction = self.db_pool.begin().await?;
             read_only(&mut transaction).await?;
-            let note: PostgreSQLNote<T> = get_note_by_loc(&mut transaction, loc).await?.into_note();
+            let note: PostgreSQLNote<T> = get_note_by_loc(&mut transaction, loc)
+                .await?
+                .into_note(self.front_matter_sync)?;
             transaction.commit().await?;
             Ok(Box::new(note) as Box<dyn Note<T>>)
-        })
+        }))
+    }
+
+    fn lookup_alias<'a>(
+        &'a self,
+        alias: &'a str,
+    ) -> BoxFuture<'a, Result<AliasTarget, NoteStoreError>> {
+        Box::pin(with_retries(move || async move {
+            let mut transaction = self.db_pool.begin().await?;
+            read_only(&mut transaction).await?;
+            let target = lookup_alias(&mut transaction, alias).await;
+            transaction.commit().await?;
+            target
+        }))
     }
 
     fn update_note<'a>(
@@ -197,96 +416,374 @@ impl<T: NoteType> NoteStore<T> for PostgreSQLStore<T> {
         note_inner: Option<T>,
         note_metadata: NoteMetadataEditable,
     ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>> {
-        Box::pin(async move {
+        Box::pin(with_retries(move || {
+            let title = title.clone();
+            let note_inner = note_inner.clone();
+            let note_metadata = note_metadata.clone();
+            async move {
+                let mut transaction = self.db_pool.begin().await?;
+                read_write(&mut transaction).await?;
+                let new_loc = self
+                    .edit_note(&mut transaction, loc, title, note_inner, note_metadata)
+                    .await?;
+                transaction.commit().await?;
+                Ok(new_loc)
+            }
+        }))
+    }
+
+    fn revert_note<'a>(
+        &'a self,
+        loc: &'a NoteLocator,
+        to: &'a Revision,
+        author: Option<String>,
+    ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>> {
+        Box::pin(with_retries(move || {
+            let author = author.clone();
+            async move {
+                let mut transaction = self.db_pool.begin().await?;
+                read_write(&mut transaction).await?;
+                let target: PostgreSQLNote<T> = get_note_by_loc(
+                    &mut transaction,
+                    &NoteLocator::Specific(loc.get_id().clone(), to.clone()),
+                )
+                .await?
+                .into_note(self.front_matter_sync)?;
+                let new_loc = self
+                    .edit_note(
+                        &mut transaction,
+                        loc,
+                        Some(target.title),
+                        Some(target.note_inner),
+                        NoteMetadataEditable {
+                            tags: Some(target.metadata.tags),
+                            aliases: Some(target.metadata.aliases),
+                            custom_metadata: Some(target.metadata.custom_metadata),
+                            author,
+                        },
+                    )
+                    .await?;
+                transaction.commit().await?;
+                Ok(new_loc)
+            }
+        }))
+    }
+
+    fn delete_note<'a>(
+        &'a self,
+        loc: &'a NoteLocator,
+    ) -> BoxFuture<'a, Result<(), NoteStoreError>> {
+        Box::pin(with_retries(move || async move {
             let mut transaction = self.db_pool.begin().await?;
             read_write(&mut transaction).await?;
-            let new_loc = update_note_helper(&mut transaction, loc, |old_note| {
-                let mut note = old_note.clone();
-                if let Some(t) = title {
-                    note.title = t;
+            delete_note_helper::<T>(
+                &mut transaction,
+                loc,
+                &HashSet::new(),
+                self.front_matter_sync,
+            )
+            .await?;
+            transaction.commit().await?;
+            Ok(())
+        }))
+    }
+
+    fn delete_note_with<'a>(
+        &'a self,
+        loc: &'a NoteLocator,
+        options: &'a DeleteOptions,
+        author: Option<String>,
+    ) -> BoxFuture<'a, Result<DeleteReport, NoteStoreError>> {
+        Box::pin(with_retries(move || {
+            let author = author.clone();
+            async move {
+                let mut transaction = self.db_pool.begin().await?;
+                read_write(&mut transaction).await?;
+                let (id, rev) = loc.unpack();
+                if !is_current(&mut transaction, loc).await? {
+                    return Err(NoteStoreError::DeleteOldRevision(
+                        id.clone(),
+                        rev.unwrap().clone(),
+                    ));
+                }
+                // Parents and previous notes come before their branches and next notes
+                let mut deleted = vec![id.clone()];
+                let mut references = vec![];
+                let mut i = 0;
+                while i < deleted.len() {
+                    let note: PostgreSQLNote<T> = get_note_by_loc(
+                        &mut transaction,
+                        &NoteLocator::Current(deleted[i].clone()),
+                    )
+                    .await?
+                    .into_note(self.front_matter_sync)?;
+                    references.push(note.references);
+                    if !options.cascade {
+                        if !note.branches.is_empty() {
+                            return Err(NoteStoreError::HasBranches(id.clone()));
+                        }
+                        break;
+                    }
+                    for child in note.branches.into_iter().chain(note.next) {
+                        if !deleted.contains(&child) {
+                            deleted.push(child);
+                        }
+                    }
+                    i += 1;
                 }
-                if let Some(n) = note_inner {
-                    note.note_inner = n;
+                let deleted_with: HashSet<NoteID> = deleted.iter().cloned().collect();
+                let mut referrers: HashMap<NoteID, Vec<NoteID>> = HashMap::new();
+                for (referent, references) in deleted.iter().zip(references) {
+                    for referrer in references {
+                        if deleted_with.contains(&referrer) {
+                            continue;
+                        }
+                        if options.rewrite_references.is_none() {
+                            return Err(NoteStoreError::HasReferences(referent.clone()));
+                        }
+                        referrers
+                            .entry(referrer)
+                            .or_default()
+                            .push(referent.clone());
+                    }
+                }
+                let mut rewritten: Vec<(NoteID, Vec<NoteID>)> = referrers.into_iter().collect();
+                rewritten.sort_by(|(a, _), (b, _)| a.as_ref().cmp(b.as_ref()));
+                let report = DeleteReport {
+                    deleted: deleted.clone(),
+                    rewritten: rewritten.iter().map(|(id, _)| id.clone()).collect(),
+                };
+                if options.dry_run {
+                    // Nothing is changed, so the transaction is simply dropped
+                    return Ok(report);
+                }
+                if let Some(rewrite) = options.rewrite_references {
+                    for (referrer, referents) in rewritten {
+                        update_note_helper(
+                            &mut transaction,
+                            &NoteLocator::Current(referrer),
+                            self.front_matter_sync,
+                            |old_note| {
+                                let mut note: PostgreSQLNoteEditable<T> = old_note.clone();
+                                for referent in referents {
+                                    note.note_inner
+                                        .remove_referent(referent, rewrite)
+                                        .map_err(NoteStoreError::note_inner)?;
+                                }
+                                note.metadata.author = author.clone();
+                                Ok(note)
+                            },
+                        )
+                        .await?;
+                    }
                 }
+                for id in deleted.iter().rev() {
+                    delete_note_helper::<T>(
+                        &mut transaction,
+                        &NoteLocator::Current(id.clone()),
+                        &deleted_with,
+                        self.front_matter_sync,
+                    )
+                    .await?;
+                }
+                transaction.commit().await?;
+                Ok(report)
+            }
+        }))
+    }
 
-                note.metadata = note.metadata.apply_editable(note_metadata);
-                Ok(note)
-            })
-            .await?;
+    fn list_deleted(&self) -> BoxFuture<'_, Result<Vec<DeletedNote<T>>, NoteStoreError>> {
+        Box::pin(with_retries(move || async move {
+            let mut transaction = self.db_pool.begin().await?;
+            read_only(&mut transaction).await?;
+            let mut deleted = vec![];
+            for (id, deleted_at) in get_deleted(&mut transaction).await? {
+                let note: PostgreSQLNote<T> = get_revisions(&mut transaction, id)
+                    .await?
+                    .pop()
+                    .ok_or_else(|| NoteStoreError::NoteNotExist(id.into()))?
+                    .into_note(self.front_matter_sync)?;
+                deleted.push((note, deleted_at));
+            }
             transaction.commit().await?;
-            Ok(new_loc)
-        })
+            Ok(deleted
+                .into_iter()
+                .map(|(note, deleted_at)| DeletedNote {
+                    note: Box::new(note) as Box<dyn Note<T>>,
+                    deleted_at,
+                })
+                .collect())
+        }))
     }
 
-    fn delete_note<'a>(
+    fn purge_note<'a>(&'a self, id: &'a NoteID) -> BoxFuture<'a, Result<(), NoteStoreError>> {
+        Box::pin(with_retries(move || async move {
+            let mut transaction = self.db_pool.begin().await?;
+            read_write(&mut transaction).await?;
+            purge_note(&mut transaction, id.try_to_uuid()?).await?;
+            transaction.commit().await?;
+            Ok(())
+        }))
+    }
+
+    fn purge_deleted(
+        &self,
+        before: DateTime<Utc>,
+    ) -> BoxFuture<'_, Result<Vec<NoteID>, NoteStoreError>> {
+        Box::pin(with_retries(move || async move {
+            let mut transaction = self.db_pool.begin().await?;
+            read_write(&mut transaction).await?;
+            let mut purged = vec![];
+            for (id, deleted_at) in get_deleted(&mut transaction).await? {
+                if deleted_at >= before {
+                    continue;
+                }
+                // The checks come before any change, so the transaction can carry on
+                match purge_note(&mut transaction, id).await {
+                    Ok(()) => purged.push(id.into()),
+                    Err(NoteStoreError::HasReferences(_))
+                    | Err(NoteStoreError::HasBranches(_))
+                    | Err(NoteStoreError::HasNext(_)) => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            transaction.commit().await?;
+            Ok(purged)
+        }))
+    }
+
+    fn compact_revisions<'a>(
         &'a self,
-        loc: &'a NoteLocator,
-    ) -> BoxFuture<'a, Result<(), NoteStoreError>> {
-        Box::pin(async move {
+        policy: &'a CompactionPolicy,
+    ) -> BoxFuture<'a, Result<usize, NoteStoreError>> {
+        Box::pin(with_retries(move || async move {
             let mut transaction = self.db_pool.begin().await?;
             read_write(&mut transaction).await?;
-            let (id, rev) = loc.unpack();
-            if !is_current(&mut transaction, loc).await? {
-                return Err(NoteStoreError::DeleteOldRevision(
-                    id.clone(),
-                    rev.unwrap().clone(),
-                ));
+            let now = Utc::now();
+            let mut linked = HashSet::new();
+            for note_inner in get_current_note_inners(&mut transaction).await? {
+                linked.extend(find_revision_links(&note_inner));
             }
-            let note: PostgreSQLNote<T> = get_note_by_loc(&mut transaction, loc).await?.into_note();
-            if !note.branches.is_empty() {
-                return Err(NoteStoreError::HasBranches(id.clone()));
+            let mut removed = vec![];
+            for (id, mut revisions) in get_revision_ages(&mut transaction).await? {
+                let id: NoteID = id.into();
+                for r in revisions.iter_mut() {
+                    r.keep |= linked.contains(&(id.clone(), r.revision.clone()));
+                }
+                for revision in policy.revisions_to_remove(revisions, now) {
+                    removed.push(revision.try_to_uuid()?);
+                }
             }
-            if !note.references.is_empty() {
-                return Err(NoteStoreError::HasReferences(id.clone()));
+            delete_revisions(&mut transaction, &removed).await?;
+            transaction.commit().await?;
+            Ok(removed.len())
+        }))
+    }
+
+    fn keep_revision<'a>(
+        &'a self,
+        id: &'a NoteID,
+        revision: &'a Revision,
+        keep: bool,
+    ) -> BoxFuture<'a, Result<(), NoteStoreError>> {
+        Box::pin(with_retries(move || async move {
+            let mut transaction = self.db_pool.begin().await?;
+            read_write(&mut transaction).await?;
+            set_revision_kept(
+                &mut transaction,
+                id.try_to_uuid()?,
+                revision.try_to_uuid()?,
+                keep,
+            )
+            .await?;
+            transaction.commit().await?;
+            Ok(())
+        }))
+    }
+
+    fn get_kept_revisions<'a>(
+        &'a self,
+        id: &'a NoteID,
+    ) -> BoxFuture<'a, Result<HashSet<Revision>, NoteStoreError>> {
+        Box::pin(with_retries(move || async move {
+            let mut transaction = self.db_pool.begin().await?;
+            read_only(&mut transaction).await?;
+            let kept = get_kept_revisions(&mut transaction, id.try_to_uuid()?).await?;
+            transaction.commit().await?;
+            Ok(kept)
+        }))
+    }
+
+    fn check_integrity(
+        &self,
+        repair: bool,
+    ) -> BoxFuture<'_, Result<Vec<IntegrityIssue>, NoteStoreError>> {
+        Box::pin(with_retries(move || async move {
+            let mut transaction = self.db_pool.begin().await?;
+            if repair {
+                read_write(&mut transaction).await?;
+            } else {
+                read_only(&mut transaction).await?;
             }
-            // This note was created by branching out from some other note
-            // It's not possible to be in the middle of a note sequence
-            // And vice versa
-            assert!(note.prev.is_none() || note.parent.is_none());
-            // Since only prev is stored, our prev note is not aware of us
-            // But we want to make sure our next note is consistent
-            // The next note basically inherits our prev and parent
-            if note.next.is_some() {
-                update_note_helper::<_, T>(
-                    &mut transaction,
-                    &NoteLocator::Current(note.next.unwrap()),
-                    |old_note| {
-                        let mut new_note = old_note.clone();
-                        // Thw below two lines shouldn't both have effects
-                        // See the above assertion
-                        new_note.prev = note.prev.map(|x| x.to_uuid().unwrap());
-                        new_note.parent = note.parent.map(|x| x.to_uuid().unwrap());
-                        Ok(new_note)
-                    },
-                )
-                .await?;
+            let (issues, repairs) = get_note_graph(&mut transaction).await?.check();
+            if repair {
+                for r in repairs {
+                    queries::repair::<T>(&mut transaction, r, self.front_matter_sync).await?;
+                }
             }
-            delete_revision(transaction, loc).await
-        })
+            transaction.commit().await?;
+            Ok(issues)
+        }))
     }
 
     fn get_revisions<'a>(
         &'a self,
         loc: &'a NoteLocator,
     ) -> BoxFuture<'a, Result<Revisions<T>, NoteStoreError>> {
-        Box::pin(async move {
+        Box::pin(with_retries(move || async move {
             let mut transaction = self.db_pool.begin().await?;
             read_only(&mut transaction).await?;
             let notes: Vec<PostgreSQLNoteRowJoined> =
                 get_revisions(&mut transaction, loc.get_id().try_to_uuid()?).await?;
             transaction.commit().await?;
-            Ok(notes
+            notes
                 .into_iter()
-                .map(|n| Box::new(n.into_note()) as Box<dyn Note<T>>)
-                .collect())
-        })
+                .map(|n| Ok(Box::new(n.into_note(self.front_matter_sync)?) as Box<dyn Note<T>>))
+                .collect()
+        }))
+    }
+
+    fn diff<'a>(
+        &'a self,
+        id: &'a NoteID,
+        from: &'a Revision,
+        to: &'a Revision,
+    ) -> BoxFuture<'a, Result<NoteDiff, NoteStoreError>> {
+        Box::pin(with_retries(move || async move {
+            let mut transaction = self.db_pool.begin().await?;
+            read_only(&mut transaction).await?;
+            let from: PostgreSQLNote<T> = get_note_by_loc(
+                &mut transaction,
+                &NoteLocator::Specific(id.clone(), from.clone()),
+            )
+            .await?
+            .into_note(self.front_matter_sync)?;
+            let to: PostgreSQLNote<T> = get_note_by_loc(
+                &mut transaction,
+                &NoteLocator::Specific(id.clone(), to.clone()),
+            )
+            .await?
+            .into_note(self.front_matter_sync)?;
+            transaction.commit().await?;
+            diff_notes(&from, &to)
+        }))
     }
 
     fn get_current_revision<'a>(
         &'a self,
         loc: &'a NoteLocator,
     ) -> BoxFuture<'a, Result<Option<Revision>, NoteStoreError>> {
-        Box::pin(async move {
+        Box::pin(with_retries(move || async move {
             let id = loc.get_id().try_to_uuid()?;
             let mut transaction = self.db_pool.begin().await?;
             read_only(&mut transaction).await?;
@@ -317,7 +814,7 @@ impl<T: NoteType> NoteStore<T> for PostgreSQLStore<T> {
                     }
                 }
             }
-        })
+        }))
     }
 
     fn append_note<'a>(
@@ -327,32 +824,20 @@ impl<T: NoteType> NoteStore<T> for PostgreSQLStore<T> {
         note_inner: T,
         metadata: NoteMetadataEditable,
     ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>> {
-        Box::pin(async move {
-            let mut transaction = self.db_pool.begin().await?;
-            read_write(&mut transaction).await?;
-            let last_note: PostgreSQLNote<T> =
-                get_note_by_loc(&mut transaction, &NoteLocator::Current(last.clone()))
-                    .await?
-                    .into_note();
-            let last_note_next = last_note.get_next();
-            if let Some(n) = last_note_next {
-                transaction.rollback().await?;
-                return Err(NoteStoreError::ExistingNext(last.clone(), n));
+        Box::pin(with_retries(move || {
+            let title = title.clone();
+            let note_inner = note_inner.clone();
+            let metadata = metadata.clone();
+            async move {
+                let mut transaction = self.db_pool.begin().await?;
+                read_write(&mut transaction).await?;
+                let loc = self
+                    .append_note_helper(&mut transaction, last, title, note_inner, metadata)
+                    .await?;
+                transaction.commit().await?;
+                Ok(loc)
             }
-            let last_uuid = last.try_to_uuid()?;
-            let loc = self
-                .new_note_helper(
-                    &mut transaction,
-                    title,
-                    note_inner,
-                    Some(last_uuid),
-                    None,
-                    metadata,
-                )
-                .await?;
-            transaction.commit().await?;
-            Ok(loc)
-        })
+        }))
     }
 
     fn add_branch<'a>(
@@ -362,51 +847,337 @@ impl<T: NoteType> NoteStore<T> for PostgreSQLStore<T> {
         note_inner: T,
         metadata: NoteMetadataEditable,
     ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>> {
-        Box::pin(async move {
+        Box::pin(with_retries(move || {
+            let title = title.clone();
+            let note_inner = note_inner.clone();
+            let metadata = metadata.clone();
+            async move {
+                let mut transaction = self.db_pool.begin().await?;
+                read_write(&mut transaction).await?;
+                let parent_uuid = parent.try_to_uuid()?;
+                let loc = self
+                    .new_note_helper(
+                        &mut transaction,
+                        title,
+                        note_inner,
+                        None,
+                        Some(parent_uuid),
+                        metadata,
+                    )
+                    .await?;
+                transaction.commit().await?;
+                Ok(loc)
+            }
+        }))
+    }
+
+    fn move_note<'a>(
+        &'a self,
+        id: &'a NoteID,
+        to: &'a MoveTarget,
+    ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>> {
+        Box::pin(with_retries(move || async move {
             let mut transaction = self.db_pool.begin().await?;
             read_write(&mut transaction).await?;
-            let parent_uuid = parent.try_to_uuid()?;
-            let loc = self
-                .new_note_helper(
-                    &mut transaction,
-                    title,
-                    note_inner,
-                    None,
-                    Some(parent_uuid),
-                    metadata,
-                )
-                .await?;
+            let loc =
+                move_note_helper::<T>(&mut transaction, id, to, self.front_matter_sync).await?;
             transaction.commit().await?;
             Ok(loc)
-        })
+        }))
+    }
+
+    fn apply_batch(
+        &self,
+        operations: Vec<BatchOperation<T>>,
+    ) -> BoxFuture<'_, Result<Vec<Option<NoteLocator>>, NoteStoreError>> {
+        Box::pin(with_retries(move || {
+            let operations = operations.clone();
+            async move {
+                let mut transaction = self.db_pool.begin().await?;
+                read_write(&mut transaction).await?;
+                let mut results = vec![];
+                for operation in operations {
+                    let result = match operation {
+                        BatchOperation::New {
+                            title,
+                            note_inner,
+                            metadata,
+                        } => Some(
+                            self.new_note_helper(
+                                &mut transaction,
+                                title,
+                                note_inner,
+                                None,
+                                None,
+                                metadata,
+                            )
+                            .await?,
+                        ),
+                        BatchOperation::Append {
+                            last,
+                            title,
+                            note_inner,
+                            metadata,
+                        } => {
+                            let last = last.resolve(&results)?;
+                            Some(
+                                self.append_note_helper(
+                                    &mut transaction,
+                                    &last,
+                                    title,
+                                    note_inner,
+                                    metadata,
+                                )
+                                .await?,
+                            )
+                        }
+                        BatchOperation::Branch {
+                            parent,
+                            title,
+                            note_inner,
+                            metadata,
+                        } => {
+                            let parent = parent.resolve(&results)?.try_to_uuid()?;
+                            Some(
+                                self.new_note_helper(
+                                    &mut transaction,
+                                    title,
+                                    note_inner,
+                                    None,
+                                    Some(parent),
+                                    metadata,
+                                )
+                                .await?,
+                            )
+                        }
+                        BatchOperation::Update {
+                            note,
+                            title,
+                            note_inner,
+                            metadata,
+                        } => {
+                            let loc = NoteLocator::Current(note.resolve(&results)?);
+                            Some(
+                                self.edit_note(&mut transaction, &loc, title, note_inner, metadata)
+                                    .await?,
+                            )
+                        }
+                        BatchOperation::UpdateSource {
+                            note,
+                            title,
+                            note_inner,
+                            metadata,
+                        } => {
+                            let loc = NoteLocator::Current(note.resolve(&results)?);
+                            let current: PostgreSQLNote<T> =
+                                get_note_by_loc(&mut transaction, &loc)
+                                    .await?
+                                    .into_note(self.front_matter_sync)?;
+                            let format = current.get_note_inner().get_format();
+                            let note_inner = T::parse(Some(format), note_inner)
+                                .map_err(NoteStoreError::note_inner)?;
+                            Some(
+                                self.edit_note(
+                                    &mut transaction,
+                                    &loc,
+                                    title,
+                                    Some(note_inner),
+                                    metadata,
+                                )
+                                .await?,
+                            )
+                        }
+                        BatchOperation::Delete { note } => {
+                            let loc = NoteLocator::Current(note.resolve(&results)?);
+                            delete_note_helper::<T>(
+                                &mut transaction,
+                                &loc,
+                                &HashSet::new(),
+                                self.front_matter_sync,
+                            )
+                            .await?;
+                            None
+                        }
+                        BatchOperation::Move { note, to } => {
+                            let to = to.try_map(|n| n.resolve(&results))?;
+                            let id = note.resolve(&results)?;
+                            Some(
+                                move_note_helper::<T>(
+                                    &mut transaction,
+                                    &id,
+                                    &to,
+                                    self.front_matter_sync,
+                                )
+                                .await?,
+                            )
+                        }
+                    };
+                    results.push(result);
+                }
+                transaction.commit().await?;
+                Ok(results)
+            }
+        }))
     }
 
     fn search<'a>(
         &'a self,
         sr: &'a SearchRequest,
     ) -> BoxFuture<'a, Result<Revisions<T>, NoteStoreError>> {
-        Box::pin(async move {
+        Box::pin(with_retries(move || async move {
             let mut transaction = self.db_pool.begin().await?;
             read_only(&mut transaction).await?;
             let notes: Vec<PostgreSQLNoteRowJoined> = search(&mut transaction, sr).await?;
             transaction.commit().await?;
-            Ok(notes
+            notes
                 .into_iter()
-                .map(|n| Box::new(n.into_note()) as Box<dyn Note<T>>)
-                .collect())
-        })
+                .map(|n| Ok(Box::new(n.into_note(self.front_matter_sync)?) as Box<dyn Note<T>>))
+                .collect()
+        }))
     }
 
+    #[allow(mismatched_lifetime_syntaxes)]
     fn tags(&self) -> BoxFuture<Result<Vec<String>, NoteStoreError>> {
-        Box::pin(async move {
+        Box::pin(with_retries(move || async move {
             let mut transaction = self.db_pool.begin().await?;
             read_only(&mut transaction).await?;
             let tags = get_tags(&mut transaction).await?;
             transaction.commit().await?;
             Ok(tags)
-        })
+        }))
+    }
+
+    fn tasks<'a>(
+        &'a self,
+        query: &'a TaskQuery,
+    ) -> BoxFuture<'a, Result<Vec<NoteTask>, NoteStoreError>> {
+        Box::pin(with_retries(move || async move {
+            let mut transaction = self.db_pool.begin().await?;
+            read_only(&mut transaction).await?;
+            let mut tasks = get_tasks(&mut transaction, query).await?;
+            transaction.commit().await?;
+            task::sort_tasks(&mut tasks);
+            Ok(tasks)
+        }))
+    }
+
+    fn toggle_task<'a>(
+        &'a self,
+        loc: &'a NoteLocator,
+        index: usize,
+        author: Option<String>,
+    ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>> {
+        Box::pin(with_retries(move || {
+            let author = author.clone();
+            async move {
+                let mut transaction = self.db_pool.begin().await?;
+                read_write(&mut transaction).await?;
+                let new_loc =
+                    update_note_helper(&mut transaction, loc, self.front_matter_sync, |old_note| {
+                        let mut note: PostgreSQLNoteEditable<T> = old_note.clone();
+                        task::toggle_task(&note.id.into(), &mut note.note_inner, index)?;
+                        note.metadata.author = author;
+                        Ok(note)
+                    })
+                    .await?;
+                transaction.commit().await?;
+                Ok(new_loc)
+            }
+        }))
+    }
+
+    fn toggle_flag<'a>(
+        &'a self,
+        loc: &'a NoteLocator,
+        flag: NoteFlag,
+        author: Option<String>,
+    ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>> {
+        Box::pin(with_retries(move || {
+            let author = author.clone();
+            async move {
+                let mut transaction = self.db_pool.begin().await?;
+                read_write(&mut transaction).await?;
+                let new_loc =
+                    update_note_helper(&mut transaction, loc, self.front_matter_sync, |old_note| {
+                        let mut note: PostgreSQLNoteEditable<T> = old_note.clone();
+                        note.metadata.toggle_flag(flag);
+                        note.metadata.author = author;
+                        Ok(note)
+                    })
+                    .await?;
+                transaction.commit().await?;
+                Ok(new_loc)
+            }
+        }))
+    }
+
+    fn convert_note<'a>(
+        &'a self,
+        loc: &'a NoteLocator,
+        format: NoteFormat,
+        author: Option<String>,
+    ) -> BoxFuture<'a, Result<NoteLocator, NoteStoreError>> {
+        Box::pin(with_retries(move || {
+            let author = author.clone();
+            async move {
+                let mut transaction = self.db_pool.begin().await?;
+                read_write(&mut transaction).await?;
+                let new_loc =
+                    update_note_helper(&mut transaction, loc, self.front_matter_sync, |old_note| {
+                        let mut note: PostgreSQLNoteEditable<T> = old_note.clone();
+                        note.note_inner =
+                            convert_note_inner(&note.id.into(), note.note_inner, format)?;
+                        note.metadata.author = author;
+                        Ok(note)
+                    })
+                    .await?;
+                transaction.commit().await?;
+                Ok(new_loc)
+            }
+        }))
+    }
+
+    fn migrate_metadata(&self) -> BoxFuture<'_, Result<usize, NoteStoreError>> {
+        Box::pin(with_retries(move || async move {
+            let mut transaction = self.db_pool.begin().await?;
+            read_write(&mut transaction).await?;
+            let migrated = migrate_metadata(&mut transaction).await?;
+            transaction.commit().await?;
+            Ok(migrated)
+        }))
+    }
+
+    fn metadata_schemas(&self) -> BoxFuture<'_, Result<MetadataSchemas, NoteStoreError>> {
+        Box::pin(with_retries(move || async move {
+            let mut transaction = self.db_pool.begin().await?;
+            read_only(&mut transaction).await?;
+            let schemas = get_metadata_schemas(&mut transaction).await?;
+            transaction.commit().await?;
+            Ok(schemas)
+        }))
+    }
+
+    fn set_metadata_schema<'a>(
+        &'a self,
+        tag: Option<&'a str>,
+        schema: Option<serde_json::Value>,
+    ) -> BoxFuture<'a, Result<(), NoteStoreError>> {
+        Box::pin(with_retries(move || {
+            let schema = schema.clone();
+            async move {
+                if let Some(ref s) = schema {
+                    check_schema(s)?;
+                }
+                let mut transaction = self.db_pool.begin().await?;
+                read_write(&mut transaction).await?;
+                set_metadata_schema(&mut transaction, tag, schema).await?;
+                transaction.commit().await?;
+                Ok(())
+            }
+        }))
     }
 
+    #[allow(mismatched_lifetime_syntaxes)]
     fn backup(&self, _path: Box<dyn AsRef<Path> + Send>) -> BoxFuture<Result<(), NoteStoreError>> {
         unimplemented!("Please use PostgreSQL's own backup utilities.")
     }
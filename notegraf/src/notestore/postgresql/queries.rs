@@ -1,13 +1,20 @@
+use super::delta::{self, FLAG_UNPACKED, MAX_DELTA_DEPTH};
 use super::PostgreSQLNote;
 use crate::errors::NoteStoreError;
-use crate::notemetadata::NoteMetadata;
+use crate::notemetadata::{NoteMetadata, NOTE_METADATA_CURRENT_SCHEMA_VERSION};
+use crate::notestore::alias::AliasTarget;
+use crate::notestore::compaction::RevisionAge;
+use crate::notestore::integrity::{NoteGraph, Repair};
 use crate::notestore::postgresql::get_new_revision;
+use crate::notestore::schema::MetadataSchemas;
 use crate::notestore::search::SearchRequest;
-use crate::{NoteID, NoteLocator, NoteType};
+use crate::notestore::structure::MoveTarget;
+use crate::notestore::task::{NoteTask, TaskQuery};
+use crate::notetype::{LinkRewrite, NoteFormat, Task};
+use crate::{NoteID, NoteLocator, NoteType, Revision};
 use chrono::{DateTime, Utc};
-use sqlx::postgres::PgQueryResult;
 use sqlx::{query, query_as, Executor, Postgres, Transaction};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::ops::DerefMut;
 use uuid::Uuid;
 
@@ -27,14 +34,25 @@ struct PostgreSQLNoteRow {
     revision: Uuid,
     id: Uuid,
     title: String,
-    note_inner: String,
+    /// `None` for non-current revisions until they are read with [`fill_note_inners`].
+    note_inner: Option<String>,
+    format: Option<String>,
     parent: Option<Uuid>,
     prev: Option<Uuid>,
     referents: Vec<Uuid>,
+    inline_tags: Vec<String>,
+    tasks: serde_json::Value,
+    /// `None` for non-current revisions, which are not searched.
+    plain_text: Option<String>,
     metadata_schema_version: i64,
     metadata_created_at: DateTime<Utc>,
     metadata_modified_at: DateTime<Utc>,
     metadata_tags: Vec<String>,
+    metadata_aliases: Vec<String>,
+    metadata_pinned: bool,
+    metadata_starred: bool,
+    metadata_archived: bool,
+    metadata_author: Option<String>,
     metadata_custom_metadata: serde_json::Value,
 }
 
@@ -47,44 +65,76 @@ where
     fn try_from(n: PostgreSQLNoteEditable<T>) -> Result<Self, Self::Error> {
         let referents: Vec<Uuid> = match n.note_inner.get_referents() {
             Ok(r) => r,
-            Err(e) => return Err(NoteStoreError::NoteInnerError(e.to_string())),
+            Err(e) => return Err(NoteStoreError::note_inner(e)),
         }
         .iter()
         .map(|x| x.try_to_uuid())
         .collect::<Result<Vec<Uuid>, NoteStoreError>>()?;
+        let inline_tags: Vec<String> = match n.note_inner.get_inline_tags() {
+            Ok(t) => t.into_iter().collect(),
+            Err(e) => return Err(NoteStoreError::note_inner(e)),
+        };
+        let tasks = match n.note_inner.get_tasks() {
+            Ok(t) => serde_json::to_value(t)?,
+            Err(e) => return Err(NoteStoreError::note_inner(e)),
+        };
+        let plain_text = n
+            .note_inner
+            .get_plain_text()
+            .map_err(NoteStoreError::note_inner)?;
         let tags: Vec<String> = n.metadata.tags.iter().cloned().collect();
+        let aliases: Vec<String> = n.metadata.aliases.iter().cloned().collect();
+        let format = Some(n.note_inner.get_format().to_string());
         let note_inner: String = n.note_inner.clone().into();
         Ok(PostgreSQLNoteRow {
             revision: n.revision,
             id: n.id,
             title: n.title,
-            note_inner,
+            note_inner: Some(note_inner),
+            format,
             parent: n.parent,
             prev: n.prev,
             referents,
+            inline_tags,
+            tasks,
+            plain_text: Some(plain_text),
             metadata_schema_version: n.metadata.schema_version as i64,
             metadata_created_at: n.metadata.created_at,
             metadata_modified_at: n.metadata.modified_at,
             metadata_tags: tags,
+            metadata_aliases: aliases,
+            metadata_pinned: n.metadata.pinned,
+            metadata_starred: n.metadata.starred,
+            metadata_archived: n.metadata.archived,
+            metadata_author: n.metadata.author,
             metadata_custom_metadata: n.metadata.custom_metadata,
         })
     }
 }
 
-impl<T> From<PostgreSQLNoteRow> for PostgreSQLNoteEditable<T>
+impl<T> TryFrom<PostgreSQLNoteRow> for PostgreSQLNoteEditable<T>
 where
     T: NoteType,
 {
-    fn from(n: PostgreSQLNoteRow) -> Self {
-        let metadata = NoteMetadata {
+    type Error = NoteStoreError;
+
+    fn try_from(n: PostgreSQLNoteRow) -> Result<Self, Self::Error> {
+        let mut metadata = NoteMetadata {
             schema_version: n.metadata_schema_version as u64,
             created_at: n.metadata_created_at,
             modified_at: n.metadata_modified_at,
             tags: HashSet::from_iter(n.metadata_tags.iter().cloned()),
+            aliases: HashSet::from_iter(n.metadata_aliases),
+            pinned: n.metadata_pinned,
+            starred: n.metadata_starred,
+            archived: n.metadata_archived,
+            author: n.metadata_author,
             custom_metadata: n.metadata_custom_metadata,
         };
-        let note_inner: T = T::from(n.note_inner);
-        PostgreSQLNoteEditable {
+        metadata.migrate()?;
+        // Callers that need the front matter enable it afterwards
+        let note_inner: T = parse_note_inner(n.format, n.note_inner, n.revision, false)?;
+        Ok(PostgreSQLNoteEditable {
             id: n.id,
             revision: n.revision,
             title: n.title,
@@ -92,7 +142,7 @@ where
             parent: n.parent,
             prev: n.prev,
             metadata,
-        }
+        })
     }
 }
 
@@ -101,24 +151,36 @@ pub(super) struct PostgreSQLNoteRowJoined {
     pub(super) revision: Uuid,
     pub(super) id: Uuid,
     pub(super) title: String,
-    pub(super) note_inner: String,
+    /// `None` for non-current revisions until they are read with [`fill_note_inners`].
+    pub(super) note_inner: Option<String>,
+    pub(super) format: Option<String>,
     pub(super) parent: Option<Uuid>,
     pub(super) branches: Option<Vec<Uuid>>,
     pub(super) prev: Option<Uuid>,
     pub(super) next: Option<Vec<Uuid>>,
     pub(super) referents: Vec<Uuid>,
     pub(super) references: Option<Vec<Uuid>>,
+    pub(super) inline_tags: Vec<String>,
     pub(super) metadata_schema_version: i64,
     pub(super) metadata_created_at: DateTime<Utc>,
     pub(super) metadata_modified_at: DateTime<Utc>,
     pub(super) metadata_tags: Vec<String>,
+    pub(super) metadata_aliases: Vec<String>,
+    pub(super) metadata_pinned: bool,
+    pub(super) metadata_starred: bool,
+    pub(super) metadata_archived: bool,
+    pub(super) metadata_author: Option<String>,
     pub(super) metadata_custom_metadata: serde_json::Value,
     pub(super) is_current: bool,
 }
 
 impl PostgreSQLNoteRowJoined {
-    pub(super) fn into_note<T: NoteType>(self) -> PostgreSQLNote<T> {
-        let note_inner: T = T::from(self.note_inner);
+    pub(super) fn into_note<T: NoteType>(
+        self,
+        front_matter: bool,
+    ) -> Result<PostgreSQLNote<T>, NoteStoreError> {
+        let note_inner: T =
+            parse_note_inner(self.format, self.note_inner, self.revision, front_matter)?;
         let parent: Option<NoteID> = self.parent.map(|x| x.into());
         let branches: HashSet<NoteID> = match self.branches {
             Some(b) => HashSet::from_iter(b.iter().map(|x| x.into())),
@@ -126,14 +188,16 @@ impl PostgreSQLNoteRowJoined {
         };
         let prev: Option<NoteID> = self.prev.map(|x| x.into());
         let next: Option<NoteID> = match self.next {
-            Some(n) => {
-                if n.is_empty() {
-                    None
-                } else {
-                    assert_eq!(n.len(), 1);
-                    Some(n[0].into())
+            Some(n) => match n.as_slice() {
+                [] => None,
+                [next] => Some(next.into()),
+                _ => {
+                    return Err(NoteStoreError::Inconsistent(
+                        self.id.into(),
+                        "several notes come after it in a sequence".to_owned(),
+                    ))
                 }
-            }
+            },
             None => None,
         };
         let referents: HashSet<NoteID> =
@@ -142,14 +206,21 @@ impl PostgreSQLNoteRowJoined {
             Some(r) => HashSet::from_iter(r.iter().map(|x| x.into())),
             None => HashSet::new(),
         };
-        let metadata = NoteMetadata {
+        let inline_tags: HashSet<String> = HashSet::from_iter(self.inline_tags);
+        let mut metadata = NoteMetadata {
             schema_version: self.metadata_schema_version as u64,
             created_at: self.metadata_created_at,
             modified_at: self.metadata_modified_at,
             tags: HashSet::from_iter(self.metadata_tags.iter().cloned()),
+            aliases: HashSet::from_iter(self.metadata_aliases),
+            pinned: self.metadata_pinned,
+            starred: self.metadata_starred,
+            archived: self.metadata_archived,
+            author: self.metadata_author,
             custom_metadata: self.metadata_custom_metadata,
         };
-        PostgreSQLNote {
+        metadata.migrate()?;
+        Ok(PostgreSQLNote {
             title: self.title,
             note_inner,
             id: self.id.into(),
@@ -160,12 +231,41 @@ impl PostgreSQLNoteRowJoined {
             next,
             referents,
             references,
+            inline_tags,
             metadata,
             is_current: self.is_current,
-        }
+        })
     }
 }
 
+/// Parse a note read back from a revision, recognizing its front matter if the store
+/// synchronizes it.
+///
+/// Revisions made before formats were recorded have no format.
+fn parse_note_inner<T: NoteType>(
+    format: Option<String>,
+    note_inner: Option<String>,
+    revision: Uuid,
+    front_matter: bool,
+) -> Result<T, NoteStoreError> {
+    let note_inner = note_inner.ok_or_else(|| {
+        NoteStoreError::ParseError(format!("The note of revision {revision} is not read"))
+    })?;
+    let format = match format {
+        Some(f) => Some(
+            f.parse::<NoteFormat>()
+                .map_err(|e| NoteStoreError::ParseError(e.to_string()))?,
+        ),
+        None => None,
+    };
+    let mut note_inner =
+        T::parse(format, note_inner).map_err(|e| NoteStoreError::ParseError(e.to_string()))?;
+    if front_matter {
+        note_inner.enable_front_matter();
+    }
+    Ok(note_inner)
+}
+
 fn get_note_query(
     columns: Vec<String>,
     joins: Vec<String>,
@@ -219,16 +319,23 @@ fn get_note_query(
             revision.id,
             revision.title,
             revision.note_inner,
+            revision.format,
             revision.parent,
             array_remove(array_agg(DISTINCT revision1.id), NULL) AS branches,
             revision.prev,
             array_remove(array_agg(DISTINCT revision2.id), NULL) AS next,
             revision.referents,
             array_remove(array_agg(DISTINCT revision3.id), NULL) AS "references",
+            revision.inline_tags,
             revision.metadata_schema_version,
             revision.metadata_created_at,
             revision.metadata_modified_at,
             revision.metadata_tags,
+            revision.metadata_aliases,
+            revision.metadata_pinned,
+            revision.metadata_starred,
+            revision.metadata_archived,
+            revision.metadata_author,
             revision.metadata_custom_metadata,
             cr.current_revision IS NOT NULL AS is_current{select_clause}
         FROM
@@ -297,11 +404,16 @@ async fn get_note_specific(
     .bind(revision)
     .fetch_one(transaction.deref_mut())
     .await;
-    if let Err(sqlx::Error::RowNotFound) = res {
-        Err(NoteStoreError::RevisionNotExist(id.into(), revision.into()))
+    let mut row = if let Err(sqlx::Error::RowNotFound) = res {
+        return Err(NoteStoreError::RevisionNotExist(id.into(), revision.into()));
     } else {
-        res.map_err(NoteStoreError::PostgreSQLError)
-    }
+        res.map_err(NoteStoreError::PostgreSQLError)?
+    };
+    fill_note_inners(transaction, std::slice::from_mut(&mut row), |r| {
+        (r.revision, &mut r.note_inner)
+    })
+    .await?;
+    Ok(row)
 }
 
 pub(super) async fn get_revisions(
@@ -320,11 +432,13 @@ pub(super) async fn get_revisions(
     .bind(id)
     .fetch_all(transaction.deref_mut())
     .await;
-    if let Err(sqlx::Error::RowNotFound) = res {
-        Err(NoteStoreError::NoteNotExist(id.into()))
+    let mut rows = if let Err(sqlx::Error::RowNotFound) = res {
+        return Err(NoteStoreError::NoteNotExist(id.into()));
     } else {
-        res.map_err(NoteStoreError::PostgreSQLError)
-    }
+        res.map_err(NoteStoreError::PostgreSQLError)?
+    };
+    fill_note_inners(transaction, &mut rows, |r| (r.revision, &mut r.note_inner)).await?;
+    Ok(rows)
 }
 
 pub(super) async fn search(
@@ -339,6 +453,7 @@ pub(super) async fn search(
     let mut orders = vec![];
     // only search current versions
     conditions.push("cr.current_revision IS NOT NULL".to_owned());
+    orders.push("revision.metadata_pinned DESC".to_owned());
     if sr.sort_by_created_at() {
         orders.push("revision.metadata_created_at DESC".to_owned());
     }
@@ -347,10 +462,20 @@ pub(super) async fn search(
         conditions.push("revision.parent IS NULL".to_owned());
         havings.push("array_remove(array_agg(revision3.id), NULL) = '{}'".to_owned());
     }
-    conditions.push("revision.metadata_tags @> $1".to_owned());
-    conditions.push("NOT revision.metadata_tags && $2".to_owned());
+    // Match against both the tags in the metadata and the inline tags
+    conditions.push("(revision.metadata_tags || revision.inline_tags) @> $1".to_owned());
+    conditions.push("NOT (revision.metadata_tags || revision.inline_tags) && $2".to_owned());
+    if sr.pinned {
+        conditions.push("revision.metadata_pinned".to_owned());
+    }
+    if sr.starred {
+        conditions.push("revision.metadata_starred".to_owned());
+    }
+    // Archived notes are excluded unless asked for
+    conditions.push(format!("revision.metadata_archived = {}", sr.archived));
     if sr.no_tag {
         conditions.push("revision.metadata_tags = '{}'".to_owned());
+        conditions.push("revision.inline_tags = '{}'".to_owned());
     }
     let is_tsquery = !sr.lexemes.is_empty() || !sr.lexemes_excluded.is_empty();
     if is_tsquery {
@@ -361,6 +486,13 @@ pub(super) async fn search(
         groupbys.push("query.query".to_owned());
         orders.push("rank DESC".to_owned());
     }
+    if !sr.authors.is_empty() {
+        // Authors of any revision of the note, not only the current one
+        let param = if is_tsquery { 4 } else { 3 };
+        conditions.push(format!(
+            "${param} <@ ARRAY(SELECT r.metadata_author FROM revision r WHERE r.id = revision.id)"
+        ));
+    }
     let query_statement = get_note_query(
         columns, joins, conditions, groupbys, havings, orders, sr.limit,
     );
@@ -373,6 +505,9 @@ pub(super) async fn search(
         terms.extend(prefixed);
         q = q.bind(terms.join(" & "));
     }
+    if !sr.authors.is_empty() {
+        q = q.bind(&sr.authors);
+    }
     let res = q.fetch_all(transaction.deref_mut()).await;
     if let Err(sqlx::Error::RowNotFound) = res {
         Ok(vec![])
@@ -393,13 +528,22 @@ async fn get_row_current(
                 revision.id,
                 revision.title,
                 revision.note_inner,
+                revision.format,
                 revision.parent,
                 revision.prev,
                 revision.referents,
+                revision.inline_tags,
+                revision.tasks,
+                revision.plain_text,
                 revision.metadata_schema_version,
                 revision.metadata_created_at,
                 revision.metadata_modified_at,
                 revision.metadata_tags,
+                revision.metadata_aliases,
+                revision.metadata_pinned,
+                revision.metadata_starred,
+                revision.metadata_archived,
+                revision.metadata_author,
                 revision.metadata_custom_metadata
             FROM revision
             LEFT JOIN current_revision cr on revision.revision = cr.current_revision
@@ -429,13 +573,22 @@ async fn get_row_specific(
                 id,
                 title,
                 note_inner,
+                format,
                 parent,
                 prev,
                 referents,
+                inline_tags,
+                tasks,
+                plain_text,
                 metadata_schema_version,
                 metadata_created_at,
                 metadata_modified_at,
                 metadata_tags,
+                metadata_aliases,
+                metadata_pinned,
+                metadata_starred,
+                metadata_archived,
+                metadata_author,
                 metadata_custom_metadata
             FROM revision
             WHERE id = $1 AND revision = $2
@@ -445,11 +598,265 @@ async fn get_row_specific(
     )
     .fetch_one(transaction.deref_mut())
     .await;
-    if let Err(sqlx::Error::RowNotFound) = res {
-        Err(NoteStoreError::NoteNotExist(id.into()))
+    let mut row = if let Err(sqlx::Error::RowNotFound) = res {
+        return Err(NoteStoreError::NoteNotExist(id.into()));
     } else {
-        res.map_err(NoteStoreError::PostgreSQLError)
+        res.map_err(NoteStoreError::PostgreSQLError)?
+    };
+    fill_note_inners(transaction, std::slice::from_mut(&mut row), |r| {
+        (r.revision, &mut r.note_inner)
+    })
+    .await?;
+    Ok(row)
+}
+
+/// How the note of a revision is stored.
+struct StoredNoteInner {
+    note_inner: Option<String>,
+    note_delta: Option<Vec<u8>>,
+    delta_base: Option<Uuid>,
+}
+
+/// Read the notes of revisions, following the deltas that non-current revisions are stored as.
+///
+/// Also returns the number of deltas followed to read each revision.
+async fn read_note_inners(
+    transaction: &mut Transaction<'_, Postgres>,
+    revisions: &[Uuid],
+) -> Result<HashMap<Uuid, (String, usize)>, NoteStoreError> {
+    let rows = query!(
+        r#"
+            WITH RECURSIVE chain AS (
+                SELECT revision, note_inner, note_delta, delta_base
+                FROM revision
+                WHERE revision = ANY($1)
+                UNION
+                SELECT r.revision, r.note_inner, r.note_delta, r.delta_base
+                FROM revision r
+                JOIN chain ON r.revision = chain.delta_base
+            )
+            SELECT revision AS "revision!", note_inner, note_delta, delta_base
+            FROM chain
+            "#,
+        revisions
+    )
+    .fetch_all(transaction.deref_mut())
+    .await?;
+    let stored: HashMap<Uuid, StoredNoteInner> = rows
+        .into_iter()
+        .map(|r| {
+            (
+                r.revision,
+                StoredNoteInner {
+                    note_inner: r.note_inner,
+                    note_delta: r.note_delta,
+                    delta_base: r.delta_base,
+                },
+            )
+        })
+        .collect();
+    let mut read = HashMap::new();
+    for &revision in revisions {
+        read_note_inner(&stored, &mut read, revision)?;
+    }
+    Ok(read)
+}
+
+fn read_note_inner(
+    stored: &HashMap<Uuid, StoredNoteInner>,
+    read: &mut HashMap<Uuid, (String, usize)>,
+    revision: Uuid,
+) -> Result<(), NoteStoreError> {
+    let corrupt = |r: Uuid| NoteStoreError::ParseError(format!("Corrupt revision {r}"));
+    // From the revision back to one that is already read or stored in full
+    let mut chain = vec![];
+    let mut r = revision;
+    while !read.contains_key(&r) {
+        let s = stored.get(&r).ok_or_else(|| corrupt(r))?;
+        chain.push(r);
+        match (&s.note_inner, &s.note_delta, s.delta_base) {
+            (None, Some(d), Some(base)) if delta::is_delta(d) && chain.len() <= stored.len() => {
+                r = base
+            }
+            (None, Some(d), _) if delta::is_delta(d) => return Err(corrupt(r)),
+            _ => break,
+        }
+    }
+    for r in chain.into_iter().rev() {
+        let s = &stored[&r];
+        let note_inner = match (&s.note_inner, &s.note_delta) {
+            (Some(n), _) => (n.clone(), 0),
+            (None, Some(d)) => match s.delta_base.and_then(|b| read.get(&b)) {
+                Some((base, depth)) if delta::is_delta(d) => {
+                    (delta::decode(d, Some(base))?, depth + 1)
+                }
+                _ => (delta::decode(d, None)?, 0),
+            },
+            (None, None) => return Err(corrupt(r)),
+        };
+        read.insert(r, note_inner);
     }
+    Ok(())
+}
+
+/// Fill in the notes of rows of non-current revisions, which are stored in `note_delta`.
+async fn fill_note_inners<R>(
+    transaction: &mut Transaction<'_, Postgres>,
+    rows: &mut [R],
+    note_inner: fn(&mut R) -> (Uuid, &mut Option<String>),
+) -> Result<(), NoteStoreError> {
+    let missing: Vec<Uuid> = rows
+        .iter_mut()
+        .map(note_inner)
+        .filter(|(_, n)| n.is_none())
+        .map(|(r, _)| r)
+        .collect();
+    if missing.is_empty() {
+        return Ok(());
+    }
+    let mut read = read_note_inners(transaction, &missing).await?;
+    for row in rows.iter_mut() {
+        let (r, n) = note_inner(row);
+        if n.is_none() {
+            *n = read.remove(&r).map(|(note_inner, _)| note_inner);
+        }
+    }
+    Ok(())
+}
+
+/// Store the note of a revision in `note_delta`, as a delta against `base` if it is smaller.
+async fn store_note_delta(
+    transaction: &mut Transaction<'_, Postgres>,
+    revision: Uuid,
+    note_inner: &str,
+    base: Option<(Uuid, &str)>,
+) -> Result<(), NoteStoreError> {
+    let encoded = delta::encode(note_inner, base.map(|(_, b)| b))?;
+    let delta_base = base.filter(|_| encoded.is_delta).map(|(b, _)| b);
+    query!(
+        r#"
+            UPDATE revision
+            SET note_inner = NULL, plain_text = NULL, note_delta = $2, delta_base = $3
+            WHERE revision = $1
+            "#,
+        revision,
+        encoded.bytes,
+        delta_base
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    Ok(())
+}
+
+/// Store a revision that is no longer current compactly, as a delta against the revision before
+/// it unless that makes the chain of deltas too long.
+async fn demote_revision(
+    transaction: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+    revision: Uuid,
+) -> Result<(), NoteStoreError> {
+    let base = query!(
+        r#"
+            SELECT r.revision
+            FROM revision r, revision demoted
+            WHERE demoted.revision = $2 AND r.id = $1
+                AND (r.metadata_modified_at, r.revision)
+                    < (demoted.metadata_modified_at, demoted.revision)
+            ORDER BY r.metadata_modified_at DESC, r.revision DESC
+            LIMIT 1
+            "#,
+        id,
+        revision
+    )
+    .fetch_optional(transaction.deref_mut())
+    .await?
+    .map(|r| r.revision);
+    let mut revisions = vec![revision];
+    revisions.extend(base);
+    let read = read_note_inners(transaction, &revisions).await?;
+    let base = base.and_then(|b| {
+        let (note_inner, depth) = &read[&b];
+        (*depth < MAX_DELTA_DEPTH).then_some((b, note_inner.as_str()))
+    });
+    store_note_delta(transaction, revision, &read[&revision].0, base).await
+}
+
+/// Encode the revisions converted by a migration against their bases, returning how many there
+/// were.
+pub(super) async fn pack_revisions(
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<usize, NoteStoreError> {
+    // Older revisions first, so that they are packed before they become bases
+    let rows = query!(
+        r#"
+            SELECT id, revision
+            FROM revision
+            WHERE get_byte(note_delta, 0) = $1
+            ORDER BY id, metadata_modified_at, revision
+            "#,
+        FLAG_UNPACKED as i32
+    )
+    .fetch_all(transaction.deref_mut())
+    .await?;
+    for row in rows.iter() {
+        demote_revision(transaction, row.id, row.revision).await?;
+    }
+    Ok(rows.len())
+}
+
+/// Compute the columns derived from the notes of the revisions marked by a migration, returning
+/// how many there were.
+///
+/// Revisions whose notes cannot be parsed are left marked, so that they are retried.
+pub(super) async fn reindex_revisions<T: NoteType>(
+    transaction: &mut Transaction<'_, Postgres>,
+    front_matter: bool,
+) -> Result<usize, NoteStoreError> {
+    let rows = query!(
+        r#"
+            SELECT revision, format, note_inner
+            FROM revision
+            WHERE reindex
+            "#
+    )
+    .fetch_all(transaction.deref_mut())
+    .await?;
+    let mut reindexed = 0;
+    for row in rows {
+        let note_inner: T =
+            match parse_note_inner(row.format, row.note_inner, row.revision, front_matter) {
+                Ok(n) => n,
+                Err(e) => {
+                    warn!("Cannot reindex revision {}: {}", row.revision, e);
+                    continue;
+                }
+            };
+        let inline_tags: Vec<String> = note_inner
+            .get_inline_tags()
+            .map_err(NoteStoreError::note_inner)?
+            .into_iter()
+            .collect();
+        let tasks =
+            serde_json::to_value(note_inner.get_tasks().map_err(NoteStoreError::note_inner)?)?;
+        let plain_text = note_inner
+            .get_plain_text()
+            .map_err(NoteStoreError::note_inner)?;
+        query!(
+            r#"
+                UPDATE revision
+                SET inline_tags = $2, tasks = $3, plain_text = $4, reindex = false
+                WHERE revision = $1
+                "#,
+            row.revision,
+            &inline_tags,
+            tasks,
+            plain_text
+        )
+        .execute(transaction.deref_mut())
+        .await?;
+        reindexed += 1;
+    }
+    Ok(reindexed)
 }
 
 pub(super) async fn get_note_by_loc(
@@ -483,23 +890,36 @@ pub(super) async fn insert_revision<T: NoteType>(
         r#"
             INSERT INTO
                 revision(
-                    revision, id, title, note_inner, parent, prev, referents,
-                    metadata_schema_version, metadata_created_at,
-                    metadata_modified_at, metadata_tags, metadata_custom_metadata
+                    revision, id, title, note_inner, format, parent, prev, referents,
+                    inline_tags, tasks, plain_text, metadata_schema_version, metadata_created_at,
+                    metadata_modified_at, metadata_tags, metadata_aliases, metadata_pinned,
+                    metadata_starred, metadata_archived, metadata_author, metadata_custom_metadata
                 )
-            VALUES($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+            VALUES(
+                $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18,
+                $19, $20, $21
+            )
             "#,
         row.revision,
         row.id,
         row.title,
         row.note_inner,
+        row.format,
         row.parent,
         row.prev,
         &row.referents,
+        &row.inline_tags,
+        row.tasks,
+        row.plain_text,
         row.metadata_schema_version,
         row.metadata_created_at,
         row.metadata_modified_at,
         &row.metadata_tags,
+        &row.metadata_aliases,
+        row.metadata_pinned,
+        row.metadata_starred,
+        row.metadata_archived,
+        row.metadata_author,
         row.metadata_custom_metadata
     )
     .execute(transaction.deref_mut())
@@ -512,7 +932,17 @@ pub(super) async fn upsert_current_revision(
     transaction: &mut Transaction<'_, Postgres>,
     id: Uuid,
     revision: Uuid,
-) -> sqlx::Result<PgQueryResult> {
+) -> Result<(), NoteStoreError> {
+    // Resurrected notes are no longer deleted
+    query!(r#"DELETE FROM deleted_note WHERE id = $1"#, id)
+        .execute(transaction.deref_mut())
+        .await?;
+    let old = query!(
+        r#"SELECT current_revision FROM current_revision WHERE id = $1"#,
+        id
+    )
+    .fetch_optional(transaction.deref_mut())
+    .await?;
     query!(
         r#"
             INSERT INTO current_revision (id, current_revision)
@@ -524,7 +954,13 @@ pub(super) async fn upsert_current_revision(
         revision
     )
     .execute(transaction.deref_mut())
-    .await
+    .await?;
+    match old {
+        Some(o) if o.current_revision != revision => {
+            demote_revision(transaction, id, o.current_revision).await
+        }
+        _ => Ok(()),
+    }
 }
 
 pub(super) async fn noteid_exist(
@@ -585,45 +1021,458 @@ pub(super) async fn is_current(
 }
 
 pub(super) async fn delete_revision(
-    mut transaction: Transaction<'_, Postgres>,
+    transaction: &mut Transaction<'_, Postgres>,
     loc: &NoteLocator,
 ) -> Result<(), NoteStoreError> {
     let (id, revision) = loc.unpack_uuid()?;
-    let query_result = match revision {
-        Some(r) => {
-            query!(
-                r#"DELETE FROM current_revision WHERE id = $1 AND current_revision = $2"#,
-                id,
-                r
-            )
-            .execute(transaction.deref_mut())
-            .await?
-        }
-        None => {
-            query!(r#"DELETE FROM current_revision WHERE id = $1"#, id)
-                .execute(transaction.deref_mut())
-                .await?
-        }
+    let deleted = match revision {
+        Some(r) => query!(
+            r#"
+                DELETE FROM current_revision WHERE id = $1 AND current_revision = $2
+                RETURNING current_revision
+                "#,
+            id,
+            r
+        )
+        .fetch_optional(transaction.deref_mut())
+        .await?
+        .map(|row| row.current_revision),
+        None => query!(
+            r#"DELETE FROM current_revision WHERE id = $1 RETURNING current_revision"#,
+            id
+        )
+        .fetch_optional(transaction.deref_mut())
+        .await?
+        .map(|row| row.current_revision),
     };
-    if query_result.rows_affected() != 1 {
-        transaction
-            .rollback()
-            .await
-            .map_err(NoteStoreError::PostgreSQLError)?;
-        match loc {
+    let Some(deleted) = deleted else {
+        return match loc {
             NoteLocator::Current(id) => Err(NoteStoreError::NoteNotExist(id.clone())),
             NoteLocator::Specific(id, revision) => Err(NoteStoreError::RevisionNotExist(
                 id.clone(),
                 revision.clone(),
             )),
+        };
+    };
+    query!(
+        r#"INSERT INTO deleted_note (id, deleted_at) VALUES ($1, $2)"#,
+        id,
+        Utc::now()
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    demote_revision(transaction, id, deleted).await?;
+    Ok(())
+}
+
+/// Delete a current note, ignoring references from `deleted_with`, which are deleted along with
+/// it.
+pub(super) async fn delete_note_helper<T: NoteType>(
+    transaction: &mut Transaction<'_, Postgres>,
+    loc: &NoteLocator,
+    deleted_with: &HashSet<NoteID>,
+    front_matter: bool,
+) -> Result<(), NoteStoreError> {
+    let (id, rev) = loc.unpack();
+    if !is_current(transaction, loc).await? {
+        return Err(NoteStoreError::DeleteOldRevision(
+            id.clone(),
+            rev.unwrap().clone(),
+        ));
+    }
+    let note: PostgreSQLNote<T> = get_note_by_loc(transaction, loc)
+        .await?
+        .into_note(front_matter)?;
+    if !note.branches.is_empty() {
+        return Err(NoteStoreError::HasBranches(id.clone()));
+    }
+    if !note.references.is_subset(deleted_with) {
+        return Err(NoteStoreError::HasReferences(id.clone()));
+    }
+    // This note was created by branching out from some other note
+    // It's not possible to be in the middle of a note sequence
+    // And vice versa
+    if note.prev.is_some() && note.parent.is_some() {
+        return Err(NoteStoreError::Inconsistent(
+            id.clone(),
+            "it is both a branch and in a sequence".to_owned(),
+        ));
+    }
+    // Since only prev is stored, our prev note is not aware of us
+    // But we want to make sure our next note is consistent
+    // The next note basically inherits our prev and parent
+    #[allow(clippy::unnecessary_unwrap)]
+    if note.next.is_some() {
+        update_note_helper::<_, T>(
+            transaction,
+            &NoteLocator::Current(note.next.unwrap()),
+            front_matter,
+            |old_note| {
+                let mut new_note = old_note.clone();
+                // Thw below two lines shouldn't both have effects
+                // See the above check
+                new_note.prev = note.prev.map(|x| x.to_uuid().unwrap());
+                new_note.parent = note.parent.map(|x| x.to_uuid().unwrap());
+                Ok(new_note)
+            },
+        )
+        .await?;
+    }
+    delete_revision(transaction, loc).await
+}
+
+/// Move a current note, see [`NoteStore::move_note`](crate::NoteStore::move_note).
+pub(super) async fn move_note_helper<T: NoteType>(
+    transaction: &mut Transaction<'_, Postgres>,
+    id: &NoteID,
+    to: &MoveTarget,
+    front_matter: bool,
+) -> Result<NoteLocator, NoteStoreError> {
+    let note: PostgreSQLNote<T> = get_note_by_loc(transaction, &NoteLocator::Current(id.clone()))
+        .await?
+        .into_note(front_matter)?;
+    let (parent, prev) = match to {
+        MoveTarget::Standalone => (None, None),
+        MoveTarget::BranchOf(target) => {
+            // The parent must be a current note
+            get_note_by_loc(transaction, &NoteLocator::Current(target.clone())).await?;
+            (Some(target.clone()), None)
         }
-    } else {
-        transaction
-            .commit()
-            .await
-            .map_err(NoteStoreError::PostgreSQLError)?;
-        Ok(())
+        MoveTarget::After(target) => {
+            let target_note: PostgreSQLNote<T> =
+                get_note_by_loc(transaction, &NoteLocator::Current(target.clone()))
+                    .await?
+                    .into_note(front_matter)?;
+            match target_note.next {
+                Some(n) if &n != id => {
+                    return Err(NoteStoreError::ExistingNext(target.clone(), n));
+                }
+                _ => (None, Some(target.clone())),
+            }
+        }
+    };
+    if note.parent == parent && note.prev == prev {
+        // Already there
+        return Ok(NoteLocator::Specific(note.id, note.revision));
     }
+    let parent = parent.map(|p| p.try_to_uuid()).transpose()?;
+    let prev = prev.map(|p| p.try_to_uuid()).transpose()?;
+    // The branches and the next note of the note refer to it, so they move along with it
+    update_note_helper::<_, T>(
+        transaction,
+        &NoteLocator::Current(id.clone()),
+        front_matter,
+        |old_note| {
+            let mut note = old_note.clone();
+            note.parent = parent;
+            note.prev = prev;
+            Ok(note)
+        },
+    )
+    .await
+}
+
+/// The relationships between notes recorded by the current revisions, and the revisions of notes
+/// that are neither current nor deleted.
+pub(super) async fn get_note_graph(
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<NoteGraph, NoteStoreError> {
+    let rows = query!(
+        r#"
+            SELECT revision.id, revision.parent, revision.prev, revision.referents
+            FROM current_revision cr
+            JOIN revision ON revision.revision = cr.current_revision
+            "#
+    )
+    .fetch_all(transaction.deref_mut())
+    .await?;
+    let mut graph = NoteGraph::default();
+    for row in rows {
+        let id: NoteID = row.id.into();
+        if let Some(parent) = row.parent {
+            graph.branches.push((parent.into(), id.clone()));
+        }
+        if let Some(prev) = row.prev {
+            graph.sequence.push((prev.into(), id.clone()));
+        }
+        for referent in row.referents {
+            graph.referents.push((id.clone(), referent.into()));
+        }
+        graph.current.insert(id);
+    }
+    let orphaned = query!(
+        r#"
+            SELECT revision.id, revision.revision
+            FROM revision
+            WHERE NOT EXISTS (SELECT 1 FROM current_revision cr WHERE cr.id = revision.id)
+              AND NOT EXISTS (SELECT 1 FROM deleted_note WHERE deleted_note.id = revision.id)
+            "#
+    )
+    .fetch_all(transaction.deref_mut())
+    .await?;
+    graph.orphaned = orphaned
+        .into_iter()
+        .map(|row| (row.id.into(), row.revision.into()))
+        .collect();
+    Ok(graph)
+}
+
+/// Make a change that repairs an integrity issue.
+///
+/// Parents and previous notes are recorded by the branches and the next notes, so they are the
+/// notes changed to detach them.
+pub(super) async fn repair<T: NoteType>(
+    transaction: &mut Transaction<'_, Postgres>,
+    repair: Repair,
+    front_matter: bool,
+) -> Result<(), NoteStoreError> {
+    match repair {
+        Repair::RemoveBranch { parent, child } => {
+            let parent = parent.try_to_uuid()?;
+            update_note_helper::<_, T>(
+                transaction,
+                &NoteLocator::Current(child),
+                front_matter,
+                |old_note| {
+                    let mut note = old_note.clone();
+                    if note.parent == Some(parent) {
+                        note.parent = None;
+                    }
+                    Ok(note)
+                },
+            )
+            .await?;
+        }
+        Repair::RemoveNext { prev, next } => {
+            let prev = prev.try_to_uuid()?;
+            update_note_helper::<_, T>(
+                transaction,
+                &NoteLocator::Current(next),
+                front_matter,
+                |old_note| {
+                    let mut note = old_note.clone();
+                    if note.prev == Some(prev) {
+                        note.prev = None;
+                    }
+                    Ok(note)
+                },
+            )
+            .await?;
+        }
+        Repair::RemoveReferent { note, referent } => {
+            update_note_helper::<_, T>(
+                transaction,
+                &NoteLocator::Current(note),
+                front_matter,
+                |old_note| {
+                    let mut note = old_note.clone();
+                    // Otherwise the recorded referents are wrong, which the new revision corrects
+                    if note
+                        .note_inner
+                        .get_referents()
+                        .map_err(NoteStoreError::note_inner)?
+                        .contains(&referent)
+                    {
+                        note.note_inner
+                            .remove_referent(referent, LinkRewrite::Tombstone)
+                            .map_err(NoteStoreError::note_inner)?;
+                    }
+                    Ok(note)
+                },
+            )
+            .await?;
+        }
+        Repair::Trash(id) => {
+            query!(
+                r#"
+                    INSERT INTO deleted_note (id, deleted_at) VALUES ($1, $2)
+                    ON CONFLICT (id) DO NOTHING
+                    "#,
+                id.try_to_uuid()?,
+                Utc::now()
+            )
+            .execute(transaction.deref_mut())
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Deleted notes and when they were deleted, the most recently deleted first.
+pub(super) async fn get_deleted(
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<Vec<(Uuid, DateTime<Utc>)>, NoteStoreError> {
+    let rows = query!(
+        r#"
+            SELECT id, deleted_at
+            FROM deleted_note
+            ORDER BY deleted_at DESC
+            "#
+    )
+    .fetch_all(transaction.deref_mut())
+    .await?;
+    Ok(rows.into_iter().map(|r| (r.id, r.deleted_at)).collect())
+}
+
+pub(super) async fn purge_note(
+    transaction: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+) -> Result<(), NoteStoreError> {
+    if !is_deleted(transaction, id).await? {
+        return Err(NoteStoreError::NotDeleted(id.into()));
+    }
+    let row = query!(
+        r#"
+            SELECT
+                EXISTS(SELECT 1 FROM revision_only_current WHERE referents @> ARRAY[$1::uuid])
+                    AS "referenced!",
+                EXISTS(SELECT 1 FROM revision_only_current WHERE parent = $1) AS "parent!",
+                EXISTS(SELECT 1 FROM revision_only_current WHERE prev = $1) AS "prev!"
+            "#,
+        id
+    )
+    .fetch_one(transaction.deref_mut())
+    .await?;
+    if row.referenced {
+        return Err(NoteStoreError::HasReferences(id.into()));
+    }
+    if row.parent {
+        return Err(NoteStoreError::HasBranches(id.into()));
+    }
+    if row.prev {
+        return Err(NoteStoreError::HasNext(id.into()));
+    }
+    // Older revisions of other notes might still point to the note
+    query!(r#"UPDATE revision SET parent = NULL WHERE parent = $1"#, id)
+        .execute(transaction.deref_mut())
+        .await?;
+    query!(r#"UPDATE revision SET prev = NULL WHERE prev = $1"#, id)
+        .execute(transaction.deref_mut())
+        .await?;
+    query!(r#"DELETE FROM deleted_note WHERE id = $1"#, id)
+        .execute(transaction.deref_mut())
+        .await?;
+    query!(r#"DELETE FROM revision WHERE id = $1"#, id)
+        .execute(transaction.deref_mut())
+        .await?;
+    query!(r#"DELETE FROM note WHERE id = $1"#, id)
+        .execute(transaction.deref_mut())
+        .await?;
+    Ok(())
+}
+
+/// All revisions of all notes as considered for compaction, grouped by notes.
+///
+/// Revisions are kept regardless of the policy if they are current or pinned.
+pub(super) async fn get_revision_ages(
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<HashMap<Uuid, Vec<RevisionAge>>, NoteStoreError> {
+    let rows = query!(
+        r#"
+            SELECT
+                revision.id AS "id!",
+                revision.revision AS "revision!",
+                revision.metadata_modified_at AS "metadata_modified_at!",
+                revision.kept AS "kept!",
+                revision.metadata_aliases AS "metadata_aliases!",
+                cr.current_revision IS NOT NULL AS "is_current!"
+            FROM revision
+            LEFT JOIN current_revision cr ON cr.current_revision = revision.revision
+            "#
+    )
+    .fetch_all(transaction.deref_mut())
+    .await?;
+    let mut revisions: HashMap<Uuid, Vec<RevisionAge>> = HashMap::new();
+    for row in rows {
+        revisions.entry(row.id).or_default().push(RevisionAge {
+            revision: row.revision.into(),
+            modified_at: row.metadata_modified_at,
+            keep: row.is_current || row.kept,
+            aliases: row.metadata_aliases.into_iter().collect(),
+        });
+    }
+    Ok(revisions)
+}
+
+pub(super) async fn set_revision_kept(
+    transaction: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+    revision: Uuid,
+    keep: bool,
+) -> Result<(), NoteStoreError> {
+    let res = query!(
+        r#"UPDATE revision SET kept = $3 WHERE id = $1 AND revision = $2"#,
+        id,
+        revision,
+        keep
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    if res.rows_affected() == 0 {
+        return Err(NoteStoreError::RevisionNotExist(id.into(), revision.into()));
+    }
+    Ok(())
+}
+
+pub(super) async fn get_kept_revisions(
+    transaction: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+) -> Result<HashSet<Revision>, NoteStoreError> {
+    let rows = query!(
+        r#"SELECT revision AS "revision!", kept AS "kept!" FROM revision WHERE id = $1"#,
+        id
+    )
+    .fetch_all(transaction.deref_mut())
+    .await?;
+    if rows.is_empty() {
+        return Err(NoteStoreError::NoteNotExist(id.into()));
+    }
+    Ok(rows
+        .into_iter()
+        .filter(|r| r.kept)
+        .map(|r| r.revision.into())
+        .collect())
+}
+
+/// The stored forms of the current revisions of all notes.
+pub(super) async fn get_current_note_inners(
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<Vec<String>, NoteStoreError> {
+    let rows = query!(r#"SELECT note_inner AS "note_inner!" FROM revision_only_current"#)
+        .fetch_all(transaction.deref_mut())
+        .await?;
+    Ok(rows.into_iter().map(|r| r.note_inner).collect())
+}
+
+pub(super) async fn delete_revisions(
+    transaction: &mut Transaction<'_, Postgres>,
+    revisions: &[Uuid],
+) -> Result<u64, NoteStoreError> {
+    // Revisions stored as deltas against the removed ones are stored in full instead
+    let dependents: Vec<Uuid> = query!(
+        r#"
+            SELECT revision
+            FROM revision
+            WHERE delta_base = ANY($1) AND NOT revision = ANY($1)
+            "#,
+        revisions
+    )
+    .fetch_all(transaction.deref_mut())
+    .await?
+    .into_iter()
+    .map(|r| r.revision)
+    .collect();
+    let read = read_note_inners(transaction, &dependents).await?;
+    for revision in dependents {
+        store_note_delta(transaction, revision, &read[&revision].0, None).await?;
+    }
+    let result = query!(
+        r#"DELETE FROM revision WHERE revision = ANY($1)"#,
+        revisions
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    Ok(result.rows_affected())
 }
 
 async fn is_deleted(
@@ -654,9 +1503,40 @@ async fn is_deleted(
     }
 }
 
+/// Whether `ancestor` is reached from `note` by following the parents and previous notes of
+/// current revisions.
+async fn comes_before(
+    transaction: &mut Transaction<'_, Postgres>,
+    ancestor: Uuid,
+    note: Uuid,
+) -> Result<bool, NoteStoreError> {
+    // UNION rather than UNION ALL, so that existing cycles don't recurse forever
+    let row = query!(
+        r#"
+            WITH RECURSIVE up(id) AS (
+                SELECT $2::uuid
+                UNION
+                SELECT link.id
+                FROM up
+                JOIN current_revision cr ON cr.id = up.id
+                JOIN revision ON revision.revision = cr.current_revision
+                CROSS JOIN LATERAL (VALUES (revision.parent), (revision.prev)) AS link(id)
+                WHERE link.id IS NOT NULL
+            )
+            SELECT EXISTS (SELECT 1 FROM up WHERE id = $1) AS "comes_before!"
+            "#,
+        ancestor,
+        note
+    )
+    .fetch_one(transaction.deref_mut())
+    .await?;
+    Ok(row.comes_before)
+}
+
 pub(super) async fn update_note_helper<F, T>(
     transaction: &mut Transaction<'_, Postgres>,
     loc: &NoteLocator,
+    front_matter: bool,
     op: F,
 ) -> Result<NoteLocator, NoteStoreError>
 where
@@ -674,11 +1554,16 @@ where
             rev.unwrap().into(),
         ));
     };
-    let old_note: PostgreSQLNoteEditable<T> = old_note_row.into();
+    let mut old_note: PostgreSQLNoteEditable<T> = old_note_row.try_into()?;
+    if front_matter {
+        old_note.note_inner.enable_front_matter();
+    }
+    // The new revision has an author only if the operation gives one
+    old_note.metadata.author = None;
     let new_revision = get_new_revision();
     let mut updated_note = op(&old_note)?;
     updated_note.revision = new_revision;
-    updated_note.metadata = updated_note.metadata.on_update_note();
+    updated_note.metadata = updated_note.metadata.on_update_note()?;
     if is_resurrecting {
         // If a note previously has a prev note, we will clear the attribute, in case the prev
         // note now has a next
@@ -686,11 +1571,99 @@ where
         updated_note.parent = None;
         updated_note.prev = None;
     }
+    for (up, old_up) in [
+        (updated_note.parent, old_note.parent),
+        (updated_note.prev, old_note.prev),
+    ] {
+        if let Some(up) = up.filter(|&up| Some(up) != old_up) {
+            if comes_before(transaction, id, up).await? {
+                return Err(NoteStoreError::Cycle(id.into(), up.into()));
+            }
+        }
+    }
     let new_loc = insert_revision(transaction, updated_note).await?;
     upsert_current_revision(transaction, id, new_revision).await?;
     Ok(new_loc)
 }
 
+/// Upgrade the metadata of all current revisions in place.
+pub(super) async fn migrate_metadata(
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<usize, NoteStoreError> {
+    let rows = query!(
+        r#"
+            SELECT
+                revision.revision,
+                revision.metadata_schema_version,
+                revision.metadata_created_at,
+                revision.metadata_modified_at,
+                revision.metadata_tags,
+                revision.metadata_aliases,
+                revision.metadata_pinned,
+                revision.metadata_starred,
+                revision.metadata_archived,
+                revision.metadata_author,
+                revision.metadata_custom_metadata
+            FROM revision
+            JOIN current_revision cr ON revision.revision = cr.current_revision
+            WHERE revision.metadata_schema_version <> $1
+            "#,
+        NOTE_METADATA_CURRENT_SCHEMA_VERSION as i64
+    )
+    .fetch_all(transaction.deref_mut())
+    .await?;
+    let mut migrated = 0;
+    for row in rows {
+        let mut metadata = NoteMetadata {
+            schema_version: row.metadata_schema_version as u64,
+            created_at: row.metadata_created_at,
+            modified_at: row.metadata_modified_at,
+            tags: HashSet::from_iter(row.metadata_tags),
+            aliases: HashSet::from_iter(row.metadata_aliases),
+            pinned: row.metadata_pinned,
+            starred: row.metadata_starred,
+            archived: row.metadata_archived,
+            author: row.metadata_author,
+            custom_metadata: row.metadata_custom_metadata,
+        };
+        if !metadata.migrate()? {
+            continue;
+        }
+        let tags: Vec<String> = metadata.tags.into_iter().collect();
+        let aliases: Vec<String> = metadata.aliases.into_iter().collect();
+        query!(
+            r#"
+                UPDATE revision
+                SET
+                    metadata_schema_version = $2,
+                    metadata_created_at = $3,
+                    metadata_modified_at = $4,
+                    metadata_tags = $5,
+                    metadata_aliases = $6,
+                    metadata_pinned = $7,
+                    metadata_starred = $8,
+                    metadata_archived = $9,
+                    metadata_custom_metadata = $10
+                WHERE revision = $1
+                "#,
+            row.revision,
+            metadata.schema_version as i64,
+            metadata.created_at,
+            metadata.modified_at,
+            &tags,
+            &aliases,
+            metadata.pinned,
+            metadata.starred,
+            metadata.archived,
+            metadata.custom_metadata
+        )
+        .execute(transaction.deref_mut())
+        .await?;
+        migrated += 1;
+    }
+    Ok(migrated)
+}
+
 pub(super) async fn read_write(
     transaction: &mut Transaction<'_, Postgres>,
 ) -> Result<(), NoteStoreError> {
@@ -719,7 +1692,7 @@ pub(super) async fn get_tags(
         FROM
             revision
         LEFT JOIN current_revision cr ON revision.revision = cr.current_revision
-        LEFT JOIN LATERAL unnest(metadata_tags) tag ON TRUE
+        LEFT JOIN LATERAL unnest(metadata_tags || inline_tags) tag ON TRUE
         WHERE cr.current_revision IS NOT NULL
     "#
     )
@@ -732,3 +1705,129 @@ pub(super) async fn get_tags(
         Ok(vec![])
     }
 }
+
+pub(super) async fn get_tasks(
+    transaction: &mut Transaction<'_, Postgres>,
+    tq: &TaskQuery,
+) -> Result<Vec<NoteTask>, NoteStoreError> {
+    let rows = query!(
+        r#"
+        SELECT
+            revision.id,
+            revision.revision,
+            revision.title,
+            task.value AS "task!"
+        FROM
+            revision
+        JOIN current_revision cr ON revision.revision = cr.current_revision
+        CROSS JOIN LATERAL jsonb_array_elements(revision.tasks) task
+        WHERE ($1::boolean IS NULL OR (task.value->>'done')::boolean = $1)
+            AND ($2::date IS NULL OR (task.value->>'due')::date >= $2)
+            AND ($3::date IS NULL OR (task.value->>'due')::date <= $3)
+            AND (revision.metadata_tags || revision.inline_tags) @> $4
+    "#,
+        tq.done,
+        tq.due_after,
+        tq.due_before,
+        &tq.tags
+    )
+    .fetch_all(transaction.deref_mut())
+    .await
+    .map_err(NoteStoreError::PostgreSQLError)?;
+    rows.into_iter()
+        .map(|row| {
+            Ok(NoteTask {
+                id: row.id.into(),
+                revision: row.revision.into(),
+                title: row.title,
+                task: serde_json::from_value::<Task>(row.task)?,
+            })
+        })
+        .collect()
+}
+
+pub(super) async fn get_metadata_schemas(
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<MetadataSchemas, NoteStoreError> {
+    let rows = query!(r#"SELECT tag, schema FROM metadata_schema"#)
+        .fetch_all(transaction.deref_mut())
+        .await?;
+    let mut schemas = MetadataSchemas::default();
+    for row in rows {
+        match row.tag {
+            Some(tag) => {
+                schemas.tags.insert(tag, row.schema);
+            }
+            None => schemas.default = Some(row.schema),
+        }
+    }
+    Ok(schemas)
+}
+
+/// Replace the schema of a tag, or the default schema if `tag` is `None`.
+pub(super) async fn set_metadata_schema(
+    transaction: &mut Transaction<'_, Postgres>,
+    tag: Option<&str>,
+    schema: Option<serde_json::Value>,
+) -> Result<(), NoteStoreError> {
+    query!(
+        r#"DELETE FROM metadata_schema WHERE tag IS NOT DISTINCT FROM $1"#,
+        tag
+    )
+    .execute(transaction.deref_mut())
+    .await?;
+    if let Some(s) = schema {
+        query!(
+            r#"INSERT INTO metadata_schema(tag, schema) VALUES ($1, $2)"#,
+            tag,
+            s
+        )
+        .execute(transaction.deref_mut())
+        .await?;
+    }
+    Ok(())
+}
+
+/// Map the aliases in the current revisions of all notes to the notes.
+pub(super) async fn get_alias_owners(
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<HashMap<String, NoteID>, NoteStoreError> {
+    let rows = query!(
+        r#"
+        SELECT revision.id, alias AS "alias!"
+        FROM revision
+        JOIN current_revision cr ON revision.revision = cr.current_revision
+        CROSS JOIN LATERAL unnest(revision.metadata_aliases) alias
+        "#
+    )
+    .fetch_all(transaction.deref_mut())
+    .await?;
+    Ok(rows.into_iter().map(|r| (r.alias, r.id.into())).collect())
+}
+
+pub(super) async fn lookup_alias(
+    transaction: &mut Transaction<'_, Postgres>,
+    alias: &str,
+) -> Result<AliasTarget, NoteStoreError> {
+    // Only notes that are not deleted have a current revision
+    let row = query!(
+        r#"
+        SELECT revision.id, revision.revision = cr.current_revision AS "is_current!"
+        FROM revision
+        JOIN current_revision cr ON revision.id = cr.id
+        WHERE revision.metadata_aliases @> ARRAY[$1]
+        ORDER BY revision.revision = cr.current_revision DESC, revision.metadata_modified_at DESC
+        LIMIT 1
+        "#,
+        alias
+    )
+    .fetch_optional(transaction.deref_mut())
+    .await?;
+    match row {
+        Some(r) => Ok(AliasTarget {
+            id: r.id.into(),
+            redirect: !r.is_current,
+        }),
+        None => Err(NoteStoreError::AliasNotExist(alias.to_owned())),
+    }
+}
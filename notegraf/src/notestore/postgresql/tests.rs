@@ -1,6 +1,7 @@
 use super::*;
+use crate::notemetadata::NOTE_METADATA_CURRENT_SCHEMA_VERSION;
 use crate::notestore::tests as common_tests;
-use crate::notetype::PlainNote;
+use crate::notetype::{MarkdownNote, PlainNote};
 use sqlx::{Connection, Executor, PgConnection};
 use std::env;
 
@@ -28,7 +29,7 @@ fn get_connect_options() -> PgConnectOptions {
     }
 }
 
-async fn get_store() -> PostgreSQLStore<PlainNote> {
+async fn get_store_builder<T: NoteType>() -> PostgreSQLStoreBuilder<T> {
     let options = get_connect_options();
     let mut connection = PgConnection::connect_with(&options)
         .await
@@ -39,8 +40,10 @@ async fn get_store() -> PostgreSQLStore<PlainNote> {
         .await
         .expect("Failed to create database.");
     PostgreSQLStoreBuilder::new(options.database(&db_name))
-        .build()
-        .await
+}
+
+async fn get_store() -> PostgreSQLStore<PlainNote> {
+    get_store_builder().await.build().await
 }
 
 #[tokio::test]
@@ -128,6 +131,118 @@ async fn search_fulltext() {
     common_tests::search_fulltext(get_store().await).await;
 }
 
+#[tokio::test]
+async fn search_plain_text() {
+    common_tests::search_plain_text(get_store().await).await;
+}
+
+#[tokio::test]
+async fn search_markdown_plain_text() {
+    common_tests::search_markdown_plain_text(get_store_builder().await.build().await).await;
+}
+
+#[cfg(feature = "notetype_org")]
+#[tokio::test]
+async fn dynamic_formats() {
+    common_tests::dynamic_formats(get_store_builder().await.build().await).await;
+}
+
+#[tokio::test]
+async fn convert_note() {
+    common_tests::convert_note(get_store_builder().await.build().await).await;
+}
+
+#[cfg(feature = "notetype_block")]
+#[tokio::test]
+async fn block_note() {
+    common_tests::block_note(get_store_builder().await.build().await).await;
+}
+
+#[tokio::test]
+async fn migrate_metadata() {
+    common_tests::migrate_metadata(get_store().await).await;
+}
+
+#[tokio::test]
+async fn metadata_schema() {
+    common_tests::metadata_schema(get_store().await).await;
+}
+
+#[tokio::test]
+async fn aliases() {
+    common_tests::aliases(get_store().await).await;
+}
+
+#[tokio::test]
+async fn note_flags() {
+    common_tests::note_flags(get_store().await).await;
+}
+
+#[tokio::test]
+async fn author() {
+    common_tests::author(get_store().await).await;
+}
+
+#[tokio::test]
+async fn operation_author() {
+    common_tests::operation_author(get_store_builder().await.build().await).await;
+}
+
+#[tokio::test]
+async fn revert_note() {
+    common_tests::revert_note(get_store().await).await;
+}
+
+#[tokio::test]
+async fn trash() {
+    common_tests::trash(get_store_builder().await.build().await).await;
+}
+
+#[tokio::test]
+async fn compact_revisions() {
+    common_tests::compact_revisions(get_store_builder().await.build().await).await;
+}
+
+#[tokio::test]
+async fn keep_revision() {
+    common_tests::keep_revision(get_store_builder().await.build().await).await;
+}
+
+#[tokio::test]
+async fn delete_note_with() {
+    common_tests::delete_note_with(get_store_builder().await.build().await).await;
+}
+
+#[tokio::test]
+async fn check_integrity() {
+    common_tests::check_integrity(get_store_builder().await.build().await).await;
+}
+
+#[tokio::test]
+async fn move_note() {
+    common_tests::move_note(get_store_builder().await.build().await).await;
+}
+
+#[tokio::test]
+async fn move_note_cycle() {
+    common_tests::move_note_cycle(get_store_builder().await.build().await).await;
+}
+
+#[tokio::test]
+async fn apply_batch() {
+    common_tests::apply_batch(get_store_builder().await.build().await).await;
+}
+
+#[tokio::test]
+async fn diff() {
+    common_tests::diff(get_store_builder().await.build().await).await;
+}
+
+#[tokio::test]
+async fn diff_plain() {
+    common_tests::diff_plain(get_store_builder().await.build().await).await;
+}
+
 #[tokio::test]
 async fn search_nonexist() {
     common_tests::search_nonexist(get_store().await).await;
@@ -182,3 +297,542 @@ async fn search_lexeme_exclude() {
 async fn issue_158() {
     common_tests::issue_158(get_store().await).await;
 }
+
+#[tokio::test]
+async fn front_matter_new_note() {
+    common_tests::front_matter_new_note(
+        get_store_builder()
+            .await
+            .front_matter_sync(true)
+            .build()
+            .await,
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn front_matter_update_metadata() {
+    common_tests::front_matter_update_metadata(
+        get_store_builder()
+            .await
+            .front_matter_sync(true)
+            .build()
+            .await,
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn front_matter_update_body() {
+    common_tests::front_matter_update_body(
+        get_store_builder()
+            .await
+            .front_matter_sync(true)
+            .build()
+            .await,
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn front_matter_sync_disabled() {
+    common_tests::front_matter_sync_disabled(get_store_builder().await.build().await).await;
+}
+
+#[tokio::test]
+async fn inline_tags() {
+    common_tests::inline_tags(get_store_builder().await.build().await).await;
+}
+
+#[tokio::test]
+async fn search_inline_tags() {
+    common_tests::search_inline_tags(get_store_builder().await.build().await).await;
+}
+
+#[tokio::test]
+async fn tasks() {
+    common_tests::tasks(get_store_builder().await.build().await).await;
+}
+
+#[tokio::test]
+async fn toggle_task() {
+    common_tests::toggle_task(get_store_builder().await.build().await).await;
+}
+
+#[tokio::test]
+async fn corrupt_note() {
+    let store: PostgreSQLStore<PlainNote> = get_store().await;
+    let loc = store
+        .new_note(
+            "".to_owned(),
+            PlainNote::new("Foo".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let (_, revision) = loc.unpack_uuid().unwrap();
+    query!(
+        "UPDATE revision SET note_inner = 'Foo' WHERE revision = $1",
+        revision.unwrap()
+    )
+    .execute(&store.db_pool)
+    .await
+    .unwrap();
+    assert!(matches!(
+        store.get_note(&loc).await,
+        Err(NoteStoreError::ParseError(_))
+    ));
+    assert!(matches!(
+        store
+            .update_note(
+                &loc,
+                Some("Bar".to_owned()),
+                None,
+                NoteMetadataEditable::unchanged()
+            )
+            .await,
+        Err(NoteStoreError::ParseError(_))
+    ));
+}
+
+#[tokio::test]
+async fn newer_metadata_schema() {
+    let store: PostgreSQLStore<PlainNote> = get_store().await;
+    let loc = store
+        .new_note(
+            "".to_owned(),
+            PlainNote::new("Foo".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let (_, revision) = loc.unpack_uuid().unwrap();
+    query!(
+        "UPDATE revision SET metadata_schema_version = $2 WHERE revision = $1",
+        revision.unwrap(),
+        NOTE_METADATA_CURRENT_SCHEMA_VERSION as i64 + 1
+    )
+    .execute(&store.db_pool)
+    .await
+    .unwrap();
+    assert!(matches!(
+        store.get_note(&loc).await,
+        Err(NoteStoreError::MetadataSchemaTooNew(_, _))
+    ));
+    assert!(matches!(
+        store
+            .update_note(&loc, None, None, NoteMetadataEditable::unchanged())
+            .await,
+        Err(NoteStoreError::MetadataSchemaTooNew(_, _))
+    ));
+    assert!(matches!(
+        store.migrate_metadata().await,
+        Err(NoteStoreError::MetadataSchemaTooNew(_, _))
+    ));
+}
+
+/// Update a note with a line appended each time, returning the bodies of all revisions.
+async fn append_lines(
+    store: &PostgreSQLStore<MarkdownNote>,
+    loc: &NoteLocator,
+    n: usize,
+) -> Vec<String> {
+    let mut body: String = (0..10)
+        .map(|i| format!("An opening line, number {i}\n"))
+        .collect();
+    let mut bodies = vec![];
+    for i in 0..n {
+        body.push_str(&format!("A line appended in revision {i}\n"));
+        store
+            .update_note(
+                &loc.current(),
+                None,
+                Some(MarkdownNote::new(body.clone())),
+                NoteMetadataEditable::unchanged(),
+            )
+            .await
+            .unwrap();
+        bodies.push(body.clone());
+    }
+    bodies
+}
+
+async fn get_bodies(store: &PostgreSQLStore<MarkdownNote>, loc: &NoteLocator) -> Vec<String> {
+    store
+        .get_revisions(loc)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|n| n.get_note_inner().into())
+        .collect()
+}
+
+#[tokio::test]
+async fn delta_storage() {
+    let store: PostgreSQLStore<MarkdownNote> = get_store_builder().await.build().await;
+    let loc = store
+        .new_note(
+            "".to_owned(),
+            MarkdownNote::new("".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let mut bodies = vec!["".to_owned()];
+    bodies.extend(append_lines(&store, &loc, 20).await);
+    assert_eq!(get_bodies(&store, &loc).await, bodies);
+    let rows = query!(
+        r#"
+            SELECT
+                note_inner IS NULL AS "note_inner_null!",
+                plain_text IS NULL AS "plain_text_null!",
+                text_searchable IS NULL AS "text_searchable_null!",
+                delta_base IS NOT NULL AS "is_delta!",
+                cr.id IS NOT NULL AS "is_current!"
+            FROM revision
+            LEFT JOIN current_revision cr ON cr.current_revision = revision.revision
+            "#
+    )
+    .fetch_all(&store.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(rows.len(), 21);
+    for row in rows.iter() {
+        assert_eq!(row.note_inner_null, !row.is_current);
+        assert_eq!(row.plain_text_null, !row.is_current);
+        assert_eq!(row.text_searchable_null, !row.is_current);
+    }
+    // In full are the empty first revision, the second one whose delta against it would be no
+    // smaller, and the one whose delta would make the chain of deltas too long
+    assert_eq!(rows.iter().filter(|r| r.is_delta).count(), 17);
+    // Deleting a note stores its last revision compactly too
+    store.delete_note(&loc.current()).await.unwrap();
+    let stored = query!(r#"SELECT count(*) AS "count!" FROM revision WHERE note_inner IS NULL"#)
+        .fetch_one(&store.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(stored.count, 21);
+    assert_eq!(get_bodies(&store, &loc).await, bodies);
+    assert_eq!(store.list_deleted().await.unwrap().len(), 1);
+    // Resurrecting a note starts from its last revision
+    let last = store.get_revisions(&loc).await.unwrap().pop().unwrap();
+    store
+        .update_note(
+            &NoteLocator::Specific(last.get_id(), last.get_revision()),
+            Some("Back".to_owned()),
+            None,
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let note = store.get_note(&loc.current()).await.unwrap();
+    assert_eq!(String::from(note.get_note_inner()), bodies[20]);
+}
+
+#[tokio::test]
+async fn delta_storage_compaction() {
+    let store: PostgreSQLStore<MarkdownNote> = get_store_builder().await.build().await;
+    let loc = store
+        .new_note(
+            "".to_owned(),
+            MarkdownNote::new("".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let mut bodies = vec!["".to_owned()];
+    bodies.extend(append_lines(&store, &loc, 10).await);
+    let revisions: Vec<Revision> = store
+        .get_revisions(&loc)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|n| n.get_revision())
+        .collect();
+    // Keep two revisions that are stored as deltas against removed ones
+    store
+        .new_note(
+            "".to_owned(),
+            MarkdownNote::new(format!(
+                "notegraf:/note/{0}/revision/{1} notegraf:/note/{0}/revision/{2}",
+                loc.get_id(),
+                revisions[5],
+                revisions[8]
+            )),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let policy = CompactionPolicy { rules: vec![] };
+    assert_eq!(store.compact_revisions(&policy).await.unwrap(), 8);
+    assert_eq!(
+        get_bodies(&store, &loc).await,
+        vec![bodies[5].clone(), bodies[8].clone(), bodies[10].clone()]
+    );
+}
+
+#[tokio::test]
+async fn pack_revisions_migrated() {
+    let store: PostgreSQLStore<MarkdownNote> = get_store_builder().await.build().await;
+    let loc = store
+        .new_note(
+            "".to_owned(),
+            MarkdownNote::new("".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let mut bodies = vec!["".to_owned()];
+    bodies.extend(append_lines(&store, &loc, 3).await);
+    // As converted by the migration
+    let revisions = store.get_revisions(&loc).await.unwrap();
+    for (note, body) in revisions.iter().zip(bodies.iter()).take(3) {
+        query!(
+            r#"
+                UPDATE revision
+                SET note_delta = '\x04'::bytea || convert_to($2, 'UTF8'), delta_base = NULL
+                WHERE revision = $1
+                "#,
+            note.get_revision().to_uuid().unwrap(),
+            body
+        )
+        .execute(&store.db_pool)
+        .await
+        .unwrap();
+    }
+    assert_eq!(get_bodies(&store, &loc).await, bodies);
+    let mut transaction = store.db_pool.begin().await.unwrap();
+    assert_eq!(queries::pack_revisions(&mut transaction).await.unwrap(), 3);
+    transaction.commit().await.unwrap();
+    assert_eq!(get_bodies(&store, &loc).await, bodies);
+    let row = query!(r#"SELECT count(*) AS "count!" FROM revision WHERE delta_base IS NOT NULL"#)
+        .fetch_one(&store.db_pool)
+        .await
+        .unwrap();
+    // Only the third revision, as the delta of the second one against the empty first one would
+    // be no smaller
+    assert_eq!(row.count, 1);
+    let mut transaction = store.db_pool.begin().await.unwrap();
+    assert_eq!(queries::pack_revisions(&mut transaction).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn reindex_migrated() {
+    let store: PostgreSQLStore<MarkdownNote> = get_store_builder().await.build().await;
+    let loc = store
+        .new_note(
+            "".to_owned(),
+            MarkdownNote::new("Some #tag\n\n- [ ] [task](https://example.com/foo)".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    // As stored before the migrations
+    query!(
+        r#"
+            UPDATE revision
+            SET inline_tags = '{}', tasks = '[]', plain_text = note_inner, reindex = true
+            WHERE revision = $1
+            "#,
+        loc.unpack_uuid().unwrap().1.unwrap()
+    )
+    .execute(&store.db_pool)
+    .await
+    .unwrap();
+    assert!(store.tags().await.unwrap().is_empty());
+    assert!(store.tasks(&TaskQuery::default()).await.unwrap().is_empty());
+    assert_eq!(
+        store.search(&("example.com".into())).await.unwrap().len(),
+        1
+    );
+    let mut transaction = store.db_pool.begin().await.unwrap();
+    assert_eq!(
+        queries::reindex_revisions::<MarkdownNote>(&mut transaction, false)
+            .await
+            .unwrap(),
+        1
+    );
+    transaction.commit().await.unwrap();
+    assert_eq!(store.tags().await.unwrap(), vec!["tag".to_owned()]);
+    assert_eq!(store.tasks(&TaskQuery::default()).await.unwrap().len(), 1);
+    assert_eq!(
+        store.search(&("example.com".into())).await.unwrap().len(),
+        0
+    );
+    assert_eq!(store.search(&("task".into())).await.unwrap().len(), 1);
+    let mut transaction = store.db_pool.begin().await.unwrap();
+    assert_eq!(
+        queries::reindex_revisions::<MarkdownNote>(&mut transaction, false)
+            .await
+            .unwrap(),
+        0
+    );
+}
+
+#[tokio::test]
+async fn rejected_note_leaves_nothing() {
+    let store: PostgreSQLStore<MarkdownNote> = get_store_builder().await.build().await;
+    // The note is only rejected once its revision is inserted, as the link is not to a UUID
+    assert!(matches!(
+        store
+            .new_note(
+                "".to_owned(),
+                MarkdownNote::new("[foo](notegraf:/note/note-1)".into()),
+                NoteMetadataEditable::unchanged(),
+            )
+            .await,
+        Err(NoteStoreError::NotUuid(_))
+    ));
+    let row = query!(r#"SELECT count(*) AS "count!" FROM note"#)
+        .fetch_one(&store.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(row.count, 0);
+    assert!(store.check_integrity(false).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn repair_integrity() {
+    let store: PostgreSQLStore<PlainNote> = get_store_builder().await.build().await;
+    let new_note = || {
+        store.new_note(
+            "".to_owned(),
+            PlainNote::new("".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+    };
+    let loc_a = new_note().await.unwrap();
+    let loc_b = store
+        .append_note(
+            loc_a.get_id(),
+            "".to_owned(),
+            PlainNote::new("".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let (loc_c, loc_d, loc_e) = (
+        new_note().await.unwrap(),
+        new_note().await.unwrap(),
+        new_note().await.unwrap(),
+    );
+    let set_prev = |loc: &NoteLocator, prev: &NoteLocator| {
+        query!(
+            "UPDATE revision SET prev = $2 WHERE revision = $1",
+            loc.get_revision().unwrap().to_uuid().unwrap(),
+            prev.get_id().to_uuid().unwrap()
+        )
+        .execute(&store.db_pool)
+    };
+    // A has two next notes, B and C, and D and E come after each other
+    set_prev(&loc_c, &loc_a).await.unwrap();
+    set_prev(&loc_d, &loc_e).await.unwrap();
+    set_prev(&loc_e, &loc_d).await.unwrap();
+    // D refers to a note that does not exist
+    let missing = Uuid::new_v4();
+    query!(
+        "UPDATE revision SET referents = ARRAY[$2::uuid] WHERE revision = $1",
+        loc_d.get_revision().unwrap().to_uuid().unwrap(),
+        missing
+    )
+    .execute(&store.db_pool)
+    .await
+    .unwrap();
+    // The current revision of a note is lost
+    let loc_f = new_note().await.unwrap();
+    query!(
+        "DELETE FROM current_revision WHERE id = $1",
+        loc_f.get_id().to_uuid().unwrap()
+    )
+    .execute(&store.db_pool)
+    .await
+    .unwrap();
+
+    let mut nexts = vec![loc_b.get_id().clone(), loc_c.get_id().clone()];
+    nexts.sort_by(|a, b| a.as_ref().cmp(b.as_ref()));
+    let issues = store.check_integrity(false).await.unwrap();
+    assert_eq!(issues.len(), 4);
+    // The notes can still be read, though not the inconsistent ones
+    assert!(matches!(
+        store.get_note(&loc_a.current()).await,
+        Err(NoteStoreError::Inconsistent(_, _))
+    ));
+    assert!(issues.contains(&IntegrityIssue::SeveralNexts {
+        note: loc_a.get_id().clone(),
+        nexts: nexts.clone(),
+    }));
+    assert!(issues.contains(&IntegrityIssue::DanglingReferent {
+        note: loc_d.get_id().clone(),
+        referent: missing.into(),
+    }));
+    assert!(issues
+        .iter()
+        .any(|i| matches!(i, IntegrityIssue::Cycle { notes } if notes.len() == 2)));
+    assert!(issues.contains(&IntegrityIssue::OrphanedRevision {
+        note: loc_f.get_id().clone(),
+        revision: loc_f.get_revision().unwrap().clone(),
+    }));
+
+    assert_eq!(store.check_integrity(true).await.unwrap(), issues);
+    assert!(store.check_integrity(false).await.unwrap().is_empty());
+    let note_a = store.get_note(&loc_a.current()).await.unwrap();
+    assert_eq!(note_a.get_next().as_ref(), Some(&nexts[0]));
+    assert!(store
+        .get_note(&loc_d.current())
+        .await
+        .unwrap()
+        .get_referents()
+        .is_empty());
+    assert_eq!(
+        store.list_deleted().await.unwrap()[0].note.get_id(),
+        *loc_f.get_id()
+    );
+}
+
+#[tokio::test]
+async fn concurrent_writes() {
+    let store: PostgreSQLStore<PlainNote> = get_store_builder().await.build().await;
+    let new_note = |body: &str| PlainNote::new(body.into());
+    let loc = store
+        .new_note(
+            "".to_owned(),
+            new_note("Head"),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let id = loc.get_id();
+    let loc = loc.current();
+
+    // Only one of the notes can come right after the head
+    let appends = (0..16).map(|i| {
+        store.append_note(
+            id,
+            "".to_owned(),
+            new_note(&format!("Next {i}")),
+            NoteMetadataEditable::unchanged(),
+        )
+    });
+    let results = futures::future::join_all(appends).await;
+    assert_eq!(results.iter().filter(|res| res.is_ok()).count(), 1);
+    for res in results {
+        assert!(
+            matches!(res, Ok(_) | Err(NoteStoreError::ExistingNext(_, _))),
+            "{res:?}"
+        );
+    }
+
+    let updates = (0..16).map(|i| {
+        store.update_note(
+            &loc,
+            Some(format!("Title {i}")),
+            None,
+            NoteMetadataEditable::unchanged(),
+        )
+    });
+    for res in futures::future::join_all(updates).await {
+        res.unwrap();
+    }
+    // Appending a note doesn't change the previous note
+    assert_eq!(store.get_revisions(&loc).await.unwrap().len(), 17);
+}
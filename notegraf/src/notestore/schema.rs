@@ -0,0 +1,181 @@
+//! Validation of custom metadata against JSON Schemas.
+use crate::errors::NoteStoreError;
+use crate::notemetadata::NoteMetadata;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// JSON Schemas that the custom metadata of notes must conform to.
+///
+/// The custom metadata of a note is validated against the default schema and the schemas of all
+/// tags in its metadata.
+/// Tags written in the body of a note don't bring in their schemas.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MetadataSchemas {
+    /// The schema that applies to all notes.
+    pub default: Option<Value>,
+    /// The schemas that apply to notes with a tag, keyed by the tag.
+    pub tags: BTreeMap<String, Value>,
+}
+
+/// A way in which the custom metadata of a note doesn't conform to a schema.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SchemaViolation {
+    /// The tag of the schema, or `None` for the default schema.
+    pub tag: Option<String>,
+    /// A JSON Pointer to the offending value in the custom metadata.
+    pub instance_path: String,
+    pub message: String,
+}
+
+impl fmt::Display for SchemaViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = if self.instance_path.is_empty() {
+            "/"
+        } else {
+            &self.instance_path
+        };
+        match self.tag {
+            Some(ref tag) => write!(f, "{path}: {} (schema of tag `{tag}`)", self.message),
+            None => write!(f, "{path}: {} (default schema)", self.message),
+        }
+    }
+}
+
+/// Check that a schema is a valid JSON Schema before it is registered.
+pub(crate) fn check_schema(schema: &Value) -> Result<(), NoteStoreError> {
+    jsonschema::validator_for(schema)
+        .map(|_| ())
+        .map_err(|e| NoteStoreError::InvalidMetadataSchema(e.to_string()))
+}
+
+impl MetadataSchemas {
+    /// Get the schema of a tag, or the default schema if `tag` is `None`.
+    pub fn get(&self, tag: Option<&str>) -> Option<&Value> {
+        match tag {
+            Some(t) => self.tags.get(t),
+            None => self.default.as_ref(),
+        }
+    }
+
+    /// Set the schema of a tag, or the default schema if `tag` is `None`.
+    ///
+    /// Setting the schema to `None` removes it.
+    pub fn set(&mut self, tag: Option<&str>, schema: Option<Value>) -> Result<(), NoteStoreError> {
+        if let Some(ref s) = schema {
+            check_schema(s)?;
+        }
+        match (tag, schema) {
+            (Some(t), Some(s)) => {
+                self.tags.insert(t.to_owned(), s);
+            }
+            (Some(t), None) => {
+                self.tags.remove(t);
+            }
+            (None, s) => self.default = s,
+        }
+        Ok(())
+    }
+
+    /// Validate the custom metadata against every schema that applies.
+    ///
+    /// All violations are reported, starting with those of the default schema.
+    pub fn validate(&self, metadata: &NoteMetadata) -> Result<(), NoteStoreError> {
+        let mut tags: Vec<&String> = metadata.tags.iter().collect();
+        tags.sort();
+        let schemas = self.default.iter().map(|s| (None, s)).chain(
+            tags.into_iter()
+                .filter_map(|t| self.tags.get(t).map(|s| (Some(t), s))),
+        );
+        let mut violations = vec![];
+        for (tag, schema) in schemas {
+            let validator = jsonschema::validator_for(schema)
+                .map_err(|e| NoteStoreError::InvalidMetadataSchema(e.to_string()))?;
+            violations.extend(validator.iter_errors(&metadata.custom_metadata).map(|e| {
+                SchemaViolation {
+                    tag: tag.cloned(),
+                    instance_path: e.instance_path.to_string(),
+                    message: e.to_string(),
+                }
+            }));
+        }
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(NoteStoreError::MetadataSchemaViolation(violations))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn status_schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {"status": {"enum": ["todo", "done"]}},
+            "additionalProperties": false
+        })
+    }
+
+    #[test]
+    fn set_invalid_schema() {
+        let mut schemas = MetadataSchemas::default();
+        assert!(matches!(
+            schemas.set(Some("project"), Some(json!({"type": "no-such-type"}))),
+            Err(NoteStoreError::InvalidMetadataSchema(_))
+        ));
+        assert!(schemas.get(Some("project")).is_none());
+    }
+
+    #[test]
+    fn validate_by_tag() {
+        let mut schemas = MetadataSchemas::default();
+        schemas.set(Some("project"), Some(status_schema())).unwrap();
+        let mut metadata = NoteMetadata {
+            custom_metadata: json!({"stauts": "done"}),
+            ..Default::default()
+        };
+        // The schema doesn't apply without the tag
+        schemas.validate(&metadata).unwrap();
+        metadata.tags.insert("project".to_owned());
+        match schemas.validate(&metadata) {
+            Err(NoteStoreError::MetadataSchemaViolation(v)) => {
+                assert_eq!(v.len(), 1);
+                assert_eq!(v[0].tag.as_deref(), Some("project"));
+                assert_eq!(v[0].instance_path, "");
+            }
+            r => panic!("unexpected result {r:?}"),
+        }
+        metadata.custom_metadata = json!({"status": "done"});
+        schemas.validate(&metadata).unwrap();
+        schemas.set(Some("project"), None).unwrap();
+        assert_eq!(schemas, MetadataSchemas::default());
+    }
+
+    #[test]
+    fn validate_all_schemas() {
+        let mut schemas = MetadataSchemas::default();
+        schemas
+            .set(None, Some(json!({"required": ["owner"]})))
+            .unwrap();
+        schemas.set(Some("project"), Some(status_schema())).unwrap();
+        let metadata = NoteMetadata {
+            tags: ["project".to_owned()].into(),
+            custom_metadata: json!({"status": 1}),
+            ..Default::default()
+        };
+        match schemas.validate(&metadata) {
+            Err(NoteStoreError::MetadataSchemaViolation(v)) => {
+                assert_eq!(v.len(), 2);
+                assert_eq!(v[0].tag, None);
+                assert_eq!(v[1].tag.as_deref(), Some("project"));
+                assert_eq!(v[1].instance_path, "/status");
+            }
+            r => panic!("unexpected result {r:?}"),
+        }
+    }
+}
@@ -3,8 +3,14 @@ pub struct SearchRequest {
     pub(super) lexemes_excluded: Vec<String>,
     pub(super) tags: Vec<String>,
     pub(super) tags_excluded: Vec<String>,
+    /// Only match notes with a revision by each of these authors.
+    pub(super) authors: Vec<String>,
     pub(super) orphan: bool,
     pub(super) no_tag: bool,
+    pub(super) pinned: bool,
+    pub(super) starred: bool,
+    /// Only match archived notes, which are excluded otherwise.
+    pub(super) archived: bool,
     pub(super) limit: Option<u64>,
 }
 
@@ -22,9 +28,13 @@ fn parse_query(query: &str) -> SearchRequest {
     let mut lexemes_excluded = vec![];
     let mut tags = vec![];
     let mut tags_excluded = vec![];
+    let mut authors = vec![];
     let mut orphan = false;
     let mut limit = None;
     let mut no_tag = false;
+    let mut pinned = false;
+    let mut starred = false;
+    let mut archived = false;
     let mut no_limit = false;
     for part in parts {
         if let Some(stripped) = part.strip_prefix('#') {
@@ -36,11 +46,21 @@ fn parse_query(query: &str) -> SearchRequest {
                 orphan = true;
             } else if stripped == "notag" {
                 no_tag = true;
+            } else if stripped == "pinned" {
+                pinned = true;
+            } else if stripped == "starred" {
+                starred = true;
+            } else if stripped == "archived" {
+                archived = true;
             } else if stripped == "nolimit" {
                 no_limit = true;
             } else if let Some(limit_str) = stripped.strip_prefix("limit=") {
                 limit = limit_str.parse::<u64>().ok();
             }
+        } else if let Some(stripped) = part.strip_prefix("author:") {
+            if !stripped.is_empty() {
+                authors.push(stripped.to_owned());
+            }
         } else if let Some(negation) = part.strip_prefix('-') {
             if let Some(stripped) = negation.strip_prefix('#') {
                 if !stripped.is_empty() {
@@ -64,8 +84,12 @@ fn parse_query(query: &str) -> SearchRequest {
         lexemes_excluded,
         tags,
         tags_excluded,
+        authors,
         orphan,
         no_tag,
+        pinned,
+        starred,
+        archived,
         limit,
     }
 }
@@ -218,4 +242,20 @@ mod tests {
         let sr: SearchRequest = "!nolimit !limit=512".into();
         assert_eq!(sr.limit, None);
     }
+
+    #[test]
+    fn flags() {
+        let sr: SearchRequest = "foo".into();
+        assert!(!sr.pinned && !sr.starred && !sr.archived);
+        let sr: SearchRequest = "!pinned !starred !archived foo".into();
+        assert!(sr.pinned && sr.starred && sr.archived);
+        assert_eq!(sr.lexemes, vec!["foo".to_owned()]);
+    }
+
+    #[test]
+    fn authors() {
+        let sr: SearchRequest = "author:alice author: runbook".into();
+        assert_eq!(sr.authors, vec!["alice".to_owned()]);
+        assert_eq!(sr.lexemes, vec!["runbook".to_owned()]);
+    }
 }
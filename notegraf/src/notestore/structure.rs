@@ -0,0 +1,32 @@
+//! Changing where notes are among branches and sequences.
+use crate::NoteID;
+use serde::Deserialize;
+
+/// Where [`NoteStore::move_note`](crate::NoteStore::move_note) moves a note.
+///
+/// Notes are given by their IDs, or by other means such as in a
+/// [batch](crate::notestore::batch::NoteRef).
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "kind", content = "note", rename_all = "snake_case")]
+pub enum MoveTarget<N = NoteID> {
+    /// Neither a branch nor in a sequence.
+    Standalone,
+    /// A branch of the note.
+    BranchOf(N),
+    /// Right after the last (or only) note in a sequence.
+    After(N),
+}
+
+impl<N> MoveTarget<N> {
+    /// Change how the note is given, e.g., from a placeholder to its ID.
+    pub fn try_map<M, E, F>(&self, f: F) -> Result<MoveTarget<M>, E>
+    where
+        F: FnOnce(&N) -> Result<M, E>,
+    {
+        Ok(match self {
+            MoveTarget::Standalone => MoveTarget::Standalone,
+            MoveTarget::BranchOf(note) => MoveTarget::BranchOf(f(note)?),
+            MoveTarget::After(note) => MoveTarget::After(f(note)?),
+        })
+    }
+}
@@ -0,0 +1,145 @@
+//! Queries of tasks across notes.
+use crate::errors::NoteStoreError;
+use crate::notetype::Task;
+use crate::{NoteID, NoteType, Revision};
+use chrono::{Datelike, Days, NaiveDate};
+use serde::Serialize;
+use std::collections::HashSet;
+
+/// A query of the tasks in the current revisions of notes.
+///
+/// A task matches if all the conditions hold.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TaskQuery {
+    /// Whether the task is done. `None` matches both.
+    pub done: Option<bool>,
+    /// The earliest due date (inclusive). Tasks without a due date don't match.
+    pub due_after: Option<NaiveDate>,
+    /// The latest due date (inclusive). Tasks without a due date don't match.
+    pub due_before: Option<NaiveDate>,
+    /// Tags that the note of the task must have, either in its metadata or in its body.
+    pub tags: Vec<String>,
+}
+
+impl TaskQuery {
+    /// Tasks that are not done yet.
+    pub fn open() -> Self {
+        TaskQuery {
+            done: Some(false),
+            ..Default::default()
+        }
+    }
+
+    /// Only match tasks due in the week (Monday to Sunday) of `day`.
+    pub fn due_in_week_of(mut self, day: NaiveDate) -> Self {
+        let monday = day - Days::new(day.weekday().num_days_from_monday() as u64);
+        self.due_after = Some(monday);
+        self.due_before = Some(monday + Days::new(6));
+        self
+    }
+
+    /// Only match tasks in notes with the tag.
+    pub fn with_tag(mut self, tag: String) -> Self {
+        self.tags.push(tag);
+        self
+    }
+
+    pub(super) fn matches(&self, task: &Task, tags: &HashSet<String>) -> bool {
+        self.done.is_none_or(|d| task.done == d)
+            && self
+                .due_after
+                .is_none_or(|after| task.due.is_some_and(|due| due >= after))
+            && self
+                .due_before
+                .is_none_or(|before| task.due.is_some_and(|due| due <= before))
+            && self.tags.iter().all(|t| tags.contains(t))
+    }
+}
+
+/// A task together with the note it is in.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NoteTask {
+    pub id: NoteID,
+    pub revision: Revision,
+    pub title: String,
+    #[serde(flatten)]
+    pub task: Task,
+}
+
+/// Sort tasks by due date, with tasks without a due date last.
+///
+/// Tasks of the same note are kept in the order they appear.
+pub(super) fn sort_tasks(tasks: &mut [NoteTask]) {
+    tasks.sort_by(|a, b| {
+        let key = |t: &NoteTask| {
+            (
+                t.task.due.is_none(),
+                t.task.due,
+                t.id.to_string(),
+                t.task.index,
+            )
+        };
+        key(a).cmp(&key(b))
+    });
+}
+
+/// Toggle whether a task in the body of a note is done.
+pub(super) fn toggle_task<T: NoteType>(
+    id: &NoteID,
+    note_inner: &mut T,
+    index: usize,
+) -> Result<(), NoteStoreError> {
+    let tasks = note_inner.get_tasks().map_err(NoteStoreError::note_inner)?;
+    let task = tasks
+        .get(index)
+        .ok_or_else(|| NoteStoreError::TaskNotExist(id.clone(), index))?;
+    note_inner
+        .set_task_done(index, !task.done)
+        .map_err(NoteStoreError::note_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(done: bool, due: Option<NaiveDate>) -> Task {
+        Task {
+            index: 0,
+            text: "foo".to_owned(),
+            done,
+            due,
+        }
+    }
+
+    #[test]
+    fn open() {
+        let query = TaskQuery::open();
+        assert!(query.matches(&task(false, None), &HashSet::new()));
+        assert!(!query.matches(&task(true, None), &HashSet::new()));
+    }
+
+    #[test]
+    fn due_in_week() {
+        // 2026-10-21 is a Wednesday
+        let query =
+            TaskQuery::default().due_in_week_of(NaiveDate::from_ymd_opt(2026, 10, 21).unwrap());
+        assert_eq!(query.due_after, NaiveDate::from_ymd_opt(2026, 10, 19));
+        assert_eq!(query.due_before, NaiveDate::from_ymd_opt(2026, 10, 25));
+        assert!(query.matches(&task(true, query.due_before), &HashSet::new()));
+        assert!(!query.matches(
+            &task(false, NaiveDate::from_ymd_opt(2026, 10, 26)),
+            &HashSet::new()
+        ));
+        assert!(!query.matches(&task(false, None), &HashSet::new()));
+    }
+
+    #[test]
+    fn tags() {
+        let query = TaskQuery::default().with_tag("foo".to_owned());
+        assert!(query.matches(
+            &task(false, None),
+            &HashSet::from(["foo".to_owned(), "bar".to_owned()])
+        ));
+        assert!(!query.matches(&task(false, None), &HashSet::from(["bar".to_owned()])));
+    }
+}
@@ -1,8 +1,32 @@
 use crate::errors::NoteStoreError;
-use crate::notemetadata::NoteMetadataEditable;
-use crate::{NoteLocator, NoteStore, PlainNote};
+use crate::notemetadata::{NoteFlag, NoteMetadataEditable, NOTE_METADATA_CURRENT_SCHEMA_VERSION};
+use crate::notestore::batch::{BatchOperation, NoteRef};
+use crate::notestore::compaction::CompactionPolicy;
+use crate::notestore::delete::DeleteOptions;
+use crate::notestore::diff::LineChangeKind;
+use crate::notestore::schema::MetadataSchemas;
+use crate::notestore::structure::MoveTarget;
+use crate::notestore::task::TaskQuery;
+#[cfg(feature = "notetype_block")]
+use crate::notetype::{Block, CalloutKind, ChecklistItem};
+use crate::notetype::{LinkRewrite, NoteFormat};
+#[cfg(feature = "notetype_block")]
+use crate::BlockNote;
+#[cfg(feature = "notetype_org")]
+use crate::OrgNote;
+use crate::{DynamicNote, MarkdownNote, Note, NoteID, NoteLocator, NoteStore, NoteType, PlainNote};
+use chrono::{NaiveDate, Utc};
+use serde_json::json;
 use std::collections::HashSet;
 use std::option::Option::None;
+use uuid::Uuid;
+
+async fn get_current(store: &impl NoteStore<PlainNote>, id: &NoteID) -> Box<dyn Note<PlainNote>> {
+    store
+        .get_note(&NoteLocator::Current(id.clone()))
+        .await
+        .unwrap()
+}
 
 async fn is_deleted(
     store: &impl NoteStore<PlainNote>,
@@ -586,242 +610,1898 @@ pub(super) async fn search_fulltext(store: impl NoteStore<PlainNote>) {
     assert_eq!(notes.len(), 2);
 }
 
-pub(super) async fn search_nonexist(store: impl NoteStore<PlainNote>) {
-    let note_inner = PlainNote::new("Foo".into());
+pub(super) async fn search_plain_text(store: impl NoteStore<PlainNote>) {
+    let loc1 = store
+        .new_note(
+            "".to_owned(),
+            PlainNote::new("Bar".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let mut note_inner = PlainNote::new("Foo".into());
+    note_inner.add_referent(loc1.get_id().clone());
     store
         .new_note(
             "hello world".to_owned(),
-            note_inner.clone(),
+            note_inner,
             NoteMetadataEditable::unchanged(),
         )
         .await
         .unwrap();
+    let notes = store.search(&("foo".into())).await.unwrap();
+    assert_eq!(notes.len(), 1);
+    // Neither the serialized fields nor the referents are searchable
+    let notes = store.search(&("body".into())).await.unwrap();
+    assert!(notes.is_empty());
+    let notes = store.search(&("referents".into())).await.unwrap();
+    assert!(notes.is_empty());
+    let notes = store
+        .search(&(loc1.get_id().as_ref().into()))
+        .await
+        .unwrap();
+    assert!(notes.is_empty());
+}
+
+pub(super) async fn search_markdown_plain_text(store: impl NoteStore<MarkdownNote>) {
     store
         .new_note(
-            "goodbye world".to_owned(),
-            note_inner.clone(),
+            "hello world".to_owned(),
+            "[a link](giraffe)\n\n```python\nprint(1)\n```".into(),
             NoteMetadataEditable::unchanged(),
         )
         .await
         .unwrap();
-    let notes = store.search(&("fizzbuzz".into())).await.unwrap();
-    assert_eq!(notes.len(), 0);
+    let notes = store.search(&("link".into())).await.unwrap();
+    assert_eq!(notes.len(), 1);
+    let notes = store.search(&("print".into())).await.unwrap();
+    assert_eq!(notes.len(), 1);
+    let notes = store.search(&("giraffe".into())).await.unwrap();
+    assert!(notes.is_empty());
+    let notes = store.search(&("python".into())).await.unwrap();
+    assert!(notes.is_empty());
 }
 
-pub(super) async fn backlink(store: impl NoteStore<PlainNote>) {
-    let note_inner_1 = PlainNote::new("Hello world".into());
-    let loc1 = store
+#[cfg(feature = "notetype_org")]
+pub(super) async fn dynamic_formats(store: impl NoteStore<DynamicNote>) {
+    let loc_md = store
         .new_note(
             "".to_owned(),
-            note_inner_1.clone(),
+            DynamicNote::Markdown(MarkdownNote::new("# Foo".to_owned())),
             NoteMetadataEditable::unchanged(),
         )
         .await
         .unwrap();
-    let mut note_inner_2 = PlainNote::new("Goodbye world".into());
-    note_inner_2.add_referent(loc1.get_id().clone());
-    let loc2 = store
+    let mut plain = PlainNote::new("Bar".to_owned());
+    plain.add_referent(loc_md.get_id().to_owned());
+    let loc_plain = store
         .new_note(
-            "goodbye world".to_owned(),
-            note_inner_2.clone(),
+            "".to_owned(),
+            DynamicNote::Plain(plain),
             NoteMetadataEditable::unchanged(),
         )
         .await
         .unwrap();
-    let note = store.get_note(&loc1.current()).await.unwrap();
-    let references = note.get_references();
-    assert!(references.contains(loc2.get_id()));
-}
-
-pub(super) async fn search_tags(store: impl NoteStore<PlainNote>) {
-    let note_inner = PlainNote::new("Foo".into());
-    let md = NoteMetadataEditable {
-        tags: Some(HashSet::from_iter(["tag1".to_owned()])),
-        custom_metadata: None,
-    };
-    store
-        .new_note("hello world".to_owned(), note_inner.clone(), md.clone())
+    // Org-mode and Markdown notes are stored in the same way, so the format must be recorded
+    let body = format!(
+        "* Baz
+[[notegraf:/note/{}][foo]]",
+        loc_md.get_id()
+    );
+    let loc_org = store
+        .new_note(
+            "".to_owned(),
+            DynamicNote::Org(OrgNote::new(body.clone())),
+            NoteMetadataEditable::unchanged(),
+        )
         .await
         .unwrap();
+    let note_org = store.get_note(&loc_org.current()).await.unwrap();
+    assert_eq!(note_org.get_note_inner().get_format(), NoteFormat::Org);
+    assert_eq!(
+        note_org.get_referents(),
+        HashSet::from([loc_md.get_id().to_owned()])
+    );
+    let note_md = store.get_note(&loc_md.current()).await.unwrap();
+    assert_eq!(note_md.get_note_inner().get_format(), NoteFormat::Markdown);
+    assert_eq!(
+        note_md.get_references(),
+        HashSet::from([loc_plain.get_id().to_owned(), loc_org.get_id().to_owned()])
+    );
+    let note_plain = store.get_note(&loc_plain.current()).await.unwrap();
+    assert_eq!(note_plain.get_note_inner().get_format(), NoteFormat::Plain);
+    // Updating other fields keeps the format
     store
-        .new_note("goodbye world".to_owned(), note_inner.clone(), md)
+        .update_note(
+            &loc_org.current(),
+            Some("Baz".to_owned()),
+            None,
+            NoteMetadataEditable::unchanged(),
+        )
         .await
         .unwrap();
-    let notes = store.search(&("hello #tag1".into())).await.unwrap();
-    assert_eq!(notes[0].get_title(), "hello world");
-    assert_eq!(notes.len(), 1);
-    let notes = store.search(&("#tag1".into())).await.unwrap();
-    assert_eq!(notes.len(), 2);
+    let revisions = store.get_revisions(&loc_org.current()).await.unwrap();
+    assert_eq!(revisions.len(), 2);
+    for revision in revisions {
+        assert_eq!(
+            revision.get_note_inner(),
+            DynamicNote::Org(OrgNote::new(body.clone()))
+        );
+    }
 }
 
-pub(super) async fn search_orphan(store: impl NoteStore<PlainNote>) {
+pub(super) async fn convert_note(store: impl NoteStore<DynamicNote>) {
     let loc1 = store
         .new_note(
             "".to_owned(),
-            PlainNote::new("Head".into()),
+            DynamicNote::Markdown(MarkdownNote::new("# Foo".to_owned())),
             NoteMetadataEditable::unchanged(),
         )
         .await
         .unwrap();
-    store
-        .append_note(
-            loc1.get_id(),
+    let mut plain = PlainNote::new("Bar".to_owned());
+    plain.add_referent(loc1.get_id().to_owned());
+    let loc2 = store
+        .new_note(
             "".to_owned(),
-            PlainNote::new("Middle".into()),
+            DynamicNote::Plain(plain),
             NoteMetadataEditable::unchanged(),
         )
         .await
         .unwrap();
-    let notes = store.search(&("!orphan".into())).await.unwrap();
-    assert_eq!(notes.len(), 1);
-}
-
-pub(super) async fn search_notag(store: impl NoteStore<PlainNote>) {
-    let note_inner = PlainNote::new("Foo".into());
-    let md = NoteMetadataEditable {
-        tags: Some(HashSet::from_iter(["tag1".to_owned()])),
-        custom_metadata: None,
-    };
-    store
-        .new_note("hello world".to_owned(), note_inner.clone(), md.clone())
+    let loc2_converted = store
+        .convert_note(&loc2.current(), NoteFormat::Markdown, None)
         .await
         .unwrap();
+    assert_ne!(loc2_converted.get_revision(), loc2.get_revision());
+    let note2 = store.get_note(&loc2.current()).await.unwrap();
+    assert_eq!(note2.get_note_inner().get_format(), NoteFormat::Markdown);
+    assert_eq!(
+        note2.get_referents(),
+        HashSet::from([loc1.get_id().to_owned()])
+    );
+    let note1 = store.get_note(&loc1.current()).await.unwrap();
+    assert_eq!(
+        note1.get_references(),
+        HashSet::from([loc2.get_id().to_owned()])
+    );
+    // The note before conversion is kept as a revision
+    let note2_old = store.get_note(&loc2).await.unwrap();
+    assert_eq!(note2_old.get_note_inner().get_format(), NoteFormat::Plain);
+    // Only the current revision can be converted
+    assert!(matches!(
+        store.convert_note(&loc2, NoteFormat::Markdown, None).await,
+        Err(NoteStoreError::UpdateOldRevision(_, _))
+    ));
+    #[cfg(feature = "notetype_org")]
+    assert!(matches!(
+        store
+            .convert_note(&loc2.current(), NoteFormat::Org, None)
+            .await,
+        Err(NoteStoreError::ConversionNotSupported(_, NoteFormat::Org))
+    ));
     store
-        .new_note(
-            "goodbye world".to_owned(),
-            note_inner.clone(),
-            NoteMetadataEditable::unchanged(),
-        )
+        .convert_note(&loc2.current(), NoteFormat::Plain, None)
         .await
         .unwrap();
-    let notes = store.search(&("world !notag".into())).await.unwrap();
-    assert_eq!(notes[0].get_title(), "goodbye world");
-    assert_eq!(notes.len(), 1);
-    let notes = store.search(&("world".into())).await.unwrap();
-    assert_eq!(notes.len(), 2);
+    let note2 = store.get_note(&loc2.current()).await.unwrap();
+    assert_eq!(note2.get_note_inner().get_format(), NoteFormat::Plain);
+    assert_eq!(
+        note2.get_referents(),
+        HashSet::from([loc1.get_id().to_owned()])
+    );
 }
 
-pub(super) async fn issue_151(store: impl NoteStore<PlainNote>) {
-    let note_inner = PlainNote::new("".into());
-    let loc = store
+#[cfg(feature = "notetype_block")]
+pub(super) async fn block_note(store: impl NoteStore<BlockNote>) {
+    let loc1 = store
         .new_note(
-            "note 1".to_owned(),
-            note_inner.clone(),
+            "".to_owned(),
+            BlockNote::default(),
             NoteMetadataEditable::unchanged(),
         )
         .await
         .unwrap();
-    let loc_next = store
-        .append_note(
-            loc.get_id(),
-            "note 2".to_owned(),
-            note_inner.clone(),
+    let loc2 = store
+        .new_note(
+            "".to_owned(),
+            BlockNote::new(vec![
+                Block::Heading {
+                    level: 1,
+                    text: "Runbook".to_owned(),
+                },
+                Block::Callout {
+                    kind: CalloutKind::Note,
+                    blocks: vec![Block::Reference {
+                        note: loc1.get_id().to_owned(),
+                        text: None,
+                    }],
+                },
+                Block::Checklist {
+                    items: vec![ChecklistItem {
+                        text: "Restart".to_owned(),
+                        done: false,
+                    }],
+                },
+            ]),
             NoteMetadataEditable::unchanged(),
         )
         .await
         .unwrap();
-    let mut note_inner_ref = PlainNote::new("ref".into());
-    note_inner_ref.add_referent(loc.get_id().clone());
-    let loc2 = store
+    let note1 = store.get_note(&loc1.current()).await.unwrap();
+    assert_eq!(
+        note1.get_references(),
+        HashSet::from([loc2.get_id().to_owned()])
+    );
+    let notes = store.search(&("runbook".into())).await.unwrap();
+    assert_eq!(notes.len(), 1);
+    store.toggle_task(&loc2.current(), 0, None).await.unwrap();
+    let tasks = store.tasks(&TaskQuery::default()).await.unwrap();
+    assert_eq!(tasks.len(), 1);
+    assert_eq!(tasks[0].task.text, "Restart");
+    assert!(tasks[0].task.done);
+}
+
+pub(super) async fn migrate_metadata(store: impl NoteStore<PlainNote>) {
+    let loc1 = store
         .new_note(
-            "note 3".to_owned(),
-            note_inner_ref.clone(),
+            "".to_owned(),
+            PlainNote::new("Foo".into()),
             NoteMetadataEditable::unchanged(),
         )
         .await
         .unwrap();
-    let loc3 = store
-        .new_note(
-            "note 4".to_owned(),
-            note_inner_ref.clone(),
+    store
+        .update_note(
+            &loc1,
+            None,
+            Some(PlainNote::new("Bar".into())),
             NoteMetadataEditable::unchanged(),
         )
         .await
         .unwrap();
-    let n = store.get_note(&loc.current()).await.unwrap();
-    assert_eq!(&n.get_next().unwrap(), loc_next.get_id());
-    assert_eq!(n.get_references().len(), 2);
-    assert!(n.get_references().contains(loc2.get_id()));
-    assert!(n.get_references().contains(loc3.get_id()));
+    // Everything is already on the current schema
+    assert_eq!(store.migrate_metadata().await.unwrap(), 0);
+    let revisions = store.get_revisions(&loc1).await.unwrap();
+    assert_eq!(revisions.len(), 2);
+    for revision in revisions {
+        assert_eq!(
+            revision.get_metadata().schema_version,
+            NOTE_METADATA_CURRENT_SCHEMA_VERSION
+        );
+    }
 }
 
-pub(super) async fn tags(store: impl NoteStore<PlainNote>) {
-    let note_inner = PlainNote::new("".into());
-    let md1 = NoteMetadataEditable {
-        tags: Some(HashSet::from(["tag1".to_owned()])),
-        custom_metadata: None,
-    };
-    let md2 = NoteMetadataEditable {
-        tags: Some(HashSet::from(["tag1".to_owned(), "tag2".to_owned()])),
-        custom_metadata: None,
-    };
-    let md3 = NoteMetadataEditable {
-        tags: Some(HashSet::from(["tag2".to_owned(), "tag3".to_owned()])),
-        custom_metadata: None,
+pub(super) async fn metadata_schema(store: impl NoteStore<PlainNote>) {
+    let schema = json!({
+        "type": "object",
+        "properties": {"status": {"enum": ["todo", "done"]}},
+        "additionalProperties": false
+    });
+    assert!(matches!(
+        store
+            .set_metadata_schema(Some("project"), Some(json!({"type": 1})))
+            .await,
+        Err(NoteStoreError::InvalidMetadataSchema(_))
+    ));
+    store
+        .set_metadata_schema(Some("project"), Some(schema.clone()))
+        .await
+        .unwrap();
+    assert_eq!(
+        store.metadata_schemas().await.unwrap().get(Some("project")),
+        Some(&schema)
+    );
+    let metadata = |tags: &[&str], custom_metadata| NoteMetadataEditable {
+        tags: Some(tags.iter().map(|t| t.to_string()).collect()),
+        aliases: None,
+        custom_metadata: Some(custom_metadata),
+        author: None,
     };
-    let _loc1 = store
-        .new_note("note 1".to_owned(), note_inner.clone(), md1)
+    let result = store
+        .new_note(
+            "".to_owned(),
+            PlainNote::new("Foo".into()),
+            metadata(&["project"], json!({"stauts": "done"})),
+        )
+        .await;
+    match result {
+        Err(NoteStoreError::MetadataSchemaViolation(v)) => {
+            assert_eq!(v.len(), 1);
+            assert_eq!(v[0].tag.as_deref(), Some("project"));
+        }
+        r => panic!("unexpected result {:?}", r.map(|l| l.get_id().clone())),
+    }
+    // The schema only applies to notes with the tag
+    let loc1 = store
+        .new_note(
+            "".to_owned(),
+            PlainNote::new("Foo".into()),
+            metadata(&[], json!({"stauts": "done"})),
+        )
         .await
         .unwrap();
-    let _loc2 = store
-        .new_note("note 2".to_owned(), note_inner.clone(), md2)
+    assert!(matches!(
+        store
+            .update_note(
+                &loc1,
+                None,
+                None,
+                metadata(&["project"], json!({"stauts": "done"})),
+            )
+            .await,
+        Err(NoteStoreError::MetadataSchemaViolation(_))
+    ));
+    let loc2 = store
+        .update_note(
+            &loc1,
+            None,
+            None,
+            metadata(&["project"], json!({"status": "done"})),
+        )
         .await
         .unwrap();
-    let loc3 = store
-        .new_note("note 3".to_owned(), note_inner.clone(), md3)
+    // The default schema applies to all notes
+    store
+        .set_metadata_schema(None, Some(json!({"required": ["owner"]})))
         .await
         .unwrap();
-    assert_eq!(store.tags().await.unwrap().len(), 3);
-    store.delete_note(&loc3).await.unwrap();
-    let tags = store.tags().await.unwrap();
-    assert_eq!(tags.len(), 2);
-    assert!(tags.contains(&"tag1".to_owned()));
-    assert!(tags.contains(&"tag2".to_owned()));
-}
-
-pub(super) async fn search_limit_override(store: impl NoteStore<PlainNote>) {
-    let note_inner = PlainNote::new("Foo".into());
+    match store
+        .update_note(
+            &loc2,
+            None,
+            None,
+            metadata(&["project"], json!({"status": "wip"})),
+        )
+        .await
+    {
+        Err(NoteStoreError::MetadataSchemaViolation(v)) => {
+            assert_eq!(v.len(), 2);
+            assert_eq!(v[0].tag, None);
+            assert_eq!(v[1].instance_path, "/status");
+        }
+        r => panic!("unexpected result {:?}", r.map(|l| l.get_id().clone())),
+    }
+    // Existing metadata is not validated if it is unchanged
     store
-        .new_note(
-            "hello world".to_owned(),
-            note_inner.clone(),
+        .update_note(
+            &loc2,
+            None,
+            Some(PlainNote::new("Bar".into())),
             NoteMetadataEditable::unchanged(),
         )
         .await
         .unwrap();
+    store.set_metadata_schema(None, None).await.unwrap();
     store
-        .new_note(
-            "goodbye world".to_owned(),
-            note_inner.clone(),
-            NoteMetadataEditable::unchanged(),
-        )
+        .set_metadata_schema(Some("project"), None)
         .await
         .unwrap();
-    let notes = store.search(&("".into())).await.unwrap();
-    assert_eq!(notes.len(), 2);
-    let notes = store.search(&("!limit=1".into())).await.unwrap();
-    assert_eq!(notes.len(), 1);
+    assert_eq!(
+        store.metadata_schemas().await.unwrap(),
+        MetadataSchemas::default()
+    );
 }
 
-pub(super) async fn search_tag_exclude(store: impl NoteStore<PlainNote>) {
-    let note_inner = PlainNote::new("Foo".into());
-    let md = NoteMetadataEditable {
-        tags: Some(HashSet::from_iter(["tag1".to_owned()])),
+pub(super) async fn aliases(store: impl NoteStore<PlainNote>) {
+    let aliases = |aliases: &[&str]| NoteMetadataEditable {
+        tags: None,
+        aliases: Some(aliases.iter().map(|a| a.to_string()).collect()),
         custom_metadata: None,
+        author: None,
     };
     let loc1 = store
-        .new_note("hello world".to_owned(), note_inner.clone(), md.clone())
+        .new_note(
+            "".to_owned(),
+            PlainNote::new("Foo".into()),
+            aliases(&["foo"]),
+        )
         .await
         .unwrap();
+    let id1 = loc1.get_id().clone();
+    let target = store.lookup_alias("foo").await.unwrap();
+    assert_eq!(target.id, id1);
+    assert!(!target.redirect);
+    assert!(matches!(
+        store
+            .new_note(
+                "".to_owned(),
+                PlainNote::new("Bar".into()),
+                aliases(&["foo"])
+            )
+            .await,
+        Err(NoteStoreError::AliasConflict(_, id)) if id == id1
+    ));
+    assert!(matches!(
+        store
+            .new_note(
+                "".to_owned(),
+                PlainNote::new("Bar".into()),
+                aliases(&["foo bar"])
+            )
+            .await,
+        Err(NoteStoreError::InvalidAlias(_))
+    ));
+    // Note IDs cannot be aliases
+    assert!(matches!(
+        store
+            .new_note(
+                "".to_owned(),
+                PlainNote::new("Bar".into()),
+                aliases(&[id1.as_ref()])
+            )
+            .await,
+        Err(NoteStoreError::InvalidAlias(_))
+    ));
     let loc2 = store
         .new_note(
-            "goodbye world".to_owned(),
-            note_inner.clone(),
+            "".to_owned(),
+            PlainNote::new("Bar".into()),
             NoteMetadataEditable::unchanged(),
         )
         .await
         .unwrap();
-    let notes = store.search(&("world".into())).await.unwrap();
+    assert!(matches!(
+        store
+            .update_note(&loc2, None, None, aliases(&["foo"]))
+            .await,
+        Err(NoteStoreError::AliasConflict(_, _))
+    ));
+    // Renaming an alias keeps the previous alias as a redirect
+    store
+        .update_note(&loc1, None, None, aliases(&["bar"]))
+        .await
+        .unwrap();
+    assert_eq!(store.lookup_alias("bar").await.unwrap().id, id1);
+    let target = store.lookup_alias("foo").await.unwrap();
+    assert_eq!(target.id, id1);
+    assert!(target.redirect);
+    // until another note takes it
+    store
+        .update_note(&loc2, None, None, aliases(&["foo"]))
+        .await
+        .unwrap();
+    let target = store.lookup_alias("foo").await.unwrap();
+    assert_eq!(&target.id, loc2.get_id());
+    assert!(!target.redirect);
+    assert!(matches!(
+        store.lookup_alias("baz").await,
+        Err(NoteStoreError::AliasNotExist(_))
+    ));
+    // Aliases of deleted notes don't refer to anything
+    store.delete_note(&loc1.current()).await.unwrap();
+    assert!(matches!(
+        store.lookup_alias("bar").await,
+        Err(NoteStoreError::AliasNotExist(_))
+    ));
+}
+
+pub(super) async fn note_flags(store: impl NoteStore<PlainNote>) {
+    let mut locs = vec![];
+    for body in ["Dashboard", "Favourite", "Finished"] {
+        let loc = store
+            .new_note(
+                "".to_owned(),
+                PlainNote::new(body.into()),
+                NoteMetadataEditable::unchanged(),
+            )
+            .await
+            .unwrap();
+        locs.push(loc);
+    }
+    let search = |query: &'static str| {
+        let store = &store;
+        async move {
+            store
+                .search(&query.into())
+                .await
+                .unwrap()
+                .iter()
+                .map(|n| n.get_note_inner().get_plain_text().unwrap())
+                .collect::<Vec<_>>()
+        }
+    };
+    // The dashboard is the oldest note, but it is pinned to the top
+    let loc = store
+        .toggle_flag(&locs[0], NoteFlag::Pinned, None)
+        .await
+        .unwrap();
+    assert!(store.get_note(&loc).await.unwrap().get_metadata().pinned);
+    store
+        .toggle_flag(&locs[1].current(), NoteFlag::Starred, None)
+        .await
+        .unwrap();
+    store
+        .toggle_flag(&locs[2].current(), NoteFlag::Archived, None)
+        .await
+        .unwrap();
+    assert_eq!(search("").await, vec!["Dashboard", "Favourite"]);
+    assert_eq!(search("!pinned").await, vec!["Dashboard"]);
+    assert_eq!(search("!starred").await, vec!["Favourite"]);
+    assert_eq!(search("!archived").await, vec!["Finished"]);
+    assert!(search("Finished").await.is_empty());
+    // Toggling again unsets the flag
+    store
+        .toggle_flag(&loc, NoteFlag::Pinned, None)
+        .await
+        .unwrap();
+    assert!(search("!pinned").await.is_empty());
+    assert_eq!(search("").await, vec!["Favourite", "Dashboard"]);
+    // Only the current revision can be toggled
+    assert!(matches!(
+        store.toggle_flag(&loc, NoteFlag::Pinned, None).await,
+        Err(NoteStoreError::UpdateOldRevision(_, _))
+    ));
+}
+
+pub(super) async fn author(store: impl NoteStore<PlainNote>) {
+    let loc = store
+        .new_note(
+            "Runbook".to_owned(),
+            PlainNote::new("Restart the server".into()),
+            NoteMetadataEditable {
+                author: Some("alice".to_owned()),
+                ..NoteMetadataEditable::unchanged()
+            },
+        )
+        .await
+        .unwrap();
+    let loc = store
+        .update_note(
+            &loc,
+            None,
+            Some(PlainNote::new("Restart the server, then the proxy".into())),
+            NoteMetadataEditable {
+                author: Some("bob".to_owned()),
+                ..NoteMetadataEditable::unchanged()
+            },
+        )
+        .await
+        .unwrap();
+    // Revisions made without an author have none, even if the previous revision had one
+    store
+        .toggle_flag(&loc, NoteFlag::Starred, None)
+        .await
+        .unwrap();
+    store
+        .new_note(
+            "Anonymous".to_owned(),
+            PlainNote::new("".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let mut authors: Vec<Option<String>> = store
+        .get_revisions(&loc)
+        .await
+        .unwrap()
+        .iter()
+        .map(|n| n.get_metadata().author)
+        .collect();
+    authors.sort();
+    assert_eq!(
+        authors,
+        vec![None, Some("alice".to_owned()), Some("bob".to_owned())]
+    );
+    // Notes match the authors of any of their revisions
+    for query in ["author:alice", "author:bob", "author:alice author:bob"] {
+        let res = store.search(&query.into()).await.unwrap();
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].get_title(), "Runbook");
+    }
+    assert!(store
+        .search(&"author:carol".into())
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(store
+        .search(&"author:alice author:carol".into())
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+pub(super) async fn operation_author(store: impl NoteStore<DynamicNote>) {
+    let loc1 = store
+        .new_note(
+            "".to_owned(),
+            DynamicNote::Markdown(MarkdownNote::new("- [ ] foo\n".to_owned())),
+            NoteMetadataEditable {
+                author: Some("alice".to_owned()),
+                ..NoteMetadataEditable::unchanged()
+            },
+        )
+        .await
+        .unwrap();
+    let loc2 = store
+        .toggle_task(&loc1, 0, Some("bob".to_owned()))
+        .await
+        .unwrap();
+    assert_eq!(
+        store.get_note(&loc2).await.unwrap().get_metadata().author,
+        Some("bob".to_owned())
+    );
+    let loc3 = store
+        .toggle_flag(&loc2, NoteFlag::Pinned, Some("carol".to_owned()))
+        .await
+        .unwrap();
+    assert_eq!(
+        store.get_note(&loc3).await.unwrap().get_metadata().author,
+        Some("carol".to_owned())
+    );
+    let loc4 = store
+        .convert_note(&loc3, NoteFormat::Plain, Some("dave".to_owned()))
+        .await
+        .unwrap();
+    assert_eq!(
+        store.get_note(&loc4).await.unwrap().get_metadata().author,
+        Some("dave".to_owned())
+    );
+    // The author of the reverted revision is not restored
+    let loc5 = store
+        .revert_note(&loc4, loc1.get_revision().unwrap(), Some("erin".to_owned()))
+        .await
+        .unwrap();
+    assert_eq!(
+        store.get_note(&loc5).await.unwrap().get_metadata().author,
+        Some("erin".to_owned())
+    );
+    // Only the rewritten referrers have the author of the deletion
+    let id1 = loc1.get_id();
+    let loc6 = store
+        .new_note(
+            "".to_owned(),
+            DynamicNote::Markdown(MarkdownNote::new(format!("[Foo](notegraf:/note/{id1})"))),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let options = DeleteOptions {
+        rewrite_references: Some(LinkRewrite::PlainText),
+        ..Default::default()
+    };
+    store
+        .delete_note_with(&loc5, &options, Some("frank".to_owned()))
+        .await
+        .unwrap();
+    assert_eq!(
+        store
+            .get_note(&loc6.current())
+            .await
+            .unwrap()
+            .get_metadata()
+            .author,
+        Some("frank".to_owned())
+    );
+    assert_eq!(
+        store.get_note(&loc6).await.unwrap().get_metadata().author,
+        None
+    );
+}
+
+pub(super) async fn diff(store: impl NoteStore<MarkdownNote>) {
+    let new_note = |title: &'static str| {
+        store.new_note(
+            title.to_owned(),
+            MarkdownNote::new("".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+    };
+    let loc_a = new_note("A").await.unwrap();
+    let loc_b = new_note("B").await.unwrap();
+    let loc1 = store
+        .new_note(
+            "Runbook".to_owned(),
+            MarkdownNote::new(format!(
+                "Restart the server\n[A](notegraf:/note/{})\n",
+                loc_a.get_id()
+            )),
+            NoteMetadataEditable {
+                tags: Some(HashSet::from(["ops".to_owned()])),
+                aliases: None,
+                custom_metadata: Some(json!({"owner": "alice", "reviewed": false})),
+                author: None,
+            },
+        )
+        .await
+        .unwrap();
+    let loc2 = store
+        .update_note(
+            &loc1,
+            Some("Server runbook".to_owned()),
+            Some(MarkdownNote::new(format!(
+                "Restart the server\nCheck the logs\n[B](notegraf:/note/{})\n",
+                loc_b.get_id()
+            ))),
+            NoteMetadataEditable {
+                tags: Some(HashSet::from(["runbook".to_owned()])),
+                aliases: None,
+                custom_metadata: Some(json!({"owner": "alice", "reviewed": true})),
+                author: None,
+            },
+        )
+        .await
+        .unwrap();
+    let (id, rev1, rev2) = (
+        loc1.get_id(),
+        loc1.get_revision().unwrap(),
+        loc2.get_revision().unwrap(),
+    );
+    let diff = store.diff(id, rev1, rev2).await.unwrap();
+    assert_eq!(
+        diff.title,
+        Some(("Runbook".to_owned(), "Server runbook".to_owned()))
+    );
+    let changes: Vec<(LineChangeKind, &str)> = diff
+        .note_inner
+        .iter()
+        .map(|c| (c.kind, c.text.as_str()))
+        .collect();
+    assert_eq!(changes[0], (LineChangeKind::Equal, "Restart the server"));
+    assert!(changes.contains(&(LineChangeKind::Insert, "Check the logs")));
+    assert_eq!(diff.tags_added, vec!["runbook".to_owned()]);
+    assert_eq!(diff.tags_removed, vec!["ops".to_owned()]);
+    assert_eq!(
+        serde_json::to_value(&diff.custom_metadata).unwrap(),
+        json!([{"op": "replace", "path": "/reviewed", "value": true}])
+    );
+    assert_eq!(diff.referents_added, vec![loc_b.get_id().clone()]);
+    assert_eq!(diff.referents_removed, vec![loc_a.get_id().clone()]);
+    // Diffing backwards swaps the changes
+    let diff = store.diff(id, rev2, rev1).await.unwrap();
+    assert_eq!(diff.tags_added, vec!["ops".to_owned()]);
+    assert_eq!(diff.referents_added, vec![loc_a.get_id().clone()]);
+    // A revision has no changes from itself
+    let diff = store.diff(id, rev2, rev2).await.unwrap();
+    assert_eq!(diff.title, None);
+    assert!(diff
+        .note_inner
+        .iter()
+        .all(|c| c.kind == LineChangeKind::Equal));
+    assert!(diff.tags_added.is_empty() && diff.tags_removed.is_empty());
+    assert!(diff.custom_metadata.0.is_empty());
+    // Both revisions must belong to the note
+    assert!(matches!(
+        store.diff(loc_a.get_id(), rev1, rev2).await,
+        Err(NoteStoreError::RevisionNotExist(_, _))
+    ));
+}
+
+pub(super) async fn diff_plain(store: impl NoteStore<PlainNote>) {
+    let loc1 = store
+        .new_note(
+            "Runbook".to_owned(),
+            PlainNote::new("Restart the server\nCheck the logs\n".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let loc2 = store
+        .update_note(
+            &loc1,
+            None,
+            Some(PlainNote::new(
+                "Restart the server\nCheck the proxy\n".into(),
+            )),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let diff = store
+        .diff(
+            loc1.get_id(),
+            loc1.get_revision().unwrap(),
+            loc2.get_revision().unwrap(),
+        )
+        .await
+        .unwrap();
+    // Lines of the body, rather than of the JSON that the note is stored as
+    let changes: Vec<(LineChangeKind, &str)> = diff
+        .note_inner
+        .iter()
+        .map(|c| (c.kind, c.text.as_str()))
+        .collect();
+    assert_eq!(
+        changes,
+        vec![
+            (LineChangeKind::Equal, "Restart the server"),
+            (LineChangeKind::Delete, "Check the logs"),
+            (LineChangeKind::Insert, "Check the proxy"),
+        ]
+    );
+}
+
+pub(super) async fn revert_note(store: impl NoteStore<PlainNote>) {
+    let loc1 = store
+        .new_note(
+            "Runbook".to_owned(),
+            PlainNote::new("Restart the server".into()),
+            NoteMetadataEditable {
+                tags: Some(HashSet::from(["ops".to_owned()])),
+                aliases: Some(HashSet::from(["runbook".to_owned()])),
+                custom_metadata: Some(json!({"owner": "alice"})),
+                author: None,
+            },
+        )
+        .await
+        .unwrap();
+    let loc2 = store
+        .update_note(
+            &loc1,
+            Some("Broken runbook".to_owned()),
+            Some(PlainNote::new("rm -rf /".into())),
+            NoteMetadataEditable {
+                tags: Some(HashSet::new()),
+                aliases: Some(HashSet::new()),
+                custom_metadata: Some(json!({})),
+                author: None,
+            },
+        )
+        .await
+        .unwrap();
+    let loc2 = store
+        .toggle_flag(&loc2, NoteFlag::Starred, None)
+        .await
+        .unwrap();
+    let rev1 = loc1.get_revision().unwrap();
+    // Reverting from a revision that is no longer current fails
+    assert!(matches!(
+        store.revert_note(&loc1, rev1, None).await,
+        Err(NoteStoreError::UpdateOldRevision(_, _))
+    ));
+    let loc3 = store.revert_note(&loc2, rev1, None).await.unwrap();
+    assert_ne!(loc3.get_revision(), loc1.get_revision());
+    let note = store.get_note(&loc1.current()).await.unwrap();
+    assert_eq!(note.get_revision(), *loc3.get_revision().unwrap());
+    assert_eq!(note.get_title(), "Runbook");
+    assert_eq!(
+        note.get_note_inner(),
+        PlainNote::new("Restart the server".into())
+    );
+    let metadata = note.get_metadata();
+    assert_eq!(metadata.tags, HashSet::from(["ops".to_owned()]));
+    assert_eq!(metadata.aliases, HashSet::from(["runbook".to_owned()]));
+    assert_eq!(metadata.custom_metadata, json!({"owner": "alice"}));
+    // Flags are not part of the content of a note
+    assert!(metadata.starred);
+    assert_eq!(store.get_revisions(&loc1).await.unwrap().len(), 4);
+    // Without a revision, the current revision is reverted
+    let loc4 = store
+        .revert_note(&loc1.current(), loc2.get_revision().unwrap(), None)
+        .await
+        .unwrap();
+    let note = store.get_note(&loc4).await.unwrap();
+    assert_eq!(note.get_title(), "Broken runbook");
+    assert!(matches!(
+        store.revert_note(&loc4, &Uuid::new_v4().into(), None).await,
+        Err(NoteStoreError::RevisionNotExist(_, _))
+    ));
+}
+
+pub(super) async fn trash(store: impl NoteStore<MarkdownNote>) {
+    let new_note = |title: &'static str, body: String| {
+        store.new_note(
+            title.to_owned(),
+            MarkdownNote::new(body),
+            NoteMetadataEditable::unchanged(),
+        )
+    };
+    let loc_a = new_note("A", "".to_owned()).await.unwrap();
+    let loc_b = new_note("B", "".to_owned()).await.unwrap();
+    let (id_a, id_b) = (loc_a.get_id(), loc_b.get_id());
+    let before = Utc::now();
+    store.delete_note(&loc_a.current()).await.unwrap();
+    let deleted = store.list_deleted().await.unwrap();
+    assert_eq!(deleted.len(), 1);
+    assert_eq!(deleted[0].note.get_id(), *id_a);
+    assert_eq!(deleted[0].note.get_title(), "A");
+    assert!(deleted[0].deleted_at >= before && deleted[0].deleted_at <= Utc::now());
+    // Only deleted notes can be purged
+    assert!(matches!(
+        store.purge_note(id_b).await,
+        Err(NoteStoreError::NotDeleted(_))
+    ));
+    assert!(matches!(
+        store.purge_note(&Uuid::new_v4().into()).await,
+        Err(NoteStoreError::NoteNotExist(_))
+    ));
+    // A deleted note that is referred to again cannot be purged
+    store
+        .update_note(
+            &loc_b.current(),
+            None,
+            Some(MarkdownNote::new(format!("[A](notegraf:/note/{id_a})"))),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    assert!(matches!(
+        store.purge_note(id_a).await,
+        Err(NoteStoreError::HasReferences(_))
+    ));
+    assert!(store.purge_deleted(Utc::now()).await.unwrap().is_empty());
+    store
+        .update_note(
+            &loc_b.current(),
+            None,
+            Some(MarkdownNote::new("".to_owned())),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    store.purge_note(id_a).await.unwrap();
+    assert!(store.list_deleted().await.unwrap().is_empty());
+    assert!(matches!(
+        store.get_note(&loc_a).await,
+        Err(NoteStoreError::NoteNotExist(_)) | Err(NoteStoreError::RevisionNotExist(_, _))
+    ));
+    // Purging a note from the middle of a sequence keeps the older revisions of its neighbours
+    let loc_c = store
+        .append_note(
+            id_b,
+            "C".to_owned(),
+            MarkdownNote::new("".to_owned()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let loc_d = store
+        .append_note(
+            loc_c.get_id(),
+            "D".to_owned(),
+            MarkdownNote::new("".to_owned()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    store.delete_note(&loc_c.current()).await.unwrap();
+    assert_eq!(
+        store.purge_deleted(before).await.unwrap(),
+        Vec::<NoteID>::new()
+    );
+    assert_eq!(
+        store.purge_deleted(Utc::now()).await.unwrap(),
+        vec![loc_c.get_id().clone()]
+    );
+    for loc in [&loc_b, &loc_d] {
+        for note in store.get_revisions(loc).await.unwrap() {
+            assert_ne!(note.get_prev().as_ref(), Some(loc_c.get_id()));
+            assert_ne!(note.get_next().as_ref(), Some(loc_c.get_id()));
+        }
+    }
+    let note_d = store.get_note(&loc_d.current()).await.unwrap();
+    assert_eq!(note_d.get_prev().as_ref(), Some(id_b));
+}
+
+pub(super) async fn compact_revisions(store: impl NoteStore<MarkdownNote>) {
+    let loc_a = store
+        .new_note(
+            "A".to_owned(),
+            MarkdownNote::new("0".to_owned()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let id_a = loc_a.get_id();
+    let current_a = loc_a.current();
+    let update = |body: &'static str| {
+        store.update_note(
+            &current_a,
+            None,
+            Some(MarkdownNote::new(body.to_owned())),
+            NoteMetadataEditable::unchanged(),
+        )
+    };
+    update("1").await.unwrap();
+    let kept = update("2").await.unwrap();
+    store
+        .keep_revision(id_a, kept.get_revision().unwrap(), true)
+        .await
+        .unwrap();
+    // Pinning the note doesn't keep the revision
+    store
+        .toggle_flag(&loc_a.current(), NoteFlag::Pinned, None)
+        .await
+        .unwrap();
+    let linked = update("4").await.unwrap();
+    let current = update("5").await.unwrap();
+    store
+        .new_note(
+            "B".to_owned(),
+            MarkdownNote::new(format!(
+                "[An old A](notegraf:/note/{id_a}/revision/{})",
+                linked.get_revision().unwrap()
+            )),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let loc_c = store
+        .new_note(
+            "C".to_owned(),
+            MarkdownNote::new("".to_owned()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    store
+        .update_note(
+            &loc_c.current(),
+            Some("C1".to_owned()),
+            None,
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    store.delete_note(&loc_c.current()).await.unwrap();
+    // Every revision is from the last day
+    let policy = CompactionPolicy::default();
+    assert_eq!(store.compact_revisions(&policy).await.unwrap(), 0);
+    assert_eq!(store.get_revisions(&loc_a).await.unwrap().len(), 6);
+    // Every revision is older than every rule
+    let policy = CompactionPolicy { rules: vec![] };
+    assert_eq!(store.compact_revisions(&policy).await.unwrap(), 4);
+    let revisions: Vec<_> = store
+        .get_revisions(&loc_a)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|n| n.get_revision())
+        .collect();
+    assert_eq!(
+        revisions,
+        vec![
+            kept.get_revision().unwrap().clone(),
+            linked.get_revision().unwrap().clone(),
+            current.get_revision().unwrap().clone()
+        ]
+    );
+    assert!(matches!(
+        store.get_note(&loc_a).await,
+        Err(NoteStoreError::RevisionNotExist(_, _))
+    ));
+    // The last revision of a deleted note is kept
+    let revisions = store.get_revisions(&loc_c).await.unwrap();
+    assert_eq!(revisions.len(), 1);
+    assert_eq!(revisions[0].get_title(), "C1");
+    assert_eq!(store.list_deleted().await.unwrap().len(), 1);
+    assert_eq!(store.compact_revisions(&policy).await.unwrap(), 0);
+}
+
+pub(super) async fn keep_revision(store: impl NoteStore<PlainNote>) {
+    let new_note = |title: &'static str| {
+        store.new_note(
+            title.to_owned(),
+            PlainNote::new("".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+    };
+    let loc_a = new_note("A").await.unwrap();
+    let loc_b = new_note("B").await.unwrap();
+    let (id, revision) = (loc_a.get_id(), loc_a.get_revision().unwrap());
+    assert!(store.get_kept_revisions(id).await.unwrap().is_empty());
+    store.keep_revision(id, revision, true).await.unwrap();
+    assert_eq!(
+        store.get_kept_revisions(id).await.unwrap(),
+        HashSet::from([revision.clone()])
+    );
+    // Keeping a revision doesn't create a new one
+    assert_eq!(store.get_revisions(&loc_a).await.unwrap().len(), 1);
+    store.keep_revision(id, revision, false).await.unwrap();
+    assert!(store.get_kept_revisions(id).await.unwrap().is_empty());
+    // The revision must belong to the note
+    let loc_b = store
+        .update_note(
+            &loc_b,
+            Some("B1".to_owned()),
+            None,
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    assert!(matches!(
+        store
+            .keep_revision(id, loc_b.get_revision().unwrap(), true)
+            .await,
+        Err(NoteStoreError::RevisionNotExist(_, _))
+    ));
+}
+
+pub(super) async fn delete_note_with(store: impl NoteStore<MarkdownNote>) {
+    let new_note = |title: &'static str, body: String| {
+        store.new_note(
+            title.to_owned(),
+            MarkdownNote::new(body),
+            NoteMetadataEditable::unchanged(),
+        )
+    };
+    let loc_p = new_note("P", "".to_owned()).await.unwrap();
+    let loc_r = store
+        .add_branch(
+            loc_p.get_id(),
+            "R".to_owned(),
+            MarkdownNote::new("".to_owned()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let loc_b = store
+        .add_branch(
+            loc_r.get_id(),
+            "B".to_owned(),
+            MarkdownNote::new("".to_owned()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let id_r = loc_r.get_id();
+    // Links between the deleted notes are left as they are
+    let loc_c = store
+        .append_note(
+            loc_b.get_id(),
+            "C".to_owned(),
+            MarkdownNote::new(format!("[R](notegraf:/note/{id_r})")),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let loc_d = store
+        .append_note(
+            id_r,
+            "D".to_owned(),
+            MarkdownNote::new("".to_owned()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let id_b = loc_b.get_id();
+    let loc_x = new_note("X", format!("See [B](notegraf:/note/{id_b})."))
+        .await
+        .unwrap();
+    let loc_y = new_note("Y", "".to_owned()).await.unwrap();
+    let id_y = loc_y.get_id();
+    let loc_z = new_note("Z", format!("See [Y](notegraf:/note/{id_y})."))
+        .await
+        .unwrap();
+
+    let mut options = DeleteOptions::default();
+    assert!(matches!(
+        store
+            .delete_note_with(&loc_r.current(), &options, None)
+            .await,
+        Err(NoteStoreError::HasBranches(_))
+    ));
+    options.cascade = true;
+    assert!(matches!(
+        store.delete_note_with(&loc_r.current(), &options, None).await,
+        Err(NoteStoreError::HasReferences(id)) if id == *id_b
+    ));
+    options.rewrite_references = Some(LinkRewrite::Tombstone);
+    options.dry_run = true;
+    let report = store
+        .delete_note_with(&loc_r.current(), &options, None)
+        .await
+        .unwrap();
+    assert_eq!(report.rewritten, vec![loc_x.get_id().clone()]);
+    assert_eq!(report.deleted.len(), 4);
+    assert_eq!(&report.deleted[0], id_r);
+    let position = |id: &NoteID| report.deleted.iter().position(|d| d == id).unwrap();
+    assert!(position(id_b) < position(loc_c.get_id()));
+    assert!(report.deleted.contains(loc_d.get_id()));
+    // A dry run changes nothing
+    assert!(store.get_current_revision(&loc_r).await.unwrap().is_some());
+    assert_eq!(
+        store
+            .get_note(&loc_x.current())
+            .await
+            .unwrap()
+            .get_revision(),
+        *loc_x.get_revision().unwrap()
+    );
+
+    options.dry_run = false;
+    let deleted = store
+        .delete_note_with(&loc_r.current(), &options, None)
+        .await
+        .unwrap();
+    assert_eq!(deleted, report);
+    for loc in [&loc_r, &loc_b, &loc_c, &loc_d] {
+        assert!(store.get_current_revision(loc).await.unwrap().is_none());
+    }
+    assert!(store
+        .get_note(&loc_p.current())
+        .await
+        .unwrap()
+        .get_branches()
+        .is_empty());
+    let note_x = store.get_note(&loc_x.current()).await.unwrap();
+    assert_eq!(
+        String::from(note_x.get_note_inner()),
+        format!("See [B](notegraf:/deleted/{id_b}).")
+    );
+    assert!(note_x.get_referents().is_empty());
+
+    // Without cascading, only the note itself is deleted
+    let options = DeleteOptions {
+        rewrite_references: Some(LinkRewrite::PlainText),
+        ..Default::default()
+    };
+    let report = store
+        .delete_note_with(&loc_y.current(), &options, None)
+        .await
+        .unwrap();
+    assert_eq!(report.deleted, vec![id_y.clone()]);
+    assert_eq!(report.rewritten, vec![loc_z.get_id().clone()]);
+    assert_eq!(
+        String::from(
+            store
+                .get_note(&loc_z.current())
+                .await
+                .unwrap()
+                .get_note_inner()
+        ),
+        "See Y."
+    );
+    assert!(matches!(
+        store.delete_note_with(&loc_x, &options, None).await,
+        Err(NoteStoreError::DeleteOldRevision(..))
+    ));
+}
+
+pub(super) async fn check_integrity(store: impl NoteStore<PlainNote>) {
+    let loc_p = store
+        .new_note(
+            "".to_owned(),
+            PlainNote::new("Parent".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    store
+        .add_branch(
+            loc_p.get_id(),
+            "".to_owned(),
+            PlainNote::new("Branch".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    store
+        .append_note(
+            loc_p.get_id(),
+            "".to_owned(),
+            PlainNote::new("Next".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let mut note_inner = PlainNote::new("Referrer".into());
+    note_inner.add_referent(loc_p.get_id().clone());
+    let loc_d = store
+        .new_note("".to_owned(), note_inner, NoteMetadataEditable::unchanged())
+        .await
+        .unwrap();
+    store.delete_note(&loc_d.current()).await.unwrap();
+    let revision_p = store.get_current_revision(&loc_p).await.unwrap();
+    assert!(store.check_integrity(false).await.unwrap().is_empty());
+    assert!(store.check_integrity(true).await.unwrap().is_empty());
+    // Nothing is repaired
+    assert_eq!(
+        store.get_current_revision(&loc_p).await.unwrap(),
+        revision_p
+    );
+}
+
+pub(super) async fn move_note(store: impl NoteStore<PlainNote>) {
+    // a -> b, with c as a branch of b, and d on its own
+    let new_note = |body: &str| PlainNote::new(body.into());
+    let a = store
+        .new_note(
+            "".to_owned(),
+            new_note("a"),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let a = a.get_id();
+    let b = store
+        .append_note(
+            a,
+            "".to_owned(),
+            new_note("b"),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let b = b.get_id();
+    let c = store
+        .add_branch(
+            b,
+            "".to_owned(),
+            new_note("c"),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let c = c.get_id();
+    let d = store
+        .new_note(
+            "".to_owned(),
+            new_note("d"),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let d = d.get_id();
+
+    // b moves along with its branch c
+    let loc = store
+        .move_note(b, &MoveTarget::After(d.clone()))
+        .await
+        .unwrap();
+    assert_eq!(loc.get_id(), b);
+    assert_eq!(get_current(&store, a).await.get_next(), None);
+    assert_eq!(get_current(&store, d).await.get_next(), Some(b.clone()));
+    assert_eq!(get_current(&store, b).await.get_prev(), Some(d.clone()));
+    assert_eq!(get_current(&store, c).await.get_parent(), Some(b.clone()));
+
+    // d moves along with b after it
+    store
+        .move_note(d, &MoveTarget::BranchOf(a.clone()))
+        .await
+        .unwrap();
+    assert_eq!(
+        get_current(&store, a).await.get_branches(),
+        HashSet::from([d.clone()])
+    );
+    assert_eq!(get_current(&store, d).await.get_parent(), Some(a.clone()));
+    assert_eq!(get_current(&store, d).await.get_next(), Some(b.clone()));
+
+    // Moving a note where it already is changes nothing
+    let revision_d = store
+        .get_current_revision(&NoteLocator::Current(d.clone()))
+        .await
+        .unwrap();
+    store
+        .move_note(d, &MoveTarget::BranchOf(a.clone()))
+        .await
+        .unwrap();
+    assert_eq!(
+        store
+            .get_current_revision(&NoteLocator::Current(d.clone()))
+            .await
+            .unwrap(),
+        revision_d
+    );
+
+    assert!(matches!(
+        store.move_note(c, &MoveTarget::After(d.clone())).await,
+        Err(NoteStoreError::ExistingNext(_, _))
+    ));
+    store.move_note(b, &MoveTarget::Standalone).await.unwrap();
+    assert_eq!(get_current(&store, b).await.get_prev(), None);
+    assert_eq!(get_current(&store, d).await.get_next(), None);
+    assert_eq!(get_current(&store, c).await.get_parent(), Some(b.clone()));
+    assert!(store.check_integrity(false).await.unwrap().is_empty());
+}
+
+pub(super) async fn move_note_cycle(store: impl NoteStore<PlainNote>) {
+    // a -> b -> c, with d as a branch of c and e as a branch of d
+    let new_note = |body: &str| PlainNote::new(body.into());
+    let a = store
+        .new_note(
+            "".to_owned(),
+            new_note("a"),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let a = a.get_id();
+    let b = store
+        .append_note(
+            a,
+            "".to_owned(),
+            new_note("b"),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let b = b.get_id();
+    let c = store
+        .append_note(
+            b,
+            "".to_owned(),
+            new_note("c"),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let c = c.get_id();
+    let d = store
+        .add_branch(
+            c,
+            "".to_owned(),
+            new_note("d"),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let d = d.get_id();
+    let e = store
+        .add_branch(
+            d,
+            "".to_owned(),
+            new_note("e"),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let e = e.get_id();
+    let mut revisions = vec![];
+    for id in [a, b, c, d, e] {
+        revisions.push(
+            store
+                .get_current_revision(&NoteLocator::Current(id.clone()))
+                .await
+                .unwrap(),
+        );
+    }
+
+    for (id, to) in [
+        (a, MoveTarget::BranchOf(a.clone())),
+        (c, MoveTarget::After(c.clone())),
+        (a, MoveTarget::BranchOf(b.clone())),
+        (a, MoveTarget::BranchOf(e.clone())),
+        (b, MoveTarget::BranchOf(d.clone())),
+        (c, MoveTarget::BranchOf(e.clone())),
+        (d, MoveTarget::After(e.clone())),
+    ] {
+        let res = store.move_note(id, &to).await;
+        assert!(
+            matches!(res, Err(NoteStoreError::Cycle(_, _))),
+            "moving {id} to {to:?}: {res:?}"
+        );
+    }
+    // Nothing is changed
+    for (id, revision) in [a, b, c, d, e].into_iter().zip(revisions) {
+        assert_eq!(
+            store
+                .get_current_revision(&NoteLocator::Current(id.clone()))
+                .await
+                .unwrap(),
+            revision
+        );
+    }
+    assert!(store.check_integrity(false).await.unwrap().is_empty());
+
+    // Moving a note out of the way first makes the move possible
+    store.move_note(c, &MoveTarget::Standalone).await.unwrap();
+    store
+        .move_note(b, &MoveTarget::BranchOf(e.clone()))
+        .await
+        .unwrap();
+    assert_eq!(
+        get_current(&store, e).await.get_branches(),
+        HashSet::from([b.clone()])
+    );
+    assert!(store.check_integrity(false).await.unwrap().is_empty());
+}
+
+pub(super) async fn apply_batch(store: impl NoteStore<PlainNote>) {
+    let new_note = |body: &str| PlainNote::new(body.into());
+    let results = store
+        .apply_batch(vec![
+            BatchOperation::New {
+                title: "".to_owned(),
+                note_inner: new_note("Head"),
+                metadata: NoteMetadataEditable::unchanged(),
+            },
+            BatchOperation::Append {
+                last: NoteRef::Created(0),
+                title: "".to_owned(),
+                note_inner: new_note("Second"),
+                metadata: NoteMetadataEditable::unchanged(),
+            },
+            BatchOperation::Branch {
+                parent: NoteRef::Created(1),
+                title: "".to_owned(),
+                note_inner: new_note("Branch"),
+                metadata: NoteMetadataEditable::unchanged(),
+            },
+            BatchOperation::Update {
+                note: NoteRef::Created(0),
+                title: Some("Title".to_owned()),
+                note_inner: None,
+                metadata: NoteMetadataEditable {
+                    tags: Some(HashSet::from(["tag".to_owned()])),
+                    aliases: None,
+                    custom_metadata: None,
+                    author: None,
+                },
+            },
+            BatchOperation::New {
+                title: "".to_owned(),
+                note_inner: new_note("Last"),
+                metadata: NoteMetadataEditable::unchanged(),
+            },
+            BatchOperation::Move {
+                note: NoteRef::Created(4),
+                to: MoveTarget::After(NoteRef::Created(1)),
+            },
+            BatchOperation::Delete {
+                note: NoteRef::Created(2),
+            },
+            // Parsed in the format of the note
+            BatchOperation::UpdateSource {
+                note: NoteRef::Created(4),
+                title: None,
+                note_inner: new_note("Last, updated").into(),
+                metadata: NoteMetadataEditable::unchanged(),
+            },
+        ])
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 8);
+    assert!(results[6].is_none());
+    assert_eq!(
+        results[7].as_ref().unwrap().get_id(),
+        results[4].as_ref().unwrap().get_id()
+    );
+    let ids: Vec<NoteID> = results[..6]
+        .iter()
+        .map(|loc| loc.as_ref().unwrap().get_id().clone())
+        .collect();
+    assert_eq!(ids[3], ids[0]);
+    assert_eq!(ids[5], ids[4]);
+    let head = get_current(&store, &ids[0]).await;
+    assert_eq!(head.get_title(), "Title");
+    assert_eq!(head.get_metadata().tags, HashSet::from(["tag".to_owned()]));
+    assert_eq!(head.get_next(), Some(ids[1].clone()));
+    let second = get_current(&store, &ids[1]).await;
+    assert_eq!(second.get_next(), Some(ids[4].clone()));
+    assert!(second.get_branches().is_empty());
+    assert_eq!(
+        get_current(&store, &ids[4]).await.get_note_inner(),
+        new_note("Last, updated")
+    );
+    assert!(is_deleted(&store, &NoteLocator::Current(ids[2].clone()))
+        .await
+        .unwrap());
+
+    // Nothing changes if an operation fails
+    let note_count = store.search(&("".into())).await.unwrap().len();
+    let revision = store
+        .get_current_revision(&NoteLocator::Current(ids[0].clone()))
+        .await
+        .unwrap();
+    type IsExpected = fn(&NoteStoreError) -> bool;
+    let failing: [(BatchOperation<PlainNote>, IsExpected); 3] = [
+        (
+            BatchOperation::Append {
+                last: NoteRef::Id(ids[0].clone()),
+                title: "".to_owned(),
+                note_inner: new_note("Conflict"),
+                metadata: NoteMetadataEditable::unchanged(),
+            },
+            |e| matches!(e, NoteStoreError::ExistingNext(_, _)),
+        ),
+        (
+            // Only earlier operations can be referred to
+            BatchOperation::Delete {
+                note: NoteRef::Created(2),
+            },
+            |e| matches!(e, NoteStoreError::InvalidPlaceholder(2)),
+        ),
+        (
+            BatchOperation::Move {
+                note: NoteRef::Id(ids[0].clone()),
+                to: MoveTarget::BranchOf(NoteRef::Created(1)),
+            },
+            |e| matches!(e, NoteStoreError::Cycle(_, _)),
+        ),
+    ];
+    for (operation, is_expected) in failing {
+        let res = store
+            .apply_batch(vec![
+                BatchOperation::Update {
+                    note: NoteRef::Id(ids[0].clone()),
+                    title: Some("Changed".to_owned()),
+                    note_inner: None,
+                    metadata: NoteMetadataEditable::unchanged(),
+                },
+                BatchOperation::Append {
+                    last: NoteRef::Id(ids[4].clone()),
+                    title: "".to_owned(),
+                    note_inner: new_note("Added"),
+                    metadata: NoteMetadataEditable::unchanged(),
+                },
+                operation,
+            ])
+            .await;
+        assert!(res.as_ref().is_err_and(is_expected), "{res:?}");
+    }
+    assert_eq!(
+        store
+            .get_current_revision(&NoteLocator::Current(ids[0].clone()))
+            .await
+            .unwrap(),
+        revision
+    );
+    assert_eq!(get_current(&store, &ids[4]).await.get_next(), None);
+    assert_eq!(store.search(&("".into())).await.unwrap().len(), note_count);
+    // Deletions are undone too
+    let res = store
+        .apply_batch(vec![
+            BatchOperation::Delete {
+                note: NoteRef::Id(ids[0].clone()),
+            },
+            BatchOperation::UpdateSource {
+                note: NoteRef::Id(ids[1].clone()),
+                title: None,
+                note_inner: "Not a plain note".to_owned(),
+                metadata: NoteMetadataEditable::unchanged(),
+            },
+        ])
+        .await;
+    assert!(matches!(res, Err(NoteStoreError::NoteInnerError(_))));
+    assert!(!is_deleted(&store, &NoteLocator::Current(ids[0].clone()))
+        .await
+        .unwrap());
+    assert_eq!(
+        get_current(&store, &ids[0]).await.get_next(),
+        Some(ids[1].clone())
+    );
+    assert_eq!(store.search(&("".into())).await.unwrap().len(), note_count);
+}
+
+pub(super) async fn search_nonexist(store: impl NoteStore<PlainNote>) {
+    let note_inner = PlainNote::new("Foo".into());
+    store
+        .new_note(
+            "hello world".to_owned(),
+            note_inner.clone(),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    store
+        .new_note(
+            "goodbye world".to_owned(),
+            note_inner.clone(),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let notes = store.search(&("fizzbuzz".into())).await.unwrap();
+    assert_eq!(notes.len(), 0);
+}
+
+pub(super) async fn backlink(store: impl NoteStore<PlainNote>) {
+    let note_inner_1 = PlainNote::new("Hello world".into());
+    let loc1 = store
+        .new_note(
+            "".to_owned(),
+            note_inner_1.clone(),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let mut note_inner_2 = PlainNote::new("Goodbye world".into());
+    note_inner_2.add_referent(loc1.get_id().clone());
+    let loc2 = store
+        .new_note(
+            "goodbye world".to_owned(),
+            note_inner_2.clone(),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let note = store.get_note(&loc1.current()).await.unwrap();
+    let references = note.get_references();
+    assert!(references.contains(loc2.get_id()));
+}
+
+pub(super) async fn search_tags(store: impl NoteStore<PlainNote>) {
+    let note_inner = PlainNote::new("Foo".into());
+    let md = NoteMetadataEditable {
+        tags: Some(HashSet::from_iter(["tag1".to_owned()])),
+        aliases: None,
+        custom_metadata: None,
+        author: None,
+    };
+    store
+        .new_note("hello world".to_owned(), note_inner.clone(), md.clone())
+        .await
+        .unwrap();
+    store
+        .new_note("goodbye world".to_owned(), note_inner.clone(), md)
+        .await
+        .unwrap();
+    let notes = store.search(&("hello #tag1".into())).await.unwrap();
+    assert_eq!(notes[0].get_title(), "hello world");
+    assert_eq!(notes.len(), 1);
+    let notes = store.search(&("#tag1".into())).await.unwrap();
+    assert_eq!(notes.len(), 2);
+}
+
+pub(super) async fn search_orphan(store: impl NoteStore<PlainNote>) {
+    let loc1 = store
+        .new_note(
+            "".to_owned(),
+            PlainNote::new("Head".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    store
+        .append_note(
+            loc1.get_id(),
+            "".to_owned(),
+            PlainNote::new("Middle".into()),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let notes = store.search(&("!orphan".into())).await.unwrap();
+    assert_eq!(notes.len(), 1);
+}
+
+pub(super) async fn search_notag(store: impl NoteStore<PlainNote>) {
+    let note_inner = PlainNote::new("Foo".into());
+    let md = NoteMetadataEditable {
+        tags: Some(HashSet::from_iter(["tag1".to_owned()])),
+        aliases: None,
+        custom_metadata: None,
+        author: None,
+    };
+    store
+        .new_note("hello world".to_owned(), note_inner.clone(), md.clone())
+        .await
+        .unwrap();
+    store
+        .new_note(
+            "goodbye world".to_owned(),
+            note_inner.clone(),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let notes = store.search(&("world !notag".into())).await.unwrap();
+    assert_eq!(notes[0].get_title(), "goodbye world");
+    assert_eq!(notes.len(), 1);
+    let notes = store.search(&("world".into())).await.unwrap();
+    assert_eq!(notes.len(), 2);
+}
+
+pub(super) async fn issue_151(store: impl NoteStore<PlainNote>) {
+    let note_inner = PlainNote::new("".into());
+    let loc = store
+        .new_note(
+            "note 1".to_owned(),
+            note_inner.clone(),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let loc_next = store
+        .append_note(
+            loc.get_id(),
+            "note 2".to_owned(),
+            note_inner.clone(),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let mut note_inner_ref = PlainNote::new("ref".into());
+    note_inner_ref.add_referent(loc.get_id().clone());
+    let loc2 = store
+        .new_note(
+            "note 3".to_owned(),
+            note_inner_ref.clone(),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let loc3 = store
+        .new_note(
+            "note 4".to_owned(),
+            note_inner_ref.clone(),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let n = store.get_note(&loc.current()).await.unwrap();
+    assert_eq!(&n.get_next().unwrap(), loc_next.get_id());
+    assert_eq!(n.get_references().len(), 2);
+    assert!(n.get_references().contains(loc2.get_id()));
+    assert!(n.get_references().contains(loc3.get_id()));
+}
+
+pub(super) async fn tags(store: impl NoteStore<PlainNote>) {
+    let note_inner = PlainNote::new("".into());
+    let md1 = NoteMetadataEditable {
+        tags: Some(HashSet::from(["tag1".to_owned()])),
+        aliases: None,
+        custom_metadata: None,
+        author: None,
+    };
+    let md2 = NoteMetadataEditable {
+        tags: Some(HashSet::from(["tag1".to_owned(), "tag2".to_owned()])),
+        aliases: None,
+        custom_metadata: None,
+        author: None,
+    };
+    let md3 = NoteMetadataEditable {
+        tags: Some(HashSet::from(["tag2".to_owned(), "tag3".to_owned()])),
+        aliases: None,
+        custom_metadata: None,
+        author: None,
+    };
+    let _loc1 = store
+        .new_note("note 1".to_owned(), note_inner.clone(), md1)
+        .await
+        .unwrap();
+    let _loc2 = store
+        .new_note("note 2".to_owned(), note_inner.clone(), md2)
+        .await
+        .unwrap();
+    let loc3 = store
+        .new_note("note 3".to_owned(), note_inner.clone(), md3)
+        .await
+        .unwrap();
+    assert_eq!(store.tags().await.unwrap().len(), 3);
+    store.delete_note(&loc3).await.unwrap();
+    let tags = store.tags().await.unwrap();
+    assert_eq!(tags.len(), 2);
+    assert!(tags.contains(&"tag1".to_owned()));
+    assert!(tags.contains(&"tag2".to_owned()));
+}
+
+pub(super) async fn search_limit_override(store: impl NoteStore<PlainNote>) {
+    let note_inner = PlainNote::new("Foo".into());
+    store
+        .new_note(
+            "hello world".to_owned(),
+            note_inner.clone(),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    store
+        .new_note(
+            "goodbye world".to_owned(),
+            note_inner.clone(),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let notes = store.search(&("".into())).await.unwrap();
+    assert_eq!(notes.len(), 2);
+    let notes = store.search(&("!limit=1".into())).await.unwrap();
+    assert_eq!(notes.len(), 1);
+}
+
+pub(super) async fn search_tag_exclude(store: impl NoteStore<PlainNote>) {
+    let note_inner = PlainNote::new("Foo".into());
+    let md = NoteMetadataEditable {
+        tags: Some(HashSet::from_iter(["tag1".to_owned()])),
+        aliases: None,
+        custom_metadata: None,
+        author: None,
+    };
+    let loc1 = store
+        .new_note("hello world".to_owned(), note_inner.clone(), md.clone())
+        .await
+        .unwrap();
+    let loc2 = store
+        .new_note(
+            "goodbye world".to_owned(),
+            note_inner.clone(),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let notes = store.search(&("world".into())).await.unwrap();
     assert_eq!(notes.len(), 2);
     let notes = store.search(&("world -#tag1".into())).await.unwrap();
     assert_eq!(notes.len(), 1);
@@ -886,3 +2566,275 @@ pub(super) async fn issue_158(store: impl NoteStore<PlainNote>) {
     assert_eq!(notes.len(), 1);
     assert_eq!(&notes[0].get_id(), loc1.get_id());
 }
+
+/// The store must have front matter synchronization enabled.
+pub(super) async fn front_matter_new_note(store: impl NoteStore<MarkdownNote>) {
+    let loc = store
+        .new_note(
+            "Ignored".to_owned(),
+            "---\ntitle: Foo\ntags: [tag1, tag2]\nstatus: draft\n---\n# Body".into(),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let note = store.get_note(&loc).await.unwrap();
+    assert_eq!(note.get_title(), "Foo");
+    assert_eq!(
+        note.get_metadata().tags,
+        HashSet::from(["tag1".to_owned(), "tag2".to_owned()])
+    );
+    assert_eq!(note.get_metadata().custom_metadata["status"], "draft");
+    assert_eq!(
+        String::from(note.get_note_inner()),
+        "---\ntitle: Foo\ntags:\n- tag1\n- tag2\nstatus: draft\n---\n# Body"
+    );
+    let outline = note.get_note_inner().get_outline().unwrap();
+    assert_eq!(outline.len(), 1);
+    assert_eq!(outline[0].text, "Body");
+}
+
+/// The store must have front matter synchronization enabled.
+pub(super) async fn front_matter_update_metadata(store: impl NoteStore<MarkdownNote>) {
+    let loc1 = store
+        .new_note(
+            "".to_owned(),
+            "---\ntitle: Foo\n---\n# Body".into(),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let loc2 = store
+        .update_note(
+            &loc1,
+            Some("Bar".to_owned()),
+            None,
+            NoteMetadataEditable {
+                tags: Some(HashSet::from(["tag1".to_owned()])),
+                aliases: None,
+                custom_metadata: Some(serde_json::json!({"status": "done"})),
+                author: None,
+            },
+        )
+        .await
+        .unwrap();
+    let note = store.get_note(&loc2).await.unwrap();
+    assert_eq!(note.get_title(), "Bar");
+    assert_eq!(
+        String::from(note.get_note_inner()),
+        "---\ntitle: Bar\ntags:\n- tag1\nstatus: done\n---\n# Body"
+    );
+}
+
+/// The store must have front matter synchronization enabled.
+pub(super) async fn front_matter_update_body(store: impl NoteStore<MarkdownNote>) {
+    let loc1 = store
+        .new_note(
+            "".to_owned(),
+            "---\ntitle: Foo\ntags: [tag1]\nstatus: draft\n---\n# Body".into(),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let loc2 = store
+        .update_note(
+            &loc1,
+            Some("Foo".to_owned()),
+            Some("---\ntitle: Foo\ntags: [tag2]\n---\n# Body".into()),
+            NoteMetadataEditable {
+                tags: Some(HashSet::from(["tag1".to_owned()])),
+                aliases: None,
+                custom_metadata: None,
+                author: None,
+            },
+        )
+        .await
+        .unwrap();
+    let note = store.get_note(&loc2).await.unwrap();
+    assert_eq!(note.get_metadata().tags, HashSet::from(["tag2".to_owned()]));
+    assert_eq!(note.get_metadata().custom_metadata, serde_json::json!({}));
+    assert_eq!(store.tags().await.unwrap(), vec!["tag2".to_owned()]);
+}
+
+/// The store must have front matter synchronization disabled.
+pub(super) async fn front_matter_sync_disabled(store: impl NoteStore<MarkdownNote>) {
+    let body = "---\ntitle: Foo\ntags: [tag1]\n---\n# Body";
+    let loc = store
+        .new_note(
+            "Bar".to_owned(),
+            body.into(),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let note = store.get_note(&loc).await.unwrap();
+    assert_eq!(note.get_title(), "Bar");
+    assert!(note.get_metadata().tags.is_empty());
+    assert_eq!(String::from(note.get_note_inner()), body);
+    // Without synchronization, the front matter is Markdown
+    let outline = note.get_note_inner().get_outline().unwrap();
+    assert_eq!(outline.len(), 2);
+    assert_eq!(outline[0].text, "title: Foo tags: [tag1]");
+}
+
+pub(super) async fn inline_tags(store: impl NoteStore<MarkdownNote>) {
+    let md = NoteMetadataEditable {
+        tags: Some(HashSet::from(["tag1".to_owned()])),
+        aliases: None,
+        custom_metadata: None,
+        author: None,
+    };
+    let loc = store
+        .new_note("".to_owned(), "Some #tag2 here".into(), md)
+        .await
+        .unwrap();
+    let note = store.get_note(&loc).await.unwrap();
+    assert_eq!(note.get_metadata().tags, HashSet::from(["tag1".to_owned()]));
+    assert_eq!(note.get_inline_tags(), HashSet::from(["tag2".to_owned()]));
+    let mut tags = store.tags().await.unwrap();
+    tags.sort();
+    assert_eq!(tags, vec!["tag1".to_owned(), "tag2".to_owned()]);
+}
+
+pub(super) async fn search_inline_tags(store: impl NoteStore<MarkdownNote>) {
+    let loc1 = store
+        .new_note(
+            "hello world".to_owned(),
+            "Some #tag1 here".into(),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let loc2 = store
+        .new_note(
+            "goodbye world".to_owned(),
+            "`#tag1` is code".into(),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let notes = store.search(&("#tag1".into())).await.unwrap();
+    assert_eq!(notes.len(), 1);
+    assert_eq!(&notes[0].get_id(), loc1.get_id());
+    let notes = store.search(&("world -#tag1".into())).await.unwrap();
+    assert_eq!(notes.len(), 1);
+    assert_eq!(&notes[0].get_id(), loc2.get_id());
+    let notes = store.search(&("!notag".into())).await.unwrap();
+    assert_eq!(notes.len(), 1);
+    assert_eq!(&notes[0].get_id(), loc2.get_id());
+}
+
+pub(super) async fn tasks(store: impl NoteStore<MarkdownNote>) {
+    let loc1 = store
+        .new_note(
+            "groceries".to_owned(),
+            "- [ ] milk @due(2026-10-21)\n- [x] eggs @due(2026-10-20)\n- [ ] bread".into(),
+            NoteMetadataEditable {
+                tags: Some(HashSet::from(["home".to_owned()])),
+                aliases: None,
+                custom_metadata: None,
+                author: None,
+            },
+        )
+        .await
+        .unwrap();
+    let loc2 = store
+        .new_note(
+            "work".to_owned(),
+            "#job\n\n- [ ] report @due(2026-10-19)\n- [ ] review @due(2026-11-02)".into(),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let summarize = |tasks: Vec<crate::notestore::task::NoteTask>| -> Vec<(String, String)> {
+        tasks
+            .into_iter()
+            .map(|t| (t.id.to_string(), t.task.text))
+            .collect()
+    };
+    let id1 = loc1.get_id().to_string();
+    let id2 = loc2.get_id().to_string();
+
+    let tasks = store.tasks(&TaskQuery::default()).await.unwrap();
+    assert_eq!(tasks.len(), 5);
+    assert_eq!(&tasks[0].id, loc2.get_id());
+    assert_eq!(&tasks[0].revision, loc2.get_revision().unwrap());
+    assert_eq!(tasks[0].title, "work");
+
+    let tasks = store.tasks(&TaskQuery::open()).await.unwrap();
+    assert_eq!(
+        summarize(tasks),
+        vec![
+            (id2.clone(), "report".to_owned()),
+            (id1.clone(), "milk".to_owned()),
+            (id2.clone(), "review".to_owned()),
+            (id1.clone(), "bread".to_owned()),
+        ]
+    );
+
+    let this_week =
+        TaskQuery::default().due_in_week_of(NaiveDate::from_ymd_opt(2026, 10, 22).unwrap());
+    let tasks = store.tasks(&this_week).await.unwrap();
+    assert_eq!(
+        summarize(tasks),
+        vec![
+            (id2.clone(), "report".to_owned()),
+            (id1.clone(), "eggs".to_owned()),
+            (id1.clone(), "milk".to_owned()),
+        ]
+    );
+
+    let tasks = store
+        .tasks(&TaskQuery::open().with_tag("job".to_owned()))
+        .await
+        .unwrap();
+    assert_eq!(
+        summarize(tasks),
+        vec![
+            (id2.clone(), "report".to_owned()),
+            (id2, "review".to_owned())
+        ]
+    );
+    let tasks = store
+        .tasks(&TaskQuery::default().with_tag("home".to_owned()))
+        .await
+        .unwrap();
+    assert_eq!(tasks.len(), 3);
+}
+
+pub(super) async fn toggle_task(store: impl NoteStore<MarkdownNote>) {
+    let loc1 = store
+        .new_note(
+            "".to_owned(),
+            "Some tasks\n\n- [ ] foo\n- [x] bar\n".into(),
+            NoteMetadataEditable::unchanged(),
+        )
+        .await
+        .unwrap();
+    let loc2 = store.toggle_task(&loc1.current(), 0, None).await.unwrap();
+    assert_ne!(loc1.get_revision(), loc2.get_revision());
+    let note = store.get_note(&loc2).await.unwrap();
+    assert_eq!(
+        String::from(note.get_note_inner()),
+        "Some tasks\n\n- [x] foo\n- [x] bar\n"
+    );
+    let loc3 = store.toggle_task(&loc2, 1, None).await.unwrap();
+    let note = store.get_note(&loc3).await.unwrap();
+    assert_eq!(
+        String::from(note.get_note_inner()),
+        "Some tasks\n\n- [x] foo\n- [ ] bar\n"
+    );
+    assert_eq!(store.get_revisions(&loc1).await.unwrap().len(), 3);
+    let tasks = store.tasks(&TaskQuery::open()).await.unwrap();
+    assert_eq!(tasks.len(), 1);
+    assert_eq!(tasks[0].task.text, "bar");
+    assert_eq!(&tasks[0].revision, loc3.get_revision().unwrap());
+    // Toggling an old revision is racy
+    assert!(matches!(
+        store.toggle_task(&loc2, 0, None).await,
+        Err(NoteStoreError::UpdateOldRevision(_, _))
+    ));
+    assert!(matches!(
+        store.toggle_task(&loc3, 2, None).await,
+        Err(NoteStoreError::TaskNotExist(_, 2))
+    ));
+}
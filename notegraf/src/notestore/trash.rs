@@ -0,0 +1,12 @@
+//! Deleted notes, which are kept until they are purged.
+use crate::Note;
+use chrono::{DateTime, Utc};
+
+/// A deleted note, see [`NoteStore::list_deleted`](crate::NoteStore::list_deleted).
+pub struct DeletedNote<T> {
+    /// The revision that was current when the note was deleted.
+    pub note: Box<dyn Note<T>>,
+    /// Notes deleted before deletion times were recorded are taken to be deleted when they were
+    /// last modified.
+    pub deleted_at: DateTime<Utc>,
+}
@@ -1,10 +1,14 @@
 use crate::notemetadata::NoteMetadataEditable;
 use crate::notestore::BoxedNoteStore;
-use crate::MarkdownNote;
+use crate::NoteType;
 use std::collections::HashSet;
 use std::option::Option::None;
 
-pub async fn populate_test_data(store: &BoxedNoteStore<MarkdownNote>) {
+/// Populate a store with notes written in Markdown, which `T` must read from a string.
+pub async fn populate_test_data<T>(store: &BoxedNoteStore<T>)
+where
+    T: NoteType + for<'a> From<&'a str>,
+{
     let loc1 = store
         .new_note(
             "A big sequence!".to_owned(),
@@ -22,7 +26,9 @@ pub async fn populate_test_data(store: &BoxedNoteStore<MarkdownNote>) {
                 tags: Some(HashSet::from_iter(
                     ["tag1".to_owned(), "tag2".to_owned()].iter().cloned(),
                 )),
-                custom_metadata: None
+                aliases: None,
+                custom_metadata: None,
+                author: None,
             },
         )
         .await
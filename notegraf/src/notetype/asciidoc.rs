@@ -0,0 +1,342 @@
+use crate::notetype::{LinkRewrite, NoteFormat};
+use crate::url::NotegrafURL;
+use crate::{NoteID, NoteType};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::ops::Range;
+
+/// A note written in AsciiDoc.
+///
+/// Notes are linked with the link macro, e.g., `link:notegraf:/note/id[text]`.
+/// Links in listing, literal, passthrough and comment blocks, and in comment lines are ignored.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "String", from = "String")]
+pub struct AsciiDocNote {
+    body: String,
+}
+
+impl From<String> for AsciiDocNote {
+    fn from(note: String) -> AsciiDocNote {
+        AsciiDocNote::new(note)
+    }
+}
+
+impl From<&str> for AsciiDocNote {
+    fn from(note: &str) -> AsciiDocNote {
+        AsciiDocNote::new(note.to_owned())
+    }
+}
+
+impl From<AsciiDocNote> for String {
+    fn from(note: AsciiDocNote) -> String {
+        note.body
+    }
+}
+
+impl AsciiDocNote {
+    pub fn new(body: String) -> Self {
+        AsciiDocNote { body }
+    }
+
+    fn extract_note_id_from_url(link: &str) -> Option<NoteID> {
+        if let Ok(NotegrafURL::Note(id)) = NotegrafURL::parse(link) {
+            Some(id)
+        } else {
+            None
+        }
+    }
+
+    /// Whether a line delimits a block whose contents are not parsed for links.
+    ///
+    /// These are listing (`----`), literal (`....`), passthrough (`++++`) and comment (`////`)
+    /// blocks, which are delimited by four or more of the same character, and fenced code blocks.
+    fn is_verbatim_delimiter(line: &str) -> bool {
+        if line == "```" {
+            return true;
+        }
+        match line.chars().next() {
+            Some(c @ ('-' | '.' | '+' | '/')) => line.len() >= 4 && line.chars().all(|d| d == c),
+            _ => false,
+        }
+    }
+
+    /// Find the targets of link macros in a line, whose first byte is at `offset` in the body.
+    fn find_links(line: &str, offset: usize, targets: &mut Vec<Range<usize>>) {
+        let mut search_from = 0;
+        while let Some(i) = line[search_from..].find("link:").map(|i| i + search_from) {
+            search_from = i + "link:".len();
+            // The macro must not be in the middle of a word, e.g., `xlink:`
+            if line[..i]
+                .chars()
+                .next_back()
+                .is_some_and(|c| c.is_alphanumeric() || c == '_')
+            {
+                continue;
+            }
+            let rest = &line[search_from..];
+            let Some(end) = rest.find(|c: char| c == '[' || c.is_whitespace()) else {
+                continue;
+            };
+            if end > 0 && rest[end..].starts_with('[') {
+                let start = offset + search_from;
+                targets.push(start..start + end);
+                search_from += end;
+            }
+        }
+    }
+
+    /// Get the byte ranges of the targets of all links in the body.
+    fn link_targets(&self) -> Vec<Range<usize>> {
+        let mut targets = vec![];
+        // The delimiter of the verbatim block we are in
+        let mut block_delimiter: Option<&str> = None;
+        let mut offset = 0;
+        for line in self.body.split_inclusive('\n') {
+            let start = offset;
+            offset += line.len();
+            let trimmed = line.trim_end();
+            if let Some(delimiter) = block_delimiter {
+                if trimmed == delimiter {
+                    block_delimiter = None;
+                }
+                continue;
+            }
+            if AsciiDocNote::is_verbatim_delimiter(trimmed) {
+                block_delimiter = Some(trimmed);
+                continue;
+            }
+            if trimmed.starts_with("//") {
+                continue;
+            }
+            AsciiDocNote::find_links(line, start, &mut targets);
+        }
+        targets
+    }
+
+    /// Get the byte range of the whole `link:` macro around a link target, and of its text if it
+    /// has one.
+    fn link_around(&self, target: &Range<usize>) -> (Range<usize>, Option<Range<usize>>) {
+        let start = target.end + 1;
+        match self.body[start..].find(']').map(|end| start + end) {
+            Some(end) => (
+                target.start - "link:".len()..end + 1,
+                (end > start).then_some(start..end),
+            ),
+            None => (target.start - "link:".len()..target.end, None),
+        }
+    }
+}
+
+impl NoteType for AsciiDocNote {
+    type Error = Infallible;
+
+    fn get_format(&self) -> NoteFormat {
+        NoteFormat::AsciiDoc
+    }
+
+    fn parse(_format: Option<NoteFormat>, note: String) -> Result<Self, Self::Error> {
+        Ok(AsciiDocNote::new(note))
+    }
+
+    fn get_referents(&self) -> Result<HashSet<NoteID>, Self::Error> {
+        Ok(self
+            .link_targets()
+            .into_iter()
+            .filter_map(|target| AsciiDocNote::extract_note_id_from_url(&self.body[target]))
+            .collect())
+    }
+
+    fn update_referent(
+        &mut self,
+        old_referent: NoteID,
+        new_referent: NoteID,
+    ) -> Result<(), Self::Error> {
+        let new_url = format!("{}", NotegrafURL::Note(new_referent));
+        // Replace from the back so that earlier ranges stay valid
+        for target in self.link_targets().into_iter().rev() {
+            if AsciiDocNote::extract_note_id_from_url(&self.body[target.clone()]).as_ref()
+                == Some(&old_referent)
+            {
+                self.body.replace_range(target, &new_url);
+            }
+        }
+        Ok(())
+    }
+
+    /// In plain text, a link becomes its text, or its target if it has none.
+    fn remove_referent(
+        &mut self,
+        referent: NoteID,
+        rewrite: LinkRewrite,
+    ) -> Result<(), Self::Error> {
+        let tombstone = format!("{}", NotegrafURL::Deleted(referent.clone()));
+        // Replace from the back so that earlier ranges stay valid
+        for target in self.link_targets().into_iter().rev() {
+            if AsciiDocNote::extract_note_id_from_url(&self.body[target.clone()]).as_ref()
+                != Some(&referent)
+            {
+                continue;
+            }
+            match rewrite {
+                LinkRewrite::Tombstone => self.body.replace_range(target, &tombstone),
+                LinkRewrite::PlainText => {
+                    let (link, text) = self.link_around(&target);
+                    let text = self.body[text.unwrap_or(target)].to_owned();
+                    self.body.replace_range(link, &text);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Links become their texts, and links without texts are left out, so that their
+    /// targets are not indexed.
+    fn get_plain_text(&self) -> Result<String, Self::Error> {
+        let mut plain_text = self.body.clone();
+        // Replace from the back so that earlier ranges stay valid
+        for target in self.link_targets().into_iter().rev() {
+            let (link, text) = self.link_around(&target);
+            plain_text.replace_range(link, text.map_or("", |r| &self.body[r]));
+        }
+        Ok(plain_text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn referents(body: &str) -> HashSet<NoteID> {
+        AsciiDocNote::new(body.to_owned()).get_referents().unwrap()
+    }
+
+    #[test]
+    fn referent_link() {
+        assert_eq!(
+            referents("See link:notegraf:/note/note-1[foo]."),
+            HashSet::from([NoteID::new("note-1".to_owned())])
+        );
+    }
+
+    #[test]
+    fn referent_link_without_text() {
+        assert_eq!(
+            referents("link:notegraf:/note/note-1[]"),
+            HashSet::from([NoteID::new("note-1".to_owned())])
+        );
+    }
+
+    #[test]
+    fn referent_multiple() {
+        assert_eq!(
+            referents("= Title\n\n* link:notegraf:/note/note-1[foo] and link:notegraf:/note/note-2[bar]\n"),
+            HashSet::from([
+                NoteID::new("note-1".to_owned()),
+                NoteID::new("note-2".to_owned())
+            ])
+        );
+    }
+
+    #[test]
+    fn referent_other_links() {
+        assert!(referents(
+            "link:https://example.com[foo] https://example.com[bar] link:notegraf:/tag/foo[] \
+             xlink:notegraf:/note/note-1[] link:notegraf:/note/note-1 notegraf:/note/note-2[]"
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn referent_verbatim() {
+        let body = "----\nlink:notegraf:/note/note-1[]\n----\n\
+                    ....\nlink:notegraf:/note/note-2[]\n....\n\
+                    ////\nlink:notegraf:/note/note-3[]\n////\n\
+                    // link:notegraf:/note/note-4[]\n\
+                    ```\nlink:notegraf:/note/note-5[]\n```\n\
+                    ____\nlink:notegraf:/note/note-6[]\n____\n";
+        assert_eq!(
+            referents(body),
+            HashSet::from([NoteID::new("note-6".to_owned())])
+        );
+    }
+
+    #[test]
+    fn referent_verbatim_nested_delimiter() {
+        // A block is only closed by a delimiter of the same length
+        let body =
+            "------\n----\nlink:notegraf:/note/note-1[]\n------\nlink:notegraf:/note/note-2[]\n";
+        assert_eq!(
+            referents(body),
+            HashSet::from([NoteID::new("note-2".to_owned())])
+        );
+    }
+
+    #[test]
+    fn rewrite_link() {
+        let id_old = NoteID::new("old".into());
+        let id_new = NoteID::new("new-and-longer".into());
+        let mut note =
+            AsciiDocNote::new("link:notegraf:/note/old[foo] and link:notegraf:/note/old[]".into());
+        note.update_referent(id_old, id_new).unwrap();
+        assert_eq!(
+            note.body,
+            "link:notegraf:/note/new-and-longer[foo] and link:notegraf:/note/new-and-longer[]"
+        );
+    }
+
+    #[test]
+    fn rewrite_only_old_referent() {
+        let id_old = NoteID::new("old".into());
+        let id_new = NoteID::new("new".into());
+        let body = "link:notegraf:/note/other[old]\n----\nlink:notegraf:/note/old[]\n----\n";
+        let mut note = AsciiDocNote::new(body.into());
+        note.update_referent(id_old, id_new).unwrap();
+        assert_eq!(note.body, body);
+    }
+
+    #[test]
+    fn remove_referent() {
+        let id = NoteID::new("old".into());
+        let body = "link:notegraf:/note/old[foo] and link:notegraf:/note/old[].";
+        let mut note = AsciiDocNote::new(body.into());
+        note.remove_referent(id.clone(), LinkRewrite::PlainText)
+            .unwrap();
+        assert_eq!(note.body, "foo and notegraf:/note/old.");
+        assert!(note.get_referents().unwrap().is_empty());
+        let mut note = AsciiDocNote::new(body.into());
+        note.remove_referent(id, LinkRewrite::Tombstone).unwrap();
+        assert_eq!(
+            note.body,
+            "link:notegraf:/deleted/old[foo] and link:notegraf:/deleted/old[]."
+        );
+        assert!(note.get_referents().unwrap().is_empty());
+    }
+
+    #[test]
+    fn plain_text() {
+        let note = AsciiDocNote::new(
+            "= Title\nSee link:notegraf:/note/note-1[foo], link:https://example.com[bar] link:notegraf:/note/note-2[]\n----\nlink:notegraf:/note/note-3[baz]\n----"
+                .into(),
+        );
+        assert_eq!(
+            note.get_plain_text().unwrap(),
+            "= Title\nSee foo, bar \n----\nlink:notegraf:/note/note-3[baz]\n----"
+        );
+    }
+
+    #[test]
+    fn serialize() {
+        let ser = serde_json::to_string(&AsciiDocNote {
+            body: "Hello, world!".to_owned(),
+        })
+        .unwrap();
+        assert_eq!(ser, "\"Hello, world!\"");
+    }
+
+    #[test]
+    fn deserialize() {
+        let note: AsciiDocNote = serde_json::from_str("\"Hello, world!\"").unwrap();
+        assert_eq!(note.body, "Hello, world!");
+    }
+}
@@ -0,0 +1,558 @@
+use crate::notetype::markdown::escape_markdown;
+use crate::notetype::{ConvertInto, LinkRewrite, MarkdownNote, NoteFormat, Task};
+use crate::url::NotegrafURL;
+use crate::{NoteID, NoteType};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum BlockNoteError {
+    #[error("task `{0}` doesn't exist")]
+    TaskNotExist(usize),
+    #[error("note cannot be parsed: `{0}`")]
+    ParseError(#[from] serde_json::Error),
+}
+
+/// The kind of a callout, which are the alerts supported by GitHub flavored Markdown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CalloutKind {
+    Note,
+    Tip,
+    Important,
+    Warning,
+    Caution,
+}
+
+impl CalloutKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            CalloutKind::Note => "NOTE",
+            CalloutKind::Tip => "TIP",
+            CalloutKind::Important => "IMPORTANT",
+            CalloutKind::Warning => "WARNING",
+            CalloutKind::Caution => "CAUTION",
+        }
+    }
+}
+
+/// An item of a checklist, which is a task of the note.
+///
+/// Like in Markdown, the due date of the task can be written as `@due(YYYY-MM-DD)` in the text.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChecklistItem {
+    pub text: String,
+    #[serde(default)]
+    pub done: bool,
+}
+
+/// A block of a [`BlockNote`].
+///
+/// Text is plain text rather than markup.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Block {
+    Heading {
+        /// From 1 to 6.
+        level: u8,
+        text: String,
+    },
+    Paragraph {
+        text: String,
+    },
+    Checklist {
+        items: Vec<ChecklistItem>,
+    },
+    Code {
+        #[serde(default)]
+        language: Option<String>,
+        code: String,
+    },
+    Callout {
+        kind: CalloutKind,
+        blocks: Vec<Block>,
+    },
+    /// A reference to another note, optionally with a text to show instead of the note.
+    Reference {
+        note: NoteID,
+        #[serde(default)]
+        text: Option<String>,
+    },
+}
+
+impl Block {
+    /// Visit this block and all blocks nested in it, in order.
+    fn walk<'a>(&'a self, f: &mut impl FnMut(&'a Block)) {
+        f(self);
+        if let Block::Callout { blocks, .. } = self {
+            for block in blocks {
+                block.walk(f);
+            }
+        }
+    }
+
+    fn walk_mut(&mut self, f: &mut impl FnMut(&mut Block)) {
+        f(self);
+        if let Block::Callout { blocks, .. } = self {
+            for block in blocks {
+                block.walk_mut(f);
+            }
+        }
+    }
+}
+
+/// A note made of a tree of typed blocks, such as headings, checklists and references to
+/// other notes.
+///
+/// The note is stored as JSON, e.g., `{"blocks":[{"type":"paragraph","text":"Hello"}]}`.
+/// Other notes are only referred to by reference blocks.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockNote {
+    pub blocks: Vec<Block>,
+}
+
+impl From<BlockNote> for String {
+    fn from(note: BlockNote) -> String {
+        serde_json::to_string(&note).unwrap()
+    }
+}
+
+/// Escape a line so that Markdown renders it byte for byte, including the whitespace around it
+/// that Markdown would strip.
+fn escape_line(line: &str) -> String {
+    let content = line.trim_end();
+    let mut escaped = escape_markdown(content);
+    // A blank line is only whitespace, which must all be kept
+    let trailing = if content.is_empty() {
+        line
+    } else {
+        &line[content.len()..]
+    };
+    for c in trailing.chars() {
+        escaped.push_str(&format!("&#{};", c as u32));
+    }
+    escaped
+}
+
+/// Escape the text of a block so that Markdown renders it byte for byte.
+///
+/// Line breaks are kept as soft breaks, except where they would leave a blank line, which would
+/// end the block, or everywhere if the block must be `inline`.
+fn escape_text(text: &str, inline: bool) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for (i, line) in text.split('\n').enumerate() {
+        if i > 0 {
+            if inline || line.is_empty() || escaped.is_empty() {
+                escaped.push_str("&#10;");
+            } else {
+                escaped.push('\n');
+            }
+        }
+        escaped.push_str(&escape_line(line));
+    }
+    escaped
+}
+
+impl BlockNote {
+    pub fn new(blocks: Vec<Block>) -> Self {
+        BlockNote { blocks }
+    }
+
+    fn walk<'a>(&'a self, mut f: impl FnMut(&'a Block)) {
+        for block in &self.blocks {
+            block.walk(&mut f);
+        }
+    }
+
+    fn walk_mut(&mut self, mut f: impl FnMut(&mut Block)) {
+        for block in &mut self.blocks {
+            block.walk_mut(&mut f);
+        }
+    }
+
+    fn block_to_markdown(block: &Block) -> String {
+        match block {
+            Block::Heading { level, text } => {
+                let level = (*level).clamp(1, 6) as usize;
+                format!("{} {}\n", "#".repeat(level), escape_text(text, true))
+            }
+            Block::Paragraph { text } => format!("{}\n", escape_text(text, false)),
+            Block::Checklist { items } => items
+                .iter()
+                .map(|item| {
+                    let marker = if item.done { "x" } else { " " };
+                    format!("- [{}] {}\n", marker, escape_text(&item.text, true))
+                })
+                .collect(),
+            Block::Code { language, code } => {
+                // The fence must be longer than any run of backticks in the code
+                let mut longest = 0;
+                let mut run = 0;
+                for c in code.chars() {
+                    run = if c == '`' { run + 1 } else { 0 };
+                    longest = longest.max(run);
+                }
+                let fence = "`".repeat((longest + 1).max(3));
+                let language = language.as_deref().unwrap_or("");
+                let newline = if code.is_empty() || code.ends_with('\n') {
+                    ""
+                } else {
+                    "\n"
+                };
+                format!("{fence}{language}\n{code}{newline}{fence}\n")
+            }
+            Block::Callout { kind, blocks } => {
+                let mut markdown = format!("> [!{}]\n", kind.as_str());
+                let inner = BlockNote::blocks_to_markdown(blocks);
+                for line in inner.lines() {
+                    if line.is_empty() {
+                        markdown.push_str(">\n");
+                    } else {
+                        markdown.push_str(&format!("> {line}\n"));
+                    }
+                }
+                markdown
+            }
+            Block::Reference { note, text } => {
+                let text = match text {
+                    Some(t) => escape_text(t, true),
+                    None => escape_text(note.as_ref(), true),
+                };
+                format!("[{}]({})\n", text, NotegrafURL::Note(note.clone()))
+            }
+        }
+    }
+
+    fn blocks_to_markdown(blocks: &[Block]) -> String {
+        blocks
+            .iter()
+            .map(BlockNote::block_to_markdown)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Export the note to Markdown.
+    ///
+    /// Callouts become GitHub flavored Markdown alerts, and reference blocks become `notegraf:`
+    /// links, so that the Markdown note refers to the same notes and has the same tasks.
+    pub fn to_markdown(&self) -> String {
+        BlockNote::blocks_to_markdown(&self.blocks)
+    }
+}
+
+impl NoteType for BlockNote {
+    type Error = BlockNoteError;
+
+    fn get_format(&self) -> NoteFormat {
+        NoteFormat::Block
+    }
+
+    fn parse(_format: Option<NoteFormat>, note: String) -> Result<Self, Self::Error> {
+        Ok(serde_json::from_str(&note)?)
+    }
+
+    fn get_referents(&self) -> Result<HashSet<NoteID>, Self::Error> {
+        let mut referents = HashSet::new();
+        self.walk(|block| {
+            if let Block::Reference { note, .. } = block {
+                referents.insert(note.clone());
+            }
+        });
+        Ok(referents)
+    }
+
+    fn update_referent(
+        &mut self,
+        old_referent: NoteID,
+        new_referent: NoteID,
+    ) -> Result<(), Self::Error> {
+        self.walk_mut(|block| {
+            if let Block::Reference { note, .. } = block {
+                if *note == old_referent {
+                    *note = new_referent.clone();
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Reference blocks to `referent` become paragraphs of their text, or without a text, of the
+    /// note ID or the tombstone URL.
+    fn remove_referent(
+        &mut self,
+        referent: NoteID,
+        rewrite: LinkRewrite,
+    ) -> Result<(), Self::Error> {
+        self.walk_mut(|block| {
+            if let Block::Reference { note, text } = block {
+                if *note == referent {
+                    let text = text.take().unwrap_or_else(|| match rewrite {
+                        LinkRewrite::PlainText => referent.to_string(),
+                        LinkRewrite::Tombstone => {
+                            NotegrafURL::Deleted(referent.clone()).to_string()
+                        }
+                    });
+                    *block = Block::Paragraph { text };
+                }
+            }
+        });
+        Ok(())
+    }
+
+    fn get_plain_text(&self) -> Result<String, Self::Error> {
+        let mut text = vec![];
+        self.walk(|block| match block {
+            Block::Heading { text: t, .. } | Block::Paragraph { text: t } => text.push(t.clone()),
+            Block::Checklist { items } => text.extend(items.iter().map(|item| item.text.clone())),
+            Block::Code { code, .. } => text.push(code.clone()),
+            Block::Reference { text: Some(t), .. } => text.push(t.clone()),
+            Block::Callout { .. } | Block::Reference { text: None, .. } => {}
+        });
+        Ok(text.join("\n"))
+    }
+
+    /// The body as Markdown, so that changes to the structure of blocks show in diffs.
+    fn get_body(&self) -> Result<String, Self::Error> {
+        Ok(self.to_markdown())
+    }
+
+    fn get_tasks(&self) -> Result<Vec<Task>, Self::Error> {
+        let mut tasks = vec![];
+        self.walk(|block| {
+            if let Block::Checklist { items } = block {
+                for item in items {
+                    let (text, due) = MarkdownNote::extract_due(&item.text);
+                    tasks.push(Task {
+                        index: tasks.len(),
+                        text: text.split_whitespace().collect::<Vec<_>>().join(" "),
+                        done: item.done,
+                        due,
+                    });
+                }
+            }
+        });
+        Ok(tasks)
+    }
+
+    fn set_task_done(&mut self, index: usize, done: bool) -> Result<(), Self::Error> {
+        let mut i = 0;
+        let mut found = false;
+        self.walk_mut(|block| {
+            if let Block::Checklist { items } = block {
+                for item in items {
+                    if i == index {
+                        item.done = done;
+                        found = true;
+                    }
+                    i += 1;
+                }
+            }
+        });
+        if found {
+            Ok(())
+        } else {
+            Err(BlockNoteError::TaskNotExist(index))
+        }
+    }
+}
+
+impl ConvertInto<MarkdownNote> for BlockNote {
+    fn convert_into(self) -> Result<MarkdownNote, BlockNoteError> {
+        Ok(MarkdownNote::new(self.to_markdown()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn note() -> BlockNote {
+        serde_json::from_str(
+            r#"{"blocks": [
+                {"type": "heading", "level": 1, "text": "Weekly sync"},
+                {"type": "paragraph", "text": "Agenda for *this* week:\n1. review"},
+                {"type": "checklist", "items": [
+                    {"text": "Write the [draft] @due(2026-10-23)", "done": true},
+                    {"text": "Send it out"}
+                ]},
+                {"type": "callout", "kind": "warning", "blocks": [
+                    {"type": "paragraph", "text": "See"},
+                    {"type": "reference", "note": "note-1", "text": "the runbook"},
+                    {"type": "checklist", "items": [{"text": "Restart"}]}
+                ]},
+                {"type": "code", "language": "sh", "code": "echo ```"},
+                {"type": "reference", "note": "note-2"}
+            ]}"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn referents() {
+        assert_eq!(
+            note().get_referents().unwrap(),
+            HashSet::from([
+                NoteID::new("note-1".to_owned()),
+                NoteID::new("note-2".to_owned())
+            ])
+        );
+        let note = BlockNote::new(vec![Block::Paragraph {
+            text: "[foo](notegraf:/note/note-1)".to_owned(),
+        }]);
+        assert!(note.get_referents().unwrap().is_empty());
+    }
+
+    #[test]
+    fn update_referent() {
+        let mut note = note();
+        note.update_referent(
+            NoteID::new("note-1".to_owned()),
+            NoteID::new("note-3".to_owned()),
+        )
+        .unwrap();
+        assert_eq!(
+            note.get_referents().unwrap(),
+            HashSet::from([
+                NoteID::new("note-2".to_owned()),
+                NoteID::new("note-3".to_owned())
+            ])
+        );
+    }
+
+    #[test]
+    fn remove_referent() {
+        let mut note = note();
+        note.remove_referent(NoteID::new("note-1".to_owned()), LinkRewrite::PlainText)
+            .unwrap();
+        note.remove_referent(NoteID::new("note-2".to_owned()), LinkRewrite::Tombstone)
+            .unwrap();
+        assert!(note.get_referents().unwrap().is_empty());
+        let Block::Callout { blocks, .. } = &note.blocks[3] else {
+            panic!("not a callout");
+        };
+        assert_eq!(
+            blocks[1],
+            Block::Paragraph {
+                text: "the runbook".to_owned()
+            }
+        );
+        assert_eq!(
+            note.blocks[5],
+            Block::Paragraph {
+                text: "notegraf:/deleted/note-2".to_owned()
+            }
+        );
+    }
+
+    #[test]
+    fn tasks() {
+        let mut note = note();
+        let tasks = note.get_tasks().unwrap();
+        assert_eq!(tasks.len(), 3);
+        assert_eq!(tasks[0].text, "Write the [draft]");
+        assert!(tasks[0].done);
+        assert_eq!(tasks[0].due, NaiveDate::from_ymd_opt(2026, 10, 23));
+        assert_eq!(tasks[2].text, "Restart");
+        assert_eq!(tasks[2].index, 2);
+        note.set_task_done(2, true).unwrap();
+        assert!(note.get_tasks().unwrap()[2].done);
+        assert!(matches!(
+            note.set_task_done(3, true),
+            Err(BlockNoteError::TaskNotExist(3))
+        ));
+    }
+
+    #[test]
+    fn to_markdown() {
+        assert_eq!(
+            note().to_markdown(),
+            "# Weekly sync\n\n\
+             Agenda for \\*this\\* week:\n1\\. review\n\n\
+             - [x] Write the \\[draft\\] @due(2026-10-23)\n- [ ] Send it out\n\n\
+             > [!WARNING]\n> See\n>\n> [the runbook](notegraf:/note/note-1)\n>\n> - [ ] Restart\n\n\
+             ````sh\necho ```\n````\n\n\
+             [note-2](notegraf:/note/note-2)\n"
+        );
+    }
+
+    /// Read back the text of the headings, paragraphs and checklist items of a Markdown export.
+    fn markdown_texts(markdown: &str) -> Vec<String> {
+        use pulldown_cmark::{Event, Options, Parser, TagEnd};
+        let mut options = Options::empty();
+        options.insert(Options::ENABLE_TASKLISTS);
+        options.insert(Options::ENABLE_SMART_PUNCTUATION);
+        let mut texts = vec![];
+        let mut text = String::new();
+        for event in Parser::new_ext(markdown, options) {
+            match event {
+                Event::Text(t) => text.push_str(&t),
+                Event::SoftBreak => text.push('\n'),
+                Event::End(TagEnd::Heading(_) | TagEnd::Paragraph | TagEnd::Item) => {
+                    texts.push(std::mem::take(&mut text))
+                }
+                _ => {}
+            }
+        }
+        texts
+    }
+
+    #[test]
+    fn to_markdown_round_trip() {
+        let texts = [
+            " Two\n lines ",
+            "    indented\n\n\tblank line  \n   \n",
+            "\nleading \"quotes\" -- and... \\*marks*\r\n#",
+            "a   task  ",
+        ];
+        let note = BlockNote::new(vec![
+            Block::Heading {
+                level: 2,
+                text: texts[0].to_owned(),
+            },
+            Block::Paragraph {
+                text: texts[1].to_owned(),
+            },
+            Block::Paragraph {
+                text: texts[2].to_owned(),
+            },
+            Block::Checklist {
+                items: vec![ChecklistItem {
+                    text: texts[3].to_owned(),
+                    done: false,
+                }],
+            },
+        ]);
+        assert_eq!(markdown_texts(&note.to_markdown()), texts);
+    }
+
+    #[test]
+    fn to_markdown_keeps_links_and_tasks() {
+        let note = note();
+        let markdown: MarkdownNote = note.clone().convert_into().unwrap();
+        assert_eq!(
+            markdown.get_referents().unwrap(),
+            note.get_referents().unwrap()
+        );
+        assert_eq!(markdown.get_tasks().unwrap(), note.get_tasks().unwrap());
+    }
+
+    #[test]
+    fn serialize() {
+        let note = BlockNote::new(vec![Block::Reference {
+            note: NoteID::new("note-1".to_owned()),
+            text: None,
+        }]);
+        let ser: String = note.clone().into();
+        assert_eq!(
+            ser,
+            r#"{"blocks":[{"type":"reference","note":"note-1","text":null}]}"#
+        );
+        assert_eq!(BlockNote::parse(None, ser).unwrap(), note);
+        assert!(matches!(
+            BlockNote::parse(None, "# foo".to_owned()),
+            Err(BlockNoteError::ParseError(_))
+        ));
+    }
+}
@@ -0,0 +1,153 @@
+use crate::notetype::markdown::escape_markdown;
+use crate::notetype::{MarkdownNote, MarkdownNoteError, PlainNote, PlainNoteError};
+use crate::url::NotegrafURL;
+use crate::{NoteID, NoteType};
+use std::collections::HashSet;
+
+/// The start of the `notegraf:` URL of a note, which is followed by its ID.
+const NOTE_URL_PREFIX: &str = "notegraf:/note/";
+
+/// Conversion of a note into another note type, keeping the notes that it links to.
+pub trait ConvertInto<U: NoteType>: NoteType {
+    fn convert_into(self) -> Result<U, Self::Error>;
+}
+
+/// The body of the plain note is escaped, so that it is rendered as is.
+/// The `notegraf:` URLs of its referents in the body become links, and the other referents are
+/// appended to the body as a list of links.
+impl ConvertInto<MarkdownNote> for PlainNote {
+    fn convert_into(self) -> Result<MarkdownNote, PlainNoteError> {
+        // Escaping leaves the URLs of notes unchanged, and the text is escaped as a whole so that
+        // the start of each line is known
+        let text = escape_markdown(&self.get_plain_text()?);
+        let referents = self.get_referents()?;
+        let mut body = String::with_capacity(text.len());
+        let mut linked = HashSet::new();
+        let mut rest = text.as_str();
+        while let Some(start) = rest.find(NOTE_URL_PREFIX) {
+            let after = &rest[start + NOTE_URL_PREFIX.len()..];
+            let id_len = after.len()
+                - after
+                    .trim_start_matches(|c: char| c.is_ascii_alphanumeric() || c == '-')
+                    .len();
+            let id = NoteID::new(after[..id_len].to_owned());
+            if referents.contains(&id) {
+                body.push_str(&rest[..start]);
+                body.push_str(&format!("<{}>", NotegrafURL::Note(id.clone())));
+                linked.insert(id);
+                rest = &after[id_len..];
+            } else {
+                body.push_str(&rest[..start + NOTE_URL_PREFIX.len()]);
+                rest = after;
+            }
+        }
+        body.push_str(rest);
+        let mut referents: Vec<_> = referents.difference(&linked).collect();
+        if referents.is_empty() {
+            return Ok(MarkdownNote::new(body));
+        }
+        referents.sort_by(|a, b| a.as_ref().cmp(b.as_ref()));
+        if !body.is_empty() {
+            body.push_str(if body.ends_with('\n') { "\n" } else { "\n\n" });
+        }
+        for referent in referents {
+            body.push_str(&format!(
+                "- [{}]({})\n",
+                referent,
+                NotegrafURL::Note(referent.clone())
+            ));
+        }
+        Ok(MarkdownNote::new(body))
+    }
+}
+
+/// The body of the Markdown note is kept as is, and its links become the referents.
+impl ConvertInto<PlainNote> for MarkdownNote {
+    fn convert_into(self) -> Result<PlainNote, MarkdownNoteError> {
+        let referents = self.get_referents()?;
+        let mut note = PlainNote::new(self.into());
+        for referent in referents {
+            note.add_referent(referent);
+        }
+        Ok(note)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_into_markdown() {
+        let mut note = PlainNote::new("Foo".to_owned());
+        note.add_referent(NoteID::new("note-2".to_owned()));
+        note.add_referent(NoteID::new("note-1".to_owned()));
+        let note: MarkdownNote = note.convert_into().unwrap();
+        assert_eq!(
+            String::from(note.clone()),
+            "Foo\n\n- [note-1](notegraf:/note/note-1)\n- [note-2](notegraf:/note/note-2)\n"
+        );
+        assert_eq!(
+            note.get_referents().unwrap(),
+            HashSet::from([
+                NoteID::new("note-1".to_owned()),
+                NoteID::new("note-2".to_owned())
+            ])
+        );
+    }
+
+    #[test]
+    fn plain_into_markdown_without_referents() {
+        let note: MarkdownNote = PlainNote::new("Foo".to_owned()).convert_into().unwrap();
+        assert_eq!(note, MarkdownNote::new("Foo".to_owned()));
+    }
+
+    #[test]
+    fn plain_into_markdown_escaped() {
+        let note: MarkdownNote = PlainNote::new("# Not a *heading* [or link]".to_owned())
+            .convert_into()
+            .unwrap();
+        assert_eq!(
+            String::from(note.clone()),
+            "\\# Not a \\*heading\\* \\[or link\\]"
+        );
+        assert_eq!(
+            note.get_plain_text().unwrap(),
+            "# Not a *heading* [or link]"
+        );
+    }
+
+    #[test]
+    fn plain_into_markdown_indented() {
+        let note: MarkdownNote = PlainNote::new("    not code\n\tnor this".to_owned())
+            .convert_into()
+            .unwrap();
+        assert_eq!(String::from(note.clone()), "&#32;   not code\n&#9;nor this");
+        assert_eq!(note.get_plain_text().unwrap(), "    not code \tnor this");
+    }
+
+    #[test]
+    fn markdown_into_plain_into_markdown() {
+        let body = "[foo](notegraf:/note/note-1) and notegraf:/note/note-2";
+        let note: PlainNote = MarkdownNote::new(body.to_owned()).convert_into().unwrap();
+        let note: MarkdownNote = note.convert_into().unwrap();
+        // The link is not appended again, and the URL of a note that isn't linked stays text
+        assert_eq!(
+            String::from(note.clone()),
+            "\\[foo\\](<notegraf:/note/note-1>) and notegraf:/note/note-2"
+        );
+        assert_eq!(
+            note.get_referents().unwrap(),
+            HashSet::from([NoteID::new("note-1".to_owned())])
+        );
+    }
+
+    #[test]
+    fn markdown_into_plain() {
+        let body = "# Foo\n[bar](notegraf:/note/note-1)\n```\n[baz](notegraf:/note/note-2)\n```";
+        let note: PlainNote = MarkdownNote::new(body.to_owned()).convert_into().unwrap();
+        let mut expected = PlainNote::new(body.to_owned());
+        expected.add_referent(NoteID::new("note-1".to_owned()));
+        assert_eq!(note, expected);
+    }
+}
@@ -0,0 +1,337 @@
+#[cfg(feature = "notetype_asciidoc")]
+use crate::notetype::AsciiDocNote;
+#[cfg(feature = "notetype_org")]
+use crate::notetype::OrgNote;
+#[cfg(feature = "notetype_block")]
+use crate::notetype::{BlockNote, BlockNoteError};
+use crate::notetype::{
+    ConvertInto, FrontMatter, Heading, LinkRewrite, MarkdownNote, MarkdownNoteError, NoteFormat,
+    PlainNote, PlainNoteError, Task,
+};
+use crate::{NoteID, NoteType};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashSet;
+use std::convert::Infallible;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum DynamicNoteError {
+    #[error(transparent)]
+    Plain(#[from] PlainNoteError),
+    #[error(transparent)]
+    Markdown(#[from] MarkdownNoteError),
+    #[cfg(feature = "notetype_block")]
+    #[error(transparent)]
+    Block(#[from] BlockNoteError),
+}
+
+impl From<Infallible> for DynamicNoteError {
+    fn from(e: Infallible) -> Self {
+        match e {}
+    }
+}
+
+/// A note whose format is chosen at runtime, so that notes of different formats can be kept in
+/// the same store.
+///
+/// A dynamic note is converted into a string (and serialized) in the same way as the note it
+/// holds, so its format must be recorded separately (see [`NoteType::get_format`]) to restore it
+/// with [`NoteType::parse`].
+/// Without a format, a string is treated as a Markdown note.
+/// Deserializing cannot tell Markdown, Org-mode and AsciiDoc notes apart, so it fails for them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+pub enum DynamicNote {
+    Plain(PlainNote),
+    Markdown(MarkdownNote),
+    #[cfg(feature = "notetype_org")]
+    Org(OrgNote),
+    #[cfg(feature = "notetype_asciidoc")]
+    AsciiDoc(AsciiDocNote),
+    #[cfg(feature = "notetype_block")]
+    Block(BlockNote),
+}
+
+/// Call a method on the note held by a [`DynamicNote`], converting the error.
+macro_rules! dispatch {
+    ($note:expr, $n:ident => $call:expr) => {
+        match $note {
+            DynamicNote::Plain($n) => Ok($call?),
+            DynamicNote::Markdown($n) => Ok($call?),
+            #[cfg(feature = "notetype_org")]
+            DynamicNote::Org($n) => Ok($call?),
+            #[cfg(feature = "notetype_asciidoc")]
+            DynamicNote::AsciiDoc($n) => Ok($call?),
+            #[cfg(feature = "notetype_block")]
+            DynamicNote::Block($n) => Ok($call?),
+        }
+    };
+}
+
+impl<'de> Deserialize<'de> for DynamicNote {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Serialized {
+            Plain(PlainNote),
+            #[cfg(feature = "notetype_block")]
+            Block(BlockNote),
+            // Markdown, Org-mode and AsciiDoc notes alike
+            #[allow(dead_code)]
+            Text(String),
+        }
+
+        match Serialized::deserialize(deserializer)? {
+            Serialized::Plain(n) => Ok(DynamicNote::Plain(n)),
+            #[cfg(feature = "notetype_block")]
+            Serialized::Block(n) => Ok(DynamicNote::Block(n)),
+            Serialized::Text(_) => Err(D::Error::custom(
+                "the format of a note serialized into text is unknown, parse it with its format",
+            )),
+        }
+    }
+}
+
+impl Default for DynamicNote {
+    fn default() -> Self {
+        DynamicNote::Markdown(Default::default())
+    }
+}
+
+impl From<String> for DynamicNote {
+    fn from(note: String) -> DynamicNote {
+        DynamicNote::Markdown(MarkdownNote::from(note))
+    }
+}
+
+impl From<&str> for DynamicNote {
+    fn from(note: &str) -> DynamicNote {
+        DynamicNote::Markdown(MarkdownNote::from(note))
+    }
+}
+
+impl From<DynamicNote> for String {
+    fn from(note: DynamicNote) -> String {
+        match note {
+            DynamicNote::Plain(n) => n.into(),
+            DynamicNote::Markdown(n) => n.into(),
+            #[cfg(feature = "notetype_org")]
+            DynamicNote::Org(n) => n.into(),
+            #[cfg(feature = "notetype_asciidoc")]
+            DynamicNote::AsciiDoc(n) => n.into(),
+            #[cfg(feature = "notetype_block")]
+            DynamicNote::Block(n) => n.into(),
+        }
+    }
+}
+
+impl NoteType for DynamicNote {
+    type Error = DynamicNoteError;
+
+    fn get_format(&self) -> NoteFormat {
+        match self {
+            DynamicNote::Plain(n) => n.get_format(),
+            DynamicNote::Markdown(n) => n.get_format(),
+            #[cfg(feature = "notetype_org")]
+            DynamicNote::Org(n) => n.get_format(),
+            #[cfg(feature = "notetype_asciidoc")]
+            DynamicNote::AsciiDoc(n) => n.get_format(),
+            #[cfg(feature = "notetype_block")]
+            DynamicNote::Block(n) => n.get_format(),
+        }
+    }
+
+    fn parse(format: Option<NoteFormat>, note: String) -> Result<Self, Self::Error> {
+        Ok(match format {
+            Some(NoteFormat::Plain) => DynamicNote::Plain(PlainNote::parse(format, note)?),
+            Some(NoteFormat::Markdown) | None => {
+                DynamicNote::Markdown(MarkdownNote::parse(format, note)?)
+            }
+            #[cfg(feature = "notetype_org")]
+            Some(NoteFormat::Org) => DynamicNote::Org(OrgNote::parse(format, note)?),
+            #[cfg(feature = "notetype_asciidoc")]
+            Some(NoteFormat::AsciiDoc) => DynamicNote::AsciiDoc(AsciiDocNote::parse(format, note)?),
+            #[cfg(feature = "notetype_block")]
+            Some(NoteFormat::Block) => DynamicNote::Block(BlockNote::parse(format, note)?),
+        })
+    }
+
+    fn convert_to(self, format: NoteFormat) -> Result<Option<Self>, Self::Error> {
+        Ok(match (self, format) {
+            (DynamicNote::Plain(n), NoteFormat::Markdown) => {
+                Some(DynamicNote::Markdown(n.convert_into()?))
+            }
+            (DynamicNote::Markdown(n), NoteFormat::Plain) => {
+                Some(DynamicNote::Plain(n.convert_into()?))
+            }
+            #[cfg(feature = "notetype_block")]
+            (DynamicNote::Block(n), NoteFormat::Markdown) => {
+                Some(DynamicNote::Markdown(n.convert_into()?))
+            }
+            (n, format) => (n.get_format() == format).then_some(n),
+        })
+    }
+
+    fn get_referents(&self) -> Result<HashSet<NoteID>, Self::Error> {
+        dispatch!(self, n => n.get_referents())
+    }
+
+    fn update_referent(
+        &mut self,
+        old_referent: NoteID,
+        new_referent: NoteID,
+    ) -> Result<(), Self::Error> {
+        dispatch!(self, n => n.update_referent(old_referent, new_referent))
+    }
+
+    fn remove_referent(
+        &mut self,
+        referent: NoteID,
+        rewrite: LinkRewrite,
+    ) -> Result<(), Self::Error> {
+        dispatch!(self, n => n.remove_referent(referent, rewrite))
+    }
+
+    fn get_inline_tags(&self) -> Result<HashSet<String>, Self::Error> {
+        dispatch!(self, n => n.get_inline_tags())
+    }
+
+    fn get_plain_text(&self) -> Result<String, Self::Error> {
+        dispatch!(self, n => n.get_plain_text())
+    }
+
+    fn get_body(&self) -> Result<String, Self::Error> {
+        dispatch!(self, n => n.get_body())
+    }
+
+    fn get_outline(&self) -> Result<Vec<Heading>, Self::Error> {
+        dispatch!(self, n => n.get_outline())
+    }
+
+    fn get_tasks(&self) -> Result<Vec<Task>, Self::Error> {
+        dispatch!(self, n => n.get_tasks())
+    }
+
+    fn set_task_done(&mut self, index: usize, done: bool) -> Result<(), Self::Error> {
+        dispatch!(self, n => n.set_task_done(index, done))
+    }
+
+    fn get_front_matter(&self) -> Result<Option<FrontMatter>, Self::Error> {
+        dispatch!(self, n => n.get_front_matter())
+    }
+
+    fn set_front_matter(&mut self, front_matter: &FrontMatter) -> Result<(), Self::Error> {
+        dispatch!(self, n => n.set_front_matter(front_matter))
+    }
+
+    fn enable_front_matter(&mut self) {
+        // Only Markdown notes embed metadata
+        if let DynamicNote::Markdown(n) = self {
+            n.enable_front_matter();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_roundtrip() {
+        let notes = vec![
+            DynamicNote::Plain(PlainNote::new("foo".to_owned())),
+            DynamicNote::Markdown(MarkdownNote::new("# foo".to_owned())),
+            #[cfg(feature = "notetype_org")]
+            DynamicNote::Org(OrgNote::new("* foo".to_owned())),
+            #[cfg(feature = "notetype_asciidoc")]
+            DynamicNote::AsciiDoc(AsciiDocNote::new("= foo".to_owned())),
+            #[cfg(feature = "notetype_block")]
+            DynamicNote::Block(BlockNote::default()),
+        ];
+        for note in notes {
+            let format = note.get_format();
+            let restored = DynamicNote::parse(Some(format), note.clone().into()).unwrap();
+            assert_eq!(restored, note);
+        }
+    }
+
+    #[test]
+    fn no_format_is_markdown() {
+        let note = DynamicNote::parse(None, "# foo".to_owned()).unwrap();
+        assert_eq!(note.get_format(), NoteFormat::Markdown);
+        assert_eq!(DynamicNote::from("# foo".to_owned()), note);
+    }
+
+    #[test]
+    fn dispatch() {
+        let note = DynamicNote::parse(
+            Some(NoteFormat::Markdown),
+            "[foo](notegraf:/note/note-1) #tag".to_owned(),
+        )
+        .unwrap();
+        assert_eq!(
+            note.get_referents().unwrap(),
+            HashSet::from([NoteID::new("note-1".to_owned())])
+        );
+        assert_eq!(
+            note.get_inline_tags().unwrap(),
+            HashSet::from(["tag".to_owned()])
+        );
+        let mut plain = PlainNote::new("foo".to_owned());
+        plain.add_referent(NoteID::new("note-1".to_owned()));
+        let mut note = DynamicNote::Plain(plain);
+        assert!(note.get_inline_tags().unwrap().is_empty());
+        assert!(matches!(
+            note.update_referent(
+                NoteID::new("note-2".to_owned()),
+                NoteID::new("note-3".to_owned())
+            ),
+            Err(DynamicNoteError::Plain(_))
+        ));
+    }
+
+    #[test]
+    fn convert_to() {
+        let mut plain = PlainNote::new("foo".to_owned());
+        plain.add_referent(NoteID::new("note-1".to_owned()));
+        let note = DynamicNote::Plain(plain)
+            .convert_to(NoteFormat::Markdown)
+            .unwrap()
+            .unwrap();
+        assert_eq!(note.get_format(), NoteFormat::Markdown);
+        assert_eq!(
+            note.get_referents().unwrap(),
+            HashSet::from([NoteID::new("note-1".to_owned())])
+        );
+        let note = note.convert_to(NoteFormat::Plain).unwrap().unwrap();
+        assert_eq!(note.get_format(), NoteFormat::Plain);
+        assert_eq!(
+            note.clone().convert_to(NoteFormat::Plain).unwrap(),
+            Some(note)
+        );
+        #[cfg(feature = "notetype_org")]
+        assert!(DynamicNote::from("foo")
+            .convert_to(NoteFormat::Org)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn serialize() {
+        let note = DynamicNote::Markdown(MarkdownNote::new("Hello, world!".to_owned()));
+        assert_eq!(serde_json::to_string(&note).unwrap(), "\"Hello, world!\"");
+        let note: DynamicNote =
+            serde_json::from_str("{\"body\":\"foo\",\"referents\":[]}").unwrap();
+        assert_eq!(note.get_format(), NoteFormat::Plain);
+        assert!(serde_json::from_str::<DynamicNote>("\"Hello, world!\"").is_err());
+        #[cfg(feature = "notetype_block")]
+        {
+            let note: DynamicNote = serde_json::from_str("{\"blocks\":[]}").unwrap();
+            assert_eq!(note.get_format(), NoteFormat::Block);
+        }
+    }
+}
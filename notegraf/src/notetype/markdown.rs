@@ -1,24 +1,42 @@
+use crate::notetype::{FrontMatter, Heading, LinkRewrite, NoteFormat, Task};
 use crate::url::NotegrafURL;
 use crate::{NoteID, NoteType};
+use chrono::NaiveDate;
 use pulldown_cmark::Tag as PTag;
 use pulldown_cmark::TagEnd as PTagEnd;
 use pulldown_cmark::{Event, LinkType, Options, Parser};
 use pulldown_cmark_to_cmark::cmark_with_options;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::ops::Range;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum MarkdownNoteError {
     #[error("format error")]
     FormatError(#[from] fmt::Error),
+    #[error("front matter error: `{0}`")]
+    FrontMatterError(String),
+    #[error("task `{0}` doesn't exist")]
+    TaskNotExist(usize),
 }
 
-#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Eq, Serialize, Deserialize)]
 #[serde(into = "String", from = "String")]
 pub struct MarkdownNote {
     body: String,
+    /// Whether a leading YAML block is front matter rather than Markdown.
+    ///
+    /// This follows the settings of the store, so it is neither stored nor compared.
+    front_matter: bool,
+}
+
+impl PartialEq for MarkdownNote {
+    fn eq(&self, other: &Self) -> bool {
+        self.body == other.body
+    }
 }
 
 impl From<String> for MarkdownNote {
@@ -41,7 +59,23 @@ impl From<MarkdownNote> for String {
 
 impl MarkdownNote {
     pub fn new(body: String) -> Self {
-        MarkdownNote { body }
+        MarkdownNote {
+            body,
+            front_matter: false,
+        }
+    }
+
+    fn cmark_options(&self) -> Options {
+        let mut options = Options::empty();
+        options.insert(Options::ENABLE_TABLES);
+        options.insert(Options::ENABLE_FOOTNOTES);
+        options.insert(Options::ENABLE_STRIKETHROUGH);
+        options.insert(Options::ENABLE_TASKLISTS);
+        options.insert(Options::ENABLE_SMART_PUNCTUATION);
+        if self.front_matter {
+            options.insert(Options::ENABLE_YAML_STYLE_METADATA_BLOCKS);
+        }
+        options
     }
 
     fn extract_note_id_from_url(link: &str) -> Option<NoteID> {
@@ -53,6 +87,216 @@ impl MarkdownNote {
         }
     }
 
+    /// Split the body into the YAML front matter (without the delimiters) and the rest.
+    ///
+    /// Like pulldown-cmark, a front matter block must start on the first line with `---`,
+    /// not be followed by a blank line, and be closed by a line of `---` or `...`.
+    fn split_front_matter(body: &str) -> Option<(&str, &str)> {
+        let rest = body
+            .strip_prefix("---\n")
+            .or_else(|| body.strip_prefix("---\r\n"))?;
+        let mut offset = 0;
+        for line in rest.split_inclusive('\n') {
+            let trimmed = line.trim_end_matches(['\r', '\n']);
+            if offset == 0 && trimmed.trim().is_empty() {
+                return None;
+            }
+            if trimmed == "---" || trimmed == "..." {
+                return Some((&rest[..offset], &rest[offset + line.len()..]));
+            }
+            offset += line.len();
+        }
+        None
+    }
+
+    fn parse_front_matter(yaml: &str) -> Result<FrontMatter, MarkdownNoteError> {
+        let value: Value = if yaml.trim().is_empty() {
+            Value::Object(Default::default())
+        } else {
+            serde_yaml::from_str(yaml)
+                .map_err(|e| MarkdownNoteError::FrontMatterError(e.to_string()))?
+        };
+        let Value::Object(mapping) = value else {
+            return Err(MarkdownNoteError::FrontMatterError(
+                "front matter is not a mapping".to_owned(),
+            ));
+        };
+        let mut front_matter = FrontMatter::default();
+        for (key, value) in mapping {
+            match (key.as_str(), value) {
+                ("title", Value::String(title)) => front_matter.title = Some(title),
+                ("tags", Value::Array(tags)) => {
+                    front_matter.tags = Some(
+                        tags.into_iter()
+                            .map(|tag| match tag {
+                                Value::String(s) => s,
+                                other => other.to_string(),
+                            })
+                            .collect(),
+                    )
+                }
+                ("tags", Value::String(tags)) => {
+                    front_matter.tags = Some(
+                        tags.split(',')
+                            .map(|tag| tag.trim().to_owned())
+                            .filter(|tag| !tag.is_empty())
+                            .collect(),
+                    )
+                }
+                (_, value) => {
+                    front_matter.custom_metadata.insert(key, value);
+                }
+            }
+        }
+        Ok(front_matter)
+    }
+
+    fn render_front_matter(front_matter: &FrontMatter) -> Result<String, MarkdownNoteError> {
+        let to_yaml = |value| {
+            serde_yaml::to_value(value)
+                .map_err(|e| MarkdownNoteError::FrontMatterError(e.to_string()))
+        };
+        // The mapping preserves the insertion order, so the title and tags come first
+        let mut yaml = serde_yaml::Mapping::new();
+        if let Some(ref title) = front_matter.title {
+            yaml.insert("title".into(), title.as_str().into());
+        }
+        if let Some(ref tags) = front_matter.tags {
+            let mut tags: Vec<&String> = tags.iter().collect();
+            tags.sort();
+            yaml.insert("tags".into(), to_yaml(serde_json::json!(tags))?);
+        }
+        for (key, value) in &front_matter.custom_metadata {
+            if !yaml.contains_key(key.as_str()) {
+                yaml.insert(key.as_str().into(), to_yaml(value.clone())?);
+            }
+        }
+        if yaml.is_empty() {
+            return Ok("---\n---\n".to_owned());
+        }
+        let yaml = serde_yaml::to_string(&yaml)
+            .map_err(|e| MarkdownNoteError::FrontMatterError(e.to_string()))?;
+        Ok(format!("---\n{yaml}---\n"))
+    }
+
+    /// Collect `#tag` tokens in a piece of text.
+    ///
+    /// A tag must not be preceded by a word character (e.g. `C#` or `foo#bar` are not tags),
+    /// and cannot consist of digits only (e.g. `#1`).
+    fn extract_hashtags(text: &str, tags: &mut HashSet<String>) {
+        let is_tag_char = |c: char| c.is_alphanumeric() || c == '_' || c == '-' || c == '/';
+        let mut prev = None;
+        let mut chars = text.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            let starts_tag = c == '#' && !prev.is_some_and(|p: char| is_tag_char(p) || p == '#');
+            prev = Some(c);
+            if !starts_tag {
+                continue;
+            }
+            let mut end = i + 1;
+            while let Some(&(j, d)) = chars.peek() {
+                if !is_tag_char(d) {
+                    break;
+                }
+                end = j + d.len_utf8();
+                prev = Some(d);
+                chars.next();
+            }
+            let tag = text[i + 1..end].trim_end_matches(['-', '/']);
+            if !tag.is_empty() && !tag.chars().all(|c| c.is_ascii_digit()) {
+                tags.insert(tag.to_owned());
+            }
+        }
+    }
+
+    /// Generate an anchor for a heading, in the same way as GitHub (and github-slugger used by
+    /// the web UI).
+    ///
+    /// `seen` counts the slugs generated so far, so that duplicates get a `-1`, `-2`, ... suffix.
+    fn slugify(text: &str, seen: &mut HashMap<String, usize>) -> String {
+        let base: String = text
+            .to_lowercase()
+            .chars()
+            .filter_map(|c| match c {
+                ' ' => Some('-'),
+                c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+                _ => None,
+            })
+            .collect();
+        let mut slug = base.clone();
+        while let Some(count) = seen.get_mut(&slug) {
+            *count += 1;
+            slug = format!("{base}-{count}");
+        }
+        seen.insert(slug.clone(), 0);
+        slug
+    }
+
+    /// Take the first `@due(YYYY-MM-DD)` token out of the text of a task.
+    pub(super) fn extract_due(text: &str) -> (String, Option<NaiveDate>) {
+        let mut search_from = 0;
+        while let Some(start) = text[search_from..].find("@due(").map(|i| i + search_from) {
+            let date_start = start + "@due(".len();
+            if let Some(len) = text[date_start..].find(')') {
+                let date_end = date_start + len;
+                if let Ok(due) = NaiveDate::parse_from_str(&text[date_start..date_end], "%Y-%m-%d")
+                {
+                    let rest = format!("{} {}", &text[..start], &text[date_end + 1..]);
+                    return (rest, Some(due));
+                }
+            }
+            search_from = date_start;
+        }
+        (text.to_owned(), None)
+    }
+
+    /// Get the tasks together with the source range of their `[ ]` or `[x]` markers.
+    fn parse_tasks(&self) -> Vec<(Task, Range<usize>)> {
+        let options = self.cmark_options();
+        let mut tasks = vec![];
+        // The text of the task being read, which ends at the end of its list item or at the
+        // start of a nested list
+        let mut current: Option<(String, bool, Range<usize>)> = None;
+        let finish = |current: &mut Option<(String, bool, Range<usize>)>,
+                      tasks: &mut Vec<(Task, Range<usize>)>| {
+            if let Some((text, done, marker)) = current.take() {
+                let (text, due) = MarkdownNote::extract_due(&text);
+                let text = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+                let task = Task {
+                    index: tasks.len(),
+                    text,
+                    done,
+                    due,
+                };
+                tasks.push((task, marker));
+            }
+        };
+        for (event, range) in Parser::new_ext(&self.body, options).into_offset_iter() {
+            match event {
+                Event::TaskListMarker(done) => {
+                    finish(&mut current, &mut tasks);
+                    current = Some((String::new(), done, range));
+                }
+                Event::Text(t) | Event::Code(t) => {
+                    if let Some((ref mut text, _, _)) = current {
+                        text.push_str(&t);
+                    }
+                }
+                Event::SoftBreak | Event::HardBreak => {
+                    if let Some((ref mut text, _, _)) = current {
+                        text.push(' ');
+                    }
+                }
+                Event::Start(PTag::List(_)) | Event::End(PTagEnd::Item) => {
+                    finish(&mut current, &mut tasks);
+                }
+                _ => {}
+            }
+        }
+        finish(&mut current, &mut tasks);
+        tasks
+    }
+
     fn change_note_url(link: &str, old: &NoteID, new: &NoteID) -> Option<String> {
         let url = NotegrafURL::parse(link);
         if let Ok(NotegrafURL::Note(ref id)) = url {
@@ -65,96 +309,77 @@ impl MarkdownNote {
             None
         }
     }
-}
-
-fn cmark_options() -> Options {
-    let mut options = Options::empty();
-    options.insert(Options::ENABLE_TABLES);
-    options.insert(Options::ENABLE_FOOTNOTES);
-    options.insert(Options::ENABLE_STRIKETHROUGH);
-    options.insert(Options::ENABLE_TASKLISTS);
-    options.insert(Options::ENABLE_SMART_PUNCTUATION);
-    options
-}
-
-impl NoteType for MarkdownNote {
-    type Error = MarkdownNoteError;
-
-    fn get_referents(&self) -> Result<HashSet<NoteID>, Self::Error> {
-        let options = cmark_options();
-        let mut referents = HashSet::new();
-        let parser = Parser::new_ext(&self.body, options);
-        for event in parser {
-            if let Event::Start(PTag::Link { dest_url, .. }) = event {
-                if let Some(id) = MarkdownNote::extract_note_id_from_url(&dest_url) {
-                    referents.insert(id);
-                }
-            }
-        }
-        Ok(referents)
-    }
 
-    fn update_referent(
-        &mut self,
-        old_referent: NoteID,
-        new_referent: NoteID,
-    ) -> Result<(), Self::Error> {
-        let options = cmark_options();
+    /// Point the links whose destination `change_url` gives a new URL for at that URL, or if
+    /// `unlink` is set, replace them with their text.
+    fn rewrite_links<F>(&mut self, change_url: F, unlink: bool) -> Result<(), MarkdownNoteError>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let options = self.cmark_options();
         let mut buf = String::new();
         let mut change_autolink_text = false;
         let mut old_autolink = None;
         let mut new_autolink = None;
-        let parser = Parser::new_ext(&self.body, options).map(|event| match event {
+        // Links cannot be nested, so the next end of a link closes the one being removed
+        let mut unlinking = false;
+        let parser = Parser::new_ext(&self.body, options).filter_map(|event| match event {
             Event::Start(PTag::Link {
                 link_type,
                 dest_url,
                 title,
                 id,
             }) => {
-                let new_destination =
-                    MarkdownNote::change_note_url(&dest_url, &old_referent, &new_referent);
-                if let Some(l) = new_destination {
+                if let Some(l) = change_url(&dest_url) {
+                    if unlink {
+                        unlinking = true;
+                        return None;
+                    }
                     if link_type == LinkType::Autolink {
                         change_autolink_text = true;
                         old_autolink = Some(dest_url.clone().into_string());
                         new_autolink = Some(l.clone());
                     }
-                    Event::Start(PTag::Link {
+                    Some(Event::Start(PTag::Link {
                         link_type,
                         dest_url: l.into(),
                         title,
                         id,
-                    })
+                    }))
                 } else {
-                    Event::Start(PTag::Link {
+                    Some(Event::Start(PTag::Link {
                         link_type,
                         dest_url,
                         title,
                         id,
-                    })
+                    }))
                 }
             }
             Event::Text(text) => {
                 if change_autolink_text {
-                    Event::Text(
+                    Some(Event::Text(
                         text.replace(
                             old_autolink.as_ref().unwrap(),
                             new_autolink.as_ref().unwrap(),
                         )
                         .into(),
-                    )
+                    ))
                 } else {
-                    Event::Text(text)
+                    Some(Event::Text(text))
                 }
             }
             Event::End(PTagEnd::Link) => {
                 change_autolink_text = false;
                 old_autolink = None;
                 new_autolink = None;
-                Event::End(PTagEnd::Link)
+                if unlinking {
+                    unlinking = false;
+                    return None;
+                }
+                Some(Event::End(PTagEnd::Link))
             }
 
-            _ => event,
+            _ => Some(event),
         });
         let pulldown_cmark_to_cmark_options = pulldown_cmark_to_cmark::Options {
             increment_ordered_list_bullets: true,
@@ -171,6 +396,258 @@ impl NoteType for MarkdownNote {
     }
 }
 
+/// Escape text so that Markdown renders it as is.
+pub(super) fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for (i, line) in text.split('\n').enumerate() {
+        if i > 0 {
+            escaped.push('\n');
+        }
+        // Characters that only have a meaning at the start of a line, such as list markers
+        let content = line.trim_start();
+        let indentation = &line[..line.len() - content.len()];
+        // Indentation would make a code block or continue a list, unless it doesn't start the
+        // line. Blank lines are left alone, as they separate paragraphs either way.
+        match indentation.chars().next() {
+            Some(c) if !content.is_empty() => {
+                escaped.push_str(&format!("&#{};", c as u32));
+                escaped.push_str(&indentation[c.len_utf8()..].replace('\r', "&#13;"));
+            }
+            _ => escaped.push_str(indentation),
+        }
+        let digits = content.len()
+            - content
+                .trim_start_matches(|c: char| c.is_ascii_digit())
+                .len();
+        let mut chars = content.char_indices().peekable();
+        if digits > 0 {
+            escaped.push_str(&content[..digits]);
+            while chars.next_if(|(j, _)| *j < digits).is_some() {}
+            if let Some((_, c @ ('.' | ')'))) = chars.peek() {
+                escaped.push('\\');
+                escaped.push(*c);
+                chars.next();
+            }
+        } else if let Some((_, c @ ('-' | '+' | '='))) = chars.peek() {
+            escaped.push('\\');
+            escaped.push(*c);
+            chars.next();
+        }
+        for (j, c) in chars {
+            let after = &content[j + c.len_utf8()..];
+            match c {
+                // A carriage return ends a line like a line feed
+                '\r' => {
+                    escaped.push_str("&#13;");
+                    continue;
+                }
+                // Smart punctuation turns these into dashes, ellipses and curly quotes
+                '-' if after.starts_with('-') => escaped.push('\\'),
+                '.' if after.starts_with("..") => escaped.push('\\'),
+                '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '#' | '~' | '|' | '&' | '!'
+                | '"' | '\'' => escaped.push('\\'),
+                _ => {}
+            }
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+impl NoteType for MarkdownNote {
+    type Error = MarkdownNoteError;
+
+    fn get_format(&self) -> NoteFormat {
+        NoteFormat::Markdown
+    }
+
+    fn parse(_format: Option<NoteFormat>, note: String) -> Result<Self, Self::Error> {
+        Ok(MarkdownNote::new(note))
+    }
+
+    fn get_referents(&self) -> Result<HashSet<NoteID>, Self::Error> {
+        let options = self.cmark_options();
+        let mut referents = HashSet::new();
+        let parser = Parser::new_ext(&self.body, options);
+        for event in parser {
+            if let Event::Start(PTag::Link { dest_url, .. }) = event {
+                if let Some(id) = MarkdownNote::extract_note_id_from_url(&dest_url) {
+                    referents.insert(id);
+                }
+            }
+        }
+        Ok(referents)
+    }
+
+    fn update_referent(
+        &mut self,
+        old_referent: NoteID,
+        new_referent: NoteID,
+    ) -> Result<(), Self::Error> {
+        self.rewrite_links(
+            |url| MarkdownNote::change_note_url(url, &old_referent, &new_referent),
+            false,
+        )
+    }
+
+    /// In plain text, a link becomes its text, which for autolinks is the URL.
+    fn remove_referent(
+        &mut self,
+        referent: NoteID,
+        rewrite: LinkRewrite,
+    ) -> Result<(), Self::Error> {
+        let tombstone = format!("{}", NotegrafURL::Deleted(referent.clone()));
+        self.rewrite_links(
+            |url| {
+                (MarkdownNote::extract_note_id_from_url(url).as_ref() == Some(&referent))
+                    .then(|| tombstone.clone())
+            },
+            rewrite == LinkRewrite::PlainText,
+        )
+    }
+
+    fn get_inline_tags(&self) -> Result<HashSet<String>, Self::Error> {
+        let options = self.cmark_options();
+        let mut tags = HashSet::new();
+        // Text can be split into several events, so we join adjacent ones
+        let mut text = String::new();
+        // Nesting depth of code blocks, links, images and front matter
+        let mut skipped = 0;
+        for (event, range) in Parser::new_ext(&self.body, options).into_offset_iter() {
+            match event {
+                // Text that differs from the source, such as `\#` or `&#35;`, is not a tag
+                Event::Text(t)
+                    if skipped == 0
+                        && self.body[range.clone()] == *t
+                        && !self.body[..range.start].ends_with('\\') =>
+                {
+                    text.push_str(&t);
+                    continue;
+                }
+                Event::Start(
+                    PTag::CodeBlock(_)
+                    | PTag::Link { .. }
+                    | PTag::Image { .. }
+                    | PTag::MetadataBlock(_),
+                ) => skipped += 1,
+                Event::End(
+                    PTagEnd::CodeBlock | PTagEnd::Link | PTagEnd::Image | PTagEnd::MetadataBlock(_),
+                ) => skipped -= 1,
+                _ => {}
+            }
+            MarkdownNote::extract_hashtags(&text, &mut tags);
+            text.clear();
+        }
+        MarkdownNote::extract_hashtags(&text, &mut tags);
+        Ok(tags)
+    }
+
+    fn get_plain_text(&self) -> Result<String, Self::Error> {
+        let options = self.cmark_options();
+        let mut text = String::new();
+        // Nesting depth of front matter, whose keys and values are already in the metadata
+        let mut skipped = 0;
+        for event in Parser::new_ext(&self.body, options) {
+            match event {
+                Event::Start(PTag::MetadataBlock(_)) => skipped += 1,
+                Event::End(PTagEnd::MetadataBlock(_)) => skipped -= 1,
+                Event::Text(t) | Event::Code(t) if skipped == 0 => text.push_str(&t),
+                Event::SoftBreak | Event::HardBreak => text.push(' '),
+                // Separate the text of block-level elements
+                Event::End(
+                    PTagEnd::Paragraph
+                    | PTagEnd::Heading(_)
+                    | PTagEnd::Item
+                    | PTagEnd::CodeBlock
+                    | PTagEnd::TableCell
+                    | PTagEnd::FootnoteDefinition,
+                ) if !text.ends_with('\n') => text.push('\n'),
+                _ => {}
+            }
+        }
+        Ok(text.trim_end().to_owned())
+    }
+
+    fn get_outline(&self) -> Result<Vec<Heading>, Self::Error> {
+        let options = self.cmark_options();
+        let mut outline = vec![];
+        let mut seen = HashMap::new();
+        // The level, offset and text of the heading being read
+        let mut current: Option<(u8, usize, String)> = None;
+        for (event, range) in Parser::new_ext(&self.body, options).into_offset_iter() {
+            match event {
+                Event::Start(PTag::Heading { level, .. }) => {
+                    current = Some((level as u8, range.start, String::new()));
+                }
+                Event::Text(t) | Event::Code(t) => {
+                    if let Some((_, _, ref mut text)) = current {
+                        text.push_str(&t);
+                    }
+                }
+                Event::SoftBreak | Event::HardBreak => {
+                    if let Some((_, _, ref mut text)) = current {
+                        text.push(' ');
+                    }
+                }
+                Event::End(PTagEnd::Heading(_)) => {
+                    if let Some((level, offset, text)) = current.take() {
+                        let text = text.trim().to_owned();
+                        outline.push(Heading {
+                            level,
+                            slug: MarkdownNote::slugify(&text, &mut seen),
+                            text,
+                            offset,
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(outline)
+    }
+
+    fn get_tasks(&self) -> Result<Vec<Task>, Self::Error> {
+        Ok(self
+            .parse_tasks()
+            .into_iter()
+            .map(|(task, _)| task)
+            .collect())
+    }
+
+    fn set_task_done(&mut self, index: usize, done: bool) -> Result<(), Self::Error> {
+        let (task, marker) = self
+            .parse_tasks()
+            .into_iter()
+            .nth(index)
+            .ok_or(MarkdownNoteError::TaskNotExist(index))?;
+        if task.done != done {
+            // Only the marker is replaced, so that the rest of the body is kept as is
+            self.body
+                .replace_range(marker, if done { "[x]" } else { "[ ]" });
+        }
+        Ok(())
+    }
+
+    fn get_front_matter(&self) -> Result<Option<FrontMatter>, Self::Error> {
+        MarkdownNote::split_front_matter(&self.body)
+            .map(|(yaml, _)| MarkdownNote::parse_front_matter(yaml))
+            .transpose()
+    }
+
+    fn set_front_matter(&mut self, front_matter: &FrontMatter) -> Result<(), Self::Error> {
+        let rest = match MarkdownNote::split_front_matter(&self.body) {
+            Some((_, rest)) => rest,
+            None => &self.body,
+        };
+        self.body = MarkdownNote::render_front_matter(front_matter)? + rest;
+        Ok(())
+    }
+
+    fn enable_front_matter(&mut self) {
+        self.front_matter = true;
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -240,6 +717,31 @@ mod tests {
         assert_eq!(note.body, r#"<notegraf:/note/new>"#)
     }
 
+    #[test]
+    fn remove_referent() {
+        let id = NoteID::new("old".into());
+        let body = "See [*foo*](notegraf:/note/old), <notegraf:/note/old> and \
+                    [bar](notegraf:/note/other).";
+        let mut note = MarkdownNote::new(body.into());
+        note.remove_referent(id.clone(), LinkRewrite::PlainText)
+            .unwrap();
+        assert_eq!(
+            note.body,
+            "See *foo*, notegraf:/note/old and [bar](notegraf:/note/other)."
+        );
+        let mut note = MarkdownNote::new(body.into());
+        note.remove_referent(id, LinkRewrite::Tombstone).unwrap();
+        assert_eq!(
+            note.body,
+            "See [*foo*](notegraf:/deleted/old), <notegraf:/deleted/old> and \
+             [bar](notegraf:/note/other)."
+        );
+        assert_eq!(
+            note.get_referents().unwrap(),
+            HashSet::from([NoteID::new("other".into())])
+        );
+    }
+
     #[test]
     fn increment_ordered_list_bullet() {
         let id_old = NoteID::new("old".into());
@@ -267,12 +769,165 @@ mod tests {
         assert_eq!(note.body, "1. <notegraf:/note/new>\n2. foobar".to_string())
     }
 
+    #[test]
+    fn front_matter() {
+        let note =
+            MarkdownNote::new("---\ntitle: Foo\ntags: [a, b]\nstatus: draft\n---\n# Body".into());
+        let front_matter = note.get_front_matter().unwrap().unwrap();
+        assert_eq!(front_matter.title.unwrap(), "Foo");
+        assert_eq!(
+            front_matter.tags.unwrap(),
+            HashSet::from(["a".to_owned(), "b".to_owned()])
+        );
+        assert_eq!(front_matter.custom_metadata["status"], "draft");
+    }
+
+    #[test]
+    fn front_matter_comma_separated_tags() {
+        let note = MarkdownNote::new("---\ntags: a, b\n---\n".into());
+        let front_matter = note.get_front_matter().unwrap().unwrap();
+        assert_eq!(
+            front_matter.tags.unwrap(),
+            HashSet::from(["a".to_owned(), "b".to_owned()])
+        );
+    }
+
+    #[test]
+    fn no_front_matter() {
+        let note = MarkdownNote::new("# Title\n---\nfoo\n---\n".into());
+        assert!(note.get_front_matter().unwrap().is_none());
+        let note = MarkdownNote::new("---\n\nfoo\n---\n".into());
+        assert!(note.get_front_matter().unwrap().is_none());
+        let note = MarkdownNote::new("---\nfoo: bar\n".into());
+        assert!(note.get_front_matter().unwrap().is_none());
+    }
+
+    #[test]
+    fn front_matter_not_a_mapping() {
+        let note = MarkdownNote::new("---\n- foo\n---\n".into());
+        assert!(matches!(
+            note.get_front_matter(),
+            Err(MarkdownNoteError::FrontMatterError(_))
+        ));
+    }
+
+    #[test]
+    fn set_front_matter() {
+        let mut note = MarkdownNote::new("---\ntitle: Foo\n---\n# Body".into());
+        let mut front_matter = FrontMatter {
+            title: Some("Bar".to_owned()),
+            tags: Some(HashSet::from(["b".to_owned(), "a".to_owned()])),
+            ..Default::default()
+        };
+        front_matter
+            .custom_metadata
+            .insert("status".to_owned(), "done".into());
+        note.set_front_matter(&front_matter).unwrap();
+        assert_eq!(
+            note.body,
+            "---\ntitle: Bar\ntags:\n- a\n- b\nstatus: done\n---\n# Body"
+        );
+        assert_eq!(note.get_front_matter().unwrap().unwrap(), front_matter);
+    }
+
+    #[test]
+    fn add_front_matter() {
+        let mut note = MarkdownNote::new("# Body".into());
+        let front_matter = FrontMatter {
+            title: Some("Foo".to_owned()),
+            ..Default::default()
+        };
+        note.set_front_matter(&front_matter).unwrap();
+        assert_eq!(note.body, "---\ntitle: Foo\n---\n# Body");
+    }
+
+    #[test]
+    fn front_matter_is_not_a_link() {
+        let mut note = MarkdownNote::new(
+            "---\nsource: <notegraf:/note/note-1>\n---\n[foo](notegraf:/note/note-2)".into(),
+        );
+        assert_eq!(note.get_referents().unwrap().len(), 2);
+        note.enable_front_matter();
+        let referents = note.get_referents().unwrap();
+        assert_eq!(referents.len(), 1);
+        assert!(referents.contains(&NoteID::new("note-2".to_owned())));
+    }
+
+    #[test]
+    fn rewrite_markdown_link_with_front_matter() {
+        let id_old = NoteID::new("old".into());
+        let id_new = NoteID::new("new".into());
+        let mut note = MarkdownNote::new("---\ntitle: Foo\n---\n[foo](notegraf:/note/old)".into());
+        note.enable_front_matter();
+        note.update_referent(id_old, id_new).unwrap();
+        assert_eq!(
+            note.body,
+            "---\ntitle: Foo\n---\n\n[foo](notegraf:/note/new)"
+        );
+    }
+
+    #[test]
+    fn front_matter_disabled() {
+        let note = MarkdownNote::new("---\ntitle: Foo #foo\n---\nBar".into());
+        assert_eq!(
+            note.get_inline_tags().unwrap(),
+            HashSet::from(["foo".to_owned()])
+        );
+        assert_eq!(note.get_outline().unwrap()[0].text, "title: Foo #foo");
+        assert_eq!(note.get_plain_text().unwrap(), "title: Foo #foo\nBar");
+    }
+
+    #[test]
+    fn inline_tags() {
+        let note = MarkdownNote::new(
+            "# Heading #h1\nSome #tag1 and #tag-2, (#nested/tag) #tag1.\n\n- [ ] #todo".into(),
+        );
+        assert_eq!(
+            note.get_inline_tags().unwrap(),
+            HashSet::from([
+                "h1".to_owned(),
+                "tag1".to_owned(),
+                "tag-2".to_owned(),
+                "nested/tag".to_owned(),
+                "todo".to_owned()
+            ])
+        );
+    }
+
+    #[test]
+    fn inline_tags_not_tags() {
+        let note = MarkdownNote::new(
+            "C# and foo#bar, issue #123, ## and #\n\n# Heading\n\n&#35;escaped \\#escaped".into(),
+        );
+        assert_eq!(note.get_inline_tags().unwrap(), HashSet::new());
+    }
+
+    #[test]
+    fn inline_tags_skip_code_and_links() {
+        let note = MarkdownNote::new(
+            "`#inline` [#link](http://example.com/#anchor) <http://example.com/#auto>\n\n\
+             ```\n#block\n```\n\n    #indented\n\n![#image](foo.png) #real"
+                .into(),
+        );
+        assert_eq!(
+            note.get_inline_tags().unwrap(),
+            HashSet::from(["real".to_owned()])
+        );
+    }
+
+    #[test]
+    fn inline_tags_skip_front_matter() {
+        let mut note = MarkdownNote::new("---\ntitle: \"#foo\"\n---\n#bar".into());
+        note.enable_front_matter();
+        assert_eq!(
+            note.get_inline_tags().unwrap(),
+            HashSet::from(["bar".to_owned()])
+        );
+    }
+
     #[test]
     fn serialize() {
-        let ser = serde_json::to_string(&MarkdownNote {
-            body: "Hello, world!".to_owned(),
-        })
-        .unwrap();
+        let ser = serde_json::to_string(&MarkdownNote::new("Hello, world!".to_owned())).unwrap();
         assert_eq!(ser, "\"Hello, world!\"");
     }
 
@@ -281,4 +936,163 @@ mod tests {
         let note: MarkdownNote = serde_json::from_str("\"Hello, world!\"").unwrap();
         assert_eq!(note.body, "Hello, world!");
     }
+
+    #[test]
+    fn tasks() {
+        let note = MarkdownNote::new(
+            "- [ ] buy *milk* @due(2026-10-20)\n- [x] call `bob`\n- not a task\n\n1. [ ] ordered\n"
+                .into(),
+        );
+        let tasks = note.get_tasks().unwrap();
+        assert_eq!(
+            tasks,
+            vec![
+                Task {
+                    index: 0,
+                    text: "buy milk".to_owned(),
+                    done: false,
+                    due: NaiveDate::from_ymd_opt(2026, 10, 20),
+                },
+                Task {
+                    index: 1,
+                    text: "call bob".to_owned(),
+                    done: true,
+                    due: None,
+                },
+                Task {
+                    index: 2,
+                    text: "ordered".to_owned(),
+                    done: false,
+                    due: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn tasks_nested() {
+        let note = MarkdownNote::new("- [ ] parent\n  - [x] child\n- [ ] sibling\n".into());
+        let tasks: Vec<(String, bool)> = note
+            .get_tasks()
+            .unwrap()
+            .into_iter()
+            .map(|t| (t.text, t.done))
+            .collect();
+        assert_eq!(
+            tasks,
+            vec![
+                ("parent".to_owned(), false),
+                ("child".to_owned(), true),
+                ("sibling".to_owned(), false)
+            ]
+        );
+    }
+
+    #[test]
+    fn tasks_invalid_due() {
+        let note = MarkdownNote::new("- [ ] foo @due(tomorrow) @due(2026-02-30)\n".into());
+        let tasks = note.get_tasks().unwrap();
+        assert_eq!(tasks[0].text, "foo @due(tomorrow) @due(2026-02-30)");
+        assert_eq!(tasks[0].due, None);
+    }
+
+    #[test]
+    fn tasks_not_in_code() {
+        let note = MarkdownNote::new("```\n- [ ] foo\n```\n".into());
+        assert!(note.get_tasks().unwrap().is_empty());
+    }
+
+    #[test]
+    fn set_task_done() {
+        let mut note = MarkdownNote::new("Intro\n\n* [ ] foo\n* [X] bar\n".into());
+        note.set_task_done(0, true).unwrap();
+        assert_eq!(note.body, "Intro\n\n* [x] foo\n* [X] bar\n");
+        note.set_task_done(1, false).unwrap();
+        assert_eq!(note.body, "Intro\n\n* [x] foo\n* [ ] bar\n");
+        note.set_task_done(1, false).unwrap();
+        assert_eq!(note.body, "Intro\n\n* [x] foo\n* [ ] bar\n");
+        assert!(matches!(
+            note.set_task_done(2, true),
+            Err(MarkdownNoteError::TaskNotExist(2))
+        ));
+    }
+
+    #[test]
+    fn outline() {
+        let mut note = MarkdownNote::new(
+            "---\ntitle: foo\n---\n# Hello, *World*!\ntext\n\nSetext `code`\n---\n### Hello world\n```\n# not a heading\n```\n"
+                .into(),
+        );
+        note.enable_front_matter();
+        let outline = note.get_outline().unwrap();
+        assert_eq!(
+            outline,
+            vec![
+                Heading {
+                    level: 1,
+                    text: "Hello, World!".to_owned(),
+                    slug: "hello-world".to_owned(),
+                    offset: 19,
+                },
+                Heading {
+                    level: 2,
+                    text: "Setext code".to_owned(),
+                    slug: "setext-code".to_owned(),
+                    offset: 43,
+                },
+                Heading {
+                    level: 3,
+                    text: "Hello world".to_owned(),
+                    slug: "hello-world-1".to_owned(),
+                    offset: 61,
+                },
+            ]
+        );
+        assert!(note.body[outline[0].offset..].starts_with("# Hello"));
+        assert!(note.body[outline[1].offset..].starts_with("Setext"));
+        assert!(note.body[outline[2].offset..].starts_with("### Hello"));
+    }
+
+    #[test]
+    fn slugify() {
+        let mut seen = HashMap::new();
+        assert_eq!(MarkdownNote::slugify("Foo Bar", &mut seen), "foo-bar");
+        assert_eq!(MarkdownNote::slugify("foo bar", &mut seen), "foo-bar-1");
+        assert_eq!(MarkdownNote::slugify("foo-bar", &mut seen), "foo-bar-2");
+        assert_eq!(MarkdownNote::slugify("foo-bar-1", &mut seen), "foo-bar-1-1");
+        assert_eq!(
+            MarkdownNote::slugify("Ünïcödé & co_", &mut seen),
+            "ünïcödé--co_"
+        );
+        assert_eq!(MarkdownNote::slugify("", &mut seen), "");
+    }
+
+    #[test]
+    fn plain_text() {
+        let mut note = MarkdownNote::new(
+            "---\nstatus: draft\n---\n# Title\nSome *emphasis* and [a link](https://example.com) <b>bold</b>\nnext line\n\n- [ ] task `code`\n\n```python\nprint(1)\n```\n\n| a | b |\n|---|---|\n| c | d |\n"
+                .into(),
+        );
+        note.enable_front_matter();
+        assert_eq!(
+            note.get_plain_text().unwrap(),
+            "Title\nSome emphasis and a link bold next line\ntask code\nprint(1)\na\nb\nc\nd"
+        );
+    }
+
+    #[test]
+    fn escape_markdown() {
+        assert_eq!(
+            super::escape_markdown("- a_b\n  10) <c> #d\n=="),
+            "\\- a\\_b\n&#32; 10\\) \\<c\\> \\#d\n\\=="
+        );
+        assert_eq!(
+            super::escape_markdown("    code\n\t- item\n  \nend"),
+            "&#32;   code\n&#9;\\- item\n  \nend"
+        );
+        assert_eq!(
+            super::escape_markdown("\"a\" -- b... c - d.\re"),
+            "\\\"a\\\" \\-- b\\... c - d.&#13;e"
+        );
+    }
 }
@@ -1,33 +1,211 @@
 use crate::NoteID;
+use chrono::NaiveDate;
 use serde::de::DeserializeOwned;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 use std::error::Error;
+use std::fmt;
 use std::fmt::Debug;
+use std::str::FromStr;
+use thiserror::Error;
 
 mod plain;
-pub use plain::PlainNote;
+pub use plain::{PlainNote, PlainNoteError};
 mod markdown;
-pub use markdown::MarkdownNote;
+pub use markdown::{MarkdownNote, MarkdownNoteError};
+#[cfg(feature = "notetype_org")]
+mod org;
+#[cfg(feature = "notetype_org")]
+pub use org::OrgNote;
+#[cfg(feature = "notetype_asciidoc")]
+mod asciidoc;
+#[cfg(feature = "notetype_asciidoc")]
+pub use asciidoc::AsciiDocNote;
+#[cfg(feature = "notetype_block")]
+mod block;
+#[cfg(feature = "notetype_block")]
+pub use block::{Block, BlockNote, BlockNoteError, CalloutKind, ChecklistItem};
+mod convert;
+pub use convert::ConvertInto;
+mod dynamic;
+pub use dynamic::{DynamicNote, DynamicNoteError};
+
+/// The format of a note, such as Markdown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NoteFormat {
+    Plain,
+    Markdown,
+    #[cfg(feature = "notetype_org")]
+    Org,
+    #[cfg(feature = "notetype_asciidoc")]
+    AsciiDoc,
+    #[cfg(feature = "notetype_block")]
+    Block,
+}
+
+impl NoteFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NoteFormat::Plain => "plain",
+            NoteFormat::Markdown => "markdown",
+            #[cfg(feature = "notetype_org")]
+            NoteFormat::Org => "org",
+            #[cfg(feature = "notetype_asciidoc")]
+            NoteFormat::AsciiDoc => "asciidoc",
+            #[cfg(feature = "notetype_block")]
+            NoteFormat::Block => "block",
+        }
+    }
+}
+
+impl fmt::Display for NoteFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("unknown note format `{0}`")]
+pub struct UnknownNoteFormat(pub String);
+
+impl FromStr for NoteFormat {
+    type Err = UnknownNoteFormat;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(NoteFormat::Plain),
+            "markdown" => Ok(NoteFormat::Markdown),
+            #[cfg(feature = "notetype_org")]
+            "org" => Ok(NoteFormat::Org),
+            #[cfg(feature = "notetype_asciidoc")]
+            "asciidoc" => Ok(NoteFormat::AsciiDoc),
+            #[cfg(feature = "notetype_block")]
+            "block" => Ok(NoteFormat::Block),
+            _ => Err(UnknownNoteFormat(s.to_owned())),
+        }
+    }
+}
+
+/// Metadata embedded in the body of a note, such as the YAML front matter of a Markdown note.
+///
+/// Keys other than the title and the tags are kept in `custom_metadata`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FrontMatter {
+    pub title: Option<String>,
+    pub tags: Option<HashSet<String>>,
+    pub custom_metadata: serde_json::Map<String, serde_json::Value>,
+}
+
+/// A task (to-do item) written in the body of a note, such as `- [ ] task` in Markdown.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Task {
+    /// The position of the task among all tasks of the note, starting from zero.
+    pub index: usize,
+    /// The text of the task, without the due date.
+    pub text: String,
+    pub done: bool,
+    pub due: Option<NaiveDate>,
+}
+
+/// A heading in the outline of a note.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Heading {
+    /// From 1 to 6.
+    pub level: u8,
+    pub text: String,
+    /// An anchor for the heading, which is unique within the note.
+    pub slug: String,
+    /// The byte offset of the heading in the body.
+    pub offset: usize,
+}
+
+/// How to rewrite the links to a note that is deleted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkRewrite {
+    /// Replace each link with its text.
+    PlainText,
+    /// Point each link at [`NotegrafURL::Deleted`](crate::url::NotegrafURL::Deleted).
+    Tombstone,
+}
 
 pub trait NoteType:
-    Serialize
-    + DeserializeOwned
-    + Clone
-    + Debug
-    + PartialEq
-    + Eq
-    + Send
-    + Sync
-    + From<String>
-    + Into<String>
-    + 'static
+    Serialize + DeserializeOwned + Clone + Debug + PartialEq + Eq + Send + Sync + Into<String> + 'static
 {
     type Error: Debug + Error;
+    /// Get the format of the note, which is recorded by stores alongside the note.
+    fn get_format(&self) -> NoteFormat;
+    /// Parse a note from its format and the string it was converted into.
+    ///
+    /// The string might come from storage or from the API, so parsing must not panic.
+    /// The format is `None` for notes stored before formats were recorded, or if it was not
+    /// given. Note types of a single format can ignore it.
+    fn parse(format: Option<NoteFormat>, note: String) -> Result<Self, Self::Error>;
+    /// Convert the note into another format with [`ConvertInto`], keeping its links.
+    ///
+    /// Returns `None` if this note type cannot hold a note converted into that format.
+    /// By default, a note can only be "converted" into its own format.
+    fn convert_to(self, format: NoteFormat) -> Result<Option<Self>, Self::Error> {
+        Ok((self.get_format() == format).then_some(self))
+    }
     fn get_referents(&self) -> Result<HashSet<NoteID>, Self::Error>;
     fn update_referent(
         &mut self,
         old_referent: NoteID,
         new_referent: NoteID,
     ) -> Result<(), Self::Error>;
+    /// Rewrite the links to `referent`, so that it is no longer a referent of the note.
+    fn remove_referent(
+        &mut self,
+        referent: NoteID,
+        rewrite: LinkRewrite,
+    ) -> Result<(), Self::Error>;
+    /// Get the tags written inline in the body, such as `#tag` in Markdown.
+    ///
+    /// These are separate from the tags set in the metadata.
+    fn get_inline_tags(&self) -> Result<HashSet<String>, Self::Error> {
+        Ok(HashSet::new())
+    }
+    /// Get the text of the body without any markup, for indexing and previews.
+    ///
+    /// By default, this is the serialized note.
+    fn get_plain_text(&self) -> Result<String, Self::Error> {
+        Ok(self.clone().into())
+    }
+    /// Get the body as users read and write it, for comparing revisions.
+    ///
+    /// By default, this is the serialized note, which suits note types stored as their source.
+    fn get_body(&self) -> Result<String, Self::Error> {
+        Ok(self.clone().into())
+    }
+    /// Get the headings of the body, in the order they appear.
+    fn get_outline(&self) -> Result<Vec<Heading>, Self::Error> {
+        Ok(vec![])
+    }
+    /// Get the tasks written in the body, in the order they appear.
+    fn get_tasks(&self) -> Result<Vec<Task>, Self::Error> {
+        Ok(vec![])
+    }
+    /// Mark the task at `index` (see [`Task::index`]) as done or not done.
+    ///
+    /// Note types without tasks ignore this.
+    fn set_task_done(&mut self, _index: usize, _done: bool) -> Result<(), Self::Error> {
+        Ok(())
+    }
+    /// Get the metadata embedded in the body, if the note has any.
+    fn get_front_matter(&self) -> Result<Option<FrontMatter>, Self::Error> {
+        Ok(None)
+    }
+    /// Replace the metadata embedded in the body.
+    ///
+    /// Note types without embedded metadata ignore this.
+    fn set_front_matter(&mut self, _front_matter: &FrontMatter) -> Result<(), Self::Error> {
+        Ok(())
+    }
+    /// Treat the metadata embedded in the body as such, instead of as part of the text.
+    ///
+    /// Stores call this on every note when they synchronize the embedded metadata.
+    /// Note types without embedded metadata ignore this.
+    fn enable_front_matter(&mut self) {}
 }
@@ -0,0 +1,369 @@
+use crate::notetype::{LinkRewrite, NoteFormat};
+use crate::url::NotegrafURL;
+use crate::{NoteID, NoteType};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::ops::Range;
+
+/// Blocks whose contents are not parsed for links.
+const VERBATIM_BLOCKS: [&str; 4] = ["src", "example", "export", "comment"];
+
+/// A note written in Org-mode.
+///
+/// Notes are linked with bracket links (`[[notegraf:/note/id][description]]` or
+/// `[[notegraf:/note/id]]`) or angle links (`<notegraf:/note/id>`).
+/// Links in source, example, export and comment blocks, comment lines and fixed-width lines are
+/// ignored.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "String", from = "String")]
+pub struct OrgNote {
+    body: String,
+}
+
+impl From<String> for OrgNote {
+    fn from(note: String) -> OrgNote {
+        OrgNote::new(note)
+    }
+}
+
+impl From<&str> for OrgNote {
+    fn from(note: &str) -> OrgNote {
+        OrgNote::new(note.to_owned())
+    }
+}
+
+impl From<OrgNote> for String {
+    fn from(note: OrgNote) -> String {
+        note.body
+    }
+}
+
+impl OrgNote {
+    pub fn new(body: String) -> Self {
+        OrgNote { body }
+    }
+
+    fn extract_note_id_from_url(link: &str) -> Option<NoteID> {
+        if let Ok(NotegrafURL::Note(id)) = NotegrafURL::parse(link) {
+            Some(id)
+        } else {
+            None
+        }
+    }
+
+    /// Find the targets of links in a line, whose first byte is at `offset` in the body.
+    fn find_links(line: &str, offset: usize, targets: &mut Vec<Range<usize>>) {
+        let mut i = 0;
+        while i < line.len() {
+            let rest = &line[i..];
+            if let Some(link) = rest.strip_prefix("[[") {
+                // The target ends at `][` if there is a description, or `]]` otherwise
+                if let Some(end) = link.find(']') {
+                    let target = &link[..end];
+                    let closed = link[end..].starts_with("][") || link[end..].starts_with("]]");
+                    if closed && !target.is_empty() && !target.contains('[') {
+                        let start = offset + i + 2;
+                        targets.push(start..start + end);
+                        i += 2 + end;
+                        continue;
+                    }
+                }
+            } else if let Some(link) = rest.strip_prefix('<') {
+                // Angle links cannot contain whitespace, which rules out timestamps
+                if let Some(end) = link.find(|c: char| c == '>' || c == '<' || c.is_whitespace()) {
+                    let target = &link[..end];
+                    if link[end..].starts_with('>') && target.contains(':') {
+                        let start = offset + i + 1;
+                        targets.push(start..start + end);
+                        i += 1 + end;
+                        continue;
+                    }
+                }
+            }
+            i += rest.chars().next().map_or(1, |c| c.len_utf8());
+        }
+    }
+
+    /// Get the byte ranges of the targets of all links in the body.
+    fn link_targets(&self) -> Vec<Range<usize>> {
+        let mut targets = vec![];
+        // The line that closes the verbatim block we are in, e.g. `#+end_src`
+        let mut block_end: Option<String> = None;
+        let mut offset = 0;
+        for line in self.body.split_inclusive('\n') {
+            let start = offset;
+            offset += line.len();
+            let trimmed = line.trim();
+            let lower = trimmed.to_lowercase();
+            if let Some(ref end) = block_end {
+                if lower.starts_with(end.as_str()) {
+                    block_end = None;
+                }
+                continue;
+            }
+            if let Some(kind) = lower.strip_prefix("#+begin_") {
+                let kind = kind.split_whitespace().next().unwrap_or("");
+                if VERBATIM_BLOCKS.contains(&kind) {
+                    block_end = Some(format!("#+end_{kind}"));
+                    continue;
+                }
+            }
+            // Fixed-width lines and comment lines
+            if trimmed == ":"
+                || trimmed.starts_with(": ")
+                || trimmed == "#"
+                || trimmed.starts_with("# ")
+            {
+                continue;
+            }
+            OrgNote::find_links(line, start, &mut targets);
+        }
+        targets
+    }
+
+    /// Get the byte range of the whole link around a link target, and of its description if it
+    /// has one.
+    fn link_around(&self, target: &Range<usize>) -> (Range<usize>, Option<Range<usize>>) {
+        let after = &self.body[target.end..];
+        if !self.body[..target.start].ends_with("[[") {
+            // An angle link
+            return (target.start - 1..target.end + 1, None);
+        }
+        if after.starts_with("][") {
+            let start = target.end + 2;
+            if let Some(end) = self.body[start..].find("]]").map(|end| start + end) {
+                return (target.start - 2..end + 2, Some(start..end));
+            }
+        }
+        (target.start - 2..target.end + 2, None)
+    }
+}
+
+impl NoteType for OrgNote {
+    type Error = Infallible;
+
+    fn get_format(&self) -> NoteFormat {
+        NoteFormat::Org
+    }
+
+    fn parse(_format: Option<NoteFormat>, note: String) -> Result<Self, Self::Error> {
+        Ok(OrgNote::new(note))
+    }
+
+    fn get_referents(&self) -> Result<HashSet<NoteID>, Self::Error> {
+        Ok(self
+            .link_targets()
+            .into_iter()
+            .filter_map(|target| OrgNote::extract_note_id_from_url(&self.body[target]))
+            .collect())
+    }
+
+    fn update_referent(
+        &mut self,
+        old_referent: NoteID,
+        new_referent: NoteID,
+    ) -> Result<(), Self::Error> {
+        let new_url = format!("{}", NotegrafURL::Note(new_referent));
+        // Replace from the back so that earlier ranges stay valid
+        for target in self.link_targets().into_iter().rev() {
+            if OrgNote::extract_note_id_from_url(&self.body[target.clone()]).as_ref()
+                == Some(&old_referent)
+            {
+                self.body.replace_range(target, &new_url);
+            }
+        }
+        Ok(())
+    }
+
+    /// In plain text, a link becomes its description, or its target if it has none.
+    fn remove_referent(
+        &mut self,
+        referent: NoteID,
+        rewrite: LinkRewrite,
+    ) -> Result<(), Self::Error> {
+        let tombstone = format!("{}", NotegrafURL::Deleted(referent.clone()));
+        // Replace from the back so that earlier ranges stay valid
+        for target in self.link_targets().into_iter().rev() {
+            if OrgNote::extract_note_id_from_url(&self.body[target.clone()]).as_ref()
+                != Some(&referent)
+            {
+                continue;
+            }
+            match rewrite {
+                LinkRewrite::Tombstone => self.body.replace_range(target, &tombstone),
+                LinkRewrite::PlainText => {
+                    let (link, description) = self.link_around(&target);
+                    let text = self.body[description.unwrap_or(target)].to_owned();
+                    self.body.replace_range(link, &text);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Links become their descriptions, and links without descriptions are left out, so that their
+    /// targets are not indexed.
+    fn get_plain_text(&self) -> Result<String, Self::Error> {
+        let mut plain_text = self.body.clone();
+        // Replace from the back so that earlier ranges stay valid
+        for target in self.link_targets().into_iter().rev() {
+            let (link, description) = self.link_around(&target);
+            plain_text.replace_range(link, description.map_or("", |r| &self.body[r]));
+        }
+        Ok(plain_text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn referents(body: &str) -> HashSet<NoteID> {
+        OrgNote::new(body.to_owned()).get_referents().unwrap()
+    }
+
+    #[test]
+    fn referent_bracket_link() {
+        assert_eq!(
+            referents("See [[notegraf:/note/note-1][foo]]."),
+            HashSet::from([NoteID::new("note-1".to_owned())])
+        );
+    }
+
+    #[test]
+    fn referent_bracket_link_without_description() {
+        assert_eq!(
+            referents("See [[notegraf:/note/note-1]]."),
+            HashSet::from([NoteID::new("note-1".to_owned())])
+        );
+    }
+
+    #[test]
+    fn referent_angle_link() {
+        assert_eq!(
+            referents("See <notegraf:/note/note-1>."),
+            HashSet::from([NoteID::new("note-1".to_owned())])
+        );
+    }
+
+    #[test]
+    fn referent_multiple() {
+        assert_eq!(
+            referents("* [[notegraf:/note/note-1][foo]]\n- <notegraf:/note/note-2> and [[notegraf:/note/note-1]]"),
+            HashSet::from([
+                NoteID::new("note-1".to_owned()),
+                NoteID::new("note-2".to_owned())
+            ])
+        );
+    }
+
+    #[test]
+    fn referent_other_links() {
+        assert!(referents(
+            "[[https://example.com][foo]] [[file:foo.org]] <https://example.com> [[notegraf:/tag/foo]] <2026-10-19 Mon 10:00>"
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn referent_verbatim() {
+        let body = "#+BEGIN_SRC org\n[[notegraf:/note/note-1]]\n#+END_SRC\n\
+                    #+begin_example\n<notegraf:/note/note-2>\n#+end_example\n\
+                    : [[notegraf:/note/note-3]]\n\
+                    # [[notegraf:/note/note-4]]\n\
+                    #+begin_quote\n[[notegraf:/note/note-5]]\n#+end_quote\n";
+        assert_eq!(
+            referents(body),
+            HashSet::from([NoteID::new("note-5".to_owned())])
+        );
+    }
+
+    #[test]
+    fn referent_unicode() {
+        assert_eq!(
+            referents("你好 [[notegraf:/note/note-1][世界]]"),
+            HashSet::from([NoteID::new("note-1".to_owned())])
+        );
+    }
+
+    #[test]
+    fn rewrite_bracket_link() {
+        let id_old = NoteID::new("old".into());
+        let id_new = NoteID::new("new".into());
+        let mut note = OrgNote::new("[[notegraf:/note/old][foo]] [[notegraf:/note/old]]".into());
+        note.update_referent(id_old, id_new).unwrap();
+        assert_eq!(
+            note.body,
+            "[[notegraf:/note/new][foo]] [[notegraf:/note/new]]"
+        );
+    }
+
+    #[test]
+    fn rewrite_angle_link() {
+        let id_old = NoteID::new("old".into());
+        let id_new = NoteID::new("new-and-longer".into());
+        let mut note = OrgNote::new("* Heading\n<notegraf:/note/old> and text".into());
+        note.update_referent(id_old, id_new).unwrap();
+        assert_eq!(
+            note.body,
+            "* Heading\n<notegraf:/note/new-and-longer> and text"
+        );
+    }
+
+    #[test]
+    fn rewrite_only_old_referent() {
+        let id_old = NoteID::new("old".into());
+        let id_new = NoteID::new("new".into());
+        let body =
+            "[[notegraf:/note/other][old]]\n#+begin_src\n[[notegraf:/note/old]]\n#+end_src\n";
+        let mut note = OrgNote::new(body.into());
+        note.update_referent(id_old, id_new).unwrap();
+        assert_eq!(note.body, body);
+    }
+
+    #[test]
+    fn remove_referent() {
+        let id = NoteID::new("old".into());
+        let body = "[[notegraf:/note/old][foo]], [[notegraf:/note/old]] and <notegraf:/note/old>.";
+        let mut note = OrgNote::new(body.into());
+        note.remove_referent(id.clone(), LinkRewrite::PlainText)
+            .unwrap();
+        assert_eq!(note.body, "foo, notegraf:/note/old and notegraf:/note/old.");
+        assert!(note.get_referents().unwrap().is_empty());
+        let mut note = OrgNote::new(body.into());
+        note.remove_referent(id, LinkRewrite::Tombstone).unwrap();
+        assert_eq!(
+            note.body,
+            "[[notegraf:/deleted/old][foo]], [[notegraf:/deleted/old]] and <notegraf:/deleted/old>."
+        );
+        assert!(note.get_referents().unwrap().is_empty());
+    }
+
+    #[test]
+    fn plain_text() {
+        let note = OrgNote::new(
+            "* Heading\nSee [[notegraf:/note/note-1][foo]], [[https://example.com][bar]] <https://example.com>\n[[notegraf:/note/note-2]]\n#+begin_src\n[[notegraf:/note/note-3][baz]]\n#+end_src"
+                .into(),
+        );
+        assert_eq!(
+            note.get_plain_text().unwrap(),
+            "* Heading\nSee foo, bar \n\n#+begin_src\n[[notegraf:/note/note-3][baz]]\n#+end_src"
+        );
+    }
+
+    #[test]
+    fn serialize() {
+        let ser = serde_json::to_string(&OrgNote {
+            body: "Hello, world!".to_owned(),
+        })
+        .unwrap();
+        assert_eq!(ser, "\"Hello, world!\"");
+    }
+
+    #[test]
+    fn deserialize() {
+        let note: OrgNote = serde_json::from_str("\"Hello, world!\"").unwrap();
+        assert_eq!(note.body, "Hello, world!");
+    }
+}
@@ -1,3 +1,4 @@
+use crate::notetype::{LinkRewrite, NoteFormat};
 use crate::{NoteID, NoteType};
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
@@ -7,6 +8,8 @@ use thiserror::Error;
 pub enum PlainNoteError {
     #[error("this note doesn't refer to `{0}`")]
     ReferenceNotExist(NoteID),
+    #[error("note cannot be parsed: `{0}`")]
+    ParseError(#[from] serde_json::Error),
 }
 
 #[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
@@ -42,6 +45,14 @@ impl PlainNote {
 impl NoteType for PlainNote {
     type Error = PlainNoteError;
 
+    fn get_format(&self) -> NoteFormat {
+        NoteFormat::Plain
+    }
+
+    fn parse(_format: Option<NoteFormat>, note: String) -> Result<Self, Self::Error> {
+        Ok(serde_json::from_str(&note)?)
+    }
+
     fn get_referents(&self) -> Result<HashSet<NoteID>, Self::Error> {
         Ok(self.referents.clone())
     }
@@ -58,17 +69,25 @@ impl NoteType for PlainNote {
         self.referents.insert(new_referent);
         Ok(())
     }
-}
 
-impl From<String> for PlainNote {
-    fn from(note: String) -> PlainNote {
-        serde_json::from_str(&note).unwrap()
+    /// The body does not link to referents, so both ways of rewriting just drop the referent.
+    fn remove_referent(
+        &mut self,
+        referent: NoteID,
+        _rewrite: LinkRewrite,
+    ) -> Result<(), Self::Error> {
+        if !self.referents.remove(&referent) {
+            return Err(Self::Error::ReferenceNotExist(referent));
+        }
+        Ok(())
+    }
+
+    fn get_plain_text(&self) -> Result<String, Self::Error> {
+        Ok(self.body.clone())
     }
-}
 
-impl From<&str> for PlainNote {
-    fn from(note: &str) -> PlainNote {
-        serde_json::from_str(note).unwrap()
+    fn get_body(&self) -> Result<String, Self::Error> {
+        Ok(self.body.clone())
     }
 }
 
@@ -82,6 +101,18 @@ impl From<PlainNote> for String {
 mod tests {
     use super::*;
 
+    #[test]
+    fn parse() {
+        let mut note = PlainNote::new("Foo".into());
+        note.add_referent(NoteID::new("ID1".into()));
+        let parsed = PlainNote::parse(None, note.clone().into()).unwrap();
+        assert_eq!(parsed, note);
+        assert!(matches!(
+            PlainNote::parse(None, "Foo".into()),
+            Err(PlainNoteError::ParseError(_))
+        ));
+    }
+
     #[test]
     fn retrieve_references() {
         let mut note = PlainNote::new("Foo".into());
@@ -122,4 +153,17 @@ mod tests {
         note.add_referent(NoteID::new("ID2".into()));
         assert_eq!(note.get_referents().unwrap().len(), 2);
     }
+
+    #[test]
+    fn remove_references() {
+        let mut note = PlainNote::new("Foo".into());
+        note.add_referent(NoteID::new("ID1".into()));
+        note.remove_referent(NoteID::new("ID1".into()), LinkRewrite::Tombstone)
+            .unwrap();
+        assert!(note.get_referents().unwrap().is_empty());
+        assert!(matches!(
+            note.remove_referent(NoteID::new("ID1".into()), LinkRewrite::PlainText),
+            Err(PlainNoteError::ReferenceNotExist(_))
+        ));
+    }
 }
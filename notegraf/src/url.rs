@@ -1,6 +1,7 @@
 //! URL utilities.
 use crate::errors::URLParseError;
-use crate::NoteID;
+use crate::{NoteID, Revision};
+use std::collections::HashSet;
 use std::fmt;
 use url::Url;
 
@@ -8,6 +9,20 @@ use url::Url;
 #[derive(Debug)]
 pub enum NotegrafURL {
     Note(NoteID),
+    /// A note referred to by one of its aliases.
+    ///
+    /// Links to aliases are resolved when they are followed, so they are not referents of notes.
+    Alias(String),
+    /// A specific revision of a note.
+    ///
+    /// Links to revisions are not referents of notes, but they keep the revisions from being
+    /// removed by [`NoteStore::compact_revisions`](crate::NoteStore::compact_revisions).
+    Revision(NoteID, Revision),
+    /// A link to a note that was deleted, left behind by
+    /// [`NoteStore::delete_note_with`](crate::NoteStore::delete_note_with).
+    ///
+    /// Tombstones are not referents of notes.
+    Deleted(NoteID),
 }
 
 impl NotegrafURL {
@@ -26,16 +41,20 @@ impl NotegrafURL {
                 return Err(URLParseError::CannotBeABase);
             }
         };
-        if parts.len() != 2 {
-            return Err(URLParseError::SyntaxError(
-                "URL has more than two parts.".into(),
-            ));
-        }
-        match parts[0] {
-            "note" => Ok(NotegrafURL::Note(NoteID::new(parts[1].into()))),
-            _ => Err(URLParseError::SyntaxError(
+        match parts[..] {
+            ["note", id] => Ok(NotegrafURL::Note(NoteID::new(id.into()))),
+            ["alias", alias] => Ok(NotegrafURL::Alias(alias.into())),
+            ["deleted", id] => Ok(NotegrafURL::Deleted(NoteID::new(id.into()))),
+            ["note", id, "revision", revision] => Ok(NotegrafURL::Revision(
+                NoteID::new(id.into()),
+                Revision::new(revision.into()),
+            )),
+            [_, _] | [_, _, _, _] => Err(URLParseError::SyntaxError(
                 "First part of the URL not recognized.".into(),
             )),
+            _ => Err(URLParseError::SyntaxError(
+                "URL has the wrong number of parts.".into(),
+            )),
         }
     }
 }
@@ -44,10 +63,34 @@ impl fmt::Display for NotegrafURL {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             NotegrafURL::Note(id) => write!(f, "notegraf:/note/{id}"),
+            NotegrafURL::Alias(alias) => write!(f, "notegraf:/alias/{alias}"),
+            NotegrafURL::Revision(id, revision) => {
+                write!(f, "notegraf:/note/{id}/revision/{revision}")
+            }
+            NotegrafURL::Deleted(id) => write!(f, "notegraf:/deleted/{id}"),
         }
     }
 }
 
+/// Find the links to specific revisions in some text, whatever format it is in.
+pub fn find_revision_links(text: &str) -> HashSet<(NoteID, Revision)> {
+    const PREFIX: &str = "notegraf:/note/";
+    text.match_indices(PREFIX)
+        .filter_map(|(start, _)| {
+            let rest = &text[start..];
+            let end = rest
+                .find(|c: char| c.is_whitespace() || "()[]<>\"'`|,".contains(c))
+                .unwrap_or(rest.len());
+            // Punctuation at the end of a sentence is not part of the link
+            let link = rest[..end].trim_end_matches(['.', ';', ':', '!', '?']);
+            match NotegrafURL::parse(link) {
+                Ok(NotegrafURL::Revision(id, revision)) => Some((id, revision)),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -61,4 +104,43 @@ mod tests {
             assert_eq!(s, "http");
         }
     }
+
+    #[test]
+    fn alias() {
+        let url = NotegrafURL::parse("notegraf:/alias/meeting-notes").unwrap();
+        assert!(matches!(url, NotegrafURL::Alias(ref a) if a == "meeting-notes"));
+        assert_eq!(url.to_string(), "notegraf:/alias/meeting-notes");
+    }
+
+    #[test]
+    fn deleted() {
+        let url = NotegrafURL::parse("notegraf:/deleted/note1").unwrap();
+        assert!(matches!(url, NotegrafURL::Deleted(ref id) if id.as_ref() == "note1"));
+        assert_eq!(url.to_string(), "notegraf:/deleted/note1");
+    }
+
+    #[test]
+    fn revision() {
+        let url = NotegrafURL::parse("notegraf:/note/note1/revision/rev1").unwrap();
+        assert!(
+            matches!(url, NotegrafURL::Revision(ref id, ref rev) if id.as_ref() == "note1" && rev.as_ref() == "rev1")
+        );
+        assert_eq!(url.to_string(), "notegraf:/note/note1/revision/rev1");
+        assert!(NotegrafURL::parse("notegraf:/note/note1/branch/rev1").is_err());
+    }
+
+    #[test]
+    fn revision_links() {
+        let links = find_revision_links(
+            "See [this](notegraf:/note/a/revision/1), notegraf:/note/b/revision/2. \
+             Not [[notegraf:/note/c]] or notegraf:/note/d/revision/3/extra.",
+        );
+        assert_eq!(
+            links,
+            HashSet::from([
+                (NoteID::new("a".into()), Revision::new("1".into())),
+                (NoteID::new("b".into()), Revision::new("2".into())),
+            ])
+        );
+    }
 }